@@ -0,0 +1,37 @@
+//! Sending requests through S3 Transfer Acceleration.
+use crate::OpenOptions;
+
+impl OpenOptions {
+    /// Send requests through the bucket's Transfer Acceleration endpoint
+    ///
+    /// Requests go to `<bucket>.s3-accelerate.amazonaws.com`, which routes them from the nearest CloudFront
+    /// edge location over Amazon's network, so transfers between continents are often much faster.
+    /// Acceleration must first be enabled on the bucket, and each accelerated transfer is charged on top of
+    /// the usual data transfer price.
+    ///
+    /// Accelerate endpoints cannot be used with path style addressing, a custom endpoint URL, or bucket
+    /// names containing dots, and requests for such clients fail. Acceleration is set on the S3 client, so
+    /// it does not apply to a backend installed with [OpenOptions::backend]. Defaults to false.
+    ///
+    /// # Arguments
+    /// * `enabled`: Whether to use the accelerate endpoint.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .transfer_acceleration(true);
+    ///
+    ///     let data = open_options.read("large/model.bin").await.unwrap();
+    /// }
+    /// ```
+    pub fn transfer_acceleration(self, enabled: bool) -> Self {
+        self.reconfigure_client(|config| config.accelerate(enabled))
+    }
+}
@@ -0,0 +1,970 @@
+//! Streaming every object under a prefix into a single tar or zip archive, and uploading the files in one.
+//!
+//! Both formats are written without compression, so objects are copied straight from S3 into the archive
+//! without ever touching the mount path. Tar entries follow POSIX ustar, with a pax extended header for
+//! keys longer than the ustar name field or objects of 8 GiB or more. Zip entries are stored, with their
+//! CRC-32 in a data descriptor after the data as it is only known once the object has been read, and
+//! Zip64 records wherever a size, offset or entry count overflows the original format.
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{backend::GetRequest, gzip, DirEntry, OpenOptions, S3FilesystemError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The kind of archive [OpenOptions::download_archive] writes.
+pub enum ArchiveFormat {
+    /// A POSIX tar archive, uncompressed.
+    Tar,
+    /// A zip archive with every entry stored uncompressed.
+    Zip,
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Zip => "zip",
+        })
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown archive format {}", s),
+            )),
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Stream every object under a prefix into one tar or zip archive
+    ///
+    /// The objects are listed, then each is downloaded in key order and written to `writer` as it arrives,
+    /// so a dataset of many small files can be exported as a single file without mirroring it first.
+    /// Nothing is written to the mount path and the cache is not consulted. Entries are named by their
+    /// full keys, and folder marker objects (keys ending in `/`) become directory entries.
+    ///
+    /// `writer` receives many small writes, so wrap files in a [tokio::io::BufWriter]. It is flushed but not
+    /// shut down once the archive is complete. If an object changes size between the listing and its
+    /// download an [io::ErrorKind::InvalidData] error is returned, and whatever has already been written
+    /// should be discarded.
+    ///
+    /// Returns the number of objects written to the archive.
+    ///
+    /// # Arguments
+    /// * `prefix`: Every object whose key starts with this is archived. Use "" for the whole bucket.
+    /// * `writer`: Where the archive is written.
+    /// * `format`: Whether to write a tar or a zip archive.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{ArchiveFormat, OpenOptions};
+    /// use tokio::io::BufWriter;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let file = tokio::fs::File::create("redasa1-Q1-20.tar").await.unwrap();
+    ///     let archived = open_options
+    ///         .download_archive("redasa1-Q1-20/", BufWriter::new(file), ArchiveFormat::Tar)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     println!("Archived {} objects", archived);
+    /// }
+    /// ```
+    pub async fn download_archive<W>(
+        &self,
+        prefix: &str,
+        mut writer: W,
+        format: ArchiveFormat,
+    ) -> Result<usize, S3FilesystemError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let (mut objects, _) = self
+            .list_objects(prefix, false, &|_| true)
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(prefix.as_ref())))?;
+        objects.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut archive = match format {
+            ArchiveFormat::Tar => Archive::Tar,
+            ArchiveFormat::Zip => Archive::Zip(ZipDirectory::default()),
+        };
+        for object in &objects {
+            let key = object.path.to_string_lossy();
+            self.archive_object(&mut archive, &mut writer, object, &key)
+                .await
+                .map_err(|e| e.with_context("GetObject", &self.bucket, Some(Path::new(&*key))))?;
+        }
+        archive.finish(&mut writer).await?;
+        writer.flush().await?;
+
+        Ok(objects.len())
+    }
+
+    async fn archive_object<W>(
+        &self,
+        archive: &mut Archive,
+        writer: &mut W,
+        object: &DirEntry,
+        key: &str,
+    ) -> Result<(), S3FilesystemError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let size = object.size.max(0) as u64;
+        let modified = object.last_modified.unwrap_or(UNIX_EPOCH);
+        archive
+            .start_entry(writer, key, size, modified, object.folder)
+            .await?;
+        if object.folder {
+            return Ok(archive.end_entry(writer, size, 0).await?);
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .cancellable(self.backend.get(GetRequest {
+                bucket: &self.bucket,
+                key,
+                range: None,
+                if_match: None,
+                if_none_match: None,
+            }))
+            .await;
+        self.record_request("GetObject", started, result.is_ok());
+        let mut body = result?.body;
+
+        let (written, crc) = self
+            .cancellable(async {
+                let mut written = 0;
+                let mut crc = Crc32::default();
+                while let Some(bytes) = body.try_next().await? {
+                    if let Some(limiter) = &self.bandwidth_limiter {
+                        limiter.acquire(bytes.len() as u64).await;
+                    }
+                    written += bytes.len() as u64;
+                    if written > size {
+                        break;
+                    }
+                    crc.update(&bytes);
+                    writer.write_all(&bytes).await?;
+                }
+                Ok((written, crc.finish()))
+            })
+            .await?;
+        drop(slot);
+        if written != size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "object was listed as {} bytes but {} were downloaded",
+                    size, written
+                ),
+            )
+            .into());
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.downloaded(key, size);
+        }
+        Ok(archive.end_entry(writer, size, crc).await?)
+    }
+
+    /// Upload every file in a tar or zip archive as an object under a prefix
+    ///
+    /// The inverse of [OpenOptions::download_archive], for ingesting data drops delivered as a single file.
+    /// Each file in the archive is uploaded to the key `prefix` + its path in the archive with
+    /// [OpenOptions::write_s3_direct], so nothing is written to the mount path. A leading `./` or `/` is
+    /// dropped from paths, and directories, links and other special entries are skipped.
+    ///
+    /// The format is told from the first bytes of the stream. Tar archives are read one file at a time.
+    /// Zip archives keep their index at the end, so they are read into memory in full; their members may be
+    /// stored or deflated. Files are uploaded in archive order, and if one fails those before it are left
+    /// in place.
+    ///
+    /// Returns the keys written, in archive order.
+    ///
+    /// # Arguments
+    /// * `reader`: The archive.
+    /// * `prefix`: Where in the bucket the files go. A `/` is added if it does not end in one; use "" for
+    ///   the root of the bucket.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use tokio::io::BufReader;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let file = tokio::fs::File::open("vendor-drop.zip").await.unwrap();
+    ///     let keys = open_options
+    ///         .upload_archive(BufReader::new(file), "ingest/2024-01-31")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     println!("Uploaded {} files", keys.len());
+    /// }
+    /// ```
+    pub async fn upload_archive<R>(
+        &self,
+        mut reader: R,
+        prefix: &str,
+    ) -> Result<Vec<PathBuf>, S3FilesystemError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let prefix = match prefix.is_empty() || prefix.ends_with('/') {
+            true => prefix.to_string(),
+            false => format!("{}/", prefix),
+        };
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).await?;
+        let mut uploaded = Vec::new();
+
+        if &magic == b"PK\x03\x04" || &magic == b"PK\x05\x06" {
+            let mut data = magic.to_vec();
+            reader.read_to_end(&mut data).await?;
+            let data = Bytes::from(data);
+            for member in zip_members(&data)? {
+                if let Some(key) = member_key(&prefix, &member.name) {
+                    let contents = member.contents(&data)?;
+                    self.write_s3_direct(&key, contents).await?;
+                    uploaded.push(PathBuf::from(key));
+                }
+            }
+            return Ok(uploaded);
+        }
+
+        let mut header = [0; 512];
+        header[..4].copy_from_slice(&magic);
+        let mut filled = 4;
+        let mut overrides = TarOverrides::default();
+        loop {
+            match reader.read_exact(&mut header[filled..]).await {
+                Ok(_) => {}
+                // Some writers leave off the two empty blocks which should end the archive.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && filled == 0 => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(not_an_archive().into())
+                }
+                Err(e) => return Err(e.into()),
+            }
+            filled = 0;
+            if header.iter().all(|&byte| byte == 0) {
+                break;
+            }
+
+            let entry = TarEntry::parse(&header, &overrides)?;
+            let mut contents = vec![0; entry.size as usize];
+            reader.read_exact(&mut contents).await?;
+            let padding = (512 - entry.size % 512) % 512;
+            reader.read_exact(&mut vec![0; padding as usize]).await?;
+
+            match entry.type_flag {
+                b'x' => overrides = TarOverrides::from_pax(&contents),
+                b'L' => {
+                    let name = contents.split(|&byte| byte == 0).next().unwrap_or_default();
+                    overrides.path = Some(String::from_utf8_lossy(name).into_owned());
+                }
+                // A global pax header applies defaults this does not use.
+                b'g' => {}
+                type_flag => {
+                    overrides = TarOverrides::default();
+                    if !matches!(type_flag, b'0' | b'\0' | b'7') {
+                        continue;
+                    }
+                    if let Some(key) = member_key(&prefix, &entry.name) {
+                        self.write_s3_direct(&key, contents).await?;
+                        uploaded.push(PathBuf::from(key));
+                    }
+                }
+            }
+        }
+
+        Ok(uploaded)
+    }
+}
+
+/// The state of an archive being written.
+enum Archive {
+    Tar,
+    Zip(ZipDirectory),
+}
+
+impl Archive {
+    async fn start_entry<W>(
+        &mut self,
+        writer: &mut W,
+        name: &str,
+        size: u64,
+        modified: SystemTime,
+        folder: bool,
+    ) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            Archive::Tar => {
+                writer
+                    .write_all(&tar_header(name, size, modified, folder))
+                    .await
+            }
+            Archive::Zip(directory) => {
+                let entry = ZipEntry {
+                    name: name.to_string(),
+                    size,
+                    crc: 0,
+                    modified: dos_time(modified),
+                    offset: directory.offset,
+                    folder,
+                };
+                let header = entry.local_header();
+                writer.write_all(&header).await?;
+                directory.offset += header.len() as u64;
+                directory.entries.push(entry);
+                Ok(())
+            }
+        }
+    }
+
+    async fn end_entry<W>(&mut self, writer: &mut W, size: u64, crc: u32) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            Archive::Tar => {
+                let padding = (512 - size % 512) % 512;
+                writer.write_all(&vec![0; padding as usize]).await
+            }
+            Archive::Zip(directory) => {
+                let entry = directory
+                    .entries
+                    .last_mut()
+                    .expect("an entry has been started");
+                entry.crc = crc;
+                let descriptor = entry.data_descriptor();
+                writer.write_all(&descriptor).await?;
+                directory.offset += size + descriptor.len() as u64;
+                Ok(())
+            }
+        }
+    }
+
+    async fn finish<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            Archive::Tar => writer.write_all(&[0; 1024]).await,
+            Archive::Zip(directory) => writer.write_all(&directory.central_directory()).await,
+        }
+    }
+}
+
+/// The largest size a ustar header can hold, 11 octal digits.
+const TAR_MAX_SIZE: u64 = 0o777_7777_7777;
+
+/// The 512 byte ustar header for an entry, preceded by a pax extended header if the name or size does
+/// not fit.
+fn tar_header(name: &str, size: u64, modified: SystemTime, folder: bool) -> Vec<u8> {
+    let mtime = modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut pax = String::new();
+    if name.len() > 100 {
+        pax.push_str(&pax_record("path", name));
+    }
+    if size > TAR_MAX_SIZE {
+        pax.push_str(&pax_record("size", &size.to_string()));
+    }
+
+    let mut header = Vec::new();
+    if !pax.is_empty() {
+        header.extend(ustar_block("././@PaxHeader", pax.len() as u64, mtime, b'x'));
+        header.extend(pax.as_bytes());
+        header.resize(header.len().next_multiple_of(512), 0);
+    }
+    let type_flag = if folder { b'5' } else { b'0' };
+    header.extend(ustar_block(name, size.min(TAR_MAX_SIZE), mtime, type_flag));
+    header
+}
+
+fn ustar_block(name: &str, size: u64, mtime: u64, type_flag: u8) -> [u8; 512] {
+    let mut block = [0; 512];
+    let name = &name.as_bytes()[..name.len().min(100)];
+    block[..name.len()].copy_from_slice(name);
+    let mode = if type_flag == b'5' { 0o755 } else { 0o644 };
+    block[100..108].copy_from_slice(format!("{:07o}\0", mode).as_bytes());
+    block[108..116].copy_from_slice(b"0000000\0");
+    block[116..124].copy_from_slice(b"0000000\0");
+    block[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    block[136..148].copy_from_slice(format!("{:011o}\0", mtime.min(0o777_7777_7777)).as_bytes());
+    block[148..156].copy_from_slice(b"        ");
+    block[156] = type_flag;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+
+    let checksum: u32 = block.iter().map(|&byte| byte as u32).sum();
+    block[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    block
+}
+
+/// A pax record, `<length> <keyword>=<value>\n`, where the length counts its own digits.
+fn pax_record(keyword: &str, value: &str) -> String {
+    let rest = keyword.len() + value.len() + 3;
+    let mut length = rest + 1;
+    while length != rest + length.to_string().len() {
+        length = rest + length.to_string().len();
+    }
+    format!("{} {}={}\n", length, keyword, value)
+}
+
+/// Zip fields which overflow are set to this and the real value kept in a Zip64 extra field.
+const ZIP64_MARKER: u32 = u32::MAX;
+/// General purpose flags: sizes and CRC follow the data (bit 3), and names are UTF-8 (bit 11).
+const ZIP_FLAGS: u16 = 0x0808;
+/// Version made by: Unix, zip specification 4.5.
+const ZIP_MADE_BY: u16 = (3 << 8) | 45;
+
+#[derive(Debug, Default)]
+struct ZipDirectory {
+    entries: Vec<ZipEntry>,
+    /// Bytes written so far, which is where the next local header starts.
+    offset: u64,
+}
+
+#[derive(Debug)]
+struct ZipEntry {
+    name: String,
+    size: u64,
+    crc: u32,
+    /// The modification time and date, in MS-DOS format.
+    modified: (u16, u16),
+    offset: u64,
+    folder: bool,
+}
+
+impl ZipEntry {
+    fn zip64(&self) -> bool {
+        self.size >= ZIP64_MARKER as u64
+    }
+
+    fn version_needed(&self) -> u16 {
+        if self.zip64() || self.offset >= ZIP64_MARKER as u64 {
+            45
+        } else {
+            20
+        }
+    }
+
+    fn local_header(&self) -> Vec<u8> {
+        let mut extra = Vec::new();
+        if self.zip64() {
+            // With the sizes in a data descriptor these are written as zero.
+            put_u16(&mut extra, 0x0001);
+            put_u16(&mut extra, 16);
+            put_u64(&mut extra, 0);
+            put_u64(&mut extra, 0);
+        }
+        let sizes = if self.zip64() { ZIP64_MARKER } else { 0 };
+
+        let mut header = Vec::new();
+        put_u32(&mut header, 0x0403_4b50);
+        put_u16(&mut header, self.version_needed());
+        put_u16(&mut header, ZIP_FLAGS);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, self.modified.0);
+        put_u16(&mut header, self.modified.1);
+        put_u32(&mut header, 0);
+        put_u32(&mut header, sizes);
+        put_u32(&mut header, sizes);
+        put_u16(&mut header, self.name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+        header.extend(self.name.as_bytes());
+        header.extend(extra);
+        header
+    }
+
+    fn data_descriptor(&self) -> Vec<u8> {
+        let mut descriptor = Vec::new();
+        put_u32(&mut descriptor, 0x0807_4b50);
+        put_u32(&mut descriptor, self.crc);
+        if self.zip64() {
+            put_u64(&mut descriptor, self.size);
+            put_u64(&mut descriptor, self.size);
+        } else {
+            put_u32(&mut descriptor, self.size as u32);
+            put_u32(&mut descriptor, self.size as u32);
+        }
+        descriptor
+    }
+
+    fn central_header(&self) -> Vec<u8> {
+        let mut extra = Vec::new();
+        if self.zip64() {
+            put_u64(&mut extra, self.size);
+            put_u64(&mut extra, self.size);
+        }
+        if self.offset >= ZIP64_MARKER as u64 {
+            put_u64(&mut extra, self.offset);
+        }
+        if !extra.is_empty() {
+            let mut field = Vec::new();
+            put_u16(&mut field, 0x0001);
+            put_u16(&mut field, extra.len() as u16);
+            field.extend(extra);
+            extra = field;
+        }
+        let size = self.size.min(ZIP64_MARKER as u64) as u32;
+        let mode: u32 = if self.folder { 0o40755 } else { 0o100644 };
+        let dos_attributes = if self.folder { 0x10 } else { 0 };
+
+        let mut header = Vec::new();
+        put_u32(&mut header, 0x0201_4b50);
+        put_u16(&mut header, ZIP_MADE_BY);
+        put_u16(&mut header, self.version_needed());
+        put_u16(&mut header, ZIP_FLAGS);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, self.modified.0);
+        put_u16(&mut header, self.modified.1);
+        put_u32(&mut header, self.crc);
+        put_u32(&mut header, size);
+        put_u32(&mut header, size);
+        put_u16(&mut header, self.name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, 0);
+        put_u32(&mut header, (mode << 16) | dos_attributes);
+        put_u32(&mut header, self.offset.min(ZIP64_MARKER as u64) as u32);
+        header.extend(self.name.as_bytes());
+        header.extend(extra);
+        header
+    }
+}
+
+impl ZipDirectory {
+    /// The central directory and the end of central directory records which close the archive.
+    fn central_directory(&self) -> Vec<u8> {
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend(entry.central_header());
+        }
+        let start = self.offset;
+        let length = directory.len() as u64;
+        let count = self.entries.len() as u64;
+
+        let zip64 = count >= u16::MAX as u64
+            || start >= ZIP64_MARKER as u64
+            || length >= ZIP64_MARKER as u64;
+        if zip64 {
+            let record_offset = start + length;
+            put_u32(&mut directory, 0x0606_4b50);
+            put_u64(&mut directory, 44);
+            put_u16(&mut directory, ZIP_MADE_BY);
+            put_u16(&mut directory, 45);
+            put_u32(&mut directory, 0);
+            put_u32(&mut directory, 0);
+            put_u64(&mut directory, count);
+            put_u64(&mut directory, count);
+            put_u64(&mut directory, length);
+            put_u64(&mut directory, start);
+
+            put_u32(&mut directory, 0x0706_4b50);
+            put_u32(&mut directory, 0);
+            put_u64(&mut directory, record_offset);
+            put_u32(&mut directory, 1);
+        }
+
+        let count = count.min(u16::MAX as u64) as u16;
+        put_u32(&mut directory, 0x0605_4b50);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, count);
+        put_u16(&mut directory, count);
+        put_u32(&mut directory, length.min(ZIP64_MARKER as u64) as u32);
+        put_u32(&mut directory, start.min(ZIP64_MARKER as u64) as u32);
+        put_u16(&mut directory, 0);
+        directory
+    }
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend(value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend(value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend(value.to_le_bytes());
+}
+
+/// The key a file in an archive is uploaded to, or None if it names a directory.
+fn member_key(prefix: &str, name: &str) -> Option<String> {
+    let mut name = name;
+    while let Some(rest) = name.strip_prefix("./").or_else(|| name.strip_prefix('/')) {
+        name = rest;
+    }
+    match name.is_empty() || name.ends_with('/') {
+        true => None,
+        false => Some(format!("{}{}", prefix, name)),
+    }
+}
+
+/// Values from a pax extended header or GNU long name entry, which replace those in the next header.
+#[derive(Debug, Default)]
+struct TarOverrides {
+    path: Option<String>,
+    size: Option<u64>,
+}
+
+impl TarOverrides {
+    fn from_pax(records: &[u8]) -> Self {
+        let mut overrides = TarOverrides::default();
+        for record in String::from_utf8_lossy(records).lines() {
+            let keyword_value = record.split_once(' ').map_or("", |(_, rest)| rest);
+            match keyword_value.split_once('=') {
+                Some(("path", path)) => overrides.path = Some(path.to_string()),
+                Some(("size", size)) => overrides.size = size.parse().ok(),
+                _ => {}
+            }
+        }
+        overrides
+    }
+}
+
+/// The parts of a tar header needed to read the entry.
+struct TarEntry {
+    name: String,
+    size: u64,
+    type_flag: u8,
+}
+
+impl TarEntry {
+    fn parse(header: &[u8; 512], overrides: &TarOverrides) -> io::Result<Self> {
+        let stored: u64 = octal(&header[148..156])?;
+        let checksum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| if (148..156).contains(&i) { b' ' } else { byte } as u64)
+            .sum();
+        if stored != checksum {
+            return Err(not_an_archive());
+        }
+
+        let name = match &overrides.path {
+            Some(path) => path.clone(),
+            None => {
+                let name = c_string(&header[..100]);
+                let prefix = c_string(&header[345..500]);
+                match &header[257..262] == b"ustar" && !prefix.is_empty() {
+                    true => format!("{}/{}", prefix, name),
+                    false => name,
+                }
+            }
+        };
+
+        // GNU tar stores sizes too large for octal as big-endian binary, marked by the top bit.
+        let size = match overrides.size {
+            Some(size) => size,
+            None if header[124] & 0x80 != 0 => header[125..136]
+                .iter()
+                .fold(0, |size, &byte| (size << 8) | byte as u64),
+            None => octal(&header[124..136])?,
+        };
+
+        Ok(TarEntry {
+            name,
+            size,
+            type_flag: header[156],
+        })
+    }
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn octal(field: &[u8]) -> io::Result<u64> {
+    let digits = c_string(field);
+    let digits = digits.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("tar header has an invalid number"))
+}
+
+/// A file in a zip archive, as described by the central directory.
+struct ZipMember {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+impl ZipMember {
+    /// The member's uncompressed contents, checked against its CRC-32.
+    fn contents(&self, data: &Bytes) -> io::Result<Bytes> {
+        if self.flags & 1 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is encrypted", self.name),
+            ));
+        }
+
+        let header = self.offset as usize;
+        if zip_field(data, header, 4)? != b"PK\x03\x04" {
+            return Err(invalid("zip local header is missing"));
+        }
+        let start =
+            header + 30 + le16(data, header + 26)? as usize + le16(data, header + 28)? as usize;
+        zip_field(data, start, self.compressed_size as usize)?;
+        let compressed = data.slice(start..start + self.compressed_size as usize);
+
+        let contents = match self.method {
+            0 => compressed,
+            8 => Bytes::from(gzip::inflate_raw(&compressed)?),
+            method => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "{} uses unsupported compression method {}",
+                        self.name, method
+                    ),
+                ))
+            }
+        };
+
+        let mut crc = Crc32::default();
+        crc.update(&contents);
+        if contents.len() as u64 != self.size || crc.finish() != self.crc {
+            return Err(invalid("zip member does not match its checksum"));
+        }
+        Ok(contents)
+    }
+}
+
+/// The files in a zip archive held in memory, from its central directory.
+fn zip_members(data: &[u8]) -> io::Result<Vec<ZipMember>> {
+    // The end record is 22 bytes followed by a comment of up to 65535.
+    let end = (data.len().saturating_sub(22 + u16::MAX as usize)..=data.len().saturating_sub(22))
+        .rev()
+        .find(|&i| data[i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| invalid("zip archive has no end of central directory"))?;
+    let mut count = le16(data, end + 10)? as u64;
+    let mut position = le32(data, end + 16)? as u64;
+
+    if end >= 20 && data[end - 20..].starts_with(b"PK\x06\x07") {
+        let record = le64(data, end - 20 + 8)? as usize;
+        if zip_field(data, record, 4)? != b"PK\x06\x06" {
+            return Err(invalid("zip64 end of central directory is missing"));
+        }
+        count = le64(data, record + 32)?;
+        position = le64(data, record + 48)?;
+    }
+
+    let mut members = Vec::new();
+    let mut position = position as usize;
+    for _ in 0..count {
+        if zip_field(data, position, 4)? != b"PK\x01\x02" {
+            return Err(invalid("zip central directory is corrupt"));
+        }
+        let name_length = le16(data, position + 28)? as usize;
+        let extra_length = le16(data, position + 30)? as usize;
+        let comment_length = le16(data, position + 32)? as usize;
+        let name = zip_field(data, position + 46, name_length)?;
+        let mut member = ZipMember {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: le16(data, position + 8)?,
+            method: le16(data, position + 10)?,
+            crc: le32(data, position + 16)?,
+            compressed_size: le32(data, position + 20)? as u64,
+            size: le32(data, position + 24)? as u64,
+            offset: le32(data, position + 42)? as u64,
+        };
+
+        let extra = zip_field(data, position + 46 + name_length, extra_length)?;
+        member.read_zip64_extra(extra)?;
+
+        // Skip symbolic links stored by Unix zip tools.
+        let made_on_unix = le16(data, position + 4)? >> 8 == 3;
+        let mode = le32(data, position + 38)? >> 16;
+        if !(made_on_unix && mode & 0o170000 == 0o120000) {
+            members.push(member);
+        }
+        position += 46 + name_length + extra_length + comment_length;
+    }
+
+    Ok(members)
+}
+
+impl ZipMember {
+    /// Replace sizes and the offset which overflowed with their values from the Zip64 extra field.
+    fn read_zip64_extra(&mut self, mut extra: &[u8]) -> io::Result<()> {
+        while extra.len() >= 4 {
+            let id = le16(extra, 0)?;
+            let length = le16(extra, 2)? as usize;
+            let field = zip_field(extra, 4, length)?;
+            if id == 0x0001 {
+                let mut values = field
+                    .chunks_exact(8)
+                    .map(|value| u64::from_le_bytes(value.try_into().expect("chunks are 8 bytes")));
+                for value in [&mut self.size, &mut self.compressed_size, &mut self.offset] {
+                    if *value == ZIP64_MARKER as u64 {
+                        *value = values
+                            .next()
+                            .ok_or_else(|| invalid("zip64 extra field is truncated"))?;
+                    }
+                }
+            }
+            extra = &extra[4 + length..];
+        }
+        Ok(())
+    }
+}
+
+fn zip_field(data: &[u8], at: usize, length: usize) -> io::Result<&[u8]> {
+    data.get(at..at.saturating_add(length))
+        .ok_or_else(|| invalid("zip archive is truncated"))
+}
+
+fn le16(data: &[u8], at: usize) -> io::Result<u16> {
+    let field = zip_field(data, at, 2)?;
+    Ok(u16::from_le_bytes([field[0], field[1]]))
+}
+
+fn le32(data: &[u8], at: usize) -> io::Result<u32> {
+    let field = zip_field(data, at, 4)?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+fn le64(data: &[u8], at: usize) -> io::Result<u64> {
+    let field = zip_field(data, at, 8)?;
+    Ok(u64::from_le_bytes(
+        field.try_into().expect("field is 8 bytes"),
+    ))
+}
+
+fn not_an_archive() -> io::Error {
+    invalid("archive is neither a zip nor a tar archive")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The MS-DOS time and date zip records modification times in, clamped to the years 1980 to 2107.
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let year = year.min(2107);
+
+    let second_of_day = seconds % 86_400;
+    let dos_time = ((second_of_day / 3600) << 11)
+        | ((second_of_day % 3600 / 60) << 5)
+        | ((second_of_day % 60) / 2);
+    let dos_date = (((year - 1980) as u64) << 9) | ((month as u64) << 5) | day as u64;
+    (dos_time as u16, dos_date as u16)
+}
+
+/// The year, month and day for a number of days since 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The CRC-32 zip stores for each entry, computed as the data streams past.
+struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32(!0)
+    }
+}
+
+impl Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+};
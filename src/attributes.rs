@@ -0,0 +1,180 @@
+//! Reading an object's attributes, including how it was split into parts, without downloading it.
+use std::{
+    path::Path,
+    time::{Instant, SystemTime},
+};
+
+use aws_sdk_s3::types::ObjectAttributes as Attribute;
+
+use crate::{key::s3_key, ChecksumAlgorithm, ObjectChecksum, OpenOptions, S3FilesystemError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One part of an object uploaded in parts, as reported by [OpenOptions::attributes].
+pub struct ObjectPart {
+    /// The part's number, counting from 1.
+    pub number: u32,
+    /// Where the part starts in the object, in bytes.
+    pub offset: u64,
+    /// The part's size in bytes.
+    pub size: u64,
+    /// The part's own checksum, if it was uploaded with a CRC-32C or SHA-256 checksum.
+    pub checksum: Option<ObjectChecksum>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What [OpenOptions::attributes] reports about an object.
+pub struct ObjectAttributes {
+    /// The object's size in bytes.
+    pub size: u64,
+    /// The object's ETag, quoted as it is in listings.
+    pub e_tag: Option<String>,
+    /// When the object was last written, if S3 said.
+    pub last_modified: Option<SystemTime>,
+    /// The object's storage class, such as "STANDARD" or "GLACIER".
+    pub storage_class: Option<String>,
+    /// The CRC-32C or SHA-256 checksum the object was uploaded with, if any. For objects uploaded in parts
+    /// this is the checksum of the part checksums.
+    pub checksum: Option<ObjectChecksum>,
+    /// How many parts the object was uploaded in, or None if it was uploaded whole.
+    pub parts_count: Option<u32>,
+    /// The object's parts in order. S3 only lists the parts of objects uploaded with an additional
+    /// checksum, so this is empty for other objects even when `parts_count` is set.
+    pub parts: Vec<ObjectPart>,
+}
+
+impl OpenOptions {
+    /// Read an object's size, checksum, storage class and parts without downloading it
+    ///
+    /// Uses GetObjectAttributes, which unlike HeadObject also reports how the object was split into parts
+    /// when it was uploaded and, for objects uploaded with [OpenOptions::upload_checksum], the checksum of
+    /// each part. The part offsets line up with the object's contents, so they can be used to plan a
+    /// parallel ranged download whose ranges can each be checked against their part's checksum, or to
+    /// check a copy without a full GET. Objects with more than 1000 parts are read a page at a time.
+    ///
+    /// Like [OpenOptions::copy_s3], this is an S3 API and always uses the S3 client, whatever
+    /// [OpenOptions::backend] is set.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the object.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let attributes = open_options.attributes("datasets/train.parquet").await.unwrap();
+    ///
+    ///     for part in &attributes.parts {
+    ///         println!(
+    ///             "Part {}: bytes {}-{}",
+    ///             part.number,
+    ///             part.offset,
+    ///             part.offset + part.size - 1
+    ///         );
+    ///     }
+    /// }
+    /// ```
+    pub async fn attributes<P>(&self, path: P) -> Result<ObjectAttributes, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.get_attributes(path)
+            .await
+            .map_err(|e| e.with_context("GetObjectAttributes", &self.bucket, Some(path)))
+    }
+
+    async fn get_attributes(&self, path: &Path) -> Result<ObjectAttributes, S3FilesystemError> {
+        let key = self.remote_key(&s3_key(path)?);
+        let mut attributes: Option<ObjectAttributes> = None;
+        let mut part_number_marker = None;
+
+        loop {
+            let mut request = self
+                .s3_client
+                .get_object_attributes()
+                .bucket(&self.bucket)
+                .key(&key)
+                .object_attributes(Attribute::ObjectParts)
+                .set_part_number_marker(part_number_marker.take());
+            if attributes.is_none() {
+                request = request
+                    .object_attributes(Attribute::ObjectSize)
+                    .object_attributes(Attribute::Etag)
+                    .object_attributes(Attribute::StorageClass)
+                    .object_attributes(Attribute::Checksum);
+            }
+
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self.cancellable(async { Ok(request.send().await?) }).await;
+            self.record_request("GetObjectAttributes", started, result.is_ok());
+            drop(slot);
+            let output = result?;
+
+            let attributes = attributes.get_or_insert_with(|| ObjectAttributes {
+                size: output.object_size().unwrap_or_default().max(0) as u64,
+                e_tag: output.e_tag().map(|e_tag| match e_tag.starts_with('"') {
+                    true => e_tag.to_string(),
+                    false => format!("\"{}\"", e_tag),
+                }),
+                last_modified: output
+                    .last_modified()
+                    .and_then(|modified| SystemTime::try_from(*modified).ok()),
+                storage_class: output
+                    .storage_class()
+                    .map(|class| class.as_str().to_string()),
+                checksum: output.checksum().and_then(|checksum| {
+                    object_checksum(checksum.checksum_crc32_c(), checksum.checksum_sha256())
+                }),
+                parts_count: None,
+                parts: Vec::new(),
+            });
+
+            let object_parts = match output.object_parts() {
+                Some(object_parts) => object_parts,
+                None => break,
+            };
+            if let Some(count) = object_parts.total_parts_count().filter(|count| *count > 0) {
+                attributes.parts_count = Some(count as u32);
+            }
+            for part in object_parts.parts() {
+                let offset = attributes
+                    .parts
+                    .last()
+                    .map_or(0, |previous| previous.offset + previous.size);
+                attributes.parts.push(ObjectPart {
+                    number: part.part_number().unwrap_or_default().max(0) as u32,
+                    offset,
+                    size: part.size().unwrap_or_default().max(0) as u64,
+                    checksum: object_checksum(part.checksum_crc32_c(), part.checksum_sha256()),
+                });
+            }
+
+            part_number_marker = object_parts.next_part_number_marker().map(str::to_string);
+            if object_parts.is_truncated() != Some(true) || part_number_marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(attributes.expect("the first response always fills in the attributes"))
+    }
+}
+
+/// The checksum S3 reported, preferring CRC-32C. Other algorithms are not uploaded by this crate.
+fn object_checksum(crc32c: Option<&str>, sha256: Option<&str>) -> Option<ObjectChecksum> {
+    let (algorithm, value) = match (crc32c, sha256) {
+        (Some(value), _) => (ChecksumAlgorithm::Crc32c, value),
+        (None, Some(value)) => (ChecksumAlgorithm::Sha256, value),
+        (None, None) => return None,
+    };
+    Some(ObjectChecksum {
+        algorithm,
+        value: value.to_string(),
+    })
+}
@@ -0,0 +1,287 @@
+//! Uploading files created or changed in the mount path by other programs, in the background.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{options::bucket_folder, OpenOptions, S3FilesystemError, SyncFailure};
+
+/// How many cycle reports are kept for an [AutoUploadTask] before it waits for them to be read.
+const CYCLE_BUFFER: usize = 64;
+
+/// How long to let a burst of filesystem events settle before scanning.
+#[cfg(feature = "notify")]
+const EVENTS_SETTLE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+/// What one cycle of an [AutoUploadTask] uploaded. Paths are keys within the bucket.
+pub struct UploadCycle {
+    /// Files created or changed in the mount path which were uploaded.
+    pub uploaded: Vec<PathBuf>,
+    /// Files which could not be read or uploaded. They are tried again next cycle.
+    pub failed: Vec<SyncFailure>,
+}
+
+impl UploadCycle {
+    /// Whether the cycle found nothing to upload.
+    pub fn is_empty(&self) -> bool {
+        self.uploaded.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Uploads running in the background, returned by [OpenOptions::spawn_auto_upload].
+///
+/// Uploading stops when the handle is dropped or [AutoUploadTask::stop] is called.
+#[derive(Debug)]
+pub struct AutoUploadTask {
+    cycles: mpsc::Receiver<Result<UploadCycle, S3FilesystemError>>,
+    task: JoinHandle<()>,
+}
+
+impl AutoUploadTask {
+    /// Wait for the next cycle to finish, returning what it uploaded
+    ///
+    /// A cycle which could not scan the mount path is returned as an error and the next cycle runs as
+    /// usual. Returns None once the task has stopped and every report has been read. Every report is
+    /// kept, so once 64 go unread the uploads wait for them to be read before scanning again.
+    pub async fn next_cycle(&mut self) -> Option<Result<UploadCycle, S3FilesystemError>> {
+        self.cycles.recv().await
+    }
+
+    /// Stop uploading. A cycle part way through is abandoned; files it already uploaded stay uploaded.
+    pub fn stop(self) {}
+}
+
+impl Drop for AutoUploadTask {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl OpenOptions {
+    /// Upload files which other programs create or change in the mount path, in the background
+    ///
+    /// Every `interval`, the bucket's folder in the mount path is scanned for files which the cache index
+    /// does not record as downloaded or uploaded in their current state: new files, and files whose size
+    /// changed or which were modified after they were cached. Each is uploaded with
+    /// [OpenOptions::write_s3] once it has been seen unchanged by two scans in a row, so a file still being
+    /// written is not uploaded half finished. Tools which only know how to write to disk can then save
+    /// straight into the mount path and have their output reach S3, making it a two-way mirror alongside
+    /// [OpenOptions::spawn_sync].
+    ///
+    /// Files deleted from the mount path are not deleted from S3. Files written with
+    /// [OpenOptions::write_back] are left for [OpenOptions::flush] instead, and the `.compressed` copies
+    /// [OpenOptions::keep_compressed] leaves beside downloads are not uploaded. Until a changed file is
+    /// uploaded, [OpenOptions::spawn_sync] leaves it in place rather than replacing or removing it. Only
+    /// [CacheLayout::Mirror](crate::CacheLayout::Mirror) is supported, as the names of new files in a hashed
+    /// mirror cannot be turned back into keys. This has to be called from within a Tokio runtime.
+    ///
+    /// Without the `notify` feature the mount path is scanned every interval, so changes are uploaded
+    /// within two intervals on any platform and filesystem. With it, the mount path is also watched for
+    /// filesystem events and a scan starts as soon as a change is reported, so a file is uploaded one
+    /// interval after it was last written. Mount paths which cannot be watched, such as network
+    /// filesystems which do not report events, fall back to scanning every interval. The uploads stop
+    /// between scans as well as during them when [OpenOptions::cancel_on]'s token is cancelled.
+    ///
+    /// # Arguments
+    /// * `interval`: How long to wait after one scan finishes before starting the next.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/shared/");
+    ///
+    ///     // An external tool writes its reports into data/shared/my_aws_s3_bucket/reports/.
+    ///     let mut uploads = open_options.spawn_auto_upload(Duration::from_secs(5));
+    ///
+    ///     while let Some(cycle) = uploads.next_cycle().await {
+    ///         match cycle {
+    ///             Ok(cycle) => {
+    ///                 for path in cycle.uploaded {
+    ///                     println!("Uploaded {}", path.display());
+    ///                 }
+    ///             }
+    ///             Err(e) => println!("Scan failed: {}", e),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn spawn_auto_upload(&self, interval: Duration) -> AutoUploadTask {
+        let (sender, cycles) = mpsc::channel(CYCLE_BUFFER);
+        let mut open_options = self.clone();
+        open_options.write_back = false;
+
+        let task = tokio::spawn(async move {
+            let watch = ChangeWatch::start(
+                &open_options
+                    .mount_path
+                    .join(bucket_folder(&open_options.bucket)),
+            );
+            let mut changed = HashMap::new();
+            loop {
+                let cycle = open_options.upload_cycle(&mut changed).await;
+                let cancelled = cycle.as_ref().is_err_and(|e| e.is_cancelled());
+                if sender.send(cycle).await.is_err() || cancelled {
+                    return;
+                }
+
+                // Files seen changing are only uploaded once a full interval has shown them unchanged.
+                let wait = async {
+                    watch.wait(interval, changed.is_empty()).await;
+                    Ok(())
+                };
+                if open_options.cancellable(wait).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        AutoUploadTask { cycles, task }
+    }
+
+    /// Scan the mount path once, uploading the files in `changed` which are still as the last scan found
+    /// them and recording the size and modification time of any other changed files.
+    async fn upload_cycle(
+        &self,
+        changed: &mut HashMap<String, (u64, SystemTime)>,
+    ) -> Result<UploadCycle, S3FilesystemError> {
+        let mut cycle = UploadCycle::default();
+        let mut files = self.mirrored_files(false).await?;
+        files.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let mut seen = HashMap::new();
+        for file in files {
+            if self.is_pending(&file.key).await? || self.is_kept_compressed(&file.key).await? {
+                continue;
+            }
+            let state = match self.local_change(&file.key, &file.path).await? {
+                Some(state) => state,
+                None => continue,
+            };
+
+            if changed.get(&file.key) != Some(&state) {
+                seen.insert(file.key, state);
+                continue;
+            }
+
+            let path = PathBuf::from(&file.key);
+            let uploaded = async {
+                let data = tokio::fs::read(&file.path).await?;
+                self.write_s3(&file.key, &data).await
+            }
+            .await;
+            match uploaded {
+                Ok(_) => cycle.uploaded.push(path),
+                Err(error) if error.is_cancelled() => return Err(error),
+                Err(error) => {
+                    seen.insert(file.key, state);
+                    cycle.failed.push(SyncFailure { path, error });
+                }
+            }
+        }
+
+        *changed = seen;
+        Ok(cycle)
+    }
+
+    /// The size and modification time of the mirrored file for `key` at `path`, if it has changed since it
+    /// was last downloaded or uploaded: if the cache index has no record of it, or its size differs from
+    /// the record or it was modified after it. Modification times are compared to the nanosecond, as far as
+    /// the filesystem keeps them. Returns None for files which are unchanged or have gone.
+    pub(crate) async fn local_change(
+        &self,
+        key: &str,
+        path: &Path,
+    ) -> Result<Option<(u64, SystemTime)>, S3FilesystemError> {
+        let (size, modified) = match tokio::fs::metadata(path).await {
+            Ok(metadata) => match metadata.modified() {
+                Ok(modified) => (metadata.len(), modified),
+                Err(_) => return Ok(None),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let cached = self.cache_index.get(key).await?;
+        match cached.is_some_and(|cached| cached.size == size && modified <= cached.cached_at) {
+            true => Ok(None),
+            false => Ok(Some((size, modified))),
+        }
+    }
+
+    /// Whether `key` is the compressed copy [OpenOptions::keep_compressed] leaves beside a download.
+    async fn is_kept_compressed(&self, key: &str) -> Result<bool, S3FilesystemError> {
+        match key.strip_suffix(".compressed") {
+            Some(decompressed) => Ok(self.cache_index.get(decompressed).await?.is_some()),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Wakes the uploads when the mount path reports a change, for as long as it is kept.
+#[cfg(feature = "notify")]
+struct ChangeWatch {
+    /// None if the folder could not be watched, leaving the uploads to scan every interval.
+    watcher: Option<notify::RecommendedWatcher>,
+    changed: std::sync::Arc<tokio::sync::Notify>,
+}
+
+#[cfg(feature = "notify")]
+impl ChangeWatch {
+    /// Start watching `folder` and everything under it, creating it if it does not exist yet.
+    fn start(folder: &Path) -> Self {
+        let changed = std::sync::Arc::new(tokio::sync::Notify::new());
+        let wake = changed.clone();
+        let watcher = std::fs::create_dir_all(folder)
+            .map_err(notify::Error::io)
+            .and_then(|()| {
+                notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                    if event.is_ok_and(|event| !event.kind.is_access()) {
+                        wake.notify_one();
+                    }
+                })
+            })
+            .and_then(|mut watcher| {
+                notify::Watcher::watch(&mut watcher, folder, notify::RecursiveMode::Recursive)?;
+                Ok(watcher)
+            })
+            .ok();
+        ChangeWatch { watcher, changed }
+    }
+
+    /// Wait for `interval`, or if `early` is set, until a change is reported if that is sooner.
+    async fn wait(&self, interval: Duration, early: bool) {
+        if self.watcher.is_none() || !early {
+            return tokio::time::sleep(interval).await;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = self.changed.notified() => tokio::time::sleep(EVENTS_SETTLE).await,
+        }
+    }
+}
+
+/// Without the `notify` feature the mount path is only ever scanned on the interval.
+#[cfg(not(feature = "notify"))]
+struct ChangeWatch;
+
+#[cfg(not(feature = "notify"))]
+impl ChangeWatch {
+    fn start(_folder: &Path) -> Self {
+        ChangeWatch
+    }
+
+    async fn wait(&self, interval: Duration, _early: bool) {
+        tokio::time::sleep(interval).await
+    }
+}
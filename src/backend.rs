@@ -0,0 +1,516 @@
+//! The object store behind an [OpenOptions], so services other than S3 can be mounted.
+use std::{
+    collections::HashMap, fmt::Debug, future::Future, ops::Deref, path::PathBuf, pin::Pin,
+    sync::Arc, time::SystemTime,
+};
+
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{ChecksumMode, Delete, ObjectIdentifier},
+    Client,
+};
+
+use crate::{
+    error::S3Error, options::is_directory_bucket, prefix::Prefixed, CannedAcl, ChecksumAlgorithm,
+    DeleteOutcome, DirEntry, ObjectChecksum, ObjectLock, OpenOptions, S3FilesystemError,
+    WritePrecondition,
+};
+
+/// The future returned by every [ObjectBackend] method.
+pub type BackendFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, S3FilesystemError>> + Send + 'a>>;
+
+/// The basic object operations [OpenOptions] downloads, uploads, lists and deletes through.
+///
+/// [S3Backend] is used unless another is installed with [OpenOptions::backend], which lets GCS, Azure
+/// Blob Storage or a plain local directory ([LocalBackend](crate::LocalBackend)) be mounted with the same
+/// caching, rate limiting, dry run and metrics machinery. Only the calls listed here go through the
+/// backend. Features built on S3-only APIs, such as [OpenOptions::copy_s3], multipart uploads, S3 Select
+/// and Glacier restores, still use the S3 client.
+///
+/// Methods return boxed futures so the trait can be used as `Arc<dyn ObjectBackend>`. Errors that mean a
+/// condition was not met, such as a failed If-Match, should be returned as
+/// [S3FilesystemError::PreconditionFailed], and an If-None-Match which matched as
+/// [S3FilesystemError::NotModified].
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::{LocalBackend, OpenOptions};
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bucket = "my_aws_s3_bucket".to_string();
+///
+///     let open_options = OpenOptions::new(bucket, None)
+///         .await
+///         .backend(Arc::new(LocalBackend::new("/srv/objects")));
+///
+///     let data = open_options.read("some_folder/some_file.csv").await.unwrap();
+/// }
+/// ```
+pub trait ObjectBackend: Send + Sync {
+    /// One page of the objects whose keys start with the request's prefix, in key order. Pages of an S3
+    /// directory bucket are each sorted, but are not in order with each other.
+    fn list<'a>(&'a self, request: ListRequest<'a>) -> BackendFuture<'a, ListPage>;
+
+    /// Stream the contents of an object, or part of it.
+    fn get<'a>(&'a self, request: GetRequest<'a>) -> BackendFuture<'a, ObjectBody>;
+
+    /// The size and ETag of an object, without its contents.
+    fn head<'a>(&'a self, bucket: &'a str, key: &'a str) -> BackendFuture<'a, ObjectHead>;
+
+    /// Store an object, returning its new ETag if the backend has one.
+    fn put<'a>(&'a self, request: PutRequest<'a>) -> BackendFuture<'a, Option<String>>;
+
+    /// Delete up to 1000 objects, reporting the outcome for each. Deleting a key that does not exist
+    /// counts as a success.
+    fn delete<'a>(
+        &'a self,
+        bucket: &'a str,
+        keys: &'a [String],
+    ) -> BackendFuture<'a, Vec<DeleteOutcome>>;
+}
+
+#[derive(Debug, Clone)]
+/// The arguments to [ObjectBackend::list].
+#[non_exhaustive]
+pub struct ListRequest<'a> {
+    /// The bucket to list.
+    pub bucket: &'a str,
+    /// Only keys starting with this are listed.
+    pub prefix: &'a str,
+    /// Roll keys with this after the prefix up into common prefixes ending in it, as ListObjectsV2 does.
+    pub delimiter: Option<&'a str>,
+    /// Only keys after this one are listed, as with ListObjectsV2's `start-after`.
+    pub start_after: Option<&'a str>,
+    /// None for the first page, then the token the previous page returned.
+    pub continuation_token: Option<String>,
+    /// The most objects and common prefixes to return, or None for the backend's default.
+    pub max_keys: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+/// The arguments to [ObjectBackend::get].
+#[non_exhaustive]
+pub struct GetRequest<'a> {
+    /// The bucket the object is in.
+    pub bucket: &'a str,
+    /// The object's key.
+    pub key: &'a str,
+    /// The first byte to return, and the last (inclusive), or None to read to the end.
+    pub range: Option<(u64, Option<u64>)>,
+    /// Fail with [S3FilesystemError::PreconditionFailed] unless the object's ETag is this one.
+    pub if_match: Option<&'a str>,
+    /// Fail with [S3FilesystemError::NotModified] if the object's ETag is this one.
+    pub if_none_match: Option<&'a str>,
+}
+
+#[derive(Debug)]
+/// The arguments to [ObjectBackend::put].
+#[non_exhaustive]
+pub struct PutRequest<'a> {
+    /// The bucket to store the object in.
+    pub bucket: &'a str,
+    /// The object's key.
+    pub key: &'a str,
+    /// The object's contents.
+    pub body: ByteStream,
+    /// The Content-Type to store the object with.
+    pub content_type: Option<&'a str>,
+    /// The canned ACL to store the object with. Backends without ACLs ignore it.
+    pub acl: Option<CannedAcl>,
+    /// A condition the existing object must meet for the write to go ahead.
+    pub precondition: Option<&'a WritePrecondition>,
+    /// A checksum of the body for the backend to check it against. Backends without checksums ignore it.
+    pub checksum: Option<&'a ObjectChecksum>,
+    /// The Object Lock retention and legal hold to store the object with.
+    pub object_lock: ObjectLock,
+}
+
+#[derive(Debug, Default)]
+/// The contents of an object, returned by [ObjectBackend::get].
+///
+/// Backends which cannot report a field leave it at its default, so construct this with
+/// `..Default::default()`.
+pub struct ObjectBody {
+    /// The requested bytes.
+    pub body: ByteStream,
+    /// The object's ETag, if the backend has one.
+    pub e_tag: Option<String>,
+    /// The Content-Encoding the object was stored with, such as "gzip", if the backend records one.
+    pub content_encoding: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// What [ObjectBackend::head] reports about an object.
+///
+/// Backends which cannot report a field leave it at its default, so construct this with
+/// `..Default::default()`.
+pub struct ObjectHead {
+    /// The object's size in bytes.
+    pub size: u64,
+    /// The object's ETag, if the backend has one.
+    pub e_tag: Option<String>,
+    /// When the object was last written, if known.
+    pub last_modified: Option<SystemTime>,
+    /// The storage class the object is kept in, such as "STANDARD" or "GLACIER", if the backend has them.
+    pub storage_class: Option<String>,
+    /// The Content-Type the object was stored with, if the backend records one.
+    pub content_type: Option<String>,
+    /// The Content-Encoding the object was stored with, such as "gzip", if the backend records one.
+    pub content_encoding: Option<String>,
+    /// User-defined metadata, the `x-amz-meta-*` headers S3 stores with an object, without the prefix.
+    pub metadata: HashMap<String, String>,
+    /// The additional CRC-32C or SHA-256 checksum the object was uploaded with, if any.
+    pub checksum: Option<ObjectChecksum>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// One page of a listing, returned by [ObjectBackend::list].
+pub struct ListPage {
+    /// The objects on this page.
+    pub entries: Vec<DirEntry>,
+    /// The common prefixes rolled up at the delimiter.
+    pub common_prefixes: Vec<String>,
+    /// The token for the next page, or None if this is the last.
+    pub continuation_token: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+/// The default [ObjectBackend], which talks to S3 with the SDK client.
+pub struct S3Backend {
+    client: Client,
+}
+
+impl S3Backend {
+    /// Use `client` for every request.
+    pub fn new(client: Client) -> Self {
+        S3Backend { client }
+    }
+}
+
+impl ObjectBackend for S3Backend {
+    fn list<'a>(&'a self, request: ListRequest<'a>) -> BackendFuture<'a, ListPage> {
+        Box::pin(async move {
+            // Directory buckets only list prefixes ending in `/` and have no start-after, so they are asked
+            // for the enclosing folder and the rest of the request is applied to each page here.
+            let directory = is_directory_bucket(request.bucket);
+            let (prefix, start_after) = match directory {
+                true => (
+                    &request.prefix[..request.prefix.rfind('/').map_or(0, |slash| slash + 1)],
+                    None,
+                ),
+                false => (request.prefix, request.start_after),
+            };
+            let wanted = |key: &str| {
+                key.starts_with(request.prefix)
+                    && request
+                        .start_after
+                        .is_none_or(|start_after| key > start_after)
+            };
+            // A folder is still listed when start-after falls inside it.
+            let wanted_prefix = |common_prefix: &str| {
+                wanted(common_prefix)
+                    || request.start_after.is_some_and(|start_after| {
+                        common_prefix.starts_with(request.prefix)
+                            && start_after.starts_with(common_prefix)
+                    })
+            };
+
+            let response = self
+                .client
+                .list_objects_v2()
+                .bucket(request.bucket)
+                .prefix(prefix)
+                .set_delimiter(request.delimiter.map(str::to_string))
+                .set_start_after(start_after.map(str::to_string))
+                .set_continuation_token(request.continuation_token)
+                .set_max_keys(
+                    request
+                        .max_keys
+                        .map(|max| max.min(i32::MAX as usize) as i32),
+                )
+                .send()
+                .await?;
+
+            let mut entries = response
+                .contents()
+                .iter()
+                .filter_map(|s3_object| {
+                    let filepath = s3_object.key()?;
+                    Some(DirEntry {
+                        path: PathBuf::from(filepath),
+                        size: s3_object.size().unwrap_or_default(),
+                        folder: filepath.ends_with('/'),
+                        e_tag: s3_object.e_tag().map(str::to_string),
+                        last_modified: s3_object
+                            .last_modified()
+                            .and_then(|modified| SystemTime::try_from(*modified).ok()),
+                        storage_class: s3_object
+                            .storage_class()
+                            .map(|class| class.as_str().to_string()),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut common_prefixes = response
+                .common_prefixes()
+                .iter()
+                .filter_map(|common_prefix| common_prefix.prefix())
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+
+            if directory {
+                entries.retain(|entry| entry.path.to_str().is_some_and(wanted));
+                entries.sort_by(|a, b| a.path.cmp(&b.path));
+                common_prefixes.retain(|common_prefix| wanted_prefix(common_prefix));
+                common_prefixes.sort();
+            }
+
+            Ok(ListPage {
+                entries,
+                common_prefixes,
+                continuation_token: match response.is_truncated() {
+                    Some(true) => response.next_continuation_token().map(str::to_string),
+                    _ => None,
+                },
+            })
+        })
+    }
+
+    fn get<'a>(&'a self, request: GetRequest<'a>) -> BackendFuture<'a, ObjectBody> {
+        Box::pin(async move {
+            let range = request.range.map(|(start, end)| match end {
+                Some(end) => format!("bytes={}-{}", start, end),
+                None => format!("bytes={}-", start),
+            });
+            let object = self
+                .client
+                .get_object()
+                .bucket(request.bucket)
+                .key(request.key)
+                .set_range(range)
+                .set_if_match(request.if_match.map(str::to_string))
+                .set_if_none_match(request.if_none_match.map(str::to_string))
+                .send()
+                .await;
+            let object = match object {
+                Ok(object) => object,
+                Err(e) => match e.raw_response() {
+                    Some(response) if response.status().as_u16() == 304 => {
+                        return Err(S3FilesystemError::NotModified)
+                    }
+                    _ => return Err(e.into()),
+                },
+            };
+
+            Ok(ObjectBody {
+                e_tag: object.e_tag().map(str::to_string),
+                content_encoding: object.content_encoding().map(str::to_string),
+                body: object.body,
+            })
+        })
+    }
+
+    fn head<'a>(&'a self, bucket: &'a str, key: &'a str) -> BackendFuture<'a, ObjectHead> {
+        Box::pin(async move {
+            let head = self
+                .client
+                .head_object()
+                .bucket(bucket)
+                .key(key)
+                .checksum_mode(ChecksumMode::Enabled)
+                .send()
+                .await?;
+
+            let checksum = match (head.checksum_crc32_c(), head.checksum_sha256()) {
+                (Some(value), _) => Some((ChecksumAlgorithm::Crc32c, value)),
+                (None, Some(value)) => Some((ChecksumAlgorithm::Sha256, value)),
+                (None, None) => None,
+            };
+
+            Ok(ObjectHead {
+                size: head.content_length().unwrap_or_default().max(0) as u64,
+                e_tag: head.e_tag().map(str::to_string),
+                last_modified: head
+                    .last_modified()
+                    .and_then(|modified| SystemTime::try_from(*modified).ok()),
+                // S3 leaves the header out for objects in the standard class.
+                storage_class: Some(
+                    head.storage_class()
+                        .map_or("STANDARD", |class| class.as_str())
+                        .to_string(),
+                ),
+                content_type: head.content_type().map(str::to_string),
+                content_encoding: head.content_encoding().map(str::to_string),
+                metadata: head.metadata().cloned().unwrap_or_default(),
+                checksum: checksum.map(|(algorithm, value)| ObjectChecksum {
+                    algorithm,
+                    value: value.to_string(),
+                }),
+            })
+        })
+    }
+
+    fn put<'a>(&'a self, request: PutRequest<'a>) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let mut put_object_builder = self
+                .client
+                .put_object()
+                .bucket(request.bucket)
+                .key(request.key)
+                .set_content_type(request.content_type.map(str::to_string))
+                .set_acl(request.acl.map(CannedAcl::to_sdk))
+                .set_object_lock_mode(request.object_lock.mode())
+                .set_object_lock_retain_until_date(request.object_lock.retain_until())
+                .set_object_lock_legal_hold_status(request.object_lock.legal_hold_status())
+                .body(request.body);
+            if let Some(checksum) = request.checksum {
+                put_object_builder = match checksum.algorithm {
+                    ChecksumAlgorithm::Crc32c => {
+                        put_object_builder.checksum_crc32_c(&checksum.value)
+                    }
+                    ChecksumAlgorithm::Sha256 => {
+                        put_object_builder.checksum_sha256(&checksum.value)
+                    }
+                };
+            }
+
+            let result = match request.precondition.cloned() {
+                Some(precondition) => {
+                    let (header, value) = match precondition {
+                        WritePrecondition::IfAbsent => ("if-none-match", "*".to_string()),
+                        WritePrecondition::IfMatch(e_tag) => ("if-match", e_tag),
+                    };
+                    put_object_builder
+                        .customize()
+                        .mutate_request(move |request| {
+                            request.headers_mut().insert(header, value.clone());
+                        })
+                        .send()
+                        .await
+                }
+                None => put_object_builder.send().await,
+            };
+
+            match result {
+                Ok(output) => Ok(output.e_tag().map(str::to_string)),
+                Err(e) => match e.raw_response() {
+                    Some(response) if response.status().as_u16() == 412 => {
+                        Err(S3FilesystemError::PreconditionFailed)
+                    }
+                    _ => Err(e.into()),
+                },
+            }
+        })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        bucket: &'a str,
+        keys: &'a [String],
+    ) -> BackendFuture<'a, Vec<DeleteOutcome>> {
+        Box::pin(async move {
+            let objects = keys
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(S3Error::construction_failure)?;
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .build()
+                .map_err(S3Error::construction_failure)?;
+
+            let response = self
+                .client
+                .delete_objects()
+                .bucket(bucket)
+                .delete(delete)
+                .send()
+                .await?;
+
+            let deleted = response
+                .deleted()
+                .iter()
+                .filter_map(|deleted| deleted.key())
+                .map(|key| DeleteOutcome::Deleted(key.to_string()));
+            let failed = response.errors().iter().map(|error| DeleteOutcome::Failed {
+                key: error.key().unwrap_or_default().to_string(),
+                code: error.code().map(str::to_string),
+                message: error.message().map(str::to_string),
+            });
+
+            Ok(deleted.chain(failed).collect())
+        })
+    }
+}
+
+/// The backend installed on an [OpenOptions], shared by all of its clones.
+#[derive(Clone)]
+pub(crate) struct Backend {
+    backend: Arc<dyn ObjectBackend>,
+    /// The backend before [OpenOptions::prefix] was applied to it.
+    unprefixed: Arc<dyn ObjectBackend>,
+    /// Whether this is the default [S3Backend], which is rebuilt whenever the client changes.
+    s3: bool,
+}
+
+impl Backend {
+    /// The default backend, sending requests with `client`.
+    pub(crate) fn s3(client: Client) -> Self {
+        let backend: Arc<dyn ObjectBackend> = Arc::new(S3Backend::new(client));
+        Backend {
+            backend: backend.clone(),
+            unprefixed: backend,
+            s3: true,
+        }
+    }
+
+    /// This backend with keys taken relative to `prefix`, replacing any prefix applied before.
+    pub(crate) fn with_prefix(self, prefix: &str) -> Self {
+        let backend = match prefix {
+            "" => self.unprefixed.clone(),
+            prefix => Arc::new(Prefixed::new(self.unprefixed.clone(), prefix)),
+        };
+        Backend { backend, ..self }
+    }
+
+    /// Whether this is the default backend rather than one installed with [OpenOptions::backend].
+    pub(crate) fn is_s3(&self) -> bool {
+        self.s3
+    }
+}
+
+impl Debug for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Backend")
+    }
+}
+
+impl Deref for Backend {
+    type Target = dyn ObjectBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend.as_ref()
+    }
+}
+
+impl OpenOptions {
+    /// Store objects somewhere other than S3
+    ///
+    /// Downloads, uploads, listings and deletes go through `backend` instead of the S3 client, keeping
+    /// the local mirror, rate limits, dry run and metrics. By default an [S3Backend] built from the client
+    /// given to [OpenOptions::new] is used. See [ObjectBackend] for which calls are covered.
+    ///
+    /// # Arguments
+    /// * `backend`: Where objects are stored.
+    pub fn backend(mut self, backend: Arc<dyn ObjectBackend>) -> Self {
+        self.backend = Backend {
+            backend: backend.clone(),
+            unprefixed: backend,
+            s3: false,
+        }
+        .with_prefix(&self.prefix);
+        self
+    }
+}
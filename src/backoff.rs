@@ -0,0 +1,183 @@
+//! Slowing down when S3 answers with 503 SlowDown, and speeding back up once it stops.
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use aws_smithy_runtime_api::{
+    box_error::BoxError,
+    client::{
+        interceptors::{context::BeforeDeserializationInterceptorContextRef, Intercept},
+        runtime_components::RuntimeComponents,
+    },
+};
+use aws_smithy_types::config_bag::ConfigBag;
+use tokio::{sync::Notify, time::Instant};
+
+use crate::OpenOptions;
+
+/// The first delay added once S3 starts throttling.
+const MIN_DELAY: Duration = Duration::from_millis(50);
+
+/// The longest delay added before each request, however long S3 keeps throttling.
+const MAX_DELAY: Duration = Duration::from_secs(20);
+
+/// Spaces out and limits the requests of every clone of an [OpenOptions] while S3 is throttling them.
+///
+/// Each throttled response doubles the delay before new requests and halves how many may be in flight,
+/// at most once per delay so a burst of throttled responses to requests already sent counts once. Each
+/// successful response shrinks the delay by a quarter and lets one more request into flight.
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    state: Mutex<BackoffState>,
+    released: Notify,
+}
+
+#[derive(Debug, Default)]
+struct BackoffState {
+    delay: Duration,
+    /// How many requests may be in flight, or None if S3 has not throttled any.
+    window: Option<usize>,
+    in_flight: usize,
+    last_throttled: Option<Instant>,
+}
+
+impl Backoff {
+    fn state(&self) -> MutexGuard<'_, BackoffState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Wait until another request may be sent, returning a guard which holds its place in flight.
+    pub(crate) async fn acquire(self: &Arc<Self>) -> InFlight {
+        let delay = loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            {
+                let mut state = self.state();
+                if state.window.is_none_or(|window| state.in_flight < window) {
+                    state.in_flight += 1;
+                    break state.delay;
+                }
+            }
+            released.await;
+        };
+
+        let in_flight = InFlight(self.clone());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        in_flight
+    }
+
+    /// S3 throttled a request.
+    fn throttled(&self) {
+        let mut state = self.state();
+        let now = Instant::now();
+        if let Some(last) = state.last_throttled {
+            if now < last + state.delay {
+                return;
+            }
+        }
+
+        state.last_throttled = Some(now);
+        state.delay = (state.delay * 2).clamp(MIN_DELAY, MAX_DELAY);
+        let in_flight = state.window.unwrap_or(state.in_flight).min(state.in_flight);
+        state.window = Some((in_flight / 2).max(1));
+    }
+
+    /// S3 answered a request without throttling it.
+    fn succeeded(&self) {
+        let mut state = self.state();
+        if state.window.is_none() {
+            return;
+        }
+
+        state.delay = state.delay * 3 / 4;
+        if state.delay < MIN_DELAY {
+            state.delay = Duration::ZERO;
+        }
+        state.window = state.window.map(|window| window + 1);
+        drop(state);
+        self.released.notify_waiters();
+    }
+}
+
+/// A request's place in flight, given back when dropped.
+pub(crate) struct InFlight(Arc<Backoff>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.state().in_flight -= 1;
+        self.0.released.notify_waiters();
+    }
+}
+
+/// Reports each response from S3 to a [Backoff].
+pub(crate) struct BackoffInterceptor(pub(crate) Arc<Backoff>);
+
+impl fmt::Debug for BackoffInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BackoffInterceptor")
+    }
+}
+
+impl Intercept for BackoffInterceptor {
+    fn name(&self) -> &'static str {
+        "s3-filesystem adaptive backoff"
+    }
+
+    fn read_before_deserialization(
+        &self,
+        context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        // S3 throttles with 503 SlowDown; other S3 compatible stores may use 429 Too Many Requests.
+        match context.response().status().as_u16() {
+            429 | 503 => self.0.throttled(),
+            status if status < 500 => self.0.succeeded(),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl OpenOptions {
+    /// Slow down automatically when S3 throttles requests
+    ///
+    /// On by default. When S3 answers a request with 503 SlowDown, every later request made through this
+    /// OpenOptions, or any clone of it, waits a little before it is sent, and fewer requests are let into
+    /// flight at once. The delay doubles, up to 20 seconds, and the number in flight halves, while S3 keeps
+    /// throttling, then both recover a step with each successful response. A bulk operation such as
+    /// [OpenOptions::sync_up] therefore settles at a rate S3 accepts, rather than carrying on at full speed
+    /// until more and more of its requests run out of retries.
+    ///
+    /// This works alongside [OpenOptions::max_requests_per_second] and
+    /// [OpenOptions::max_concurrent_requests], which set fixed limits, and only watches requests sent with
+    /// the S3 client, not a backend installed with [OpenOptions::backend]. Pass `enabled` = false to send
+    /// requests as soon as the other limits allow.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     // A job with its own retry logic, which would rather see SlowDown errors straight away.
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .adaptive_backoff(false);
+    /// }
+    /// ```
+    pub fn adaptive_backoff(mut self, enabled: bool) -> Self {
+        self.adaptive_backoff = enabled;
+        self
+    }
+}
@@ -0,0 +1,369 @@
+//! `s3fs`, a command line tool for reading and writing S3 through the local mirror.
+//!
+//! Objects are named with `s3://<bucket>/<key>` URLs. Downloads go through the mount path just as they do
+//! for [OpenOptions::open_s3], so repeated `cat`s and `get`s of the same object are served from disk.
+//! Credentials and region are loaded from your environment, as the AWS CLI does, or from the profile given
+//! with `--profile`.
+use std::{path::PathBuf, process::ExitCode};
+
+use s3_filesystem::{
+    CachePolicy, DeleteOutcome, OpenOptions, OpenOptionsBuilder, S3FilesystemError,
+};
+use tokio::io::AsyncWriteExt;
+
+const USAGE: &str = "\
+Usage: s3fs [options] <command> [arguments]
+
+Commands:
+  ls <s3://bucket/prefix>             List the objects under a prefix
+  cat <s3://bucket/key>               Write an object to stdout
+  get <s3://bucket/key> [local path]  Download an object, by default to its file name
+  put <local path> <s3://bucket/key>  Upload a file
+  rm <s3://bucket/key>...             Delete objects
+  sync <local dir> <s3://bucket/prefix>
+                                      Upload the files in a folder which differ from S3
+
+Options:
+  --mount-path <dir>  Where downloaded objects are mirrored (default target/temp)
+  --profile <name>    Use credentials and region from this AWS profile
+  --force-download    Download objects again even when they are mirrored
+  --dry-run           Report uploads and deletes without making them
+  -h, --help          Show this message
+";
+
+/// A command line which could not be understood.
+struct UsageError(String);
+
+#[derive(Debug)]
+enum Command {
+    Ls(S3Url),
+    Cat(S3Url),
+    Get(S3Url, Option<PathBuf>),
+    Put(PathBuf, S3Url),
+    Rm(Vec<S3Url>),
+    Sync(PathBuf, S3Url),
+}
+
+#[derive(Debug)]
+struct S3Url {
+    bucket: String,
+    key: String,
+}
+
+impl S3Url {
+    fn parse(url: &str) -> Result<Self, UsageError> {
+        let (bucket, key) = url
+            .strip_prefix("s3://")
+            .map(|rest| rest.split_once('/').unwrap_or((rest, "")))
+            .filter(|(bucket, _)| !bucket.is_empty())
+            .ok_or_else(|| UsageError(format!("{} is not an s3://bucket/key URL", url)))?;
+        Ok(S3Url {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+struct Options {
+    mount_path: Option<PathBuf>,
+    profile: Option<String>,
+    cache_policy: CachePolicy,
+    dry_run: bool,
+    command: Command,
+}
+
+/// Parse the arguments after the program name, or None if help was asked for.
+fn parse_args(args: Vec<String>) -> Result<Option<Options>, UsageError> {
+    let mut mount_path = None;
+    let mut profile = None;
+    let mut cache_policy = CachePolicy::UseCacheIfPresent;
+    let mut dry_run = false;
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--mount-path" => {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| UsageError("--mount-path needs a folder".to_string()))?;
+                mount_path = Some(PathBuf::from(dir));
+            }
+            "--profile" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| UsageError("--profile needs a profile name".to_string()))?;
+                profile = Some(name);
+            }
+            "--force-download" => cache_policy = CachePolicy::AlwaysDownload,
+            "--dry-run" => dry_run = true,
+            option if option.starts_with("--") => {
+                return Err(UsageError(format!("unknown option {}", option)))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let (name, arguments) = positional
+        .split_first()
+        .ok_or_else(|| UsageError("no command given".to_string()))?;
+    let command = match (name.as_str(), arguments) {
+        ("ls", [url]) => Command::Ls(S3Url::parse(url)?),
+        ("cat", [url]) => Command::Cat(S3Url::parse(url)?),
+        ("get", [url]) => Command::Get(S3Url::parse(url)?, None),
+        ("get", [url, local]) => Command::Get(S3Url::parse(url)?, Some(PathBuf::from(local))),
+        ("put", [local, url]) => Command::Put(PathBuf::from(local), S3Url::parse(url)?),
+        ("rm", urls) if !urls.is_empty() => Command::Rm(
+            urls.iter()
+                .map(|url| S3Url::parse(url))
+                .collect::<Result<_, _>>()?,
+        ),
+        ("sync", [local, url]) => Command::Sync(PathBuf::from(local), S3Url::parse(url)?),
+        ("ls" | "cat" | "get" | "put" | "rm" | "sync", _) => {
+            return Err(UsageError(format!("wrong arguments for {}", name)))
+        }
+        _ => return Err(UsageError(format!("unknown command {}", name))),
+    };
+
+    Ok(Some(Options {
+        mount_path,
+        profile,
+        cache_policy,
+        dry_run,
+        command,
+    }))
+}
+
+impl Options {
+    async fn open(&self, bucket: &str) -> OpenOptions {
+        let mut builder = OpenOptionsBuilder::new(bucket)
+            .cache_policy(self.cache_policy)
+            .dry_run(self.dry_run);
+        if let Some(mount_path) = &self.mount_path {
+            builder = builder.mount_path(mount_path);
+        }
+        if let Some(profile) = &self.profile {
+            builder = builder.profile(profile);
+        }
+        builder.connect().await
+    }
+
+    /// Run the command, returning whether everything it attempted succeeded.
+    async fn run(self) -> Result<bool, S3FilesystemError> {
+        match &self.command {
+            Command::Ls(url) => {
+                let open_options = self.open(&url.bucket).await;
+                for entry in open_options.walkdir(&url.key).await? {
+                    if !entry.folder {
+                        println!("{:>12}  {}", entry.size, entry.path.display());
+                    }
+                }
+            }
+            Command::Cat(url) => {
+                let data = self.open(&url.bucket).await.read(&url.key).await?;
+                let mut stdout = tokio::io::stdout();
+                stdout.write_all(&data).await?;
+                stdout.flush().await?;
+            }
+            Command::Get(url, local) => {
+                let local = match local {
+                    Some(local) => local.clone(),
+                    None => PathBuf::from(url.key.rsplit('/').next().unwrap_or_default()),
+                };
+                self.open(&url.bucket)
+                    .await
+                    .open_s3_to(&url.key, &local)
+                    .await?;
+            }
+            Command::Put(local, url) => {
+                let data = tokio::fs::read(local).await?;
+                self.open(&url.bucket)
+                    .await
+                    .write_s3(&url.key, &data)
+                    .await?;
+            }
+            Command::Rm(urls) => {
+                let mut succeeded = true;
+                let mut buckets: Vec<&str> = urls.iter().map(|url| url.bucket.as_str()).collect();
+                buckets.sort();
+                buckets.dedup();
+
+                for bucket in buckets {
+                    let keys = urls
+                        .iter()
+                        .filter(|url| url.bucket == bucket)
+                        .map(|url| &url.key);
+                    for outcome in self.open(bucket).await.delete_many(keys).await? {
+                        if let DeleteOutcome::Failed { key, code, message } = outcome {
+                            eprintln!(
+                                "s3fs: could not delete s3://{}/{}: {}",
+                                bucket,
+                                key,
+                                message.or(code).unwrap_or_default()
+                            );
+                            succeeded = false;
+                        }
+                    }
+                }
+                return Ok(succeeded);
+            }
+            Command::Sync(local, url) => {
+                let report = self
+                    .open(&url.bucket)
+                    .await
+                    .sync_up(local, &url.key)
+                    .await?;
+                for failure in &report.failed {
+                    eprintln!(
+                        "s3fs: could not upload {}: {}",
+                        failure.path.display(),
+                        failure.error
+                    );
+                }
+                println!(
+                    "{} uploaded, {} unchanged, {} failed",
+                    report.uploaded.len(),
+                    report.unchanged.len(),
+                    report.failed.len()
+                );
+                return Ok(report.failed.is_empty());
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(UsageError(message)) => {
+            eprint!("s3fs: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match options.run().await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("s3fs: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn parse(line: &str) -> Options {
+        match parse_args(args(line)) {
+            Ok(Some(options)) => options,
+            Ok(None) => panic!("{:?} asked for help", line),
+            Err(UsageError(message)) => panic!("{:?} was rejected: {}", line, message),
+        }
+    }
+
+    fn usage_error(line: &str) -> String {
+        match parse_args(args(line)) {
+            Err(UsageError(message)) => message,
+            _ => panic!("{:?} was accepted", line),
+        }
+    }
+
+    #[test]
+    fn test_options_before_the_command() {
+        let options =
+            parse("--mount-path /tmp/mirror --profile dev --force-download --dry-run ls s3://logs");
+        assert_eq!(options.mount_path, Some(PathBuf::from("/tmp/mirror")));
+        assert_eq!(options.profile.as_deref(), Some("dev"));
+        assert_eq!(options.cache_policy, CachePolicy::AlwaysDownload);
+        assert!(options.dry_run);
+        assert!(
+            matches!(&options.command, Command::Ls(url) if url.bucket == "logs" && url.key.is_empty())
+        );
+
+        let options = parse("cat s3://logs/2024/app.log");
+        assert_eq!(options.mount_path, None);
+        assert_eq!(options.profile, None);
+        assert_eq!(options.cache_policy, CachePolicy::UseCacheIfPresent);
+        assert!(!options.dry_run);
+        assert!(
+            matches!(&options.command, Command::Cat(url) if url.bucket == "logs" && url.key == "2024/app.log")
+        );
+    }
+
+    #[test]
+    fn test_commands_and_their_arguments() {
+        assert!(matches!(
+            parse("get s3://data/a/b.csv").command,
+            Command::Get(url, None) if url.key == "a/b.csv"
+        ));
+        assert!(matches!(
+            parse("get s3://data/a/b.csv local.csv --dry-run").command,
+            Command::Get(_, Some(local)) if local.as_path() == Path::new("local.csv")
+        ));
+        assert!(matches!(
+            parse("put report.pdf s3://data/reports/report.pdf").command,
+            Command::Put(local, url) if local.as_path() == Path::new("report.pdf") && url.key == "reports/report.pdf"
+        ));
+        assert!(matches!(
+            parse("sync out/ s3://data/out/").command,
+            Command::Sync(local, url) if local.as_path() == Path::new("out/") && url.key == "out/"
+        ));
+
+        let Command::Rm(urls) = parse("rm s3://one/a s3://two/b s3://one/c").command else {
+            panic!("rm was not parsed as Rm");
+        };
+        let urls: Vec<_> = urls
+            .iter()
+            .map(|url| (url.bucket.as_str(), url.key.as_str()))
+            .collect();
+        assert_eq!(urls, [("one", "a"), ("two", "b"), ("one", "c")]);
+    }
+
+    #[test]
+    fn test_help_wins_over_everything_else() {
+        assert!(matches!(parse_args(args("--help")), Ok(None)));
+        assert!(matches!(parse_args(args("ls s3://logs -h")), Ok(None)));
+        assert!(matches!(parse_args(args("-h no-such-command")), Ok(None)));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        assert_eq!(usage_error(""), "no command given");
+        assert_eq!(usage_error("--dry-run"), "no command given");
+        assert_eq!(usage_error("mv s3://a/b s3://a/c"), "unknown command mv");
+        assert_eq!(
+            usage_error("--verbose ls s3://logs"),
+            "unknown option --verbose"
+        );
+        assert_eq!(
+            usage_error("ls s3://logs --mount-path"),
+            "--mount-path needs a folder"
+        );
+        assert_eq!(usage_error("--profile"), "--profile needs a profile name");
+        assert_eq!(usage_error("ls"), "wrong arguments for ls");
+        assert_eq!(usage_error("put s3://data/key"), "wrong arguments for put");
+        assert_eq!(usage_error("rm"), "wrong arguments for rm");
+        assert_eq!(
+            usage_error("cat data/key"),
+            "data/key is not an s3://bucket/key URL"
+        );
+        assert_eq!(
+            usage_error("rm s3://data/a s3:///b"),
+            "s3:///b is not an s3://bucket/key URL"
+        );
+    }
+}
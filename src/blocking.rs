@@ -0,0 +1,109 @@
+//! A synchronous facade over [OpenOptions] for code that is not async.
+use std::{io, path::Path};
+
+use aws_sdk_s3::Client;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{DirEntry, OpenOptions, OpenOptionsBuilder, S3FilesystemError};
+
+/// A blocking version of [OpenOptions], for CLI tools and build scripts that are not async.
+///
+/// Each instance runs its own single threaded Tokio runtime, and every method blocks the calling thread
+/// until the operation has finished. Files are returned as [std::fs::File]. Methods must not be called
+/// from inside an async runtime, where they panic; use [OpenOptions] there instead.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::{BlockingOpenOptions, OpenOptionsBuilder};
+/// use std::io::Read;
+///
+/// fn main() {
+///     let open_options =
+///         BlockingOpenOptions::connect(OpenOptionsBuilder::new("my_aws_s3_bucket").mount_path("data/test/"))
+///             .unwrap();
+///
+///     let mut file = open_options.open_s3("some_folder/some_file.csv").unwrap();
+///     let mut contents = String::new();
+///     file.read_to_string(&mut contents).unwrap();
+///
+///     for entry in open_options.walkdir("some_folder/").unwrap() {
+///         println!("{}", entry.path.display());
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BlockingOpenOptions {
+    open_options: OpenOptions,
+    runtime: Runtime,
+}
+
+impl BlockingOpenOptions {
+    /// Create a blocking OpenOptions with the default settings, as [OpenOptions::new] does.
+    ///
+    /// Fails only if the runtime cannot be started.
+    pub fn new(bucket: String, client: Option<Client>) -> io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let open_options = runtime.block_on(OpenOptions::new(bucket, client));
+        Ok(BlockingOpenOptions {
+            open_options,
+            runtime,
+        })
+    }
+
+    /// Create a blocking OpenOptions from settings made with an [OpenOptionsBuilder].
+    ///
+    /// Fails only if the runtime cannot be started.
+    pub fn connect(builder: OpenOptionsBuilder) -> io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let open_options = runtime.block_on(builder.connect());
+        Ok(BlockingOpenOptions {
+            open_options,
+            runtime,
+        })
+    }
+
+    /// The async OpenOptions this wraps, for reading its settings.
+    pub fn open_options(&self) -> &OpenOptions {
+        &self.open_options
+    }
+
+    /// Open a file from S3, as [OpenOptions::open_s3] does.
+    pub fn open_s3<P>(&self, path: P) -> Result<std::fs::File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.runtime
+            .block_on(async { Ok(self.open_options.open_s3(path).await?.into_std().await) })
+    }
+
+    /// Read the entire contents of a file from S3, as [OpenOptions::read] does.
+    pub fn read<P>(&self, path: P) -> Result<Vec<u8>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.runtime.block_on(self.open_options.read(path))
+    }
+
+    /// Write a file to S3, as [OpenOptions::write_s3] does.
+    pub fn write_s3<P>(&self, path: P, buf: &[u8]) -> Result<std::fs::File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.runtime.block_on(async {
+            Ok(self
+                .open_options
+                .write_s3(path, buf)
+                .await?
+                .into_std()
+                .await)
+        })
+    }
+
+    /// List the objects under a path, as [OpenOptions::walkdir] does.
+    pub fn walkdir<P>(&self, path: P) -> Result<Vec<DirEntry>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.runtime.block_on(self.open_options.walkdir(path))
+    }
+}
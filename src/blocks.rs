@@ -0,0 +1,186 @@
+//! Caching the parts of large objects read through [S3File](crate::S3File) as fixed-size blocks.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+
+use crate::{
+    cache::{hashed_name, PrefixStats},
+    options::bucket_folder,
+    OpenOptions, S3FilesystemError,
+};
+
+/// The folder, below the bucket's folder in the mount path, holding one folder of blocks per object. `%` is
+/// always escaped in mirrored paths, so no key is mirrored into it.
+pub(crate) const BLOCKS_FOLDER: &str = "%blocks";
+
+/// The file in each object's block folder recording which object, version and block size the blocks are
+/// from.
+const OBJECT_FILE: &str = "object";
+
+/// Where the blocks of one version of an object are kept on disk.
+#[derive(Debug, Clone)]
+pub(crate) struct BlockCache {
+    folder: PathBuf,
+    pub(crate) block_size: u64,
+}
+
+impl BlockCache {
+    /// Where the block starting at `start` is kept.
+    pub(crate) fn block_path(&self, start: u64) -> PathBuf {
+        self.folder.join((start / self.block_size).to_string())
+    }
+
+    /// The block starting at `start`, if it has been cached.
+    pub(crate) async fn read(&self, start: u64) -> io::Result<Option<Bytes>> {
+        match tokio::fs::read(self.block_path(start)).await {
+            Ok(block) => Ok(Some(block.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Cache the block starting at `start`. It is written beside its final name and renamed into place,
+    /// so a block cut short is never read back.
+    pub(crate) async fn write(&self, start: u64, block: &[u8]) -> io::Result<()> {
+        let path = self.block_path(start);
+        let mut part_path = path.clone().into_os_string();
+        part_path.push(".part");
+
+        tokio::fs::write(&part_path, block).await?;
+        tokio::fs::rename(&part_path, &path).await
+    }
+}
+
+impl OpenOptions {
+    /// Cache objects read with [OpenOptions::open_s3_lazy] in fixed-size blocks
+    ///
+    /// [S3File](crate::S3File) reads then fetch whole blocks of `block_size` bytes, aligned to multiples of the block
+    /// size, and keep each block in the mount path. A block which has been read before is served from disk,
+    /// so seek-heavy access to huge objects, such as Parquet or zip files, only ever stores and downloads
+    /// the blocks actually read rather than the whole object.
+    ///
+    /// Blocks are kept in a `%blocks` folder below the bucket's folder in the mount path, one folder per
+    /// object. They are reused only while the object's ETag, checked by the HeadObject request made on
+    /// opening, and the block size are unchanged; otherwise the object's blocks are thrown away. Blocks are
+    /// removed by [OpenOptions::purge_cache], but are not counted by [OpenOptions::cache_stats] or towards
+    /// [OpenOptions::cache_quota].
+    ///
+    /// # Arguments
+    /// * `block_size`: How many bytes each block holds, such as 8 MiB. 0 is treated as 1.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::io::SeekFrom;
+    /// use tokio::io::{AsyncReadExt, AsyncSeekExt};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/cache/")
+    ///         .block_cache(8 * 1024 * 1024);
+    ///
+    ///     let mut file = open_options
+    ///         .open_s3_lazy("archives/huge.zip")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     // Only the block holding the zip's central directory is downloaded, and it is kept for next time.
+    ///     let mut end_of_directory = [0; 22];
+    ///     file.seek(SeekFrom::End(-22)).await.unwrap();
+    ///     file.read_exact(&mut end_of_directory).await.unwrap();
+    /// }
+    /// ```
+    pub fn block_cache(mut self, block_size: u64) -> Self {
+        self.block_size = Some(block_size.max(1));
+        self
+    }
+
+    /// The folder the blocks of `key` are kept in.
+    fn block_folder(&self, key: &str) -> PathBuf {
+        self.mount_path
+            .join(bucket_folder(&self.bucket))
+            .join(BLOCKS_FOLDER)
+            .join(hashed_name(key))
+    }
+
+    /// The block cache for the version of `key` with `e_tag`, if block caching is on. Blocks cached from
+    /// another version, or with another block size, are removed.
+    pub(crate) async fn open_blocks(
+        &self,
+        key: &str,
+        e_tag: Option<&str>,
+    ) -> Result<Option<BlockCache>, S3FilesystemError> {
+        let block_size = match self.block_size {
+            Some(block_size) => block_size,
+            None => return Ok(None),
+        };
+        let folder = self.block_folder(key);
+        let object = format!("{}\n{}\n{}", block_size, e_tag.unwrap_or_default(), key);
+
+        let cached = tokio::fs::read_to_string(folder.join(OBJECT_FILE))
+            .await
+            .ok();
+        if cached.as_deref() != Some(object.as_str()) {
+            remove_folder(&folder).await?;
+            tokio::fs::create_dir_all(&folder).await?;
+            tokio::fs::write(folder.join(OBJECT_FILE), object).await?;
+        }
+
+        Ok(Some(BlockCache { folder, block_size }))
+    }
+
+    /// Remove every cached block of `key`.
+    pub(crate) async fn evict_blocks(&self, key: &str) -> io::Result<()> {
+        remove_folder(&self.block_folder(key)).await
+    }
+
+    /// Remove the cached blocks of every object whose key starts with `prefix`, returning how many blocks
+    /// and bytes were removed.
+    pub(crate) async fn purge_blocks(&self, prefix: &str) -> io::Result<PrefixStats> {
+        let root = self.mount_path.join(bucket_folder(&self.bucket));
+        let mut purged = PrefixStats::default();
+
+        let mut folders = match tokio::fs::read_dir(root.join(BLOCKS_FOLDER)).await {
+            Ok(folders) => folders,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(purged),
+            Err(e) => return Err(e),
+        };
+        while let Some(folder) = folders.next_entry().await? {
+            let folder = folder.path();
+            // A folder with no record of its object is left over from an interrupted open, and goes too.
+            let object = tokio::fs::read_to_string(folder.join(OBJECT_FILE))
+                .await
+                .unwrap_or_default();
+            let key = object.splitn(3, '\n').nth(2);
+            if key.is_some_and(|key| !key.starts_with(prefix)) {
+                continue;
+            }
+
+            let mut blocks = tokio::fs::read_dir(&folder).await?;
+            while let Some(block) = blocks.next_entry().await? {
+                if block.file_name() != OBJECT_FILE {
+                    purged.bytes += block.metadata().await?.len();
+                    purged.files += 1;
+                }
+            }
+            remove_folder(&folder).await?;
+        }
+
+        Ok(purged)
+    }
+}
+
+/// Remove `folder` and everything in it, if it exists.
+async fn remove_folder(folder: &Path) -> io::Result<()> {
+    match tokio::fs::remove_dir_all(folder).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,610 @@
+//! Configuring an [OpenOptions] without an async context.
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use aws_sdk_s3::Client;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    options::DEFAULT_DATA_STORE, tls::config_loader, CacheLayout, CachePolicy, CannedAcl,
+    ChecksumAlgorithm, MetricsSink, ObjectBackend, OpenOptions, RequestHook, TlsConfig,
+};
+
+/// Synchronous configuration for an [OpenOptions], connected at the end with [OpenOptionsBuilder::connect].
+///
+/// [OpenOptions::new] has to be awaited before any settings can be made, as it may need to load AWS
+/// credentials. The builder takes the same settings without touching the network, so it can be filled in
+/// by ordinary configuration code and handed to async code to connect. Each setting behaves exactly like
+/// the [OpenOptions] method of the same name.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::OpenOptionsBuilder;
+///
+/// fn configure(bucket: &str, read_only: bool) -> OpenOptionsBuilder {
+///     OpenOptionsBuilder::new(bucket)
+///         .mount_path("data/test/")
+///         .read_only(read_only)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let open_options = configure("my_aws_s3_bucket", true).connect().await;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct OpenOptionsBuilder {
+    bucket: String,
+    client: Option<Client>,
+    profile: Option<String>,
+    backend: Option<Arc<dyn ObjectBackend>>,
+    mount_path: PathBuf,
+    prefix: String,
+    cache_policy: CachePolicy,
+    max_bandwidth: Option<u64>,
+    max_requests_per_second: Option<u64>,
+    max_concurrent_requests: Option<usize>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    operation_timeout: Option<Duration>,
+    transfer_acceleration: bool,
+    cache_layout: CacheLayout,
+    cache_quota: Option<u64>,
+    block_size: Option<u64>,
+    sparse_cache: bool,
+    decompress: bool,
+    decompress_gz_suffix: bool,
+    keep_compressed: bool,
+    skip_unchanged_uploads: bool,
+    write_back: bool,
+    trash: Option<String>,
+    adaptive_backoff: bool,
+    upload_acl: Option<CannedAcl>,
+    upload_checksum: Option<ChecksumAlgorithm>,
+    parallel_download: usize,
+    download_buffer_size: usize,
+    part_size: Option<u64>,
+    upload_concurrency: usize,
+    prefetch: usize,
+    dry_run: bool,
+    read_only: bool,
+    offline: bool,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    request_hooks: Vec<Arc<dyn RequestHook>>,
+    tls: Option<TlsConfig>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl std::fmt::Debug for OpenOptionsBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("OpenOptionsBuilder")
+            .field("bucket", &self.bucket)
+            .field("client", &self.client)
+            .field("profile", &self.profile)
+            .field("backend", &self.backend.is_some())
+            .field("mount_path", &self.mount_path)
+            .field("prefix", &self.prefix)
+            .field("cache_policy", &self.cache_policy)
+            .field("max_bandwidth", &self.max_bandwidth)
+            .field("max_requests_per_second", &self.max_requests_per_second)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("operation_timeout", &self.operation_timeout)
+            .field("transfer_acceleration", &self.transfer_acceleration)
+            .field("cache_layout", &self.cache_layout)
+            .field("cache_quota", &self.cache_quota)
+            .field("block_size", &self.block_size)
+            .field("sparse_cache", &self.sparse_cache)
+            .field("decompress", &self.decompress)
+            .field("decompress_gz_suffix", &self.decompress_gz_suffix)
+            .field("keep_compressed", &self.keep_compressed)
+            .field("skip_unchanged_uploads", &self.skip_unchanged_uploads)
+            .field("write_back", &self.write_back)
+            .field("trash", &self.trash)
+            .field("adaptive_backoff", &self.adaptive_backoff)
+            .field("upload_acl", &self.upload_acl)
+            .field("upload_checksum", &self.upload_checksum)
+            .field("parallel_download", &self.parallel_download)
+            .field("download_buffer_size", &self.download_buffer_size)
+            .field("part_size", &self.part_size)
+            .field("upload_concurrency", &self.upload_concurrency)
+            .field("prefetch", &self.prefetch)
+            .field("dry_run", &self.dry_run)
+            .field("read_only", &self.read_only)
+            .field("offline", &self.offline)
+            .field("metrics", &self.metrics.is_some())
+            .field("request_hooks", &self.request_hooks.len())
+            .field("tls", &self.tls)
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+impl OpenOptionsBuilder {
+    /// Start configuring access to `bucket`, with the same defaults as [OpenOptions::new].
+    pub fn new<B>(bucket: B) -> Self
+    where
+        B: Into<String>,
+    {
+        OpenOptionsBuilder {
+            bucket: bucket.into(),
+            client: None,
+            profile: None,
+            backend: None,
+            mount_path: PathBuf::from(DEFAULT_DATA_STORE),
+            prefix: String::new(),
+            cache_policy: CachePolicy::UseCacheIfPresent,
+            max_bandwidth: None,
+            max_requests_per_second: None,
+            max_concurrent_requests: None,
+            connect_timeout: None,
+            read_timeout: None,
+            operation_timeout: None,
+            transfer_acceleration: false,
+            cache_layout: CacheLayout::Mirror,
+            cache_quota: None,
+            block_size: None,
+            sparse_cache: false,
+            decompress: false,
+            decompress_gz_suffix: false,
+            keep_compressed: false,
+            skip_unchanged_uploads: false,
+            write_back: false,
+            trash: None,
+            adaptive_backoff: true,
+            upload_acl: None,
+            upload_checksum: None,
+            parallel_download: 1,
+            download_buffer_size: 0,
+            part_size: None,
+            upload_concurrency: 1,
+            prefetch: 0,
+            dry_run: false,
+            read_only: false,
+            offline: false,
+            metrics: None,
+            request_hooks: Vec::new(),
+            tls: None,
+            cancellation: None,
+        }
+    }
+
+    /// Use this client rather than creating one from your environment (the AWS CLI).
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Load credentials and region from a named profile in your AWS config files
+    ///
+    /// By default the client is created from your environment as the AWS CLI does, using the profile
+    /// named by `AWS_PROFILE` or the default profile. This picks the profile instead, such as a
+    /// read-only role kept alongside your usual credentials in `~/.aws/config`, without building the
+    /// client yourself. Ignored when a client is given with [OpenOptionsBuilder::client].
+    ///
+    /// # Arguments
+    /// * `name`: The profile's name, as it appears in `~/.aws/config` and `~/.aws/credentials`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptionsBuilder;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let open_options = OpenOptionsBuilder::new("my_aws_s3_bucket")
+    ///         .profile("prod-readonly")
+    ///         .read_only(true)
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn profile<N>(mut self, name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.profile = Some(name.into());
+        self
+    }
+
+    /// See [OpenOptions::backend].
+    pub fn backend(mut self, backend: Arc<dyn ObjectBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// See [OpenOptions::mount_path].
+    pub fn mount_path<P>(mut self, folder_path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.mount_path = folder_path.into();
+        self
+    }
+
+    /// See [OpenOptions::prefix].
+    pub fn prefix<P>(mut self, prefix: P) -> Self
+    where
+        P: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// See [OpenOptions::cache_policy].
+    pub fn cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = policy;
+        self
+    }
+
+    /// See [OpenOptions::max_bandwidth].
+    pub fn max_bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.max_bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// See [OpenOptions::max_requests_per_second].
+    pub fn max_requests_per_second(mut self, requests_per_second: u64) -> Self {
+        self.max_requests_per_second = Some(requests_per_second);
+        self
+    }
+
+    /// See [OpenOptions::max_concurrent_requests].
+    pub fn max_concurrent_requests(mut self, requests: usize) -> Self {
+        self.max_concurrent_requests = Some(requests);
+        self
+    }
+
+    /// See [OpenOptions::adaptive_backoff].
+    pub fn adaptive_backoff(mut self, enabled: bool) -> Self {
+        self.adaptive_backoff = enabled;
+        self
+    }
+
+    /// See [OpenOptions::connect_timeout].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// See [OpenOptions::read_timeout].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// See [OpenOptions::operation_timeout].
+    pub fn operation_timeout(mut self, timeout: Duration) -> Self {
+        self.operation_timeout = Some(timeout);
+        self
+    }
+
+    /// See [OpenOptions::transfer_acceleration].
+    pub fn transfer_acceleration(mut self, enabled: bool) -> Self {
+        self.transfer_acceleration = enabled;
+        self
+    }
+
+    /// See [OpenOptions::cache_layout].
+    pub fn cache_layout(mut self, layout: CacheLayout) -> Self {
+        self.cache_layout = layout;
+        self
+    }
+
+    /// See [OpenOptions::cache_quota].
+    pub fn cache_quota(mut self, bytes: u64) -> Self {
+        self.cache_quota = Some(bytes);
+        self
+    }
+
+    /// See [OpenOptions::block_cache].
+    pub fn block_cache(mut self, block_size: u64) -> Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// See [OpenOptions::sparse_cache].
+    pub fn sparse_cache(mut self, enabled: bool) -> Self {
+        self.sparse_cache = enabled;
+        self
+    }
+
+    /// See [OpenOptions::decompress].
+    pub fn decompress(mut self, enabled: bool) -> Self {
+        self.decompress = enabled;
+        self
+    }
+
+    /// See [OpenOptions::decompress_gz_suffix].
+    pub fn decompress_gz_suffix(mut self, enabled: bool) -> Self {
+        self.decompress_gz_suffix = enabled;
+        self
+    }
+
+    /// See [OpenOptions::keep_compressed].
+    pub fn keep_compressed(mut self, keep: bool) -> Self {
+        self.keep_compressed = keep;
+        self
+    }
+
+    /// See [OpenOptions::skip_unchanged_uploads].
+    pub fn skip_unchanged_uploads(mut self, enabled: bool) -> Self {
+        self.skip_unchanged_uploads = enabled;
+        self
+    }
+
+    /// See [OpenOptions::write_back].
+    pub fn write_back(mut self, enabled: bool) -> Self {
+        self.write_back = enabled;
+        self
+    }
+
+    /// See [OpenOptions::trash].
+    pub fn trash<T>(mut self, prefix: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.trash = Some(prefix.into());
+        self
+    }
+
+    /// See [OpenOptions::upload_acl].
+    pub fn upload_acl(mut self, acl: CannedAcl) -> Self {
+        self.upload_acl = Some(acl);
+        self
+    }
+
+    /// See [OpenOptions::upload_checksum].
+    pub fn upload_checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.upload_checksum = Some(algorithm);
+        self
+    }
+
+    /// See [OpenOptions::parallel_download].
+    pub fn parallel_download(mut self, parts: usize) -> Self {
+        self.parallel_download = parts;
+        self
+    }
+
+    /// See [OpenOptions::download_buffer_size].
+    pub fn download_buffer_size(mut self, bytes: usize) -> Self {
+        self.download_buffer_size = bytes;
+        self
+    }
+
+    /// See [OpenOptions::part_size].
+    pub fn part_size(mut self, bytes: u64) -> Self {
+        self.part_size = Some(bytes);
+        self
+    }
+
+    /// See [OpenOptions::upload_concurrency].
+    pub fn upload_concurrency(mut self, parts: usize) -> Self {
+        self.upload_concurrency = parts;
+        self
+    }
+
+    /// See [OpenOptions::prefetch].
+    pub fn prefetch(mut self, count: usize) -> Self {
+        self.prefetch = count;
+        self
+    }
+
+    /// See [OpenOptions::dry_run].
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// See [OpenOptions::read_only].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// See [OpenOptions::offline].
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// See [OpenOptions::metrics].
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    /// See [OpenOptions::request_hook]. Can be called more than once.
+    pub fn request_hook(mut self, hook: Arc<dyn RequestHook>) -> Self {
+        self.request_hooks.push(hook);
+        self
+    }
+
+    /// See [OpenOptions::tls].
+    pub fn tls(mut self, config: TlsConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// See [OpenOptions::cancel_on].
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Create the [OpenOptions], loading a client from your environment if none was given.
+    pub async fn connect(self) -> OpenOptions {
+        let client = match (self.client, self.profile) {
+            (Some(client), _) => Some(client),
+            (None, Some(profile)) => {
+                let config = config_loader().profile_name(profile).load().await;
+                Some(Client::new(&config))
+            }
+            (None, None) => None,
+        };
+
+        let mut open_options = OpenOptions::new(self.bucket, client)
+            .await
+            .mount_path(self.mount_path)
+            .prefix(self.prefix)
+            .cache_policy(self.cache_policy)
+            .cache_layout(self.cache_layout)
+            .decompress(self.decompress)
+            .decompress_gz_suffix(self.decompress_gz_suffix)
+            .keep_compressed(self.keep_compressed)
+            .sparse_cache(self.sparse_cache)
+            .skip_unchanged_uploads(self.skip_unchanged_uploads)
+            .write_back(self.write_back)
+            .adaptive_backoff(self.adaptive_backoff)
+            .parallel_download(self.parallel_download)
+            .download_buffer_size(self.download_buffer_size)
+            .upload_concurrency(self.upload_concurrency)
+            .prefetch(self.prefetch)
+            .dry_run(self.dry_run)
+            .read_only(self.read_only)
+            .offline(self.offline);
+
+        if let Some(bytes) = self.cache_quota {
+            open_options = open_options.cache_quota(bytes);
+        }
+        if let Some(block_size) = self.block_size {
+            open_options = open_options.block_cache(block_size);
+        }
+        if let Some(bytes_per_second) = self.max_bandwidth {
+            open_options = open_options.max_bandwidth(bytes_per_second);
+        }
+        if let Some(requests_per_second) = self.max_requests_per_second {
+            open_options = open_options.max_requests_per_second(requests_per_second);
+        }
+        if let Some(requests) = self.max_concurrent_requests {
+            open_options = open_options.max_concurrent_requests(requests);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            open_options = open_options.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            open_options = open_options.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.operation_timeout {
+            open_options = open_options.operation_timeout(timeout);
+        }
+        if self.transfer_acceleration {
+            open_options = open_options.transfer_acceleration(true);
+        }
+        if let Some(bytes) = self.part_size {
+            open_options = open_options.part_size(bytes);
+        }
+        if let Some(prefix) = self.trash {
+            open_options = open_options.trash(prefix);
+        }
+        if let Some(acl) = self.upload_acl {
+            open_options = open_options.upload_acl(acl);
+        }
+        if let Some(algorithm) = self.upload_checksum {
+            open_options = open_options.upload_checksum(algorithm);
+        }
+        if let Some(config) = self.tls {
+            open_options = open_options.tls(config);
+        }
+        if let Some(backend) = self.backend {
+            open_options = open_options.backend(backend);
+        }
+        if let Some(sink) = self.metrics {
+            open_options = open_options.metrics(sink);
+        }
+        for hook in self.request_hooks {
+            open_options = open_options.request_hook(hook);
+        }
+        if let Some(token) = self.cancellation {
+            open_options = open_options.cancel_on(token);
+        }
+
+        open_options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::Region;
+    use aws_smithy_runtime_api::client::{
+        http::{http_client_fn, HttpConnector, HttpConnectorFuture, SharedHttpConnector},
+        orchestrator::{HttpRequest, HttpResponse},
+    };
+    use aws_smithy_types::body::SdkBody;
+    use std::sync::Mutex;
+
+    /// Answers every request with an empty 200, keeping the last Authorization header.
+    #[derive(Debug, Default, Clone)]
+    struct SignedWith(Arc<Mutex<Option<String>>>);
+
+    impl HttpConnector for SignedWith {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            *self.0.lock().unwrap() = request.headers().get("authorization").map(str::to_string);
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                200.try_into().unwrap(),
+                SdkBody::empty(),
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profile_credentials_and_region_are_loaded() {
+        let folder = PathBuf::from("target/test-profile/");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(
+            folder.join("config"),
+            "[default]\nregion = us-east-1\n\n[profile prod-readonly]\nregion = eu-north-1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            folder.join("credentials"),
+            "[default]\naws_access_key_id = AKIDDEFAULT\naws_secret_access_key = default\n\n\
+             [prod-readonly]\naws_access_key_id = AKIDREADONLY\naws_secret_access_key = readonly\n",
+        )
+        .unwrap();
+        // Nothing else in this crate's unit tests reads the AWS environment.
+        for name in [
+            "AWS_PROFILE",
+            "AWS_REGION",
+            "AWS_DEFAULT_REGION",
+            "AWS_ACCESS_KEY_ID",
+            "AWS_SECRET_ACCESS_KEY",
+            "AWS_SESSION_TOKEN",
+        ] {
+            std::env::remove_var(name);
+        }
+        std::env::set_var("AWS_CONFIG_FILE", folder.join("config"));
+        std::env::set_var("AWS_SHARED_CREDENTIALS_FILE", folder.join("credentials"));
+
+        let open_options = OpenOptionsBuilder::new("profiled")
+            .profile("prod-readonly")
+            .connect()
+            .await;
+        let config = open_options.s3_client.config();
+        assert_eq!(config.region(), Some(&Region::new("eu-north-1")));
+
+        // The SDK has no getter for the credentials, so see what a request is signed with.
+        let signed_with = SignedWith::default();
+        let connector = SharedHttpConnector::new(signed_with.clone());
+        let client = Client::from_conf(
+            config
+                .to_builder()
+                .http_client(http_client_fn(move |_, _| connector.clone()))
+                .build(),
+        );
+        client
+            .head_bucket()
+            .bucket("profiled")
+            .send()
+            .await
+            .unwrap();
+
+        let authorization = signed_with.0.lock().unwrap().clone().unwrap();
+        assert!(
+            authorization.contains("Credential=AKIDREADONLY/")
+                && authorization.contains("/eu-north-1/s3/aws4_request"),
+            "{}",
+            authorization
+        );
+    }
+}
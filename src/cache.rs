@@ -0,0 +1,380 @@
+//! How the local mirror of a bucket is laid out, statistics about it, and clearing it out.
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    blocks::BLOCKS_FOLDER,
+    manifest::to_hex,
+    options::{bucket_folder, unescape_key},
+    sparse::SPARSE_FOLDER,
+    OpenOptions, S3FilesystemError,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How files are arranged under the mount path, chosen with [OpenOptions::cache_layout].
+pub enum CacheLayout {
+    /// Files are stored at their key below `<mount_path>/<bucket>/`, so the folder structure of the
+    /// bucket is kept and files can be found by hand. Characters Windows does not allow in file names
+    /// are percent encoded. Keys whose mirrored path would have a name or path too long for the OS are
+    /// stored in a `%long` folder, named as [CacheLayout::Hashed] names them; on Windows, paths over the
+    /// 260 character MAX_PATH limit are opened with the `\\?\` extended-length prefix instead.
+    #[default]
+    Mirror,
+    /// Files are stored directly in `<mount_path>/<bucket>/`, each named by the SHA-256 of its key. This
+    /// avoids very deep folder trees and any limits on file name characters or length, at the cost of
+    /// the files only being recognisable through the cache index.
+    Hashed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// When a copy already in the local mirror is used instead of downloading the object, chosen with
+/// [OpenOptions::cache_policy].
+pub enum CachePolicy {
+    /// Always download the object, replacing any cached copy.
+    AlwaysDownload,
+    /// Use a cached copy whenever there is one, without contacting S3. Only files missing from the mirror
+    /// are downloaded.
+    #[default]
+    UseCacheIfPresent,
+    /// Ask S3 whether a cached copy is still current by requesting it with If-None-Match on the ETag
+    /// recorded in the cache index. S3 answers 304 Not Modified, with no body, when nothing has changed,
+    /// so only objects which differ from the cached copy are downloaded again. Copies the index has no
+    /// ETag for are downloaded.
+    RevalidateEtag,
+    /// Use a cached copy without contacting S3 if it was downloaded or last revalidated less than this
+    /// long ago, and revalidate older copies as [CachePolicy::RevalidateEtag] does.
+    MaxAge(Duration),
+    /// Never contact S3 for a download: serve cached copies and fail with a not found I/O error for files
+    /// missing from the mirror.
+    OfflineOnly,
+}
+
+/// The name a file is stored under with [CacheLayout::Hashed].
+pub(crate) fn hashed_name(key: &str) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+/// The folder, below the bucket's folder in the mount path, where [CacheLayout::Mirror] keeps files whose
+/// mirrored path would be too long, named as [CacheLayout::Hashed] names them. `%` is always escaped in
+/// mirrored paths, so no key is mirrored into it.
+pub(crate) const LONG_KEY_FOLDER: &str = "%long";
+
+/// Room left in names and paths for the suffixes added to cached files, such as `.etag.part`.
+const SUFFIX_ROOM: usize = 16;
+
+/// The longest file or folder name most filesystems accept, in bytes.
+const MAX_NAME_LENGTH: usize = 255;
+
+/// The longest path the OS accepts: 32,767 characters on Windows with the `\\?\` prefix, and PATH_MAX
+/// elsewhere.
+#[cfg(windows)]
+const MAX_PATH_LENGTH: usize = 32_767;
+#[cfg(not(windows))]
+const MAX_PATH_LENGTH: usize = 4096;
+
+/// Where `key`, mirrored at `path` below `root`, is stored with [CacheLayout::Mirror].
+///
+/// That is `path` unless a name in it, or the whole path, is too long for the OS, in which case the file is
+/// stored in [LONG_KEY_FOLDER] instead. On Windows, paths longer than MAX_PATH are given the `\\?\`
+/// extended-length prefix, which lifts the 260 character limit.
+pub(crate) fn fit_mirror_path(root: &Path, path: PathBuf, key: &str) -> PathBuf {
+    let absolute = std::path::absolute(&path).unwrap_or_else(|_| path.clone());
+    let too_long = absolute.as_os_str().len() + SUFFIX_ROOM > MAX_PATH_LENGTH
+        || path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .iter()
+            .any(|name| name.len() + SUFFIX_ROOM > MAX_NAME_LENGTH);
+
+    match too_long {
+        true => extended_length(root.join(LONG_KEY_FOLDER).join(hashed_name(key))),
+        false => extended_length(path),
+    }
+}
+
+/// Give a path too long for the Windows MAX_PATH limit the `\\?\` prefix, so it can still be opened.
+#[cfg(windows)]
+fn extended_length(path: PathBuf) -> PathBuf {
+    const MAX_PATH: usize = 260;
+
+    if path.as_os_str().len() + SUFFIX_ROOM < MAX_PATH {
+        return path;
+    }
+    // Extended-length paths are passed to the filesystem as they are, so they have to be absolute and
+    // normalised, which std::path::absolute does on Windows.
+    let absolute =
+        match std::path::absolute(&path).map(|absolute| absolute.to_str().map(String::from)) {
+            Ok(Some(absolute)) => absolute,
+            _ => return path,
+        };
+    if absolute.starts_with(r"\\?\") {
+        return PathBuf::from(absolute);
+    }
+    match absolute.strip_prefix(r"\\") {
+        Some(share) => PathBuf::from(format!(r"\\?\UNC\{}", share)),
+        None => PathBuf::from(format!(r"\\?\{}", absolute)),
+    }
+}
+
+/// Paths are only limited by [MAX_PATH_LENGTH] on this platform.
+#[cfg(not(windows))]
+fn extended_length(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// A file found in the local mirror.
+pub(crate) struct MirroredFile {
+    /// The key the file holds, with `.part` appended for a partial download.
+    pub(crate) key: String,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+}
+
+/// Hits and misses counted by [OpenOptions::open_s3], shared by every clone of an [OpenOptions].
+#[derive(Debug, Default)]
+pub(crate) struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    pub(crate) fn record(&self, hit: bool) {
+        match hit {
+            true => self.hits.fetch_add(1, Ordering::Relaxed),
+            false => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The size of the part of the local mirror under one prefix.
+pub struct PrefixStats {
+    /// Bytes on disk under the prefix.
+    pub bytes: u64,
+    /// Number of cached files under the prefix.
+    pub files: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// A snapshot of the local mirror, returned by [OpenOptions::cache_stats].
+pub struct CacheStats {
+    /// Bytes on disk across every cached file in the bucket.
+    pub total_bytes: u64,
+    /// Number of cached files in the bucket.
+    pub file_count: u64,
+    /// Number of [OpenOptions::open_s3] calls served from the mirror.
+    pub hits: u64,
+    /// Number of [OpenOptions::open_s3] calls that had to download from S3.
+    pub misses: u64,
+    /// Bytes and files broken down by top level prefix - the first "folder" of each key. Files at
+    /// the root of the bucket are counted under "".
+    pub prefixes: BTreeMap<String, PrefixStats>,
+}
+
+impl OpenOptions {
+    /// Summarise the local mirror of the bucket
+    ///
+    /// Walks the files under the mount path for this bucket to total their size, and reports how many
+    /// [OpenOptions::open_s3] calls were cache hits or misses since this OpenOptions was created
+    /// (counts are shared by its clones). Partially downloaded files are not counted.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let stats = open_options.cache_stats().await.unwrap();
+    ///
+    ///     println!("{} files, {} bytes cached", stats.file_count, stats.total_bytes);
+    ///     for (prefix, prefix_stats) in &stats.prefixes {
+    ///         println!("{}: {} bytes", prefix, prefix_stats.bytes);
+    ///     }
+    /// }
+    /// ```
+    pub async fn cache_stats(&self) -> Result<CacheStats, S3FilesystemError> {
+        let mut stats = CacheStats {
+            hits: self.cache_counters.hits.load(Ordering::Relaxed),
+            misses: self.cache_counters.misses.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        };
+
+        for MirroredFile { key, size, .. } in self.mirrored_files(false).await? {
+            let prefix = match key.split_once('/') {
+                Some((first, _)) => first.to_string(),
+                None => String::new(),
+            };
+
+            stats.total_bytes += size;
+            stats.file_count += 1;
+            let prefix_stats = stats.prefixes.entry(prefix).or_default();
+            prefix_stats.bytes += size;
+            prefix_stats.files += 1;
+        }
+
+        Ok(stats)
+    }
+    /// Delete the locally mirrored files under a prefix
+    ///
+    /// Removes every cached file whose key starts with `prefix`, including partial downloads, blocks kept
+    /// by [OpenOptions::block_cache] and sparse files kept by [OpenOptions::sparse_cache], along with their
+    /// cache index entries and any folders left empty, so disk can be reclaimed without knowing how the
+    /// mount path is laid out. Nothing in S3 is touched, so this is allowed on [OpenOptions::read_only]
+    /// mounts, and purged files are simply downloaded again the next time they are opened.
+    ///
+    /// Returns how many files and bytes were removed.
+    ///
+    /// # Arguments
+    /// * `prefix`: Only cached files whose keys start with this are removed. Use "" for the whole bucket.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let purged = open_options.purge_cache("redasa1-Q1-20/").await.unwrap();
+    ///
+    ///     println!("Freed {} bytes from {} files", purged.bytes, purged.files);
+    /// }
+    /// ```
+    pub async fn purge_cache(&self, prefix: &str) -> Result<PrefixStats, S3FilesystemError> {
+        let root = self.mount_path.join(bucket_folder(&self.bucket));
+        let mut purged = PrefixStats::default();
+
+        for MirroredFile { key, path, size } in self.mirrored_files(true).await? {
+            if !key.starts_with(prefix) {
+                continue;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            purged.bytes += size;
+            purged.files += 1;
+
+            // Folders still holding other files refuse to be removed, which ends the climb.
+            let mut folder = path.parent();
+            while let Some(parent) = folder.filter(|parent| *parent != root) {
+                if tokio::fs::remove_dir(parent).await.is_err() {
+                    break;
+                }
+                folder = parent.parent();
+            }
+        }
+
+        for stats in [
+            self.purge_blocks(prefix).await?,
+            self.purge_sparse(prefix).await?,
+        ] {
+            purged.bytes += stats.bytes;
+            purged.files += stats.files;
+        }
+
+        self.cache_index.remove_prefix(prefix).await?;
+        Ok(purged)
+    }
+
+    /// Every file in the local mirror of the bucket. Partial downloads are only included with `partial`.
+    ///
+    /// With [CacheLayout::Hashed], keys are recovered from the cache index, so files it does not know
+    /// about are skipped.
+    pub(crate) async fn mirrored_files(&self, partial: bool) -> io::Result<Vec<MirroredFile>> {
+        let root = self.mount_path.join(bucket_folder(&self.bucket));
+        let mut files = Vec::new();
+
+        let hashed = match self.cache_layout {
+            CacheLayout::Mirror => tokio::fs::try_exists(root.join(LONG_KEY_FOLDER))
+                .await
+                .unwrap_or(false),
+            CacheLayout::Hashed => true,
+        };
+        let hashed_keys: HashMap<String, String> = match hashed {
+            false => HashMap::new(),
+            true => self
+                .cache_index
+                .all()
+                .await?
+                .into_iter()
+                .map(|object| (hashed_name(&object.key), object.key))
+                .collect(),
+        };
+
+        let mut directories: Vec<PathBuf> = vec![root.clone()];
+        while let Some(directory) = directories.pop() {
+            let mut entries = match tokio::fs::read_dir(&directory).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    // Blocks and sparse files only hold part of an object, so are not files of the mirror.
+                    if path != root.join(BLOCKS_FOLDER) && path != root.join(SPARSE_FOLDER) {
+                        directories.push(path);
+                    }
+                    continue;
+                }
+                if !partial
+                    && path
+                        .extension()
+                        .is_some_and(|extension| extension == "part")
+                {
+                    continue;
+                }
+
+                let relative = path.strip_prefix(&root).unwrap_or(&path);
+                let escaped = relative
+                    .iter()
+                    .map(|component| component.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let long_key = escaped
+                    .strip_prefix(LONG_KEY_FOLDER)
+                    .and_then(|name| name.strip_prefix('/'));
+                let key = match (self.cache_layout, long_key) {
+                    (CacheLayout::Mirror, None) => unescape_key(&escaped),
+                    (_, long_key) => {
+                        let hashed = long_key.unwrap_or(&escaped);
+                        let (name, suffix) = match hashed.find('.') {
+                            Some(index) => hashed.split_at(index),
+                            None => (hashed, ""),
+                        };
+                        match hashed_keys.get(name) {
+                            Some(key) => format!("{}{}", key, suffix),
+                            None => continue,
+                        }
+                    }
+                };
+                files.push(MirroredFile {
+                    key,
+                    path,
+                    size: metadata.len(),
+                });
+            }
+        }
+
+        Ok(files)
+    }
+}
@@ -0,0 +1,61 @@
+//! Stopping long running S3 work part way.
+use std::future::Future;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// Stop downloads, multipart uploads and listings once `token` is cancelled
+    ///
+    /// Operations which are running when the token is cancelled, or which start after, fail with an error for
+    /// which [S3FilesystemError::is_cancelled] is true. Nothing is left half done: a partial download is
+    /// deleted rather than kept to be resumed, and a multipart upload is aborted in S3 and its staged data
+    /// removed. Listings stop before fetching their next page.
+    ///
+    /// Cancel the token when your service shuts down so in-flight S3 work ends promptly. Clones of the
+    /// [OpenOptions] share the token; use [CancellationToken::child_token] to cancel some work separately.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{CancellationToken, OpenOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///     let shutdown = CancellationToken::new();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .cancel_on(shutdown.clone());
+    ///
+    ///     let download = tokio::spawn(async move { open_options.open_s3("large/file.bin").await });
+    ///
+    ///     tokio::signal::ctrl_c().await.unwrap();
+    ///     shutdown.cancel();
+    ///
+    ///     if let Err(e) = download.await.unwrap() {
+    ///         assert!(e.is_cancelled());
+    ///     }
+    /// }
+    /// ```
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Run `future`, giving up with [S3FilesystemError::Cancelled] if the token is cancelled first.
+    pub(crate) async fn cancellable<F, T>(&self, future: F) -> Result<T, S3FilesystemError>
+    where
+        F: Future<Output = Result<T, S3FilesystemError>>,
+    {
+        match &self.cancellation {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(S3FilesystemError::Cancelled),
+                result = future => result,
+            },
+            None => future.await,
+        }
+    }
+}
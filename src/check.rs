@@ -0,0 +1,116 @@
+//! Checking a bucket can be reached before any files are accessed.
+use std::time::Instant;
+
+use aws_sdk_s3::Client;
+
+use crate::{OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// Create a new OpenOptions struct, checking the bucket can be used
+    ///
+    /// Behaves like [OpenOptions::new], then makes a HeadBucket request so a misconfiguration is reported
+    /// straight away rather than on the first file access. The error says what is wrong:
+    /// [S3FilesystemError::is_not_found] if the bucket does not exist,
+    /// [S3FilesystemError::WrongRegion] if it is in a different region from the client, and
+    /// [S3FilesystemError::is_access_denied] if the credentials may not use it.
+    ///
+    /// HeadBucket only needs permission to list the bucket. With `list_objects` = true a ListObjectsV2
+    /// request for a single key is made as well, which also catches bucket policies that deny listing.
+    ///
+    /// # Arguments
+    /// * `bucket`: The bucket to use.
+    /// * `client`: The client to use, or None to create one from your environment (the AWS CLI).
+    /// * `list_objects`: Whether to check listing works too.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, S3FilesystemError};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = match OpenOptions::new_checked(bucket, None, false).await {
+    ///         Ok(open_options) => open_options.mount_path("data/test/"),
+    ///         Err(e) if e.is_not_found() => panic!("The bucket does not exist"),
+    ///         Err(e) => match e.without_context() {
+    ///             S3FilesystemError::WrongRegion(region) => panic!("Use a client for {}", region),
+    ///             _ => panic!("{}", e),
+    ///         },
+    ///     };
+    /// }
+    /// ```
+    pub async fn new_checked(
+        bucket: String,
+        client: Option<Client>,
+        list_objects: bool,
+    ) -> Result<Self, S3FilesystemError> {
+        let open_options = OpenOptions::new(bucket, client).await;
+
+        open_options
+            .head_bucket()
+            .await
+            .map_err(|e| e.with_context("HeadBucket", &open_options.bucket, None))?;
+
+        if list_objects {
+            open_options
+                .list_one()
+                .await
+                .map_err(|e| e.with_context("ListObjectsV2", &open_options.bucket, None))?;
+        }
+
+        Ok(open_options)
+    }
+
+    async fn head_bucket(&self) -> Result<(), S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .head_bucket()
+            .bucket(&self.bucket)
+            .send()
+            .await;
+        self.record_request("HeadBucket", started, result.is_ok());
+        drop(slot);
+
+        let e = match result {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        // S3 answers requests sent to the wrong region with a redirect naming the bucket's region.
+        let bucket_region = e
+            .raw_response()
+            .and_then(|response| response.headers().get("x-amz-bucket-region"))
+            .map(str::to_string);
+        let client_region = self
+            .s3_client
+            .config()
+            .region()
+            .map(|region| region.as_ref());
+        match bucket_region {
+            Some(bucket_region) if Some(bucket_region.as_str()) != client_region => {
+                Err(S3FilesystemError::WrongRegion(bucket_region))
+            }
+            _ => Err(e.into()),
+        }
+    }
+
+    async fn list_one(&self) -> Result<(), S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&self.prefix)
+            .max_keys(1)
+            .send()
+            .await;
+        self.record_request("ListObjectsV2", started, result.is_ok());
+        drop(slot);
+        result?;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Additional checksums sent with uploads, for buckets which require them.
+//!
+//! Checksums are computed locally and sent in the `x-amz-checksum-*` headers, so S3 rejects any upload
+//! whose contents were damaged on the way. The values are base64 encoded digests, as S3 reports them. For
+//! multipart uploads S3 checksums each part, and the object's checksum is the checksum of the parts'
+//! digests followed by `-<part count>`.
+use std::{fmt, str::FromStr};
+
+use aws_smithy_types::base64;
+use sha2::{Digest, Sha256};
+
+use crate::OpenOptions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// An algorithm S3 can check uploads against, chosen with [OpenOptions::upload_checksum].
+pub enum ChecksumAlgorithm {
+    /// CRC-32C, the fastest to compute. `x-amz-checksum-crc32c`
+    Crc32c,
+    /// SHA-256. `x-amz-checksum-sha256`
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// The digest of `data`.
+    pub(crate) fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    pub(crate) fn to_sdk(self) -> aws_sdk_s3::types::ChecksumAlgorithm {
+        match self {
+            ChecksumAlgorithm::Crc32c => aws_sdk_s3::types::ChecksumAlgorithm::Crc32C,
+            ChecksumAlgorithm::Sha256 => aws_sdk_s3::types::ChecksumAlgorithm::Sha256,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumAlgorithm::Crc32c => f.write_str("crc32c"),
+            ChecksumAlgorithm::Sha256 => f.write_str("sha256"),
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "crc32c" => Ok(ChecksumAlgorithm::Crc32c),
+            "sha256" => Ok(ChecksumAlgorithm::Sha256),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The additional checksum an object was uploaded with, as recorded in the cache index.
+pub struct ObjectChecksum {
+    /// The algorithm used.
+    pub algorithm: ChecksumAlgorithm,
+    /// The base64 encoded digest, followed by `-<part count>` for objects uploaded in parts.
+    pub value: String,
+}
+
+impl ObjectChecksum {
+    /// The checksum of `data` uploaded in a single request.
+    pub(crate) fn of(algorithm: ChecksumAlgorithm, data: &[u8]) -> Self {
+        ObjectChecksum {
+            algorithm,
+            value: base64::encode(algorithm.digest(data)),
+        }
+    }
+
+    /// The checksum of an object uploaded in parts with these base64 encoded part checksums, in order.
+    pub(crate) fn of_parts<'a, I>(algorithm: ChecksumAlgorithm, parts: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut digests = Vec::new();
+        let mut count = 0;
+        for part in parts {
+            digests.extend(base64::decode(part).ok()?);
+            count += 1;
+        }
+
+        Some(ObjectChecksum {
+            algorithm,
+            value: format!("{}-{}", base64::encode(algorithm.digest(&digests)), count),
+        })
+    }
+}
+
+impl fmt::Display for ObjectChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.value)
+    }
+}
+
+impl FromStr for ObjectChecksum {
+    type Err = ();
+
+    fn from_str(checksum: &str) -> Result<Self, Self::Err> {
+        let (algorithm, value) = checksum.split_once(':').ok_or(())?;
+        Ok(ObjectChecksum {
+            algorithm: algorithm.parse()?,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl OpenOptions {
+    /// Send an additional checksum with every upload
+    ///
+    /// The checksum of each upload is computed locally and sent alongside it, and S3 refuses the upload if
+    /// the data it received does not match, so buckets whose policies require a checksum accept writes
+    /// from this crate. Multipart uploads send a checksum with every part. The object's checksum is
+    /// recorded in the cache index, where [OpenOptions::cached_object] returns it for later verification.
+    ///
+    /// Uploads in progress keep the algorithm they started with when resumed. By default no additional
+    /// checksum is sent.
+    ///
+    /// # Arguments
+    /// * `algorithm`: The algorithm to checksum uploads with.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{ChecksumAlgorithm, OpenOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .upload_checksum(ChecksumAlgorithm::Crc32c);
+    ///
+    ///     open_options.write_s3("reports/latest.csv", b"a,b,c").await.unwrap();
+    ///
+    ///     let cached = open_options.cached_object("reports/latest.csv").await.unwrap();
+    ///     println!("Uploaded with {}", cached.unwrap().checksum.unwrap());
+    /// }
+    /// ```
+    pub fn upload_checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.upload_checksum = Some(algorithm);
+        self
+    }
+}
@@ -0,0 +1,277 @@
+//! Server-side copies between buckets, including objects too large for a single CopyObject.
+use std::{path::Path, sync::Arc, time::Instant};
+
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    dry_run::DryRunOperation, key::s3_key, options::copy_source, OpenOptions, S3FilesystemError,
+};
+
+/// The largest object a single CopyObject request can copy.
+const MAX_SINGLE_COPY: i64 = 5 * 1024 * 1024 * 1024;
+
+/// The smallest part used for multipart copies. Parts grow beyond this when needed to stay within
+/// S3's limit of 10,000 parts.
+const MIN_PART_SIZE: i64 = 512 * 1024 * 1024;
+
+/// The most parts S3 allows in a multipart upload.
+const MAX_PARTS: i64 = 10_000;
+
+/// How many parts of a multipart copy run at once.
+const PART_CONCURRENCY: usize = 8;
+
+impl OpenOptions {
+    /// Copy an object from one bucket to another
+    ///
+    /// The copy happens server-side, so the data never passes through your machine; this is the way to
+    /// promote data between environments. Objects up to 5 GiB are copied with a single CopyObject request,
+    /// and larger ones part by part with UploadPartCopy. If a multipart copy fails part way, the incomplete
+    /// upload is aborted so no storage is left behind.
+    ///
+    /// Both buckets are reached with this OpenOptions' client, so its credentials need read access to the
+    /// source and write access to the destination. If the destination is this OpenOptions' bucket, any
+    /// locally mirrored copy of the destination key is removed.
+    ///
+    /// # Arguments
+    /// * `src_bucket`: The bucket to copy from.
+    /// * `src_key`: The path, including filename, of the object to copy.
+    /// * `dst_bucket`: The bucket to copy to.
+    /// * `dst_key`: The path, including filename, the object should be copied to.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_staging_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     open_options
+    ///         .copy_between(
+    ///             "my_staging_bucket",
+    ///             "models/latest.bin",
+    ///             "my_production_bucket",
+    ///             "models/latest.bin",
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn copy_between<P, Q>(
+        &self,
+        src_bucket: &str,
+        src_key: P,
+        dst_bucket: &str,
+        dst_key: Q,
+    ) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let dst_key = dst_key.as_ref();
+        self.copy_across(src_bucket, src_key.as_ref(), dst_bucket, dst_key)
+            .await
+            .map_err(|e| e.with_context("CopyObject", dst_bucket, Some(dst_key)))
+    }
+
+    pub(crate) async fn copy_across(
+        &self,
+        src_bucket: &str,
+        src_key: &Path,
+        dst_bucket: &str,
+        dst_key: &Path,
+    ) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let source_key = s3_key(src_key)?;
+        let destination_key = s3_key(dst_key)?;
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::CopyBetween {
+                from_bucket: src_bucket.to_string(),
+                from: source_key,
+                to_bucket: dst_bucket.to_string(),
+                to: destination_key,
+            });
+            return Ok(());
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .head_object()
+            .bucket(src_bucket)
+            .key(&source_key)
+            .send()
+            .await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+        let size = result?.content_length().unwrap_or_default();
+
+        let source = copy_source(src_bucket, &source_key);
+        if size <= MAX_SINGLE_COPY {
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self
+                .s3_client
+                .copy_object()
+                .bucket(dst_bucket)
+                .copy_source(source)
+                .key(&destination_key)
+                .send()
+                .await;
+            self.record_request("CopyObject", started, result.is_ok());
+            drop(slot);
+            result?;
+        } else {
+            self.multipart_copy(source, dst_bucket, &destination_key, size)
+                .await?;
+        }
+
+        // Keys here are full keys, so only those under the prefix are mirrored locally.
+        if dst_bucket == self.bucket {
+            if let Some(key) = self.relative_key(&destination_key) {
+                self.evict(key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy `size` bytes from `source` with UploadPartCopy, aborting the upload if any part fails.
+    async fn multipart_copy(
+        &self,
+        source: String,
+        bucket: &str,
+        key: &str,
+        size: i64,
+    ) -> Result<(), S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await;
+        self.record_request("CreateMultipartUpload", started, result.is_ok());
+        drop(slot);
+        let upload_id = result?.upload_id().unwrap_or_default().to_string();
+
+        let copied = self
+            .copy_parts(&source, bucket, key, &upload_id, size)
+            .await;
+
+        let completed = match copied {
+            Ok(parts) => {
+                let slot = self.throttle_request().await;
+                let started = Instant::now();
+                let result = self
+                    .s3_client
+                    .complete_multipart_upload()
+                    .bucket(bucket)
+                    .key(key)
+                    .upload_id(&upload_id)
+                    .multipart_upload(
+                        CompletedMultipartUpload::builder()
+                            .set_parts(Some(parts))
+                            .build(),
+                    )
+                    .send()
+                    .await;
+                self.record_request("CompleteMultipartUpload", started, result.is_ok());
+                drop(slot);
+                result.map(|_| ()).map_err(S3FilesystemError::from)
+            }
+            Err(e) => Err(e),
+        };
+
+        if completed.is_err() {
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self
+                .s3_client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            self.record_request("AbortMultipartUpload", started, result.is_ok());
+            drop(slot);
+        }
+
+        completed
+    }
+
+    async fn copy_parts(
+        &self,
+        source: &str,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        size: i64,
+    ) -> Result<Vec<CompletedPart>, S3FilesystemError> {
+        let part_size = MIN_PART_SIZE.max((size + MAX_PARTS - 1) / MAX_PARTS);
+        let semaphore = Arc::new(Semaphore::new(PART_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+
+        for (index, start) in (0..size).step_by(part_size as usize).enumerate() {
+            let open_options = self.clone();
+            let source = source.to_string();
+            let bucket = bucket.to_string();
+            let key = key.to_string();
+            let upload_id = upload_id.to_string();
+            let semaphore = semaphore.clone();
+            let part_number = index as i32 + 1;
+            let end = (start + part_size).min(size) - 1;
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let slot = open_options.throttle_request().await;
+                let started = Instant::now();
+                let result = open_options
+                    .s3_client
+                    .upload_part_copy()
+                    .bucket(&bucket)
+                    .key(&key)
+                    .upload_id(&upload_id)
+                    .part_number(part_number)
+                    .copy_source(source)
+                    .copy_source_range(format!("bytes={}-{}", start, end))
+                    .send()
+                    .await;
+                open_options.record_request("UploadPartCopy", started, result.is_ok());
+                drop(slot);
+
+                let e_tag = result?
+                    .copy_part_result()
+                    .and_then(|part| part.e_tag())
+                    .map(str::to_string);
+                Ok::<_, S3FilesystemError>(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(e_tag)
+                        .build(),
+                )
+            });
+        }
+
+        let mut parts = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(part) => parts.push(part?),
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            }
+        }
+
+        parts.sort_by_key(|part| part.part_number());
+        Ok(parts)
+    }
+}
@@ -0,0 +1,345 @@
+//! Downloading CSV objects and deserializing their rows.
+use std::{io, path::Path};
+
+use serde::de::{
+    self, value::Error as DeError, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess,
+    SeqAccess, Visitor,
+};
+
+use crate::{OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// Download a CSV object and deserialize each row into a `T`
+    ///
+    /// The object is read as [OpenOptions::read] reads it, so it is cached in the local mirror like any
+    /// other download. The first row is the header, and each field of the following rows is matched to
+    /// the struct field, or map key, named by its column. Numbers and `true`/`false` are parsed from the
+    /// text, an empty field deserializes to `None` for an `Option`, and rows may also be read as tuples or
+    /// `Vec<String>` in column order. Fields may be quoted with `"`, with `""` for a quote inside them,
+    /// and rows may end with `\n` or `\r\n`.
+    ///
+    /// A row which cannot be deserialized, or which has a different number of fields from the header,
+    /// fails the whole read with an [io::ErrorKind::InvalidData] error naming the row.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the object.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Reading {
+    ///     station: String,
+    ///     temperature: f64,
+    ///     note: Option<String>,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let readings: Vec<Reading> = open_options.read_csv("weather/readings.csv").await.unwrap();
+    ///
+    ///     for reading in readings {
+    ///         println!("{}: {}", reading.station, reading.temperature);
+    ///     }
+    /// }
+    /// ```
+    pub async fn read_csv<T, P>(&self, path: P) -> Result<Vec<T>, S3FilesystemError>
+    where
+        T: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = self.read(path).await?;
+
+        parse_csv(&contents).map_err(|e| e.with_context("GetObject", &self.bucket, Some(path)))
+    }
+}
+
+/// Parse `contents` as CSV with a header row, deserializing every other row.
+fn parse_csv<T: DeserializeOwned>(contents: &[u8]) -> Result<Vec<T>, S3FilesystemError> {
+    let text =
+        std::str::from_utf8(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    let mut records = records(text)?.into_iter();
+    let headers = match records.next() {
+        Some(headers) => headers,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut rows = Vec::new();
+    for (index, fields) in records.enumerate() {
+        // Rows count from 1 for the header, as they would in a spreadsheet.
+        let row = index + 2;
+        if fields.len() != headers.len() {
+            return Err(invalid_row(
+                row,
+                format!(
+                    "{} fields where the header has {}",
+                    fields.len(),
+                    headers.len()
+                ),
+            ));
+        }
+        let record = RecordDeserializer {
+            headers: &headers,
+            fields,
+        };
+        rows.push(T::deserialize(record).map_err(|e| invalid_row(row, e.to_string()))?);
+    }
+    Ok(rows)
+}
+
+fn invalid_row(row: usize, message: String) -> S3FilesystemError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("CSV row {}: {}", row, message),
+    )
+    .into()
+}
+
+/// Split `text` into records of fields, handling quoted fields and both line endings. Blank lines are
+/// skipped.
+fn records(text: &str) -> Result<Vec<Vec<String>>, S3FilesystemError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() && !was_quoted => {
+                quoted = true;
+                was_quoted = true;
+            }
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                was_quoted = false;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() || was_quoted {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                was_quoted = false;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(invalid_row(
+            records.len() + 1,
+            "a quoted field is never closed".to_string(),
+        ));
+    }
+    if !record.is_empty() || !field.is_empty() || was_quoted {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Deserializes one row as a map from column name to field, or as a sequence of fields.
+struct RecordDeserializer<'a> {
+    headers: &'a [String],
+    fields: Vec<String>,
+}
+
+impl<'de> de::Deserializer<'de> for RecordDeserializer<'_> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_map(Columns {
+            columns: self.headers.iter().zip(self.fields),
+            field: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_seq(Fields(self.fields.into_iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct enum identifier ignored_any
+    }
+}
+
+struct Columns<'a, I: Iterator<Item = (&'a String, String)>> {
+    columns: I,
+    field: Option<String>,
+}
+
+impl<'de, 'a, I: Iterator<Item = (&'a String, String)>> MapAccess<'de> for Columns<'a, I> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeError> {
+        match self.columns.next() {
+            Some((header, field)) => {
+                self.field = Some(field);
+                seed.deserialize(header.as_str().into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        let field = self.field.take().unwrap_or_default();
+        seed.deserialize(FieldDeserializer(field))
+    }
+}
+
+struct Fields(std::vec::IntoIter<String>);
+
+impl<'de> SeqAccess<'de> for Fields {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeError> {
+        self.0
+            .next()
+            .map(|field| seed.deserialize(FieldDeserializer(field)))
+            .transpose()
+    }
+}
+
+/// Deserializes one field, parsing it as whatever type is asked for.
+struct FieldDeserializer(String);
+
+impl FieldDeserializer {
+    fn parse<T>(&self) -> Result<T, DeError>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.0
+            .trim()
+            .parse()
+            .map_err(|e| de::Error::custom(format!("{:?}: {}", self.0, e)))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for FieldDeserializer {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.0.is_empty() {
+            true => visitor.visit_none(),
+            false => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
@@ -0,0 +1,140 @@
+//! Decompressing gzip encoded objects as they are downloaded, so the mirror holds plain data.
+use std::{
+    ffi::OsString,
+    io::{BufReader, BufWriter, SeekFrom},
+    path::Path,
+};
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{gzip, options::part_path, OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// Decompress objects stored with `Content-Encoding: gzip` as they are downloaded
+    ///
+    /// An object uploaded with `Content-Encoding: gzip` holds its data compressed, but is meant to be read
+    /// as the data itself. With `enabled` = true, [OpenOptions::open_s3], [OpenOptions::open_s3_to] and the
+    /// `read` family decompress it on the way into the local file, so downstream readers see plain data.
+    /// The cache index records the decompressed size. Once the download is complete it is decompressed
+    /// from the partial file into the local file, without being held in memory. Room is made for the
+    /// decompressed file under [OpenOptions::cache_quota] and on disk, as it was for the download. Files
+    /// opened lazily with [OpenOptions::open_s3_lazy] read the bytes as stored.
+    ///
+    /// Off by default, so objects are kept exactly as stored.
+    pub fn decompress(mut self, enabled: bool) -> Self {
+        self.decompress = enabled;
+        self
+    }
+
+    /// Also decompress objects whose keys end in `.gz`
+    ///
+    /// Many gzip files are uploaded without a Content-Encoding. With `enabled` = true, any object whose key
+    /// ends in `.gz` is decompressed as it is downloaded, as [OpenOptions::decompress] does for encoded
+    /// objects. The local file keeps the object's name, `.gz` and all. Off by default.
+    pub fn decompress_gz_suffix(mut self, enabled: bool) -> Self {
+        self.decompress_gz_suffix = enabled;
+        self
+    }
+
+    /// Keep the compressed object next to the decompressed file
+    ///
+    /// With `keep` = true, a download which is decompressed also leaves the object as stored in a file
+    /// named after the decompressed one with `.compressed` appended. Off by default.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/")
+    ///         .decompress_gz_suffix(true)
+    ///         .keep_compressed(true);
+    ///
+    ///     // data/test/my_aws_s3_bucket/logs/2024-01-01.log.gz holds the plain log, and
+    ///     // logs/2024-01-01.log.gz.compressed the object as stored.
+    ///     let log = open_options.read_to_string("logs/2024-01-01.log.gz").await.unwrap();
+    ///     println!("{}", log);
+    /// }
+    /// ```
+    pub fn keep_compressed(mut self, keep: bool) -> Self {
+        self.keep_compressed = keep;
+        self
+    }
+
+    /// Decompress the finished download of `key` to `path` if it is gzip encoded, returning its size. Room
+    /// is made for the decompressed file as it was for the download, evicting old downloads if `mirrored`.
+    pub(crate) async fn decode_download(
+        &self,
+        key: &str,
+        path: &Path,
+        mirrored: bool,
+        content_encoding: Option<String>,
+        size: u64,
+    ) -> Result<u64, S3FilesystemError> {
+        let encoded = self.decompress
+            && content_encoding.is_some_and(|encoding| {
+                encoding
+                    .split(',')
+                    .any(|encoding| encoding.trim().eq_ignore_ascii_case("gzip"))
+            });
+        let suffixed = self.decompress_gz_suffix && key.ends_with(".gz");
+        if !encoded && !suffixed {
+            return Ok(size);
+        }
+
+        let part_path = part_path(path);
+        // The last member's trailer gives its size modulo 2^32, which is the whole size for most objects.
+        let expected = match size {
+            0..=3 => 0,
+            _ => {
+                let mut file = tokio::fs::File::open(&part_path).await?;
+                file.seek(SeekFrom::End(-4)).await?;
+                file.read_u32_le().await? as u64
+            }
+        };
+        self.reserve_space(path, mirrored, expected).await?;
+
+        let decoded_path = part_path.with_extension("decoded.part");
+        let decoded = tokio::task::spawn_blocking({
+            let part_path = part_path.clone();
+            let decoded_path = decoded_path.clone();
+            move || {
+                let compressed = BufReader::new(std::fs::File::open(part_path)?);
+                let decoded = BufWriter::new(std::fs::File::create(decoded_path)?);
+                gzip::decompress_to(compressed, decoded)
+            }
+        })
+        .await;
+        let decoded_size = match decoded {
+            Ok(decoded_size) => decoded_size,
+            Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+        };
+        let decoded_size = match decoded_size {
+            Ok(decoded_size) => decoded_size,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&decoded_path).await;
+                return Err(e.into());
+            }
+        };
+        // Larger than the trailer said, so check the quota again now the real size is known.
+        if mirrored && decoded_size > expected {
+            if let Err(e) = self.fit_in_quota(&decoded_path, decoded_size).await {
+                tokio::fs::remove_file(&decoded_path).await?;
+                return Err(e);
+            }
+        }
+
+        if self.keep_compressed {
+            let mut kept: OsString = path.as_os_str().to_os_string();
+            kept.push(".compressed");
+            tokio::fs::rename(&part_path, &kept).await?;
+        }
+        tokio::fs::rename(&decoded_path, &part_path).await?;
+        Ok(decoded_size)
+    }
+}
@@ -0,0 +1,92 @@
+//! Skipping uploads whose contents S3 already holds.
+use std::time::Instant;
+
+use md5::{Digest, Md5};
+
+use crate::{manifest::to_hex, ObjectChecksum, ObjectHead, OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// Skip uploads whose data is already stored in S3
+    ///
+    /// With `enabled` = true, [OpenOptions::write_s3], [OpenOptions::write_s3_with] and
+    /// [OpenOptions::write_s3_direct] first look the object up with a HeadObject request, and if it holds
+    /// exactly the data being written no PutObject is sent. The data is compared with the additional
+    /// checksum the object was uploaded with if it has one, otherwise with its ETag, which is an MD5 digest
+    /// for objects not uploaded in parts. Objects uploaded in parts without an additional checksum cannot be
+    /// compared and are always uploaded again, so pair this with [OpenOptions::upload_checksum] for large
+    /// outputs.
+    ///
+    /// Only the data is compared: a skipped upload keeps the object's existing Content-Type, ACL and
+    /// metadata. Writes with a precondition, such as [OpenOptions::write_s3_if_absent], are always sent.
+    /// Off by default, as the lookup costs a request on every write.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .skip_unchanged_uploads(true);
+    ///
+    ///     // Re-running the pipeline only uploads outputs which have changed.
+    ///     open_options
+    ///         .write_s3("outputs/summary.csv", b"total,42")
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn skip_unchanged_uploads(mut self, enabled: bool) -> Self {
+        self.skip_unchanged_uploads = enabled;
+        self
+    }
+
+    /// The object at `key`, if skipping unchanged uploads is enabled and it already holds `data`.
+    pub(crate) async fn unchanged_remote(
+        &self,
+        key: &str,
+        data: &[u8],
+    ) -> Result<Option<ObjectHead>, S3FilesystemError> {
+        if !self.skip_unchanged_uploads {
+            return Ok(None);
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self.cancellable(self.backend.head(&self.bucket, key)).await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+        let head = match result {
+            Ok(head) => head,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(holds(&head, data).then_some(head))
+    }
+}
+
+/// Whether the object described by `head` holds exactly `data`.
+fn holds(head: &ObjectHead, data: &[u8]) -> bool {
+    if head.size != data.len() as u64 {
+        return false;
+    }
+
+    if let Some(checksum) = &head.checksum {
+        // Checksums of objects uploaded in parts depend on the part size, so are only compared when whole.
+        if !checksum.value.contains('-') {
+            return *checksum == ObjectChecksum::of(checksum.algorithm, data);
+        }
+    }
+
+    // ETags of multipart uploads end in -<part count> and are not a digest of the whole object.
+    match head.e_tag.as_deref().map(|e_tag| e_tag.trim_matches('"')) {
+        Some(md5) if md5.len() == 32 && md5.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+            md5.eq_ignore_ascii_case(&to_hex(&Md5::digest(data)))
+        }
+        _ => false,
+    }
+}
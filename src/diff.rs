@@ -0,0 +1,145 @@
+//! Comparing the local mirror with the objects in S3.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use crate::{OpenOptions, S3FilesystemError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A file which is both cached and in S3, but whose copies differ.
+pub struct DiffChange {
+    /// Path of the object within the bucket.
+    pub path: PathBuf,
+    /// Size of the cached file.
+    pub local_size: u64,
+    /// Size of the object in S3.
+    pub remote_size: u64,
+    /// The ETag recorded in the cache index when the file was cached, if any.
+    pub cached_e_tag: Option<String>,
+    /// The object's ETag in S3 now, if S3 gave one.
+    pub remote_e_tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The outcome of [OpenOptions::diff]. Every list is sorted by path.
+pub struct DiffReport {
+    /// Files in the mount path with no object in S3.
+    pub local_only: Vec<PathBuf>,
+    /// Objects in S3 which have not been cached.
+    pub remote_only: Vec<PathBuf>,
+    /// Files whose size or ETag differs between the mount path and S3.
+    pub changed: Vec<DiffChange>,
+    /// Files which match S3 as far as their size and ETag show.
+    pub unchanged: Vec<PathBuf>,
+}
+
+impl DiffReport {
+    /// Whether the mount path holds exactly the objects in S3, all up to date.
+    pub fn is_current(&self) -> bool {
+        self.local_only.is_empty() && self.remote_only.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl OpenOptions {
+    /// Compare the cached files under a prefix with the objects in S3
+    ///
+    /// Reports which files exist only in the mount path, which objects exist only in S3, and which are in
+    /// both but differ in size or in ETag. ETags are compared with the one recorded in the cache index when
+    /// the file was downloaded or uploaded, so files put in the mount path by other means are compared on
+    /// size alone. Partial downloads and folder marker objects are left out.
+    ///
+    /// This costs one listing of the prefix and no downloads, so it is cheap enough to run before a sync or
+    /// in CI. Use [OpenOptions::verify] to also check cached contents against their MD5.
+    ///
+    /// # Arguments
+    /// * `prefix`: Only keys starting with this are compared. Use "" for the whole bucket.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let report = open_options.diff("redasa1-Q1-20/").await.unwrap();
+    ///
+    ///     for change in &report.changed {
+    ///         println!("{} is out of date", change.path.display());
+    ///     }
+    ///     assert!(report.is_current(), "the mirror is out of date");
+    /// }
+    /// ```
+    pub async fn diff(&self, prefix: &str) -> Result<DiffReport, S3FilesystemError> {
+        self.diff_prefix(prefix)
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(prefix.as_ref())))
+    }
+
+    async fn diff_prefix(&self, prefix: &str) -> Result<DiffReport, S3FilesystemError> {
+        let local: BTreeMap<String, u64> = self
+            .mirrored_files(false)
+            .await?
+            .into_iter()
+            .filter(|file| file.key.starts_with(prefix))
+            .map(|file| (file.key, file.size))
+            .collect();
+
+        let (objects, _) = self
+            .list_objects(prefix, false, &|entry| !entry.folder)
+            .await?;
+        let remote: BTreeMap<String, (u64, Option<String>)> = objects
+            .into_iter()
+            .map(|entry| {
+                let key = entry.path.to_string_lossy().into_owned();
+                (key, (entry.size.max(0) as u64, entry.e_tag))
+            })
+            .collect();
+
+        let mut report = DiffReport::default();
+        let keys: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+        for key in keys {
+            let path = PathBuf::from(key);
+            let (local_size, (remote_size, remote_e_tag)) = match (local.get(key), remote.get(key))
+            {
+                (Some(local_size), Some(remote)) => (*local_size, remote.clone()),
+                (Some(_), None) => {
+                    report.local_only.push(path);
+                    continue;
+                }
+                _ => {
+                    report.remote_only.push(path);
+                    continue;
+                }
+            };
+
+            let cached_e_tag = self
+                .cache_index
+                .get(key)
+                .await?
+                .and_then(|cached| cached.e_tag);
+            let e_tag_differs = matches!(
+                (&cached_e_tag, &remote_e_tag),
+                (Some(cached), Some(remote)) if cached != remote
+            );
+
+            match local_size != remote_size || e_tag_differs {
+                true => report.changed.push(DiffChange {
+                    path,
+                    local_size,
+                    remote_size,
+                    cached_e_tag,
+                    remote_e_tag,
+                }),
+                false => report.unchanged.push(path),
+            }
+        }
+
+        Ok(report)
+    }
+}
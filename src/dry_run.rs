@@ -0,0 +1,67 @@
+//! Recording of the mutations a dry run would have made.
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+/// A mutating S3 call skipped because dry run mode is enabled.
+pub enum DryRunOperation {
+    /// An object would have been uploaded.
+    Upload {
+        /// The key the object would have been written to.
+        key: String,
+        /// The number of bytes that would have been uploaded.
+        size: u64,
+    },
+    /// An object would have been copied within the bucket.
+    Copy {
+        /// The key that would have been copied from.
+        from: String,
+        /// The key that would have been copied to.
+        to: String,
+    },
+    /// An object would have been copied from one bucket to another.
+    CopyBetween {
+        /// The bucket that would have been copied from.
+        from_bucket: String,
+        /// The key that would have been copied from.
+        from: String,
+        /// The bucket that would have been copied to.
+        to_bucket: String,
+        /// The key that would have been copied to.
+        to: String,
+    },
+    /// An object would have been deleted.
+    Delete {
+        /// The key that would have been deleted.
+        key: String,
+    },
+    /// An archived object would have been restored.
+    Restore {
+        /// The key that would have been restored.
+        key: String,
+        /// How many days the restored copy would have been kept for.
+        days: i32,
+    },
+}
+
+/// Shared log of skipped operations, in the order they were attempted.
+#[derive(Debug, Default)]
+pub(crate) struct DryRunLog {
+    operations: Mutex<Vec<DryRunOperation>>,
+}
+
+impl DryRunLog {
+    pub(crate) fn record(&self, operation: DryRunOperation) {
+        self.operations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(operation);
+    }
+
+    pub(crate) fn operations(&self) -> Vec<DryRunOperation> {
+        self.operations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
@@ -1,48 +1,481 @@
-use aws_sdk_s3::{error::SdkError, primitives::ByteStreamError};
-use std::{fmt::Debug, io};
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::primitives::ByteStreamError;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+
+use crate::object_lock::is_object_locked;
+use std::{fmt::Debug, io, path::Path};
+
+/// An error returned by S3, covering every operation this crate performs.
+///
+/// The per-operation service errors from the SDK are folded into [aws_sdk_s3::Error] so that failures from
+/// different operations share one type.
+pub type S3Error = SdkError<aws_sdk_s3::Error, HttpResponse>;
 
 #[derive(Debug)]
 /// Container for errors that can occur due to AWS or local I/O.
-pub enum S3FilesystemError<E, R> {
+pub enum S3FilesystemError {
     /// Occurs when a request to S3 is unsuccessful - for instance when a non-existent object is requested.
-    S3(SdkError<E, R>),
+    S3(Box<S3Error>),
     /// Occurs when a reading or writing to/from a ByteStream (used for S3 downloads/uploads).
     ByteStream(ByteStreamError),
     /// Occurs when there are issues with the local file system - for instance, creating a file with an invalid character in the filename.
     Io(io::Error),
+    /// Occurs when a write or delete is attempted through an [OpenOptions](crate::OpenOptions) configured with
+    /// [read_only](crate::OpenOptions::read_only).
+    ReadOnly,
+    /// Occurs when a conditional write is rejected because the object was created or changed by someone else.
+    PreconditionFailed,
+    /// Occurs when a conditional read is answered with 304 Not Modified, because the object still has the
+    /// ETag the caller already holds.
+    NotModified,
+    /// Occurs when a key would be mirrored outside the mount path, because it has `..` segments or is
+    /// absolute. Holds the offending key.
+    PathTraversal(String),
+    /// Occurs when a path or string cannot be used as a key, because it is empty, longer than
+    /// [MAX_KEY_LENGTH](crate::MAX_KEY_LENGTH) bytes or not valid UTF-8. No request is made.
+    InvalidKey {
+        /// The key, with Windows separators normalised and anything which is not UTF-8 replaced.
+        key: String,
+        /// Why the key cannot be used.
+        reason: &'static str,
+    },
+    /// Occurs when the bucket lives in a different region from the one the client is configured for.
+    /// Holds the bucket's region.
+    WrongRegion(String),
+    /// Occurs when a download would not fit, either on the disk holding its destination or within the
+    /// [cache_quota](crate::OpenOptions::cache_quota). Nothing is written.
+    InsufficientSpace {
+        /// The size of the download in bytes.
+        needed: u64,
+        /// The bytes free on the disk, or left in the quota once everything evictable was evicted.
+        available: u64,
+    },
+    /// Occurs when an operation is stopped part way because the token given to
+    /// [cancel_on](crate::OpenOptions::cancel_on) was cancelled.
+    Cancelled,
+    /// Occurs when a request to SQS is unsuccessful while processing event notifications.
+    #[cfg(feature = "sqs")]
+    Sqs(Box<SdkError<aws_sdk_sqs::Error, HttpResponse>>),
+    /// Wraps an error from an [OpenOptions](crate::OpenOptions) method with the request it was making.
+    ///
+    /// Use [S3FilesystemError::without_context] to get at the underlying error.
+    WithContext {
+        /// The operation, bucket and key the error happened on.
+        context: Box<ErrorContext>,
+        /// The error itself.
+        source: Box<S3FilesystemError>,
+    },
+}
+
+#[derive(Debug, Clone)]
+/// Where an error happened: the S3 operation, bucket and key involved, and S3's id for the request.
+pub struct ErrorContext {
+    pub(crate) operation: &'static str,
+    pub(crate) bucket: String,
+    pub(crate) key: Option<String>,
+    pub(crate) request_id: Option<String>,
+}
+
+impl ErrorContext {
+    /// The S3 operation being performed, such as "GetObject".
+    pub fn operation(&self) -> &str {
+        self.operation
+    }
+
+    /// The bucket the request was made against.
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// The key or prefix the request was for. Bulk operations such as
+    /// [delete_many](crate::OpenOptions::delete_many) have no single key.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// The `x-amz-request-id` S3 returned, if the request got a response. Quote this when raising a
+    /// support case with AWS.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
 }
 
-impl<E, R> From<io::Error> for S3FilesystemError<E, R> {
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} s3://{}/", self.operation, self.bucket)?;
+        if let Some(key) = &self.key {
+            write!(f, "{}", key)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request id {})", request_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<io::Error> for S3FilesystemError {
     fn from(err: io::Error) -> Self {
         Self::Io(err)
     }
 }
 
-impl<E, R> From<SdkError<E, R>> for S3FilesystemError<E, R> {
-    fn from(err: SdkError<E, R>) -> Self {
-        Self::S3(err)
+impl<E> From<SdkError<E, HttpResponse>> for S3FilesystemError
+where
+    E: Into<aws_sdk_s3::Error>,
+{
+    fn from(err: SdkError<E, HttpResponse>) -> Self {
+        Self::S3(Box::new(err.map_service_error(Into::into)))
     }
 }
 
-impl<E, R> From<ByteStreamError> for S3FilesystemError<E, R> {
+impl From<ByteStreamError> for S3FilesystemError {
     fn from(err: ByteStreamError) -> Self {
         Self::ByteStream(err)
     }
 }
-impl<E, R> std::fmt::Display for S3FilesystemError<E, R> {
+/// Converts to an [io::Error] whose [kind](io::Error::kind) matches what went wrong, so the crate can be
+/// used behind APIs that only deal in [io::Result].
+///
+/// A missing object or bucket becomes [io::ErrorKind::NotFound], denied access and
+/// [S3FilesystemError::ReadOnly] become [io::ErrorKind::PermissionDenied], and a bare
+/// [S3FilesystemError::Io] is returned as it was. Anything without a closer match is
+/// [io::ErrorKind::Other]. The original error, context included, is kept as the
+/// [inner error](io::Error::get_ref).
+impl From<S3FilesystemError> for io::Error {
+    fn from(err: S3FilesystemError) -> Self {
+        match err {
+            S3FilesystemError::Io(io_err) => io_err,
+            err => io::Error::new(err.io_error_kind(), err),
+        }
+    }
+}
+
+impl std::fmt::Display for S3FilesystemError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
+            S3FilesystemError::S3(_) if self.is_object_locked() => write!(
+                f,
+                "Object locked: the object version is under an Object Lock retention period or legal hold"
+            ),
             S3FilesystemError::S3(s3_err) => write!(f, "S3 Error: {}", s3_err),
             S3FilesystemError::Io(io_err) => write!(f, "IO Error: {}", io_err),
             S3FilesystemError::ByteStream(bytestream_error) => {
                 write!(f, "ByteStream error: {}", bytestream_error)
             }
+            S3FilesystemError::ReadOnly => {
+                write!(f, "Read only: mutating operations are disabled")
+            }
+            S3FilesystemError::PreconditionFailed => {
+                write!(
+                    f,
+                    "Precondition failed: the object was changed by another writer"
+                )
+            }
+            S3FilesystemError::NotModified => {
+                write!(f, "Not modified: the object still has the given ETag")
+            }
+            S3FilesystemError::PathTraversal(key) => {
+                write!(
+                    f,
+                    "Path traversal: {} would resolve outside the mount path",
+                    key
+                )
+            }
+            S3FilesystemError::InvalidKey { key, reason } => {
+                write!(f, "Invalid key {:?}: {}", key, reason)
+            }
+            S3FilesystemError::WrongRegion(region) => {
+                write!(f, "Wrong region: the bucket is in {}", region)
+            }
+            S3FilesystemError::InsufficientSpace { needed, available } => write!(
+                f,
+                "Insufficient space: {} bytes needed but only {} available",
+                needed, available
+            ),
+            S3FilesystemError::Cancelled => write!(f, "Cancelled: the operation was stopped"),
+            #[cfg(feature = "sqs")]
+            S3FilesystemError::Sqs(sqs_err) => write!(f, "SQS Error: {}", sqs_err),
+            S3FilesystemError::WithContext { context, source } => {
+                write!(f, "{}: {}", context, source)
+            }
         }
     }
 }
-impl<E, R> std::error::Error for S3FilesystemError<E, R>
-where
-    E: std::error::Error + 'static,
-    R: Debug,
-{
+impl std::error::Error for S3FilesystemError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            S3FilesystemError::S3(s3_err) => Some(s3_err.as_ref()),
+            S3FilesystemError::ByteStream(bytestream_error) => Some(bytestream_error),
+            S3FilesystemError::Io(io_err) => Some(io_err),
+            S3FilesystemError::ReadOnly
+            | S3FilesystemError::PreconditionFailed
+            | S3FilesystemError::NotModified
+            | S3FilesystemError::PathTraversal(_)
+            | S3FilesystemError::InvalidKey { .. }
+            | S3FilesystemError::WrongRegion(_)
+            | S3FilesystemError::InsufficientSpace { .. }
+            | S3FilesystemError::Cancelled => None,
+            #[cfg(feature = "sqs")]
+            S3FilesystemError::Sqs(sqs_err) => Some(sqs_err.as_ref()),
+            S3FilesystemError::WithContext { source, .. } => source.source(),
+        }
+    }
+}
+
+impl S3FilesystemError {
+    /// Attach the operation, bucket and key to an error, picking up the request id from the S3 response.
+    ///
+    /// [S3FilesystemError::ReadOnly] is left bare as it is raised before any request is made, and errors
+    /// that already carry context keep their original context.
+    pub(crate) fn with_context(
+        self,
+        operation: &'static str,
+        bucket: &str,
+        key: Option<&Path>,
+    ) -> Self {
+        if matches!(
+            self,
+            S3FilesystemError::ReadOnly | S3FilesystemError::WithContext { .. }
+        ) {
+            return self;
+        }
+
+        let request_id = match &self {
+            S3FilesystemError::S3(s3_err) => s3_err
+                .raw_response()
+                .and_then(|response| response.headers().get("x-amz-request-id"))
+                .map(str::to_string),
+            _ => None,
+        };
+
+        S3FilesystemError::WithContext {
+            context: Box::new(ErrorContext {
+                operation,
+                bucket: bucket.to_string(),
+                key: key.map(|key| key.to_string_lossy().into_owned()),
+                request_id,
+            }),
+            source: Box::new(self),
+        }
+    }
+
+    /// The operation, bucket, key and request id the error happened on, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            S3FilesystemError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The underlying error, with any [ErrorContext] stripped off. Match on this rather than on the
+    /// error itself to find out what went wrong.
+    pub fn without_context(&self) -> &S3FilesystemError {
+        match self {
+            S3FilesystemError::WithContext { source, .. } => source.without_context(),
+            _ => self,
+        }
+    }
+
+    /// Whether a conditional write was rejected because the object was created or changed by someone else.
+    pub fn is_precondition_failed(&self) -> bool {
+        matches!(
+            self.without_context(),
+            S3FilesystemError::PreconditionFailed
+        )
+    }
+
+    /// Whether a conditional read found the object unchanged, so there was nothing to download.
+    pub fn is_not_modified(&self) -> bool {
+        matches!(self.without_context(), S3FilesystemError::NotModified)
+    }
+
+    /// Whether a download was refused because there was no room for it on disk or in the cache quota.
+    pub fn is_insufficient_space(&self) -> bool {
+        matches!(
+            self.without_context(),
+            S3FilesystemError::InsufficientSpace { .. }
+        )
+    }
+
+    /// Whether the operation was stopped by the token given to [cancel_on](crate::OpenOptions::cancel_on).
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.without_context(), S3FilesystemError::Cancelled)
+    }
+
+    /// Whether the object, bucket or file does not exist.
+    ///
+    /// Covers S3's not found errors and local [io::ErrorKind::NotFound] errors, which is how backends other
+    /// than S3, such as [LocalBackend](crate::LocalBackend), report a missing object.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.code(), Some("NoSuchKey" | "NoSuchBucket" | "NotFound"))
+            || self.status() == Some(404)
+            || matches!(self.without_context(), S3FilesystemError::Io(e) if e.kind() == io::ErrorKind::NotFound)
+    }
+
+    /// Whether S3 refused the request because the credentials in use lack permission.
+    pub fn is_access_denied(&self) -> bool {
+        matches!(
+            self.code(),
+            Some("AccessDenied" | "AllAccessDisabled" | "InvalidAccessKeyId")
+        ) || (self.status() == Some(403) && !self.is_archived())
+    }
+
+    /// Whether S3 refused the request because the object version is under an Object Lock retention
+    /// period or legal hold.
+    ///
+    /// Deletes through [delete_many](crate::OpenOptions::delete_many) report this per key instead, with
+    /// [DeleteOutcome::is_object_locked](crate::DeleteOutcome::is_object_locked).
+    pub fn is_object_locked(&self) -> bool {
+        is_object_locked(self.code(), self.message())
+    }
+
+    /// Whether the object is archived in Glacier or Deep Archive and has to be restored before it can be read.
+    ///
+    /// Start a restore with [OpenOptions::restore](crate::OpenOptions::restore) and check on it with
+    /// [OpenOptions::restore_status](crate::OpenOptions::restore_status).
+    pub fn is_archived(&self) -> bool {
+        self.code() == Some("InvalidObjectState")
+    }
+
+    /// Whether S3 asked for requests to be slowed down.
+    ///
+    /// Throttled requests are worth retrying after a delay, or reducing concurrency for.
+    pub fn is_throttled(&self) -> bool {
+        matches!(
+            self.code(),
+            Some(
+                "SlowDown"
+                    | "Throttling"
+                    | "ThrottlingException"
+                    | "RequestLimitExceeded"
+                    | "TooManyRequestsException"
+                    | "RequestThrottled"
+            )
+        ) || matches!(self.status(), Some(429 | 503))
+    }
+
+    /// Whether the request never got a response because S3 could not be reached, for instance when the
+    /// network is down or the connection timed out.
+    pub fn is_unreachable(&self) -> bool {
+        match self.without_context() {
+            S3FilesystemError::S3(s3_err) => matches!(
+                s3_err.as_ref(),
+                SdkError::DispatchFailure(_) | SdkError::TimeoutError(_)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether retrying the same request could succeed.
+    ///
+    /// Timeouts, dropped connections, throttling and 5xx responses are transient and worth retrying with
+    /// a backoff, as are network errors part way through a download. Other 4xx responses (missing
+    /// objects, denied access, failed preconditions) and local I/O errors, including failing to read the
+    /// file being uploaded, will fail the same way again.
+    pub fn retryable(&self) -> bool {
+        match self.without_context() {
+            S3FilesystemError::S3(s3_err) => match s3_err.as_ref() {
+                SdkError::ServiceError(_) => {
+                    self.is_throttled()
+                        || matches!(
+                            self.code(),
+                            Some("InternalError" | "RequestTimeout" | "ServiceUnavailable")
+                        )
+                        || matches!(self.status(), Some(500..=599))
+                }
+                other => transient(other),
+            },
+            S3FilesystemError::ByteStream(err) => body_transient(err),
+            #[cfg(feature = "sqs")]
+            S3FilesystemError::Sqs(sqs_err) => match sqs_err.as_ref() {
+                SdkError::ServiceError(context) => context.raw().status().is_server_error(),
+                other => transient(other),
+            },
+            _ => false,
+        }
+    }
+
+    /// The [io::ErrorKind] closest to this error, used when converting it into an [io::Error].
+    pub fn io_error_kind(&self) -> io::ErrorKind {
+        if self.is_not_found() {
+            return io::ErrorKind::NotFound;
+        }
+        if self.is_access_denied() {
+            return io::ErrorKind::PermissionDenied;
+        }
+
+        match self.without_context() {
+            S3FilesystemError::Io(io_err) => io_err.kind(),
+            S3FilesystemError::ReadOnly => io::ErrorKind::PermissionDenied,
+            S3FilesystemError::PreconditionFailed => io::ErrorKind::AlreadyExists,
+            S3FilesystemError::PathTraversal(_) => io::ErrorKind::InvalidInput,
+            S3FilesystemError::InvalidKey { .. } => io::ErrorKind::InvalidInput,
+            S3FilesystemError::InsufficientSpace { .. } => io::ErrorKind::StorageFull,
+            S3FilesystemError::Cancelled => io::ErrorKind::Interrupted,
+            S3FilesystemError::ByteStream(_) => io::ErrorKind::UnexpectedEof,
+            S3FilesystemError::S3(s3_err) => match s3_err.as_ref() {
+                SdkError::TimeoutError(_) => io::ErrorKind::TimedOut,
+                SdkError::DispatchFailure(failure) if failure.is_timeout() => {
+                    io::ErrorKind::TimedOut
+                }
+                SdkError::DispatchFailure(_) => io::ErrorKind::NotConnected,
+                _ => io::ErrorKind::Other,
+            },
+            _ => io::ErrorKind::Other,
+        }
+    }
+
+    /// The error code S3 returned, such as "NoSuchKey", if the request reached S3.
+    pub fn code(&self) -> Option<&str> {
+        match self.without_context() {
+            S3FilesystemError::S3(s3_err) => match s3_err.as_ref() {
+                SdkError::ServiceError(context) => context.err().code(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The error message S3 returned alongside [S3FilesystemError::code], if the request reached S3.
+    pub fn message(&self) -> Option<&str> {
+        match self.without_context() {
+            S3FilesystemError::S3(s3_err) => match s3_err.as_ref() {
+                SdkError::ServiceError(context) => context.err().message(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The HTTP status S3 responded with, if the request got a response.
+    pub fn status(&self) -> Option<u16> {
+        match self.without_context() {
+            S3FilesystemError::S3(s3_err) => s3_err
+                .raw_response()
+                .map(|response| response.status().as_u16()),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a request failed before S3 could answer it for a reason that may not recur.
+fn transient<E>(err: &SdkError<E, HttpResponse>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => true,
+        SdkError::DispatchFailure(failure) => failure.is_timeout() || failure.is_io(),
+        _ => false,
+    }
+}
+
+/// Whether a body failed because of the network rather than the local file it was read from.
+///
+/// Bodies read from a file report the file's [io::Error] directly, while network failures arrive
+/// wrapped in the HTTP client's own error type.
+fn body_transient(err: &ByteStreamError) -> bool {
+    use std::error::Error;
+
+    match err.source() {
+        Some(source) => !source.is::<io::Error>(),
+        None => false,
+    }
 }
@@ -0,0 +1,684 @@
+//! Mount a bucket as a local filesystem with FUSE.
+//!
+//! Reads go through [OpenOptions::open_s3], so files are downloaded into the mount path on first
+//! access and served from there afterwards. Writes land in the same local copy and are pushed back
+//! to S3 with [OpenOptions::write_s3] when the file is flushed or closed.
+use fuser::{
+    BackgroundSession, Config, Errno, FileAttr, FileHandle, FileType, Filesystem, FopenFlags,
+    Generation, INodeNo, LockOwner, MountOption, OpenFlags, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow, WriteFlags,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    io::{self, Read, Seek},
+    os::unix::fs::{FileExt, MetadataExt},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use tokio::runtime::Handle;
+
+use crate::{error::S3FilesystemError, OpenOptions};
+
+/// How long the kernel may cache attributes and directory entries.
+const TTL: Duration = Duration::from_secs(1);
+
+/// A bucket mounted with FUSE.
+///
+/// The filesystem stays mounted for as long as this value is alive. Dropping it, or calling
+/// [S3Mount::unmount], unmounts it.
+pub struct S3Mount {
+    session: BackgroundSession,
+}
+
+impl S3Mount {
+    /// Unmount the filesystem and wait for the FUSE session to finish.
+    pub fn unmount(self) -> io::Result<()> {
+        self.session.umount_and_join()
+    }
+}
+
+impl OpenOptions {
+    /// Mount the bucket as a local filesystem.
+    ///
+    /// The bucket is listed with [OpenOptions::walkdir] and exposed at `mountpoint`, so tools that know
+    /// nothing about S3 can browse and read it. File contents are fetched lazily with
+    /// [OpenOptions::open_s3] and cached under the mount path as usual. Files created or modified through
+    /// the mount are uploaded with [OpenOptions::write_s3] once they are flushed or closed.
+    ///
+    /// `mountpoint` must be an existing, empty directory, and must not be inside the mount path used for
+    /// caching. New directories only exist locally until a file is written into them, as S3 has no concept
+    /// of an empty directory. Deleting and renaming are not supported. If [OpenOptions::read_only] is set the
+    /// filesystem is mounted read only.
+    ///
+    /// This must be called from within a multi-threaded Tokio runtime, which is used to drive the S3
+    /// requests made on behalf of the filesystem.
+    ///
+    /// # Arguments
+    /// * `mountpoint`: The local directory the bucket should appear at.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let mount = open_options.mount_fuse("/mnt/my_bucket").await.unwrap();
+    ///
+    ///     tokio::signal::ctrl_c().await.unwrap();
+    ///
+    ///     mount.unmount().unwrap();
+    /// }
+    /// ```
+    pub async fn mount_fuse<P>(&self, mountpoint: P) -> Result<S3Mount, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let owner = std::fs::metadata(&mountpoint)?;
+
+        let mut tree = Tree::new(owner.uid(), owner.gid());
+        for entry in self.walkdir("").await? {
+            if let Some(key) = entry.path.to_str() {
+                tree.insert_key(key, entry.size.max(0) as u64);
+            }
+        }
+
+        let filesystem = S3Fuse {
+            options: self.clone(),
+            runtime: Handle::current(),
+            state: Mutex::new(State {
+                tree,
+                handles: HashMap::new(),
+                next_handle: 1,
+            }),
+        };
+
+        let mut config = Config::default();
+        config.mount_options = vec![
+            MountOption::FSName(format!("s3-filesystem:{}", self.bucket)),
+            MountOption::Subtype("s3".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        if self.read_only {
+            config.mount_options.push(MountOption::RO);
+        }
+
+        let session = fuser::spawn_mount(filesystem, mountpoint, &config)?;
+
+        Ok(S3Mount { session })
+    }
+}
+
+/// A file or directory in the mounted tree.
+struct Node {
+    key: String,
+    kind: FileType,
+    size: u64,
+    parent: u64,
+    children: BTreeMap<String, u64>,
+    /// A truncation requested while the file was not open, applied when it is next opened.
+    pending_truncate: Option<u64>,
+}
+
+/// The inode table for the mount, built from a bucket listing.
+struct Tree {
+    nodes: HashMap<u64, Node>,
+    next_ino: u64,
+    uid: u32,
+    gid: u32,
+    mounted_at: SystemTime,
+}
+
+impl Tree {
+    fn new(uid: u32, gid: u32) -> Self {
+        let root = Node {
+            key: String::new(),
+            kind: FileType::Directory,
+            size: 0,
+            parent: INodeNo::ROOT.0,
+            children: BTreeMap::new(),
+            pending_truncate: None,
+        };
+
+        Tree {
+            nodes: HashMap::from([(INodeNo::ROOT.0, root)]),
+            next_ino: INodeNo::ROOT.0 + 1,
+            uid,
+            gid,
+            mounted_at: SystemTime::now(),
+        }
+    }
+
+    /// Add an S3 key, creating any parent directories. Keys ending in "/" are folder markers.
+    fn insert_key(&mut self, key: &str, size: u64) {
+        let is_folder = key.ends_with('/');
+        let segments: Vec<&str> = key.split('/').filter(|s| !s.is_empty()).collect();
+
+        let mut parent = INodeNo::ROOT.0;
+        for (index, segment) in segments.iter().enumerate() {
+            let is_last = index + 1 == segments.len();
+            let kind = if is_last && !is_folder {
+                FileType::RegularFile
+            } else {
+                FileType::Directory
+            };
+
+            parent = match self.child(parent, segment) {
+                Some(ino) => ino,
+                None => self.add_child(parent, segment, kind),
+            };
+
+            if is_last && !is_folder {
+                if let Some(node) = self.nodes.get_mut(&parent) {
+                    node.size = size;
+                }
+            }
+        }
+    }
+
+    fn child(&self, parent: u64, name: &str) -> Option<u64> {
+        self.nodes.get(&parent)?.children.get(name).copied()
+    }
+
+    fn add_child(&mut self, parent: u64, name: &str, kind: FileType) -> u64 {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+
+        let key = match self.nodes.get(&parent) {
+            Some(node) if !node.key.is_empty() => format!("{}/{}", node.key, name),
+            _ => name.to_string(),
+        };
+
+        self.nodes.insert(
+            ino,
+            Node {
+                key,
+                kind,
+                size: 0,
+                parent,
+                children: BTreeMap::new(),
+                pending_truncate: None,
+            },
+        );
+
+        if let Some(node) = self.nodes.get_mut(&parent) {
+            node.children.insert(name.to_string(), ino);
+        }
+
+        ino
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let node = self.nodes.get(&ino)?;
+        let (perm, nlink) = match node.kind {
+            FileType::Directory => (0o755, 2),
+            _ => (0o644, 1),
+        };
+
+        Some(FileAttr {
+            ino: INodeNo(ino),
+            size: node.size,
+            blocks: node.size.div_ceil(512),
+            atime: self.mounted_at,
+            mtime: self.mounted_at,
+            ctime: self.mounted_at,
+            crtime: self.mounted_at,
+            kind: node.kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+/// A file opened through the mount, backed by its locally cached copy.
+struct OpenFile {
+    ino: u64,
+    file: std::fs::File,
+    dirty: bool,
+}
+
+struct State {
+    tree: Tree,
+    handles: HashMap<u64, OpenFile>,
+    next_handle: u64,
+}
+
+/// The [Filesystem] implementation handed to FUSE.
+struct S3Fuse {
+    options: OpenOptions,
+    runtime: Handle,
+    state: Mutex<State>,
+}
+
+impl S3Fuse {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Open the locally cached copy of `key`, downloading it first if needed.
+    fn open_local(&self, key: &str) -> io::Result<std::fs::File> {
+        self.runtime
+            .block_on(self.options.open_s3(key))
+            .map_err(io::Error::from)?;
+
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.options.local_path(key).map_err(io::Error::from)?)
+    }
+
+    /// Upload the local copy of an open file if it has been written to.
+    fn upload(&self, fh: u64) -> Result<(), Errno> {
+        let (key, mut file) = {
+            let mut state = self.state();
+            let (ino, file) = match state.handles.get_mut(&fh) {
+                Some(handle) if handle.dirty => {
+                    handle.dirty = false;
+                    (handle.ino, handle.file.try_clone()?)
+                }
+                Some(_) => return Ok(()),
+                None => return Err(Errno::EBADF),
+            };
+            match state.tree.nodes.get(&ino) {
+                Some(node) => (node.key.clone(), file),
+                None => return Err(Errno::ENOENT),
+            }
+        };
+
+        let mut contents = Vec::new();
+        file.seek(io::SeekFrom::Start(0))?;
+        file.read_to_end(&mut contents)?;
+
+        self.runtime
+            .block_on(self.options.write_s3(&key, &contents))
+            .map(|_| ())
+            .map_err(|_| Errno::EIO)
+    }
+
+    fn add_handle(&self, state: &mut State, ino: u64, file: std::fs::File, dirty: bool) -> u64 {
+        let fh = state.next_handle;
+        state.next_handle += 1;
+        state.handles.insert(fh, OpenFile { ino, file, dirty });
+        fh
+    }
+}
+
+impl Filesystem for S3Fuse {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let state = self.state();
+        let attr = name
+            .to_str()
+            .and_then(|name| state.tree.child(parent.0, name))
+            .and_then(|ino| state.tree.attr(ino));
+
+        match attr {
+            Some(attr) => reply.entry(&TTL, &attr, Generation(0)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.state().tree.attr(ino.0) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn setattr(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<FileHandle>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<fuser::BsdFileFlags>,
+        reply: ReplyAttr,
+    ) {
+        let mut state = self.state();
+
+        if let Some(size) = size {
+            match fh.and_then(|fh| state.handles.get_mut(&fh.0)) {
+                Some(handle) => {
+                    if let Err(e) = handle.file.set_len(size) {
+                        return reply.error(e.into());
+                    }
+                    handle.dirty = true;
+                }
+                None => {
+                    if let Some(node) = state.tree.nodes.get_mut(&ino.0) {
+                        node.pending_truncate = Some(size);
+                    }
+                }
+            }
+
+            if let Some(node) = state.tree.nodes.get_mut(&ino.0) {
+                node.size = size;
+            }
+        }
+
+        match state.tree.attr(ino.0) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn mkdir(
+        &self,
+        _req: &Request,
+        parent: INodeNo,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let mut state = self.state();
+        let Some(name) = name.to_str() else {
+            return reply.error(Errno::EINVAL);
+        };
+        if state.tree.child(parent.0, name).is_some() {
+            return reply.error(Errno::EEXIST);
+        }
+
+        let ino = state.tree.add_child(parent.0, name, FileType::Directory);
+        match state.tree.attr(ino) {
+            Some(attr) => reply.entry(&TTL, &attr, Generation(0)),
+            None => reply.error(Errno::EIO),
+        }
+    }
+
+    fn open(&self, _req: &Request, ino: INodeNo, _flags: OpenFlags, reply: ReplyOpen) {
+        let (key, pending_truncate) = match self.state().tree.nodes.get(&ino.0) {
+            Some(node) if node.kind == FileType::Directory => return reply.error(Errno::EISDIR),
+            Some(node) => (node.key.clone(), node.pending_truncate),
+            None => return reply.error(Errno::ENOENT),
+        };
+
+        let file = match self.open_local(&key) {
+            Ok(file) => file,
+            Err(_) => return reply.error(Errno::EIO),
+        };
+
+        let mut state = self.state();
+        if let Some(size) = pending_truncate {
+            if let Err(e) = file.set_len(size) {
+                return reply.error(e.into());
+            }
+        }
+
+        if let Some(node) = state.tree.nodes.get_mut(&ino.0) {
+            node.pending_truncate = None;
+            if let Ok(metadata) = file.metadata() {
+                node.size = metadata.len();
+            }
+        }
+
+        let fh = self.add_handle(&mut state, ino.0, file, pending_truncate.is_some());
+        reply.opened(FileHandle(fh), FopenFlags::empty());
+    }
+
+    fn create(
+        &self,
+        _req: &Request,
+        parent: INodeNo,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let mut state = self.state();
+        let Some(name) = name.to_str() else {
+            return reply.error(Errno::EINVAL);
+        };
+        if state.tree.child(parent.0, name).is_some() {
+            return reply.error(Errno::EEXIST);
+        }
+
+        let ino = state.tree.add_child(parent.0, name, FileType::RegularFile);
+        let local_path = match state
+            .tree
+            .nodes
+            .get(&ino)
+            .map(|node| self.options.local_path(&node.key))
+        {
+            Some(Ok(local_path)) => local_path,
+            _ => return reply.error(Errno::EIO),
+        };
+
+        let file = local_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&local_path)
+            });
+
+        match (file, state.tree.attr(ino)) {
+            (Ok(file), Some(attr)) => {
+                let fh = self.add_handle(&mut state, ino, file, true);
+                reply.created(
+                    &TTL,
+                    &attr,
+                    Generation(0),
+                    FileHandle(fh),
+                    FopenFlags::empty(),
+                );
+            }
+            (Err(e), _) => reply.error(e.into()),
+            (_, None) => reply.error(Errno::EIO),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let state = self.state();
+        let Some(handle) = state.handles.get(&fh.0) else {
+            return reply.error(Errno::EBADF);
+        };
+
+        let mut buf = vec![0; size as usize];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match handle
+                .file
+                .read_at(&mut buf[filled..], offset + filled as u64)
+            {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return reply.error(e.into()),
+            }
+        }
+
+        reply.data(&buf[..filled]);
+    }
+
+    fn write(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        fh: FileHandle,
+        offset: u64,
+        data: &[u8],
+        _write_flags: WriteFlags,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyWrite,
+    ) {
+        let mut state = self.state();
+        let Some(handle) = state.handles.get_mut(&fh.0) else {
+            return reply.error(Errno::EBADF);
+        };
+
+        if let Err(e) = handle.file.write_all_at(data, offset) {
+            return reply.error(e.into());
+        }
+        handle.dirty = true;
+
+        if let Some(node) = state.tree.nodes.get_mut(&ino.0) {
+            node.size = node.size.max(offset + data.len() as u64);
+        }
+
+        reply.written(data.len() as u32);
+    }
+
+    fn flush(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        _lock_owner: LockOwner,
+        reply: ReplyEmpty,
+    ) {
+        match self.upload(fh.0) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn release(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        let result = self.upload(fh.0);
+        self.state().handles.remove(&fh.0);
+
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let state = self.state();
+        let Some(node) = state.tree.nodes.get(&ino.0) else {
+            return reply.error(Errno::ENOENT);
+        };
+        if node.kind != FileType::Directory {
+            return reply.error(Errno::ENOTDIR);
+        }
+
+        let mut entries = vec![
+            (ino.0, FileType::Directory, "."),
+            (node.parent, FileType::Directory, ".."),
+        ];
+        for (name, child) in &node.children {
+            if let Some(child_node) = state.tree.nodes.get(child) {
+                entries.push((*child, child_node.kind, name.as_str()));
+            }
+        }
+
+        for (index, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(INodeNo(ino), (index + 1) as u64, kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_become_inodes_under_their_folders() {
+        let mut tree = Tree::new(1000, 100);
+        tree.insert_key("reports/2024/summary.csv", 42);
+        tree.insert_key("reports/readme.txt", 7);
+        tree.insert_key("empty/", 0);
+
+        let reports = tree.child(INodeNo::ROOT.0, "reports").unwrap();
+        let year = tree.child(reports, "2024").unwrap();
+        let summary = tree.child(year, "summary.csv").unwrap();
+        assert_eq!(tree.child(reports, "readme.txt"), Some(summary + 1));
+        assert_eq!(tree.nodes[&summary].key, "reports/2024/summary.csv");
+        assert_eq!(tree.nodes[&summary].parent, year);
+        assert_eq!(
+            tree.nodes[&reports].children.keys().collect::<Vec<_>>(),
+            ["2024", "readme.txt"]
+        );
+
+        let empty = tree.child(INodeNo::ROOT.0, "empty").unwrap();
+        assert_eq!(tree.nodes[&empty].kind, FileType::Directory);
+        assert!(tree.nodes[&empty].children.is_empty());
+
+        // Listing the same key again reuses its inode.
+        tree.insert_key("reports/readme.txt", 9);
+        assert_eq!(tree.child(reports, "readme.txt"), Some(summary + 1));
+        assert_eq!(tree.nodes[&(summary + 1)].size, 9);
+    }
+
+    #[test]
+    fn test_attributes_follow_the_node() {
+        let mut tree = Tree::new(1000, 100);
+        tree.insert_key("data/large.bin", 1025);
+        let data = tree.child(INodeNo::ROOT.0, "data").unwrap();
+        let large = tree.child(data, "large.bin").unwrap();
+
+        let file = tree.attr(large).unwrap();
+        assert_eq!(file.ino, INodeNo(large));
+        assert_eq!(file.kind, FileType::RegularFile);
+        assert_eq!((file.size, file.blocks), (1025, 3));
+        assert_eq!((file.perm, file.nlink), (0o644, 1));
+        assert_eq!((file.uid, file.gid), (1000, 100));
+        assert_eq!(file.mtime, tree.mounted_at);
+
+        let folder = tree.attr(data).unwrap();
+        assert_eq!(folder.kind, FileType::Directory);
+        assert_eq!((folder.perm, folder.nlink, folder.size), (0o755, 2, 0));
+
+        let root = tree.attr(INodeNo::ROOT.0).unwrap();
+        assert_eq!(root.kind, FileType::Directory);
+        assert!(tree.attr(large + 1).is_none());
+    }
+}
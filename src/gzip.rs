@@ -0,0 +1,417 @@
+//! A small gzip decoder for the compressed reports S3 Inventory writes, objects stored with
+//! `Content-Encoding: gzip`, and deflated zip members.
+//!
+//! Only decompression is needed, so this follows the layout of RFC 1952 (gzip) and RFC 1951 (DEFLATE)
+//! directly rather than pulling in a compression crate. Input is read as it is needed and output is written
+//! out as it is produced, holding back only the last 32 KiB DEFLATE can refer to, so large objects can be
+//! decompressed from one file into another without being held in memory.
+use std::io::{self, BufRead, Write};
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are stored in for dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// How far back DEFLATE can copy from.
+const WINDOW: usize = 32 * 1024;
+
+/// The CRC-32 of each byte value, for the checksum in every gzip trailer.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// Decompress a gzip stream from `input` into `output`, returning how many bytes were written. Files made
+/// of several gzip members one after another are decompressed whole.
+pub(crate) fn decompress_to<R, W>(mut input: R, output: W) -> io::Result<u64>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut output = Window::new(output);
+
+    while !input.fill_buf()?.is_empty() {
+        let mut reader = BitReader::new(&mut input);
+        skip_header(&mut reader)?;
+
+        output.start_member();
+        inflate(&mut reader, &mut output)?;
+
+        let crc = reader.u32_le("gzip trailer is truncated")?;
+        let size = reader.u32_le("gzip trailer is truncated")?;
+        if output.member_crc() != crc || output.member_size as u32 != size {
+            return Err(invalid("gzip checksum does not match its contents"));
+        }
+    }
+
+    output.finish().map(|(_, size)| size)
+}
+
+/// Decompress raw DEFLATE data, as zip archives store it.
+pub(crate) fn inflate_raw(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Window::new(Vec::new());
+    inflate(&mut BitReader::new(data), &mut output)?;
+    output.finish().map(|(output, _)| output)
+}
+
+/// Skip a gzip member header, leaving the reader at its compressed data.
+fn skip_header<R: BufRead>(reader: &mut BitReader<R>) -> io::Result<()> {
+    const TRUNCATED: &str = "gzip header is truncated";
+
+    let mut header = [0; 10];
+    reader.read_exact(&mut header, TRUNCATED)?;
+    if header[0] != 0x1f || header[1] != 0x8b || header[2] != 8 {
+        return Err(invalid("not a gzip file"));
+    }
+    let flags = header[3];
+
+    if flags & 0x04 != 0 {
+        let mut length = [0; 2];
+        reader.read_exact(&mut length, TRUNCATED)?;
+        for _ in 0..u16::from_le_bytes(length) {
+            reader.byte(TRUNCATED)?;
+        }
+    }
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            while reader.byte(TRUNCATED)? != 0 {}
+        }
+    }
+    if flags & 0x02 != 0 {
+        reader.read_exact(&mut [0; 2], TRUNCATED)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a DEFLATE stream least significant bit first.
+struct BitReader<R> {
+    input: R,
+    buffer: u32,
+    count: u32,
+}
+
+impl<R: BufRead> BitReader<R> {
+    fn new(input: R) -> Self {
+        BitReader {
+            input,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// The next whole byte of input, failing with `truncated` if there is none. Only valid when no bits
+    /// are buffered, at the start or after [BitReader::align].
+    fn byte(&mut self, truncated: &str) -> io::Result<u8> {
+        let mut byte = [0];
+        self.read_exact(&mut byte, truncated)?;
+        Ok(byte[0])
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8], truncated: &str) -> io::Result<()> {
+        match self.input.read_exact(buf) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(invalid(truncated)),
+            result => result,
+        }
+    }
+
+    fn u32_le(&mut self, truncated: &str) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.read_exact(&mut bytes, truncated)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn bits(&mut self, needed: u32) -> io::Result<u32> {
+        while self.count < needed {
+            let byte = self.byte("compressed data is truncated")?;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1 << needed) - 1);
+        self.buffer >>= needed;
+        self.count -= needed;
+        Ok(value)
+    }
+
+    /// Drop any bits left in the current byte.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// Where decompressed data goes: written on to `output`, except for the most recent bytes, which are held
+/// back for later codes to copy from.
+struct Window<W> {
+    output: W,
+    recent: Vec<u8>,
+    /// Bytes written on to `output` so far.
+    written: u64,
+    /// The running CRC-32 and size of the current gzip member.
+    crc: u32,
+    member_size: u64,
+}
+
+impl<W: Write> Window<W> {
+    fn new(output: W) -> Self {
+        Window {
+            output,
+            recent: Vec::with_capacity(4 * WINDOW),
+            written: 0,
+            crc: !0,
+            member_size: 0,
+        }
+    }
+
+    fn start_member(&mut self) {
+        self.crc = !0;
+        self.member_size = 0;
+    }
+
+    fn member_crc(&self) -> u32 {
+        !self.crc
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.recent.push(byte);
+        self.crc = CRC_TABLE[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        self.member_size += 1;
+    }
+
+    /// Repeat `length` bytes starting `distance` back from the end of the output.
+    fn copy(&mut self, distance: usize, length: usize) -> io::Result<()> {
+        if distance as u64 > self.written + self.recent.len() as u64 {
+            return Err(invalid("distance reaches before the start of the data"));
+        }
+
+        let start = self.recent.len() - distance;
+        for offset in 0..length {
+            self.push(self.recent[start + offset]);
+        }
+        Ok(())
+    }
+
+    /// Write on everything but the last [WINDOW] bytes once enough has built up.
+    fn spill(&mut self) -> io::Result<()> {
+        if self.recent.len() >= 4 * WINDOW {
+            let excess = self.recent.len() - WINDOW;
+            self.output.write_all(&self.recent[..excess])?;
+            self.recent.drain(..excess);
+            self.written += excess as u64;
+        }
+        Ok(())
+    }
+
+    /// Write on whatever is left, returning the output and how many bytes it was given in all.
+    fn finish(mut self) -> io::Result<(W, u64)> {
+        self.output.write_all(&self.recent)?;
+        self.output.flush()?;
+        Ok((self.output, self.written + self.recent.len() as u64))
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode<R: BufRead>(&self, reader: &mut BitReader<R>) -> io::Result<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("invalid Huffman code in compressed data"))
+    }
+}
+
+fn inflate<R: BufRead, W: Write>(
+    reader: &mut BitReader<R>,
+    output: &mut Window<W>,
+) -> io::Result<()> {
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => stored(reader, output)?,
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                codes(
+                    reader,
+                    output,
+                    &Huffman::new(&lengths),
+                    &Huffman::new(&[5; 30]),
+                )?
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(reader)?;
+                codes(reader, output, &literals, &distances)?
+            }
+            _ => return Err(invalid("invalid block type in compressed data")),
+        }
+
+        if last {
+            reader.align();
+            return Ok(());
+        }
+    }
+}
+
+fn stored<R: BufRead, W: Write>(
+    reader: &mut BitReader<R>,
+    output: &mut Window<W>,
+) -> io::Result<()> {
+    reader.align();
+    let length = reader.bits(16)? as usize;
+    let complement = reader.bits(16)? as usize;
+    if length != !complement & 0xffff {
+        return Err(invalid("stored block length does not match its complement"));
+    }
+
+    for _ in 0..length {
+        let byte = reader.byte("compressed data is truncated")?;
+        output.push(byte);
+    }
+    output.spill()
+}
+
+fn dynamic_tables<R: BufRead>(reader: &mut BitReader<R>) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeated code length with nothing to repeat"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return Err(invalid("too many code lengths in compressed data"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn codes<R: BufRead, W: Write>(
+    reader: &mut BitReader<R>,
+    output: &mut Window<W>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(invalid("invalid length code in compressed data"));
+                }
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(invalid("invalid distance code in compressed data"));
+                }
+                let distance = DISTANCE_BASE[index] as usize
+                    + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                output.copy(distance, length)?;
+            }
+        }
+        output.spill()?;
+    }
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
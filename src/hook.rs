@@ -0,0 +1,116 @@
+//! Callbacks run on the S3 client's requests and responses.
+use std::{fmt, sync::Arc};
+
+use aws_smithy_runtime_api::{
+    box_error::BoxError,
+    client::{
+        interceptors::{
+            context::{
+                BeforeDeserializationInterceptorContextRef, BeforeTransmitInterceptorContextMut,
+            },
+            Intercept,
+        },
+        orchestrator::{HttpRequest, HttpResponse},
+        runtime_components::RuntimeComponents,
+    },
+};
+use aws_smithy_types::config_bag::ConfigBag;
+
+use crate::OpenOptions;
+
+/// Sees every request the S3 client sends and every response it receives, installed with
+/// [OpenOptions::request_hook].
+///
+/// Both methods do nothing by default, so implement only the ones needed. Retries call them again for
+/// each attempt.
+pub trait RequestHook: Send + Sync {
+    /// Change a request before it is signed, so headers added here are covered by the signature.
+    fn before_send(&self, request: &mut HttpRequest) {
+        let _ = request;
+    }
+
+    /// Look at a response as it arrives, before the SDK parses it. Object bodies are still streaming, so
+    /// only the status and headers are available for downloads.
+    fn after_response(&self, response: &HttpResponse) {
+        let _ = response;
+    }
+}
+
+/// Runs a [RequestHook] as an SDK interceptor.
+struct HookInterceptor(Arc<dyn RequestHook>);
+
+impl fmt::Debug for HookInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HookInterceptor")
+    }
+}
+
+impl Intercept for HookInterceptor {
+    fn name(&self) -> &'static str {
+        "s3-filesystem RequestHook"
+    }
+
+    fn modify_before_signing(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.0.before_send(context.request_mut());
+        Ok(())
+    }
+
+    fn read_before_deserialization(
+        &self,
+        context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.0.after_response(context.response());
+        Ok(())
+    }
+}
+
+impl OpenOptions {
+    /// Run `hook` on every request sent to S3 and every response received
+    ///
+    /// Lets requests be changed, for instance to add headers a proxy or bucket policy expects, and
+    /// responses be observed, without building a customised client to pass to [OpenOptions::new]. Hooks
+    /// are added to those already installed and run in the order they were added.
+    ///
+    /// Hooks are set on the S3 client, so they do not apply to a backend installed with
+    /// [OpenOptions::backend].
+    ///
+    /// # Arguments
+    /// * `hook`: The callbacks to run.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{HttpRequest, HttpResponse, OpenOptions, RequestHook};
+    /// use std::sync::Arc;
+    ///
+    /// struct TagRequests;
+    ///
+    /// impl RequestHook for TagRequests {
+    ///     fn before_send(&self, request: &mut HttpRequest) {
+    ///         request.headers_mut().insert("x-team", "data-platform");
+    ///     }
+    ///
+    ///     fn after_response(&self, response: &HttpResponse) {
+    ///         println!("S3 answered {}", response.status());
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .request_hook(Arc::new(TagRequests));
+    /// }
+    /// ```
+    pub fn request_hook(self, hook: Arc<dyn RequestHook>) -> Self {
+        self.reconfigure_client(|config| config.interceptor(HookInterceptor(hook)))
+    }
+}
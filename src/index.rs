@@ -0,0 +1,387 @@
+//! A persistent record of what is in the local mirror.
+//!
+//! The index lives at `<mount_path>/.s3-filesystem/<bucket>.index`, outside the bucket's own folder so it
+//! can never clash with an object key. It starts with a header naming the generation of the file, and
+//! every change after that is appended as a line of its own, the last line for a key winning:
+//!
+//! ```text
+//! # s3-filesystem index <generation>
+//! <cached at, seconds since the unix epoch>\t<size>\t<etag, or - if unknown>\t<checksum, or ->\t<key>
+//! -\t<key removed from the index>
+//! ```
+//!
+//! The time is written to the nanosecond, as `<seconds>.<nanoseconds>`, though older lines hold whole
+//! seconds. Checksums are written as `<algorithm>:<value>`. Keys are escaped as they are in the mirror, so tabs
+//! and newlines in keys cannot break a line. Once most lines have been superseded, the index is compacted
+//! by writing the live records to a temporary file under a new generation and renaming it over the old
+//! one. Files without a header were written before changes were appended and hold unescaped keys, one
+//! line per key, with no checksum field on the oldest lines. They are compacted on the first change.
+//!
+//! Changes are made while holding a lock on `<bucket>.index.lock`, and each access first reads whatever
+//! other processes have appended since, so separately constructed OpenOptions and other processes can
+//! share a mount path.
+use std::{
+    collections::BTreeMap,
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use crate::{
+    journal::Journal,
+    key::s3_key,
+    lock::lock_file,
+    options::{bucket_folder, escape_key, unescape_key},
+    write_back::PendingWrites,
+    ObjectChecksum, OpenOptions, S3FilesystemError,
+};
+
+/// Folder under the mount path that holds the index and saved listings for each bucket.
+pub(crate) const INDEX_DIR: &str = ".s3-filesystem";
+
+/// Start of the first line of an index which changes are appended to.
+const HEADER: &str = "# s3-filesystem index ";
+
+/// Fewest lines an index has before it is compacted, so small indexes are never rewritten.
+const COMPACT_AFTER: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An object held in the local mirror, as recorded in the cache index.
+pub struct CachedObject {
+    /// The object's key.
+    pub key: String,
+    /// The ETag S3 reported when the object was downloaded or uploaded, if it sent one.
+    pub e_tag: Option<String>,
+    /// The additional checksum the object was uploaded with, if [OpenOptions::upload_checksum] was set.
+    pub checksum: Option<ObjectChecksum>,
+    /// The number of bytes cached.
+    pub size: u64,
+    /// When the object was downloaded or uploaded.
+    pub cached_at: SystemTime,
+}
+
+/// The on-disk index for one bucket, brought up to date with the file before every use.
+#[derive(Debug)]
+pub(crate) struct CacheIndex {
+    path: PathBuf,
+    records: Mutex<Records>,
+    /// Files written with [OpenOptions::write_back] and not yet flushed.
+    pub(crate) pending: PendingWrites,
+    /// Uploads and downloads in progress, for [OpenOptions::recover].
+    pub(crate) journal: Journal,
+}
+
+/// The index as read so far.
+#[derive(Debug, Default)]
+struct Records {
+    objects: BTreeMap<String, CachedObject>,
+    /// The generation named in the header of the file read, or None for a file without a header.
+    generation: Option<String>,
+    /// How many bytes of the file have been read.
+    offset: u64,
+    /// How many records and removals the file holds.
+    lines: usize,
+}
+
+impl CacheIndex {
+    pub(crate) fn new(mount_path: &Path, bucket: &str) -> Self {
+        CacheIndex {
+            path: mount_path
+                .join(INDEX_DIR)
+                .join(format!("{}.index", bucket_folder(bucket))),
+            records: Mutex::new(Records::default()),
+            pending: PendingWrites::new(mount_path, bucket),
+            journal: Journal::new(mount_path, bucket),
+        }
+    }
+
+    pub(crate) async fn get(&self, key: &str) -> io::Result<Option<CachedObject>> {
+        let mut records = self.records.lock().await;
+        self.refresh(&mut records).await?;
+        Ok(records.objects.get(key).cloned())
+    }
+
+    pub(crate) async fn all(&self) -> io::Result<Vec<CachedObject>> {
+        let mut records = self.records.lock().await;
+        self.refresh(&mut records).await?;
+        Ok(records.objects.values().cloned().collect())
+    }
+
+    pub(crate) async fn insert(&self, object: CachedObject) -> io::Result<()> {
+        let mut records = self.records.lock().await;
+        let _lock = lock_file(self.path.with_extension("index.lock")).await?;
+        self.refresh(&mut records).await?;
+
+        let line = record_line(&object);
+        records.objects.insert(object.key.clone(), object);
+        self.append(&mut records, vec![line]).await
+    }
+
+    pub(crate) async fn remove(&self, key: &str) -> io::Result<()> {
+        self.remove_matching(|cached| cached == key).await
+    }
+
+    pub(crate) async fn remove_prefix(&self, prefix: &str) -> io::Result<()> {
+        self.remove_matching(|key| key.starts_with(prefix)).await
+    }
+
+    async fn remove_matching<F>(&self, matches: F) -> io::Result<()>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut records = self.records.lock().await;
+        let _lock = lock_file(self.path.with_extension("index.lock")).await?;
+        self.refresh(&mut records).await?;
+
+        let removed: Vec<String> = records
+            .objects
+            .keys()
+            .filter(|key| matches(key))
+            .cloned()
+            .collect();
+        if removed.is_empty() {
+            return Ok(());
+        }
+        for key in &removed {
+            records.objects.remove(key);
+        }
+        let lines = removed
+            .iter()
+            .map(|key| format!("-\t{}\n", escape_key(key)))
+            .collect();
+        self.append(&mut records, lines).await
+    }
+
+    /// Read whatever has been appended to the index since it was last read, or all of it again if it has
+    /// been compacted or removed since.
+    async fn refresh(&self, records: &mut Records) -> io::Result<()> {
+        let mut file = match tokio::fs::File::open(&self.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                *records = Records::default();
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut first_line = vec![0; HEADER.len() + 64];
+        let read = read_up_to(&mut file, &mut first_line).await?;
+        first_line.truncate(read);
+        let generation = first_line
+            .split(|&byte| byte == b'\n')
+            .next()
+            .and_then(|line| std::str::from_utf8(line).ok())
+            .and_then(|line| line.strip_prefix(HEADER))
+            .map(str::to_string);
+
+        let length = file.metadata().await?.len();
+        if generation != records.generation || length < records.offset {
+            *records = Records {
+                generation,
+                ..Records::default()
+            };
+        }
+        if length == records.offset {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(records.offset)).await?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended).await?;
+        // A line still being appended by another process is left for the next read.
+        let complete = match appended.iter().rposition(|&byte| byte == b'\n') {
+            Some(last) => last + 1,
+            None => return Ok(()),
+        };
+        records.offset += complete as u64;
+
+        let escaped = records.generation.is_some();
+        for line in String::from_utf8_lossy(&appended[..complete]).lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(key) = line.strip_prefix("-\t") {
+                records.objects.remove(&unescape_key(key));
+                records.lines += 1;
+            } else if let Some(mut object) = parse_line(line) {
+                if escaped {
+                    object.key = unescape_key(&object.key);
+                }
+                records.objects.insert(object.key.clone(), object);
+                records.lines += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Append `lines` to the index, or compact it if the file has no header or mostly holds lines which
+    /// have since been superseded. Must be called holding the index lock, straight after a refresh.
+    async fn append(&self, records: &mut Records, lines: Vec<String>) -> io::Result<()> {
+        let total = records.lines + lines.len();
+        if records.generation.is_none()
+            || (total > COMPACT_AFTER && total > 2 * records.objects.len())
+        {
+            return self.compact(records).await;
+        }
+
+        let contents = lines.concat();
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(contents.as_bytes()).await?;
+        file.flush().await?;
+        records.offset += contents.len() as u64;
+        records.lines = total;
+        Ok(())
+    }
+
+    /// Write the live records to a temporary file under a new generation and rename it over the index, so a
+    /// crash never leaves a half written index behind.
+    async fn compact(&self, records: &mut Records) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let generation = format!("{}-{}", since_epoch.as_nanos(), process::id());
+        let mut contents = format!("{}{}\n", HEADER, generation);
+        for object in records.objects.values() {
+            contents.push_str(&record_line(object));
+        }
+
+        let temporary = self.path.with_extension("index.tmp");
+        tokio::fs::write(&temporary, &contents).await?;
+        tokio::fs::rename(&temporary, &self.path).await?;
+
+        records.generation = Some(generation);
+        records.offset = contents.len() as u64;
+        records.lines = records.objects.len();
+        Ok(())
+    }
+}
+
+/// Read into `buf` until it is full or the file ends, returning how many bytes were read.
+pub(crate) async fn read_up_to(file: &mut tokio::fs::File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+fn record_line(object: &CachedObject) -> String {
+    let cached_at = object
+        .cached_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let checksum = object
+        .checksum
+        .as_ref()
+        .map_or_else(|| "-".to_string(), ObjectChecksum::to_string);
+    format!(
+        "{}.{:09}\t{}\t{}\t{}\t{}\n",
+        cached_at.as_secs(),
+        cached_at.subsec_nanos(),
+        object.size,
+        object.e_tag.as_deref().unwrap_or("-"),
+        checksum,
+        escape_key(&object.key)
+    )
+}
+
+fn parse_line(line: &str) -> Option<CachedObject> {
+    let mut fields = line.splitn(4, '\t');
+    let cached_at = fields.next()?;
+    let cached_at = match cached_at.split_once('.') {
+        Some((seconds, nanoseconds)) => {
+            Duration::new(seconds.parse().ok()?, nanoseconds.parse().ok()?)
+        }
+        None => Duration::from_secs(cached_at.parse().ok()?),
+    };
+    let size = fields.next()?.parse().ok()?;
+    let e_tag = match fields.next()? {
+        "-" => None,
+        e_tag => Some(e_tag.to_string()),
+    };
+    let rest = fields.next()?;
+    let (checksum, key) = match rest.split_once('\t') {
+        Some(("-", key)) => (None, key),
+        Some((checksum, key)) => match checksum.parse() {
+            Ok(checksum) => (Some(checksum), key),
+            Err(_) => (None, rest),
+        },
+        None => (None, rest),
+    };
+
+    Some(CachedObject {
+        key: key.to_string(),
+        e_tag,
+        checksum,
+        size,
+        cached_at: UNIX_EPOCH + cached_at,
+    })
+}
+
+impl OpenOptions {
+    /// Look up a key in the cache index
+    ///
+    /// Returns the ETag, size and time recorded when the object was last downloaded or uploaded
+    /// through this mount path, or None if it is not in the index. Files placed in the mount path by
+    /// other means are not indexed.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the object.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     if let Some(cached) = open_options.cached_object("some_folder/some_file.csv").await.unwrap() {
+    ///         println!("Cached {} bytes with ETag {:?}", cached.size, cached.e_tag);
+    ///     }
+    /// }
+    /// ```
+    pub async fn cached_object<P>(&self, path: P) -> Result<Option<CachedObject>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let key = s3_key(path.as_ref())?;
+        Ok(self.cache_index.get(&key).await?)
+    }
+
+    /// Every object in the cache index, in key order.
+    pub async fn cached_objects(&self) -> Result<Vec<CachedObject>, S3FilesystemError> {
+        Ok(self.cache_index.all().await?)
+    }
+
+    /// Remove a key from the local mirror and the cache index, ignoring it if it was never cached.
+    pub(crate) async fn evict(&self, key: &str) -> Result<(), S3FilesystemError> {
+        match tokio::fs::remove_file(self.local_path(key)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        self.evict_blocks(key).await?;
+        self.evict_sparse(key).await?;
+        Ok(self.cache_index.remove(key).await?)
+    }
+}
@@ -0,0 +1,541 @@
+//! Listing objects from S3 Inventory reports instead of live ListObjectsV2 requests.
+//!
+//! An inventory is described by a `manifest.json` written alongside the report files. Only CSV reports are
+//! read; ORC and Parquet inventories are rejected with [io::ErrorKind::Unsupported].
+//!
+//! Report files are streamed. The download is handed chunk by chunk to a blocking thread, which
+//! decompresses and parses it and sends each entry on as soon as its row is complete, so only a few
+//! chunks of a report are held in memory however large it is.
+use std::{
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+    time::SystemTime,
+};
+
+use aws_smithy_types::{date_time::Format, DateTime};
+use bytes::{Buf, Bytes};
+use serde_json::Value;
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use crate::{
+    gzip,
+    options::{decode_key, is_access_point},
+    walk::Selection,
+    DirEntry, OpenOptions, S3FilesystemError,
+};
+
+/// How many downloaded chunks of a report may wait for the parser.
+const CHUNK_BUFFER: usize = 8;
+
+/// How many parsed entries may wait to be read from a report's stream.
+const ENTRY_BUFFER: usize = 1024;
+
+/// Where each field sits in a report row, taken from the manifest's `fileSchema`.
+#[derive(Debug, Clone)]
+struct Schema {
+    key: usize,
+    size: Option<usize>,
+    last_modified: Option<usize>,
+    e_tag: Option<usize>,
+    storage_class: Option<usize>,
+    is_latest: Option<usize>,
+    is_delete_marker: Option<usize>,
+}
+
+/// One report file of an S3 Inventory delivery, returned by
+/// [WalkDir::inventory_reports](crate::WalkDir::inventory_reports).
+#[derive(Debug, Clone)]
+pub struct InventoryReport {
+    open_options: OpenOptions,
+    inventory_bucket: String,
+    key: String,
+    schema: Schema,
+    /// The listed prefix as S3 knows it, with [OpenOptions::prefix] in front.
+    remote_prefix: String,
+    selection: Selection,
+}
+
+impl OpenOptions {
+    /// Read the manifest `manifest_key` in `inventory_bucket`, returning a report for each file it names.
+    pub(crate) async fn inventory_reports(
+        &self,
+        inventory_bucket: &str,
+        manifest_key: &str,
+        selection: Selection,
+    ) -> Result<Vec<InventoryReport>, S3FilesystemError> {
+        let manifest = self
+            .get_inventory_file(inventory_bucket, manifest_key)
+            .await?;
+        let manifest: Value = serde_json::from_slice(&manifest)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // An access point's ARN or alias cannot be compared with the name of the bucket behind it.
+        let through_access_point =
+            is_access_point(&self.bucket) || self.bucket.ends_with("-s3alias");
+        if let Some(source) = manifest["sourceBucket"].as_str() {
+            if source != self.bucket && !through_access_point {
+                return Err(invalid(format!(
+                    "the inventory lists bucket {} rather than {}",
+                    source, self.bucket
+                ))
+                .into());
+            }
+        }
+
+        match manifest["fileFormat"].as_str() {
+            Some("CSV") => {}
+            Some(format) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{} inventories are not supported, only CSV", format),
+                )
+                .into())
+            }
+            None => return Err(invalid("the inventory manifest has no fileFormat".into()).into()),
+        }
+
+        let schema = Schema::parse(manifest["fileSchema"].as_str().unwrap_or_default())?;
+        let remote_prefix = self.remote_key(&selection.prefix);
+        let files = manifest["files"].as_array().map_or(&[][..], Vec::as_slice);
+
+        Ok(files
+            .iter()
+            .filter_map(|file| file["key"].as_str())
+            .map(|key| InventoryReport {
+                open_options: self.clone(),
+                inventory_bucket: inventory_bucket.to_string(),
+                key: key.to_string(),
+                schema: schema.clone(),
+                remote_prefix: remote_prefix.clone(),
+                selection: selection.clone(),
+            })
+            .collect())
+    }
+
+    /// List the objects recorded by the inventory whose manifest is `manifest_key` in `inventory_bucket`,
+    /// reading up to `concurrency` report files at once.
+    pub(crate) async fn list_inventory(
+        &self,
+        inventory_bucket: &str,
+        manifest_key: &str,
+        selection: Selection,
+        concurrency: usize,
+    ) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        let reports = self
+            .inventory_reports(inventory_bucket, manifest_key, selection)
+            .await?;
+
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+
+        for report in reports {
+            let semaphore = semaphore.clone();
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                report.entries().collect::<Result<Vec<_>, _>>().await
+            });
+        }
+
+        let mut entries = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(listed) => entries.extend(listed?),
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+        Ok(entries)
+    }
+
+    /// Download a manifest from the bucket the inventory is delivered to.
+    async fn get_inventory_file(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Bytes, S3FilesystemError> {
+        self.download_inventory_file(bucket, key)
+            .await
+            .map_err(|e| e.with_context("GetObject", bucket, Some(Path::new(key))))
+    }
+
+    async fn download_inventory_file(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Bytes, S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await;
+        self.record_request("GetObject", started, result.is_ok());
+
+        let bytes = result?.body.collect().await?.into_bytes();
+        drop(slot);
+
+        if let Some(limiter) = &self.bandwidth_limiter {
+            limiter.acquire(bytes.len() as u64).await;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.downloaded(key, bytes.len() as u64);
+        }
+        Ok(bytes)
+    }
+}
+
+impl InventoryReport {
+    /// The key of the report file in the bucket the inventory is delivered to.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Stream the entries recorded in this report file
+    ///
+    /// The file is downloaded, decompressed and parsed as the stream is read, in the order its rows are
+    /// written. Noncurrent versions, delete markers and entries the walk would leave out are skipped. An
+    /// error ends the stream. Dropping the stream stops the download. This must be called from within a
+    /// Tokio runtime, as the download happens on a spawned task.
+    pub fn entries(&self) -> impl Stream<Item = Result<DirEntry, S3FilesystemError>> {
+        let (sender, receiver) = mpsc::channel(ENTRY_BUFFER);
+        let report = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = report.send_entries(&sender).await {
+                let e = e.with_context(
+                    "GetObject",
+                    &report.inventory_bucket,
+                    Some(Path::new(&report.key)),
+                );
+                let _ = sender.send(Err(e)).await;
+            }
+        });
+
+        ReceiverStream::new(receiver)
+    }
+
+    /// Download the report, handing it to a blocking parser which sends its entries on `sender`.
+    async fn send_entries(
+        &self,
+        sender: &mpsc::Sender<Result<DirEntry, S3FilesystemError>>,
+    ) -> Result<(), S3FilesystemError> {
+        let open_options = &self.open_options;
+        let slot = open_options.throttle_request().await;
+        let started = Instant::now();
+        let result = open_options
+            .cancellable(async {
+                Ok(open_options
+                    .s3_client
+                    .get_object()
+                    .bucket(&self.inventory_bucket)
+                    .key(&self.key)
+                    .send()
+                    .await?)
+            })
+            .await;
+        open_options.record_request("GetObject", started, result.is_ok());
+        let mut body = result?.body;
+
+        let (chunks, chunk_receiver) = mpsc::channel(CHUNK_BUFFER);
+        let parser = {
+            let report = self.clone();
+            let sender = sender.clone();
+            tokio::task::spawn_blocking(move || report.parse(chunk_receiver, &sender))
+        };
+
+        let mut downloaded = 0;
+        let fetched = open_options
+            .cancellable(async {
+                while let Some(chunk) = body.try_next().await? {
+                    if let Some(limiter) = &open_options.bandwidth_limiter {
+                        limiter.acquire(chunk.len() as u64).await;
+                    }
+                    downloaded += chunk.len() as u64;
+                    // The parser has stopped, because the stream was dropped or the report is malformed.
+                    if chunks.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+            .await;
+        if fetched.is_err() {
+            // Stop the parser before it reads the incomplete row at the end of what arrived.
+            let _ = chunks
+                .send(Err(io::Error::other("the report download failed")))
+                .await;
+        }
+        drop(chunks);
+        drop(slot);
+        if let Some(metrics) = &open_options.metrics {
+            metrics.downloaded(&self.key, downloaded);
+        }
+
+        let parsed = match parser.await {
+            Ok(parsed) => parsed,
+            Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+        };
+        fetched?;
+        match parsed {
+            // The stream was dropped, so nobody is left to tell.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            parsed => Ok(parsed?),
+        }
+    }
+
+    /// Decompress and parse the report arriving on `chunks`, sending each entry kept on `sender`.
+    fn parse(
+        &self,
+        chunks: mpsc::Receiver<io::Result<Bytes>>,
+        sender: &mpsc::Sender<Result<DirEntry, S3FilesystemError>>,
+    ) -> io::Result<()> {
+        let mut reader = ChunkReader {
+            chunks,
+            current: Bytes::new(),
+        };
+        let mut rows = RowWriter {
+            report: self,
+            sender,
+            row: Vec::new(),
+        };
+
+        match self.key.ends_with(".gz") {
+            true => gzip::decompress_to(reader, &mut rows)?,
+            false => io::copy(&mut reader, &mut rows)?,
+        };
+        rows.finish()
+    }
+
+    /// The entry for one report row, if it is a current object the walk keeps.
+    fn entry(&self, row: &str) -> Option<DirEntry> {
+        let mut entry = self.schema.entry(row)?;
+        let key = entry.path.to_string_lossy().into_owned();
+        if !key.starts_with(&self.remote_prefix) {
+            return None;
+        }
+        entry.path = self.open_options.relative_key(&key).unwrap_or(&key).into();
+        Some(entry).filter(|entry| self.selection.keeps(entry))
+    }
+}
+
+/// Reads the chunks of a download as they arrive, from blocking code.
+struct ChunkReader {
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+    current: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl BufRead for ChunkReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.current.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.current = chunk?,
+                None => break,
+            }
+        }
+        Ok(&self.current)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.current.advance(amount);
+    }
+}
+
+/// Splits decompressed report data into rows, sending the entry for each as soon as it is complete.
+struct RowWriter<'a> {
+    report: &'a InventoryReport,
+    sender: &'a mpsc::Sender<Result<DirEntry, S3FilesystemError>>,
+    row: Vec<u8>,
+}
+
+impl RowWriter<'_> {
+    fn send_row(&mut self) -> io::Result<()> {
+        let row = String::from_utf8_lossy(&self.row);
+        let row = row.strip_suffix('\r').unwrap_or(&row);
+        if let Some(entry) = Some(row)
+            .filter(|row| !row.is_empty())
+            .and_then(|row| self.report.entry(row))
+        {
+            self.sender.blocking_send(Ok(entry)).map_err(|_| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "the report stream was dropped")
+            })?;
+        }
+        self.row.clear();
+        Ok(())
+    }
+
+    /// Send the last row, if the report does not end with a newline.
+    fn finish(mut self) -> io::Result<()> {
+        self.send_row()
+    }
+}
+
+impl Write for RowWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            self.row.extend_from_slice(&rest[..end]);
+            self.send_row()?;
+            rest = &rest[end + 1..];
+        }
+        self.row.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Schema {
+    /// Parse a schema such as "Bucket, Key, Size, LastModifiedDate, ETag".
+    fn parse(schema: &str) -> Result<Schema, S3FilesystemError> {
+        let columns: Vec<&str> = schema.split(',').map(str::trim).collect();
+        let column = |name: &str| columns.iter().position(|column| *column == name);
+
+        Ok(Schema {
+            key: column("Key")
+                .ok_or_else(|| invalid("the inventory schema has no Key column".into()))?,
+            size: column("Size"),
+            last_modified: column("LastModifiedDate"),
+            e_tag: column("ETag"),
+            storage_class: column("StorageClass"),
+            is_latest: column("IsLatest"),
+            is_delete_marker: column("IsDeleteMarker"),
+        })
+    }
+
+    /// The entry for one report row, or None for noncurrent versions and delete markers.
+    fn entry(&self, row: &str) -> Option<DirEntry> {
+        let fields = split_row(row);
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| fields.get(index))
+                .filter(|field| !field.is_empty())
+        };
+
+        if field(self.is_latest).is_some_and(|latest| latest == "false")
+            || field(self.is_delete_marker).is_some_and(|marker| marker == "true")
+        {
+            return None;
+        }
+
+        let key = decode_key(fields.get(self.key)?);
+        Some(DirEntry {
+            folder: key.ends_with('/'),
+            path: key.into(),
+            size: field(self.size)
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            // ListObjectsV2 returns ETags quoted, while inventories leave the quotes off.
+            e_tag: field(self.e_tag).map(|e_tag| format!("\"{}\"", e_tag.trim_matches('"'))),
+            last_modified: field(self.last_modified)
+                .and_then(|modified| DateTime::from_str(modified, Format::DateTime).ok())
+                .and_then(|modified| SystemTime::try_from(modified).ok()),
+            storage_class: field(self.storage_class).cloned(),
+        })
+    }
+}
+
+/// Split a CSV row into its fields, removing quotes.
+fn split_row(row: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            char => field.push(char),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_row_unquotes_fields() {
+        assert_eq!(
+            split_row(r#""bucket","a,b.csv","say ""hi""",,plain"#),
+            ["bucket", "a,b.csv", r#"say "hi""#, "", "plain"]
+        );
+        assert_eq!(split_row(""), [""]);
+    }
+
+    #[test]
+    fn test_schema_reads_the_named_columns() {
+        let schema =
+            Schema::parse("Bucket, Key, Size, LastModifiedDate, ETag, StorageClass").unwrap();
+        let entry = schema
+            .entry(r#""bucket","data/my+file%2C1.csv","12","2024-01-01T00:00:00.000Z","abc","GLACIER""#)
+            .unwrap();
+
+        assert_eq!(entry.path, Path::new("data/my file,1.csv"));
+        assert_eq!(entry.size, 12);
+        assert_eq!(entry.e_tag.as_deref(), Some("\"abc\""));
+        assert_eq!(entry.storage_class.as_deref(), Some("GLACIER"));
+        assert_eq!(
+            entry.last_modified,
+            Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_704_067_200))
+        );
+        assert!(!entry.folder);
+
+        let folder = schema.entry(r#""bucket","data/","0",,,"#).unwrap();
+        assert!(folder.folder);
+        assert_eq!(folder.last_modified, None);
+        assert_eq!(folder.e_tag, None);
+    }
+
+    #[test]
+    fn test_schema_skips_old_versions_and_delete_markers() {
+        let schema =
+            Schema::parse("Bucket, Key, VersionId, IsLatest, IsDeleteMarker, Size").unwrap();
+
+        assert!(schema.entry("b,current.csv,v2,true,false,3").is_some());
+        assert!(schema.entry("b,replaced.csv,v1,false,false,3").is_none());
+        assert!(schema.entry("b,deleted.csv,v3,true,true,").is_none());
+    }
+
+    #[test]
+    fn test_schema_needs_a_key_column() {
+        assert!(matches!(
+            Schema::parse("Bucket, Size"),
+            Err(S3FilesystemError::Io(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+}
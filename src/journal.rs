@@ -0,0 +1,240 @@
+//! A journal of the uploads and downloads in progress, for recovering the local mirror after a crash.
+//!
+//! The journal lives at `<mount_path>/.s3-filesystem/<bucket>.journal`, a [KeyedLog] shared by every
+//! process using the mount path. An entry is set before an operation changes the mirror and removed once it
+//! has finished, whether it succeeded or failed, so entries left behind were cut short by the process
+//! stopping. Each entry's value is one of:
+//!
+//! ```text
+//! upload\t<bytes being uploaded>
+//! download\t-
+//! ```
+//!
+//! Finished operations are dropped from the file once they make up most of it, even while others are still
+//! in progress, and [OpenOptions::recover] always drops them.
+use std::{io, path::Path};
+
+use crate::{
+    index::INDEX_DIR,
+    keyed_log::KeyedLog,
+    options::{bucket_folder, discard_partial, part_e_tag_path, part_path},
+    OpenOptions, S3FilesystemError, WriteOptions,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What an interrupted operation was doing.
+pub enum JournalOperation {
+    /// Writing a file to the mirror and uploading it, as [OpenOptions::write_s3] does.
+    Upload,
+    /// Downloading a file into the mirror, as [OpenOptions::open_s3] does.
+    Download,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An operation left unfinished in the journal, as returned by [OpenOptions::interrupted_operations].
+pub struct InterruptedOperation {
+    /// The key being uploaded or downloaded.
+    pub key: String,
+    /// What was being done to it.
+    pub operation: JournalOperation,
+    /// For uploads, the size of the file being uploaded, so a mirrored copy cut short can be told apart.
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How [OpenOptions::recover] deals with interrupted operations.
+pub enum Recovery {
+    /// Finish each operation. Interrupted downloads are resumed and interrupted uploads are sent again
+    /// from the mirror, or rolled back if the mirrored copy was not completely written.
+    Complete,
+    /// Undo each operation, removing anything it left in the mirror so the next read fetches S3's copy.
+    /// Interrupted multipart uploads are aborted.
+    RollBack,
+}
+
+/// The operations in progress for one bucket.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    log: KeyedLog,
+}
+
+impl Journal {
+    pub(crate) fn new(mount_path: &Path, bucket: &str) -> Self {
+        let path = mount_path
+            .join(INDEX_DIR)
+            .join(format!("{}.journal", bucket_folder(bucket)));
+        Journal {
+            log: KeyedLog::new(path, "journal"),
+        }
+    }
+
+    /// Record that `operation` has started on `key`.
+    pub(crate) async fn begin(
+        &self,
+        key: &str,
+        operation: JournalOperation,
+        size: Option<u64>,
+    ) -> io::Result<()> {
+        let operation = match operation {
+            JournalOperation::Upload => "upload",
+            JournalOperation::Download => "download",
+        };
+        let size = size.map_or_else(|| "-".to_string(), |size| size.to_string());
+        self.log.set(key, &format!("{}\t{}", operation, size)).await
+    }
+
+    /// Record that the operation on `key` has finished.
+    pub(crate) async fn end(&self, key: &str) -> io::Result<()> {
+        self.log.remove(key).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn all(&self) -> io::Result<Vec<InterruptedOperation>> {
+        self.log
+            .read(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|(key, value)| parse_value(key, value))
+                    .collect()
+            })
+            .await
+    }
+
+    /// Rewrite the journal with only the operations still in progress.
+    pub(crate) async fn compact_now(&self) -> io::Result<()> {
+        self.log.compact_now().await
+    }
+}
+
+/// The operation recorded for `key` by [Journal::begin], or None if `value` is not one.
+fn parse_value(key: &str, value: &str) -> Option<InterruptedOperation> {
+    let (operation, size) = value.split_once('\t')?;
+    let operation = match operation {
+        "upload" => JournalOperation::Upload,
+        "download" => JournalOperation::Download,
+        _ => return None,
+    };
+    let size = match size {
+        "-" => None,
+        size => Some(size.parse().ok()?),
+    };
+
+    Some(InterruptedOperation {
+        key: key.to_string(),
+        operation,
+        size,
+    })
+}
+
+impl OpenOptions {
+    /// The uploads and downloads a previous run left unfinished, in key order
+    ///
+    /// Every write and every download into the mount path is journalled under the mount path while it runs,
+    /// so after a crash this lists the files whose mirrored copies may be incomplete or differ from S3.
+    /// Pass them to [OpenOptions::recover] to tidy up. Only call this before starting new work with the
+    /// same mount path, as operations still running are listed too.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, Recovery};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/job/");
+    ///
+    ///     // On start up, before any other work.
+    ///     for interrupted in open_options.interrupted_operations().await.unwrap() {
+    ///         println!("{:?} of {} was interrupted", interrupted.operation, interrupted.key);
+    ///     }
+    ///     open_options.recover(Recovery::Complete).await.unwrap();
+    /// }
+    /// ```
+    pub async fn interrupted_operations(
+        &self,
+    ) -> Result<Vec<InterruptedOperation>, S3FilesystemError> {
+        Ok(self.cache_index.journal.all().await?)
+    }
+
+    /// Complete or roll back the operations a previous run left unfinished
+    ///
+    /// Each operation listed by [OpenOptions::interrupted_operations] is dealt with as `recovery` says, one
+    /// after another, and taken off the journal once done. Recovery stops at the first operation which
+    /// fails, returning its error, and the rest stay in the journal for another attempt.
+    ///
+    /// Completing an upload sends the mirrored file again, or finishes it with
+    /// [OpenOptions::resume_upload] if it was a multipart upload; a download is resumed from the bytes it
+    /// had already received. Returns the operations recovered.
+    ///
+    /// # Arguments
+    /// * `recovery`: Whether to complete or roll back the interrupted operations.
+    pub async fn recover(
+        &self,
+        recovery: Recovery,
+    ) -> Result<Vec<InterruptedOperation>, S3FilesystemError> {
+        let interrupted = self.cache_index.journal.all().await?;
+        for entry in &interrupted {
+            self.recover_one(entry, recovery).await?;
+            self.cache_index.journal.end(&entry.key).await?;
+        }
+        self.cache_index.journal.compact_now().await?;
+        Ok(interrupted)
+    }
+
+    async fn recover_one(
+        &self,
+        entry: &InterruptedOperation,
+        recovery: Recovery,
+    ) -> Result<(), S3FilesystemError> {
+        let key = entry.key.as_str();
+        match (entry.operation, recovery) {
+            (JournalOperation::Upload, Recovery::Complete) => {
+                if self
+                    .pending_uploads()
+                    .await?
+                    .iter()
+                    .any(|pending| pending.key == key)
+                {
+                    return self.resume_upload(key).await;
+                }
+                let buf = match tokio::fs::read(self.local_path(key)?).await {
+                    Ok(buf) => buf,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                    Err(e) => return Err(e.into()),
+                };
+                // A write cut short left less than was being uploaded, so there is nothing to send.
+                if entry.size != Some(buf.len() as u64) {
+                    return self.roll_back_upload(key).await;
+                }
+
+                let mut upload = self.clone();
+                upload.write_back = false;
+                upload
+                    .write_s3_with(key, &buf, &WriteOptions::new())
+                    .await?;
+                Ok(self.cache_index.pending.remove(key).await?)
+            }
+            (JournalOperation::Upload, Recovery::RollBack) => self.roll_back_upload(key).await,
+            // The mirrored copy may be half written, so only the partial download is kept to resume from.
+            (JournalOperation::Download, Recovery::Complete) => {
+                self.evict(key).await?;
+                self.open_s3(key).await.map(|_| ())
+            }
+            (JournalOperation::Download, Recovery::RollBack) => {
+                let local_path = self.local_path(key)?;
+                discard_partial(&part_path(&local_path), &part_e_tag_path(&local_path)).await?;
+                self.evict(key).await
+            }
+        }
+    }
+
+    /// Undo an interrupted upload of `key`, leaving S3's copy to be fetched again.
+    async fn roll_back_upload(&self, key: &str) -> Result<(), S3FilesystemError> {
+        self.abort_upload(key).await?;
+        self.evict(key).await?;
+        Ok(self.cache_index.pending.remove(key).await?)
+    }
+}
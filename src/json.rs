@@ -0,0 +1,105 @@
+//! Writing listings out as JSON, and reading JSON objects back into typed values.
+use std::{io, path::Path};
+
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// List the objects under a path and write them to `writer` as a JSON array
+    ///
+    /// Each element is a serialized [DirEntry](crate::DirEntry), so the listing can be saved as a manifest or
+    /// sent to another process and read back with `serde_json`. The listing is the same as
+    /// [OpenOptions::walkdir] returns. Returns how many entries were written.
+    ///
+    /// # Arguments
+    /// * `path`: A path to search within the S3 bucket. If you want the entire bucket, just specify an empty string: "".
+    /// * `writer`: Where the JSON is written.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{DirEntry, OpenOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let mut manifest = tokio::fs::File::create("listing.json").await.unwrap();
+    ///     open_options
+    ///         .walkdir_to_json("datasets/", &mut manifest)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let listing: Vec<DirEntry> =
+    ///         serde_json::from_slice(&std::fs::read("listing.json").unwrap()).unwrap();
+    /// }
+    /// ```
+    pub async fn walkdir_to_json<P, W>(
+        &self,
+        path: P,
+        writer: &mut W,
+    ) -> Result<usize, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+        W: AsyncWrite + Unpin,
+    {
+        let entries = self.walkdir(path).await?;
+
+        let json = serde_json::to_vec(&entries)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.write_all(&json).await?;
+        writer.flush().await?;
+
+        Ok(entries.len())
+    }
+
+    /// Download a JSON object and deserialize it into a `T`
+    ///
+    /// The object is read as [OpenOptions::read] reads it, so it is cached in the local mirror like any
+    /// other download. If the contents are not valid JSON for `T` an [io::ErrorKind::InvalidData] error
+    /// is returned.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the object.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Config {
+    ///     name: String,
+    ///     replicas: u32,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let config: Config = open_options.read_json("config/service.json").await.unwrap();
+    ///     println!("{} runs {} replicas", config.name, config.replicas);
+    /// }
+    /// ```
+    pub async fn read_json<T, P>(&self, path: P) -> Result<T, S3FilesystemError>
+    where
+        T: DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = self.read(path).await?;
+
+        serde_json::from_slice(&contents).map_err(|e| {
+            S3FilesystemError::from(io::Error::new(io::ErrorKind::InvalidData, e)).with_context(
+                "GetObject",
+                &self.bucket,
+                Some(path),
+            )
+        })
+    }
+}
@@ -0,0 +1,169 @@
+//! Object keys, checked and normalised once instead of at every call.
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::S3FilesystemError;
+
+/// The longest key S3 accepts, in bytes of UTF-8.
+pub const MAX_KEY_LENGTH: usize = 1024;
+
+/// A key S3 will accept, such as "reports/2024/q1.csv".
+///
+/// Construction normalises Windows separators, so `reports\2024\q1.csv` becomes `reports/2024/q1.csv`, and
+/// rejects keys which are empty, longer than [MAX_KEY_LENGTH] bytes or, when made from a path, not valid
+/// UTF-8, with [S3FilesystemError::InvalidKey]. S3Key implements `AsRef<Path>`, so it can be passed to any
+/// [OpenOptions](crate::OpenOptions) method taking a path; plain paths and strings are still accepted and
+/// checked in the same way when the call is made.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::{OpenOptions, S3Key};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bucket = "my_aws_s3_bucket".to_string();
+///
+///     let open_options = OpenOptions::new(bucket, None).await;
+///
+///     // A key typed in by a user is checked before any request is made.
+///     let key: S3Key = r"reports\2024\q1.csv".parse().unwrap();
+///     assert_eq!(key.as_str(), "reports/2024/q1.csv");
+///
+///     open_options.open_s3(&key).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct S3Key(String);
+
+impl S3Key {
+    /// Check and normalise `key`.
+    pub fn new<K>(key: K) -> Result<Self, S3FilesystemError>
+    where
+        K: Into<String>,
+    {
+        let key = normalise(key.into())?;
+        match key.is_empty() {
+            true => Err(invalid(key, "keys cannot be empty")),
+            false => Ok(S3Key(key)),
+        }
+    }
+
+    /// Check and normalise a local style path as a key.
+    pub fn from_path<P>(path: P) -> Result<Self, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        match path.to_str() {
+            Some(key) => S3Key::new(key),
+            None => Err(invalid(
+                path.to_string_lossy().into_owned(),
+                "keys must be valid UTF-8",
+            )),
+        }
+    }
+
+    /// The key as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The key as an owned string.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<Path> for S3Key {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl AsRef<str> for S3Key {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for S3Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for S3Key {
+    type Err = S3FilesystemError;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        S3Key::new(key)
+    }
+}
+
+impl TryFrom<String> for S3Key {
+    type Error = S3FilesystemError;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        S3Key::new(key)
+    }
+}
+
+impl TryFrom<&str> for S3Key {
+    type Error = S3FilesystemError;
+
+    fn try_from(key: &str) -> Result<Self, Self::Error> {
+        S3Key::new(key)
+    }
+}
+
+impl TryFrom<&Path> for S3Key {
+    type Error = S3FilesystemError;
+
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        S3Key::from_path(path)
+    }
+}
+
+impl From<S3Key> for String {
+    fn from(key: S3Key) -> Self {
+        key.0
+    }
+}
+
+/// Convert a local style path into an S3 key, as [S3Key::from_path] does.
+pub(crate) fn s3_key(path: &Path) -> Result<String, S3FilesystemError> {
+    S3Key::from_path(path).map(S3Key::into_string)
+}
+
+/// Convert a local style path into a key prefix. As [s3_key], except that "" is allowed, for the whole
+/// bucket.
+pub(crate) fn s3_prefix(path: &Path) -> Result<String, S3FilesystemError> {
+    match path.to_str() {
+        Some(prefix) => normalise(prefix.to_string()),
+        None => Err(invalid(
+            path.to_string_lossy().into_owned(),
+            "keys must be valid UTF-8",
+        )),
+    }
+}
+
+/// Turn Windows separators into `/` and check the result is short enough to be a key.
+fn normalise(key: String) -> Result<String, S3FilesystemError> {
+    let key = match key.contains('\\') {
+        true => key.replace('\\', "/"),
+        false => key,
+    };
+    match key.len() > MAX_KEY_LENGTH {
+        true => Err(invalid(key, "keys are at most 1024 bytes")),
+        false => Ok(key),
+    }
+}
+
+/// An error for a key which cannot be used.
+fn invalid(key: String, reason: &'static str) -> S3FilesystemError {
+    S3FilesystemError::InvalidKey { key, reason }
+}
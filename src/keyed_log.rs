@@ -0,0 +1,270 @@
+//! Append-only files mapping keys to values, which the pending writes and the journal are kept in.
+//!
+//! Each file starts with a header naming what it holds and the generation of the file, and every change
+//! after that is appended as a line of its own, the last line for a key winning:
+//!
+//! ```text
+//! # s3-filesystem <kind> <generation>
+//! <value>\t<key>
+//! -\t<key removed>
+//! ```
+//!
+//! Keys are escaped as they are in the mirror, so tabs and newlines in keys cannot break a line, and values
+//! may hold tabs of their own. As with the cache index, changes are made holding a lock on
+//! `<file>.lock` after reading whatever other processes have appended, and once most lines have been
+//! superseded the live entries are written to a temporary file under a new generation and renamed over the
+//! old one. Compaction keeps every live entry, whichever process wrote it.
+use std::{
+    collections::BTreeMap,
+    io::{self, SeekFrom},
+    path::PathBuf,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use crate::{
+    index::read_up_to,
+    lock::lock_file,
+    options::{escape_key, unescape_key},
+};
+
+/// Fewest lines a file has before it is compacted, so small files are never rewritten.
+const COMPACT_AFTER: usize = 1024;
+
+/// One file of keys and values, brought up to date with the file before every use.
+#[derive(Debug)]
+pub(crate) struct KeyedLog {
+    path: PathBuf,
+    /// The start of the header line, up to the generation.
+    header: String,
+    entries: Mutex<Entries>,
+}
+
+/// The file as read so far.
+#[derive(Debug, Default)]
+struct Entries {
+    values: BTreeMap<String, String>,
+    /// The generation named in the header of the file read, or None if there is no file yet.
+    generation: Option<String>,
+    /// How many bytes of the file have been read.
+    offset: u64,
+    /// How many entries and removals the file holds.
+    lines: usize,
+}
+
+impl KeyedLog {
+    /// The log at `path`, whose header names it as `kind`.
+    pub(crate) fn new(path: PathBuf, kind: &str) -> Self {
+        KeyedLog {
+            path,
+            header: format!("# s3-filesystem {} ", kind),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Run `read` on the current keys and values.
+    pub(crate) async fn read<T, F>(&self, read: F) -> io::Result<T>
+    where
+        F: FnOnce(&BTreeMap<String, String>) -> T,
+    {
+        let mut entries = self.entries.lock().await;
+        self.refresh(&mut entries).await?;
+        Ok(read(&entries.values))
+    }
+
+    /// Set the value of `key`, appending nothing if it already has that value.
+    pub(crate) async fn set(&self, key: &str, value: &str) -> io::Result<()> {
+        let mut entries = self.entries.lock().await;
+        let _lock = lock_file(self.path.with_extension(self.extension("lock"))).await?;
+        self.refresh(&mut entries).await?;
+
+        if entries.values.get(key).map(String::as_str) == Some(value) {
+            return Ok(());
+        }
+        entries.values.insert(key.to_string(), value.to_string());
+        self.append(&mut entries, &format!("{}\t{}\n", value, escape_key(key)))
+            .await
+    }
+
+    /// Remove `key`, returning whether it was there.
+    pub(crate) async fn remove(&self, key: &str) -> io::Result<bool> {
+        let mut entries = self.entries.lock().await;
+        let _lock = lock_file(self.path.with_extension(self.extension("lock"))).await?;
+        self.refresh(&mut entries).await?;
+
+        if entries.values.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.append(&mut entries, &format!("-\t{}\n", escape_key(key)))
+            .await?;
+        Ok(true)
+    }
+
+    /// Rewrite the file with only its live entries, if any line has been superseded.
+    pub(crate) async fn compact_now(&self) -> io::Result<()> {
+        let mut entries = self.entries.lock().await;
+        let _lock = lock_file(self.path.with_extension(self.extension("lock"))).await?;
+        self.refresh(&mut entries).await?;
+
+        if entries.lines > entries.values.len() {
+            self.compact(&mut entries).await?;
+        }
+        Ok(())
+    }
+
+    /// The extension of the file, with `suffix` added, for the lock and temporary files beside it.
+    fn extension(&self, suffix: &str) -> String {
+        let extension = self.path.extension().unwrap_or_default().to_string_lossy();
+        format!("{}.{}", extension, suffix)
+    }
+
+    /// Read whatever has been appended since the file was last read, or all of it again if it has been
+    /// compacted or removed since.
+    async fn refresh(&self, entries: &mut Entries) -> io::Result<()> {
+        let mut file = match tokio::fs::File::open(&self.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                *entries = Entries::default();
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut first_line = vec![0; self.header.len() + 64];
+        let read = read_up_to(&mut file, &mut first_line).await?;
+        first_line.truncate(read);
+        let generation = first_line
+            .split(|&byte| byte == b'\n')
+            .next()
+            .and_then(|line| std::str::from_utf8(line).ok())
+            .and_then(|line| line.strip_prefix(self.header.as_str()))
+            .map(str::to_string);
+
+        let length = file.metadata().await?.len();
+        if generation.is_none() || generation != entries.generation || length < entries.offset {
+            *entries = Entries {
+                generation,
+                ..Entries::default()
+            };
+        }
+        if length == entries.offset {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(entries.offset)).await?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).await?;
+        // A line still being appended by another process is left for the next read.
+        let complete = match contents.iter().rposition(|&byte| byte == b'\n') {
+            Some(last) => last + 1,
+            None => return Ok(()),
+        };
+        entries.offset += complete as u64;
+
+        for line in String::from_utf8_lossy(&contents[..complete]).lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            match line.rsplit_once('\t') {
+                Some(("-", key)) => {
+                    entries.values.remove(&unescape_key(key));
+                }
+                Some((value, key)) => {
+                    entries.values.insert(unescape_key(key), value.to_string());
+                }
+                None => continue,
+            }
+            entries.lines += 1;
+        }
+        Ok(())
+    }
+
+    /// Append `line`, or compact the file if there is none with a header yet or it mostly holds lines which
+    /// have since been superseded. Must be called holding the lock, straight after a refresh.
+    async fn append(&self, entries: &mut Entries, line: &str) -> io::Result<()> {
+        let total = entries.lines + 1;
+        if entries.generation.is_none()
+            || (total > COMPACT_AFTER && total > 2 * entries.values.len())
+        {
+            return self.compact(entries).await;
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        entries.offset += line.len() as u64;
+        entries.lines = total;
+        Ok(())
+    }
+
+    /// Write the live entries to a temporary file under a new generation and rename it over the file, so a
+    /// crash never leaves a half written file behind.
+    async fn compact(&self, entries: &mut Entries) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let generation = format!("{}-{}", since_epoch.as_nanos(), process::id());
+        let mut contents = format!("{}{}\n", self.header, generation);
+        for (key, value) in &entries.values {
+            contents.push_str(&format!("{}\t{}\n", value, escape_key(key)));
+        }
+
+        let temporary = self.path.with_extension(self.extension("tmp"));
+        tokio::fs::write(&temporary, &contents).await?;
+        tokio::fs::rename(&temporary, &self.path).await?;
+
+        entries.generation = Some(generation);
+        entries.offset = contents.len() as u64;
+        entries.lines = entries.values.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_logs_share_changes_and_compact_live_entries() {
+        let folder = PathBuf::from("target/test-keyed-log/");
+        let _ = std::fs::remove_dir_all(&folder);
+        let path = folder.join("bucket.journal");
+        let first = KeyedLog::new(path.clone(), "journal");
+        let second = KeyedLog::new(path.clone(), "journal");
+
+        let odd_key = "reports/tab\there/line\nbreak.txt";
+        first.set(odd_key, "upload\t3").await.unwrap();
+        second.set("other.txt", "download\t-").await.unwrap();
+        let values = first.read(|values| values.clone()).await.unwrap();
+        assert_eq!(values[odd_key], "upload\t3");
+        assert_eq!(values["other.txt"], "download\t-");
+
+        for round in 0..COMPACT_AFTER {
+            first.set("busy.txt", &round.to_string()).await.unwrap();
+        }
+        // Compacted while both entries were still open, keeping the one the other log wrote.
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < COMPACT_AFTER, "{} lines", lines);
+        let values = second.read(|values| values.clone()).await.unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["busy.txt"], (COMPACT_AFTER - 1).to_string());
+
+        assert!(second.remove(odd_key).await.unwrap());
+        assert!(!first.remove(odd_key).await.unwrap());
+        first.compact_now().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+        assert!(!path.with_extension("journal.lock").exists() || cfg!(not(unix)));
+    }
+}
@@ -1,9 +1,143 @@
 #![doc = include_str!("../README.md")]
 #![deny(missing_docs, unused_imports)]
 
+mod accelerate;
+mod archive;
+mod attributes;
+mod auto_upload;
+mod backend;
+mod backoff;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod blocks;
+mod builder;
+mod cache;
+mod cancel;
+mod check;
+mod checksum;
+mod copy;
+#[cfg(feature = "serde")]
+mod csv;
+mod decompress;
+mod dedup;
+mod diff;
+mod dry_run;
 mod error;
-mod fs;
+pub mod fs;
+#[cfg(feature = "fuse")]
+mod fuse;
+mod gzip;
+mod hook;
+mod index;
+#[cfg(feature = "inventory")]
+mod inventory;
+mod journal;
+#[cfg(feature = "serde")]
+mod json;
+mod key;
+mod keyed_log;
+mod limit;
+mod local;
+mod lock;
+mod manifest;
+mod metadata;
+mod metrics;
+mod mime;
+#[cfg(feature = "mock")]
+mod mock;
+mod mounts;
+mod object_lock;
+mod offline;
+mod options;
+mod overlay;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod prefetch;
+mod prefix;
+mod preload;
+mod ranged;
+mod restore;
+mod s3_file;
+mod s3_lock;
+mod schedule;
+mod scoped;
+mod select;
+mod space;
+mod sparse;
+#[cfg(feature = "sqs")]
+mod sqs;
+mod stat;
+mod stream;
+mod sync;
+mod timeout;
+mod tls;
+mod trash;
+mod upload;
+mod verify;
+mod walk;
+mod watch;
+mod write_back;
 
+pub use crate::archive::ArchiveFormat;
+pub use crate::attributes::{ObjectAttributes, ObjectPart};
+pub use crate::auto_upload::{AutoUploadTask, UploadCycle};
+pub use crate::backend::{
+    BackendFuture, GetRequest, ListPage, ListRequest, ObjectBackend, ObjectBody, ObjectHead,
+    PutRequest, S3Backend,
+};
+#[cfg(feature = "blocking")]
+pub use crate::blocking::BlockingOpenOptions;
+pub use crate::builder::OpenOptionsBuilder;
+pub use crate::cache::{CacheLayout, CachePolicy, CacheStats, PrefixStats};
+pub use crate::checksum::{ChecksumAlgorithm, ObjectChecksum};
+pub use crate::diff::{DiffChange, DiffReport};
+pub use crate::dry_run::DryRunOperation;
+pub use crate::error::ErrorContext;
+pub use crate::error::S3Error;
 pub use crate::error::S3FilesystemError;
-pub use crate::fs::DirEntry;
-pub use crate::fs::OpenOptions;
+#[cfg(feature = "fuse")]
+pub use crate::fuse::S3Mount;
+pub use crate::hook::RequestHook;
+pub use crate::index::CachedObject;
+#[cfg(feature = "inventory")]
+pub use crate::inventory::InventoryReport;
+pub use crate::journal::{InterruptedOperation, JournalOperation, Recovery};
+pub use crate::key::{S3Key, MAX_KEY_LENGTH};
+pub use crate::local::LocalBackend;
+pub use crate::manifest::{
+    Checksum, Manifest, ManifestEntry, ManifestFailure, ManifestFailureReason, ManifestReport,
+};
+pub use crate::metrics::MetricsSink;
+#[cfg(feature = "mock")]
+pub use crate::mock::MockS3;
+pub use crate::mounts::S3Mounts;
+pub use crate::object_lock::{ObjectLock, RetentionMode};
+pub use crate::offline::OpenedFile;
+pub use crate::options::CannedAcl;
+pub use crate::options::DeleteOutcome;
+pub use crate::options::DirEntry;
+pub use crate::options::OpenOptions;
+pub use crate::options::WriteOptions;
+pub use crate::options::WritePrecondition;
+pub use crate::overlay::S3Overlay;
+#[cfg(feature = "parquet")]
+pub use crate::parquet::ParquetObject;
+pub use crate::preload::{Preload, PreloadProgress};
+pub use crate::restore::{RestoreStatus, RestoreTier};
+pub use crate::s3_file::S3File;
+pub use crate::s3_lock::S3Lock;
+pub use crate::schedule::{SyncCycle, SyncTask};
+pub use crate::select::SelectInput;
+#[cfg(feature = "sqs")]
+pub use crate::sqs::{CacheUpdate, SqsInvalidator};
+pub use crate::stat::Metadata;
+pub use crate::sync::{SyncFailure, SyncReport};
+pub use crate::tls::TlsConfig;
+pub use crate::upload::PendingUpload;
+pub use crate::verify::{VerifyMismatch, VerifyProblem, VerifyReport};
+pub use crate::walk::SortKey;
+pub use crate::walk::SortOrder;
+pub use crate::walk::WalkDir;
+pub use crate::watch::WatchEvent;
+pub use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+pub use tokio_util::sync::CancellationToken;
@@ -0,0 +1,149 @@
+//! Rate limiting shared between clones of an [OpenOptions](crate::OpenOptions).
+use aws_sdk_s3::primitives::{ByteStream, SdkBody};
+use bytes::Bytes;
+use http_body::{Body, SizeHint};
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    sync::OwnedSemaphorePermit,
+    time::{Instant, Sleep},
+};
+
+use crate::backoff::InFlight;
+
+/// A request's share of the concurrency limits, held until the request is done.
+#[must_use]
+pub(crate) struct RequestSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    _in_flight: Option<InFlight>,
+}
+
+impl RequestSlot {
+    pub(crate) fn new(permit: Option<OwnedSemaphorePermit>, in_flight: Option<InFlight>) -> Self {
+        RequestSlot {
+            _permit: permit,
+            _in_flight: in_flight,
+        }
+    }
+}
+
+/// A token bucket limiting units of work (bytes, requests) to a fixed rate.
+///
+/// The bucket holds up to one second's worth of tokens, so short bursts go through immediately
+/// while sustained use averages out at the rate. Each reservation pushes back the time the next
+/// unit may start, so concurrent users of a shared limiter split the rate between them.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    units_per_second: f64,
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(units_per_second: u64) -> Self {
+        // Start with a full bucket, so the first second's worth goes straight through.
+        let now = Instant::now();
+        RateLimiter {
+            units_per_second: units_per_second.max(1) as f64,
+            next_free: Mutex::new(now.checked_sub(Self::BURST).unwrap_or(now)),
+        }
+    }
+
+    /// The burst allowance, i.e. how far behind the present the bucket can refill to.
+    const BURST: Duration = Duration::from_secs(1);
+
+    /// Reserve `units` and return when the caller may consider them used.
+    fn reserve(&self, units: u64) -> Instant {
+        let mut next_free = self
+            .next_free
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let now = Instant::now();
+        let start = (*next_free).max(now.checked_sub(Self::BURST).unwrap_or(now));
+        *next_free = start + Duration::from_secs_f64(units as f64 / self.units_per_second);
+        *next_free
+    }
+
+    /// Wait until `units` may be used.
+    pub(crate) async fn acquire(&self, units: u64) {
+        tokio::time::sleep_until(self.reserve(units)).await;
+    }
+
+    /// Wrap an upload body so it is sent no faster than the limiter allows.
+    pub(crate) fn throttle_body(self: &Arc<Self>, byte_stream: ByteStream) -> ByteStream {
+        let limiter = self.clone();
+        byte_stream.map(move |body| {
+            SdkBody::from_body_0_4(ThrottledBody {
+                inner: body,
+                limiter: limiter.clone(),
+                delayed: None,
+            })
+        })
+    }
+}
+
+/// An upload body which holds back each chunk until the limiter has room for it.
+struct ThrottledBody {
+    inner: SdkBody,
+    limiter: Arc<RateLimiter>,
+    delayed: Option<(Pin<Box<Sleep>>, Bytes)>,
+}
+
+impl Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = aws_smithy_types::body::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.delayed.is_none() {
+            let chunk = match Pin::new(&mut self.inner).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => chunk,
+                other => return other,
+            };
+            let until = self.limiter.reserve(chunk.len() as u64);
+            self.delayed = Some((Box::pin(tokio::time::sleep_until(until)), chunk));
+        }
+
+        match &mut self.delayed {
+            Some((sleep, _)) => match sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(self.delayed.take().map(|(_, chunk)| Ok(chunk))),
+                Poll::Pending => Poll::Pending,
+            },
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.delayed.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self
+            .delayed
+            .as_ref()
+            .map_or(0, |(_, chunk)| chunk.len() as u64);
+        let inner = Body::size_hint(&self.inner);
+
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + buffered);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint
+    }
+}
@@ -0,0 +1,273 @@
+//! An [ObjectBackend] that keeps objects in a plain local directory.
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use aws_sdk_s3::primitives::ByteStream;
+use aws_smithy_types::byte_stream::Length;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    backend::{
+        BackendFuture, GetRequest, ListPage, ListRequest, ObjectBody, ObjectHead, PutRequest,
+    },
+    options::{bucket_folder, mirror_path, unescape_key},
+    DeleteOutcome, DirEntry, ObjectBackend, S3FilesystemError, WritePrecondition,
+};
+
+#[derive(Debug, Clone)]
+/// Stores each bucket as a folder under a root directory, laid out like the local mirror.
+///
+/// Useful for running against a shared network drive, or for tests. ETags are made from each file's size
+/// and modification time, so they change whenever a file is rewritten. Content types and ACLs are not
+/// kept.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::{LocalBackend, OpenOptions};
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bucket = "my_aws_s3_bucket".to_string();
+///
+///     let open_options = OpenOptions::new(bucket, None)
+///         .await
+///         .backend(Arc::new(LocalBackend::new("/mnt/shared/objects")));
+///
+///     open_options.write_s3("reports/latest.csv", b"a,b,c").await.unwrap();
+/// }
+/// ```
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    /// Keep the objects of each bucket under `root/<bucket>/`.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        LocalBackend { root: root.into() }
+    }
+
+    fn object_path(&self, bucket: &str, key: &str) -> Result<PathBuf, S3FilesystemError> {
+        mirror_path(&self.root.join(bucket_folder(bucket)), key)
+    }
+}
+
+impl ObjectBackend for LocalBackend {
+    fn list<'a>(&'a self, request: ListRequest<'a>) -> BackendFuture<'a, ListPage> {
+        Box::pin(async move {
+            let (prefix, delimiter) = (request.prefix, request.delimiter);
+            let bucket_root = self.root.join(bucket_folder(request.bucket));
+            let mut files = Vec::new();
+            let mut folders = vec![bucket_root.clone()];
+
+            while let Some(folder) = folders.pop() {
+                let mut read_dir = match tokio::fs::read_dir(&folder).await {
+                    Ok(read_dir) => read_dir,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                while let Some(entry) = read_dir.next_entry().await? {
+                    let metadata = entry.metadata().await?;
+                    match metadata.is_dir() {
+                        true => folders.push(entry.path()),
+                        false => {
+                            let key = key_for(&bucket_root, &entry.path());
+                            if key.starts_with(prefix) {
+                                files.push((key, metadata));
+                            }
+                        }
+                    }
+                }
+            }
+            files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            // The token is the last key or common prefix returned, so the next page starts after it. It is
+            // always later than the key the listing was asked to start after.
+            let after = request
+                .continuation_token
+                .or(request.start_after.map(str::to_string))
+                .unwrap_or_default();
+            let max_keys = request.max_keys.unwrap_or(usize::MAX).max(1);
+
+            let mut page = ListPage::default();
+            let mut common_prefixes = BTreeSet::new();
+            let mut returned = 0;
+            let mut last_listed = None;
+            for (key, metadata) in files {
+                let rolled_up = delimiter.and_then(|delimiter| {
+                    key[prefix.len()..]
+                        .find(delimiter)
+                        .map(|index| key[..prefix.len() + index + delimiter.len()].to_string())
+                });
+                let listed_as = rolled_up.as_ref().unwrap_or(&key);
+                if (!after.is_empty() && listed_as.as_str() <= after.as_str())
+                    || common_prefixes.contains(listed_as)
+                {
+                    continue;
+                }
+                if returned == max_keys {
+                    page.continuation_token = last_listed;
+                    break;
+                }
+                returned += 1;
+                last_listed = Some(listed_as.clone());
+
+                match rolled_up {
+                    Some(common_prefix) => {
+                        common_prefixes.insert(common_prefix);
+                    }
+                    None => page.entries.push(DirEntry {
+                        path: PathBuf::from(key),
+                        size: metadata.len() as i64,
+                        folder: false,
+                        e_tag: Some(e_tag_of(&metadata)),
+                        last_modified: metadata.modified().ok(),
+                        storage_class: None,
+                    }),
+                }
+            }
+            page.common_prefixes = common_prefixes.into_iter().collect();
+
+            Ok(page)
+        })
+    }
+
+    fn get<'a>(&'a self, request: GetRequest<'a>) -> BackendFuture<'a, ObjectBody> {
+        Box::pin(async move {
+            let path = self.object_path(request.bucket, request.key)?;
+            let metadata = tokio::fs::metadata(&path).await?;
+            let e_tag = e_tag_of(&metadata);
+            if let Some(if_match) = request.if_match {
+                if if_match != e_tag {
+                    return Err(S3FilesystemError::PreconditionFailed);
+                }
+            }
+            if request.if_none_match == Some(e_tag.as_str()) {
+                return Err(S3FilesystemError::NotModified);
+            }
+
+            let size = metadata.len();
+            let (start, end) = match request.range {
+                Some((start, end)) => {
+                    (start.min(size), end.map_or(size, |end| (end + 1).min(size)))
+                }
+                None => (0, size),
+            };
+            let body = ByteStream::read_from()
+                .path(&path)
+                .offset(start)
+                .length(Length::Exact(end.saturating_sub(start)))
+                .build()
+                .await?;
+
+            Ok(ObjectBody {
+                body,
+                e_tag: Some(e_tag),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn head<'a>(&'a self, bucket: &'a str, key: &'a str) -> BackendFuture<'a, ObjectHead> {
+        Box::pin(async move {
+            let metadata = tokio::fs::metadata(self.object_path(bucket, key)?).await?;
+            Ok(ObjectHead {
+                size: metadata.len(),
+                e_tag: Some(e_tag_of(&metadata)),
+                last_modified: metadata.modified().ok(),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn put<'a>(&'a self, mut request: PutRequest<'a>) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let path = self.object_path(request.bucket, request.key)?;
+            let existing = tokio::fs::metadata(&path).await.ok();
+            match (request.precondition, &existing) {
+                (Some(WritePrecondition::IfAbsent), Some(_)) => {
+                    return Err(S3FilesystemError::PreconditionFailed)
+                }
+                (Some(WritePrecondition::IfMatch(e_tag)), existing)
+                    if existing.as_ref().map(e_tag_of).as_ref() != Some(e_tag) =>
+                {
+                    return Err(S3FilesystemError::PreconditionFailed)
+                }
+                _ => {}
+            }
+
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // Written beside the object and renamed over it, so readers never see half a file.
+            let mut staging = path.clone().into_os_string();
+            staging.push(".upload");
+            let staging = PathBuf::from(staging);
+
+            let mut file = tokio::fs::File::create(&staging).await?;
+            while let Some(bytes) = request.body.try_next().await? {
+                file.write_all(&bytes).await?;
+            }
+            file.sync_all().await?;
+            drop(file);
+            tokio::fs::rename(&staging, &path).await?;
+
+            Ok(Some(e_tag_of(&tokio::fs::metadata(&path).await?)))
+        })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        bucket: &'a str,
+        keys: &'a [String],
+    ) -> BackendFuture<'a, Vec<DeleteOutcome>> {
+        Box::pin(async move {
+            let mut outcomes = Vec::with_capacity(keys.len());
+            for key in keys {
+                let removed = match self.object_path(bucket, key) {
+                    Ok(path) => match tokio::fs::remove_file(path).await {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.to_string()),
+                        _ => Ok(()),
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                outcomes.push(match removed {
+                    Ok(()) => DeleteOutcome::Deleted(key.clone()),
+                    Err(message) => DeleteOutcome::Failed {
+                        key: key.clone(),
+                        code: None,
+                        message: Some(message),
+                    },
+                });
+            }
+            Ok(outcomes)
+        })
+    }
+}
+
+/// The key a file under `bucket_root` is stored for.
+fn key_for(bucket_root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(bucket_root).unwrap_or(path);
+    let segments: Vec<_> = relative
+        .components()
+        .map(|component| unescape_key(&component.as_os_str().to_string_lossy()))
+        .collect();
+    segments.join("/")
+}
+
+/// A quoted ETag built from a file's size and modification time.
+fn e_tag_of(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", modified.as_nanos(), metadata.len())
+}
@@ -0,0 +1,134 @@
+//! Advisory locks which stop processes sharing a mount path from writing the same file at once.
+//!
+//! Each file being written has a lock file at `<mount_path>/.s3-filesystem/<bucket>.locks/<sha256>.lock`,
+//! named after the SHA-256 of the file's local path. Locks are taken with [std::fs::File::try_lock], so they
+//! hold against other processes as well as other tasks, and waiting for one backs off asynchronously
+//! rather than tying up a blocking thread.
+//!
+//! The holder removes the lock file when the lock is dropped. A waiter which had already opened it then finds
+//! the file it locked is no longer the one at the lock path, and starts again with a fresh lock file. Files
+//! can only be compared on unix, so elsewhere lock files are left in place.
+use std::{
+    fs::{File, TryLockError},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{cache::hashed_name, index::INDEX_DIR, options::bucket_folder, OpenOptions};
+
+/// How long to wait before the first retry of a held lock.
+const FIRST_BACKOFF: Duration = Duration::from_millis(1);
+
+/// The longest wait between retries of a held lock.
+const MAX_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Holds the lock on a file in the cache until dropped.
+#[derive(Debug)]
+pub(crate) struct CacheLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so any waiter on this file sees it has gone once it gets the lock.
+        if cfg!(unix) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+impl OpenOptions {
+    /// Wait until no other process or task is writing `path`, then hold it until the lock is dropped.
+    pub(crate) async fn lock_cache_file(&self, path: &Path) -> io::Result<CacheLock> {
+        let lock_path = self
+            .mount_path
+            .join(INDEX_DIR)
+            .join(format!("{}.locks", bucket_folder(&self.bucket)))
+            .join(format!("{}.lock", hashed_name(&path.to_string_lossy())));
+
+        lock_file(lock_path).await
+    }
+}
+
+/// Wait until no other process or task holds the lock file at `lock_path`, creating it if needed, then hold
+/// it until the lock is dropped.
+pub(crate) async fn lock_file(lock_path: PathBuf) -> io::Result<CacheLock> {
+    loop {
+        if let Some(parent) = lock_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .await?
+            .into_std()
+            .await;
+
+        let mut backoff = FIRST_BACKOFF;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(TryLockError::Error(error)) => return Err(error),
+            }
+        }
+
+        if is_current(&file, &lock_path)? {
+            return Ok(CacheLock {
+                file,
+                path: lock_path,
+            });
+        }
+    }
+}
+
+/// Whether `file` is still the lock file at `lock_path`, rather than one a previous holder removed.
+#[cfg(unix)]
+fn is_current(file: &File, lock_path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let locked = file.metadata()?;
+    match std::fs::metadata(lock_path) {
+        Ok(current) => Ok(current.dev() == locked.dev() && current.ino() == locked.ino()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lock files are never removed on this platform, so the one locked is always current.
+#[cfg(not(unix))]
+fn is_current(_file: &File, _lock_path: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_waits_for_the_holder_and_removes_its_file() {
+        let lock_path = PathBuf::from("target/test-lock/locks/data.lock");
+        let _ = std::fs::remove_dir_all("target/test-lock/");
+
+        let first = lock_file(lock_path.clone()).await.unwrap();
+        let waiter = tokio::spawn(lock_file(lock_path.clone()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        drop(first);
+        let second = waiter.await.unwrap().unwrap();
+        assert!(lock_path.exists());
+        assert!(is_current(&second.file, &lock_path).unwrap());
+
+        drop(second);
+        assert_eq!(lock_path.exists(), cfg!(not(unix)));
+    }
+}
@@ -0,0 +1,292 @@
+//! Batch downloads driven by a manifest of expected objects.
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, io, path::PathBuf, str::FromStr, sync::Arc};
+use tokio::{io::AsyncReadExt, sync::Semaphore, task::JoinSet};
+
+use crate::{error::S3FilesystemError, key::s3_key, OpenOptions};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A checksum an object is expected to have, as lowercase hex.
+pub enum Checksum {
+    /// An MD5 digest. For objects not uploaded in parts this matches their ETag.
+    Md5(String),
+    /// A SHA-256 digest.
+    Sha256(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An object listed in a [Manifest].
+pub struct ManifestEntry {
+    /// Path of the object within the bucket.
+    pub path: PathBuf,
+    /// The size the object should be in bytes, if known.
+    pub size: Option<u64>,
+    /// The checksum the object should have, if known.
+    pub checksum: Option<Checksum>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// A list of objects to download together with [OpenOptions::download_manifest].
+///
+/// Manifests can be built from entries directly, or parsed from text with one object per line. Each line
+/// holds the key, then optionally the size in bytes, then optionally a checksum written as `md5:<hex>` or
+/// `sha256:<hex>`, separated by whitespace. Blank lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// # key                      size   checksum
+/// redasa1-Q1-20/part-0.csv   1024   md5:0cc175b9c0f1b6a831c399e269772661
+/// redasa1-Q1-20/part-1.csv   2048
+/// redasa1-Q1-20/notes.txt
+/// ```
+pub struct Manifest {
+    /// The objects to download.
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl FromStr for Manifest {
+    type Err = io::Error;
+
+    fn from_str(manifest: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+
+        for (index, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid manifest line {}: {}", index + 1, reason),
+                )
+            };
+
+            let mut fields = line.split_whitespace();
+            let path = PathBuf::from(fields.next().unwrap_or_default());
+            let size = match fields.next() {
+                Some(size) => Some(size.parse().map_err(|_| invalid("size is not a number"))?),
+                None => None,
+            };
+            let checksum = match fields.next() {
+                Some(checksum) => match checksum.split_once(':') {
+                    Some(("md5", hex)) => Some(Checksum::Md5(hex.to_lowercase())),
+                    Some(("sha256", hex)) => Some(Checksum::Sha256(hex.to_lowercase())),
+                    _ => return Err(invalid("checksum must be md5:<hex> or sha256:<hex>")),
+                },
+                None => None,
+            };
+            if fields.next().is_some() {
+                return Err(invalid("too many fields"));
+            }
+
+            entries.push(ManifestEntry {
+                path,
+                size,
+                checksum,
+            });
+        }
+
+        Ok(Manifest { entries })
+    }
+}
+
+#[derive(Debug)]
+/// Why an entry in a manifest could not be downloaded.
+pub enum ManifestFailureReason {
+    /// The object could not be downloaded.
+    Download(S3FilesystemError),
+    /// The downloaded object was not the expected size.
+    SizeMismatch {
+        /// Size from the manifest.
+        expected: u64,
+        /// Size of the downloaded file.
+        actual: u64,
+    },
+    /// The downloaded object did not have the expected checksum.
+    ChecksumMismatch {
+        /// Checksum from the manifest.
+        expected: Checksum,
+        /// Checksum of the downloaded file, using the same algorithm.
+        actual: Checksum,
+    },
+}
+
+#[derive(Debug)]
+/// A manifest entry which failed to download or verify.
+pub struct ManifestFailure {
+    /// Path of the object within the bucket.
+    pub path: PathBuf,
+    /// What went wrong.
+    pub reason: ManifestFailureReason,
+}
+
+#[derive(Debug, Default)]
+/// The outcome of [OpenOptions::download_manifest].
+pub struct ManifestReport {
+    /// Paths of objects which were downloaded and verified, or found in the cache and verified.
+    pub succeeded: Vec<PathBuf>,
+    /// Objects which could not be downloaded or did not match the manifest.
+    pub failed: Vec<ManifestFailure>,
+    /// Paths of objects which are archived in Glacier, Deep Archive or an Intelligent-Tiering archive
+    /// tier, and have to be restored with [OpenOptions::restore] before they can be downloaded.
+    pub archived: Vec<PathBuf>,
+}
+
+impl OpenOptions {
+    /// Download every object listed in a manifest
+    ///
+    /// Objects are fetched concurrently with [OpenOptions::open_s3], so cached copies are used as the
+    /// [OpenOptions::cache_policy] allows. Once present, each file is checked against the size and checksum
+    /// in the manifest where those are given. Files which fail verification are removed from the mount path so
+    /// they are not mistaken for good copies later.
+    ///
+    /// Failures do not stop the batch; every entry is accounted for in the returned [ManifestReport].
+    ///
+    /// # Arguments
+    /// * `manifest`: The objects to download.
+    /// * `concurrency`: The most downloads to run at once. Zero is treated as one.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{Manifest, OpenOptions};
+    /// use tokio::io::AsyncReadExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let mut manifest = String::new();
+    ///     open_options
+    ///         .open_s3("manifest.txt")
+    ///         .await
+    ///         .unwrap()
+    ///         .read_to_string(&mut manifest)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let manifest: Manifest = manifest.parse().unwrap();
+    ///     let report = open_options.download_manifest(&manifest, 8).await;
+    ///
+    ///     println!("{} downloaded, {} failed", report.succeeded.len(), report.failed.len());
+    /// }
+    /// ```
+    pub async fn download_manifest(
+        &self,
+        manifest: &Manifest,
+        concurrency: usize,
+    ) -> ManifestReport {
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+
+        for entry in manifest.entries.iter().cloned() {
+            let open_options = self.clone();
+            let semaphore = semaphore.clone();
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let result = open_options.download_entry(&entry).await;
+                (entry.path, result)
+            });
+        }
+
+        let mut report = ManifestReport::default();
+
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((path, Ok(()))) => report.succeeded.push(path),
+                Ok((path, Err(ManifestFailureReason::Download(err)))) if err.is_archived() => {
+                    report.archived.push(path)
+                }
+                Ok((path, Err(reason))) => report.failed.push(ManifestFailure { path, reason }),
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            }
+        }
+
+        report
+    }
+
+    async fn download_entry(&self, entry: &ManifestEntry) -> Result<(), ManifestFailureReason> {
+        let mut file = self
+            .open_s3(&entry.path)
+            .await
+            .map_err(ManifestFailureReason::Download)?;
+
+        let key = s3_key(&entry.path).map_err(ManifestFailureReason::Download)?;
+        let evict = |reason| async {
+            let _ = self.evict(&key).await;
+            Err(reason)
+        };
+
+        if let Some(expected) = entry.size {
+            let actual = file
+                .metadata()
+                .await
+                .map_err(|e| ManifestFailureReason::Download(e.into()))?
+                .len();
+            if actual != expected {
+                return evict(ManifestFailureReason::SizeMismatch { expected, actual }).await;
+            }
+        }
+
+        if let Some(expected) = &entry.checksum {
+            let actual = checksum_of(&mut file, expected)
+                .await
+                .map_err(|e| ManifestFailureReason::Download(e.into()))?;
+            if &actual != expected {
+                return evict(ManifestFailureReason::ChecksumMismatch {
+                    expected: expected.clone(),
+                    actual,
+                })
+                .await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash a file with the same algorithm as `like`.
+pub(crate) async fn checksum_of(
+    file: &mut tokio::fs::File,
+    like: &Checksum,
+) -> Result<Checksum, io::Error> {
+    let mut buf = vec![0; 64 * 1024];
+
+    match like {
+        Checksum::Md5(_) => {
+            let mut hasher = Md5::new();
+            loop {
+                let read = file.read(&mut buf).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..read]);
+            }
+            Ok(Checksum::Md5(to_hex(&hasher.finalize())))
+        }
+        Checksum::Sha256(_) => {
+            let mut hasher = Sha256::new();
+            loop {
+                let read = file.read(&mut buf).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..read]);
+            }
+            Ok(Checksum::Sha256(to_hex(&hasher.finalize())))
+        }
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
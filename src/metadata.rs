@@ -0,0 +1,62 @@
+//! Fetching the full details of a listed object on demand.
+use std::time::Instant;
+
+use crate::{key::s3_key, DirEntry, ObjectHead, OpenOptions, S3FilesystemError};
+
+impl DirEntry {
+    /// Fetch the object's full details with a HeadObject request
+    ///
+    /// Listings only carry the size, ETag, modification time and storage class, which keeps
+    /// [OpenOptions::walkdir] to one request per thousand objects. This asks for the rest for a single entry:
+    /// its Content-Type, user-defined metadata and any additional checksum it was uploaded with. The
+    /// object is not downloaded. Folders rolled up from longer keys have no object, so asking for their
+    /// metadata fails with a not found error.
+    ///
+    /// # Arguments
+    /// * `open_options`: The OpenOptions the entry was listed with.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     for entry in open_options.walkdir("reports/").await.unwrap() {
+    ///         if entry.folder {
+    ///             continue;
+    ///         }
+    ///
+    ///         let metadata = entry.metadata(&open_options).await.unwrap();
+    ///         println!("{}: {:?}", entry.path.display(), metadata.content_type);
+    ///     }
+    /// }
+    /// ```
+    pub async fn metadata(
+        &self,
+        open_options: &OpenOptions,
+    ) -> Result<ObjectHead, S3FilesystemError> {
+        open_options
+            .head_object(self)
+            .await
+            .map_err(|e| e.with_context("HeadObject", &open_options.bucket, Some(&self.path)))
+    }
+}
+
+impl OpenOptions {
+    async fn head_object(&self, entry: &DirEntry) -> Result<ObjectHead, S3FilesystemError> {
+        let key = s3_key(&entry.path)?;
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .cancellable(self.backend.head(&self.bucket, &key))
+            .await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+        result
+    }
+}
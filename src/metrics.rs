@@ -0,0 +1,103 @@
+//! Hooks for exporting counters and latencies to a metrics system.
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::OpenOptions;
+
+/// Receives counters and timings from an [OpenOptions].
+///
+/// Every method has an empty default, so implementations only need to handle what they export. Methods
+/// are called inline on the task doing the work, so they should be cheap - incrementing a Prometheus
+/// counter or histogram is ideal.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::{MetricsSink, OpenOptions};
+/// use std::sync::{
+///     atomic::{AtomicU64, Ordering},
+///     Arc,
+/// };
+///
+/// #[derive(Default)]
+/// struct Downloads(AtomicU64);
+///
+/// impl MetricsSink for Downloads {
+///     fn downloaded(&self, _key: &str, bytes: u64) {
+///         self.0.fetch_add(bytes, Ordering::Relaxed);
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bucket = "my_aws_s3_bucket".to_string();
+///     let downloads = Arc::new(Downloads::default());
+///
+///     let open_options = OpenOptions::new(bucket, None)
+///         .await
+///         .metrics(downloads.clone());
+///
+///     open_options.open_s3("some_folder/some_file.csv").await.unwrap();
+///
+///     println!("Downloaded {} bytes", downloads.0.load(Ordering::Relaxed));
+/// }
+/// ```
+pub trait MetricsSink: Send + Sync {
+    /// A file was served from the local mirror without contacting S3.
+    fn cache_hit(&self, _key: &str) {}
+
+    /// A file was not in the local mirror (or a download was forced) so it will be fetched from S3.
+    fn cache_miss(&self, _key: &str) {}
+
+    /// An object finished downloading.
+    fn downloaded(&self, _key: &str, _bytes: u64) {}
+
+    /// An object finished uploading.
+    fn uploaded(&self, _key: &str, _bytes: u64) {}
+
+    /// A request to S3 completed, successfully or not. `operation` is the S3 API name, such as
+    /// "GetObject", and `latency` covers the request itself, not time spent waiting on rate limits.
+    fn request(&self, _operation: &str, _latency: Duration, _succeeded: bool) {}
+}
+
+/// The sink installed on an [OpenOptions], shared by all of its clones.
+#[derive(Clone)]
+pub(crate) struct Metrics(Arc<dyn MetricsSink>);
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+impl Deref for Metrics {
+    type Target = dyn MetricsSink;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl OpenOptions {
+    /// Report counters and latencies to a [MetricsSink]
+    ///
+    /// The sink is told about cache hits and misses, bytes downloaded and uploaded, and how long each
+    /// S3 request took. It is shared by every clone of this OpenOptions.
+    ///
+    /// # Arguments
+    /// * `sink`: Where to send the metrics.
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(Metrics(sink));
+        self
+    }
+
+    /// Report how long a request started at `started` took, if a sink is installed.
+    pub(crate) fn record_request(&self, operation: &str, started: Instant, succeeded: bool) {
+        if let Some(metrics) = &self.metrics {
+            metrics.request(operation, started.elapsed(), succeeded);
+        }
+    }
+}
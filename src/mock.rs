@@ -0,0 +1,1119 @@
+//! An in-memory stand-in for S3, so code built on this crate can be tested without AWS.
+//!
+//! [MockS3] answers the HTTP requests the S3 client makes, so an [OpenOptions](crate::OpenOptions)
+//! given [MockS3::client] runs exactly the code it would against S3. It understands the object calls
+//! this crate makes: GetObject (with ranges, If-Match and If-None-Match), HeadObject, PutObject (with
+//! If-None-Match and If-Match, keeping `x-amz-meta-*` headers and checking any CRC-32C or SHA-256
+//! checksum sent), CopyObject, DeleteObject, DeleteObjects, ListObjectsV2, HeadBucket, GetObjectAttributes
+//! and multipart uploads. Buckets named like S3 Express One Zone directory buckets (ending `--x-s3`) accept
+//! the SDK's CreateSession call and list with their restrictions: prefixes must end in `/`, StartAfter is
+//! refused, and keys come back out of order. Bodies the SDK streams with `aws-chunked` framing are decoded, with their trailing
+//! checksums treated as headers. Anything else is answered with a 501 NotImplemented error. Requests are
+//! not retried, and throttling can be simulated with [MockS3::throttle_next] and archived objects with
+//! [MockS3::set_storage_class].
+use md5::{Digest, Md5};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use aws_sdk_s3::{
+    config::{retry::RetryConfig, BehaviorVersion, Credentials, Region},
+    primitives::ByteStream,
+    Client, Config,
+};
+use aws_smithy_runtime_api::client::{
+    http::{http_client_fn, HttpConnector, HttpConnectorFuture, SharedHttpConnector},
+    orchestrator::{HttpRequest, HttpResponse},
+};
+use aws_smithy_types::{base64, body::SdkBody, date_time::Format, DateTime};
+
+use crate::{ChecksumAlgorithm, ObjectChecksum};
+
+/// An in-memory object store that S3 clients can be pointed at.
+///
+/// Clones share the same objects, so a test can keep one to seed and inspect the store while an
+/// [OpenOptions](crate::OpenOptions) uses another through [MockS3::client].
+///
+/// # Examples
+/// ```
+/// use s3_filesystem::{CachePolicy, MockS3, OpenOptions};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mock = MockS3::new().with_bucket("my_bucket");
+///     mock.put_object("my_bucket", "folder/hello.txt", "hello");
+///
+///     let open_options = OpenOptions::new("my_bucket".to_string(), Some(mock.client()))
+///         .await
+///         .mount_path("target/mock-example/")
+///         .cache_policy(CachePolicy::AlwaysDownload);
+///
+///     let contents = open_options.read_to_string("folder/hello.txt").await.unwrap();
+///     assert_eq!(contents, "hello");
+///
+///     open_options.write_s3("folder/reply.txt", b"hi").await.unwrap();
+///     assert_eq!(mock.get_object("my_bucket", "folder/reply.txt").unwrap(), b"hi");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockS3 {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    buckets: BTreeMap<String, BTreeMap<String, MockObject>>,
+    uploads: HashMap<String, MockUpload>,
+    next_upload_id: u64,
+    /// How many more requests to answer with 503 SlowDown.
+    throttled: usize,
+}
+
+#[derive(Debug, Clone)]
+struct MockObject {
+    data: Vec<u8>,
+    e_tag: String,
+    last_modified: SystemTime,
+    content_type: Option<String>,
+    content_encoding: Option<String>,
+    /// The `x-amz-meta-*` headers the object was stored with.
+    metadata: Vec<(String, String)>,
+    /// The `x-amz-checksum-*` header the object was stored with, as name and value.
+    checksum: Option<(String, String)>,
+    /// The size and checksum of each part, for objects uploaded in parts.
+    parts: Vec<(u64, Option<(String, String)>)>,
+    storage_class: String,
+}
+
+impl MockObject {
+    fn new(data: Vec<u8>, content_type: Option<String>) -> Self {
+        MockObject {
+            e_tag: format!("\"{}\"", to_hex(&Md5::digest(&data))),
+            data,
+            last_modified: SystemTime::now(),
+            content_type,
+            content_encoding: None,
+            metadata: Vec::new(),
+            checksum: None,
+            parts: Vec::new(),
+            storage_class: "STANDARD".to_string(),
+        }
+    }
+
+    /// Whether GetObject is refused until the object is restored, as for S3's archive storage classes.
+    fn is_archived(&self) -> bool {
+        matches!(self.storage_class.as_str(), "GLACIER" | "DEEP_ARCHIVE")
+    }
+}
+
+#[derive(Debug)]
+struct MockUpload {
+    bucket: String,
+    key: String,
+    content_type: Option<String>,
+    parts: BTreeMap<i32, Vec<u8>>,
+    part_checksums: BTreeMap<i32, (String, String)>,
+}
+
+impl MockS3 {
+    /// Create an empty store with no buckets.
+    pub fn new() -> Self {
+        MockS3::default()
+    }
+
+    /// Add an empty bucket, keeping any objects already in it.
+    pub fn with_bucket<B>(self, bucket: B) -> Self
+    where
+        B: Into<String>,
+    {
+        self.state().buckets.entry(bucket.into()).or_default();
+        self
+    }
+
+    /// Store an object, creating the bucket if it does not exist.
+    pub fn put_object<B, K, D>(&self, bucket: B, key: K, data: D)
+    where
+        B: Into<String>,
+        K: Into<String>,
+        D: Into<Vec<u8>>,
+    {
+        self.state()
+            .buckets
+            .entry(bucket.into())
+            .or_default()
+            .insert(key.into(), MockObject::new(data.into(), None));
+    }
+
+    /// Remove an object, if it exists.
+    pub fn delete_object(&self, bucket: &str, key: &str) {
+        if let Some(objects) = self.state().buckets.get_mut(bucket) {
+            objects.remove(key);
+        }
+    }
+
+    /// Move an existing object to another storage class, such as "GLACIER"
+    ///
+    /// Objects in "GLACIER" or "DEEP_ARCHIVE" are listed and can be stat'd, but reading one fails with 403
+    /// InvalidObjectState, as it does on S3 before the object is restored.
+    pub fn set_storage_class(&self, bucket: &str, key: &str, storage_class: &str) {
+        if let Some(object) = self
+            .state()
+            .buckets
+            .get_mut(bucket)
+            .and_then(|objects| objects.get_mut(key))
+        {
+            object.storage_class = storage_class.to_string();
+        }
+    }
+
+    /// The contents of an object, or None if it does not exist.
+    pub fn get_object(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        let state = self.state();
+        Some(state.buckets.get(bucket)?.get(key)?.data.clone())
+    }
+
+    /// Every key in a bucket, in order.
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        self.state()
+            .buckets
+            .get(bucket)
+            .map(|objects| objects.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Answer the next `requests` requests with 503 SlowDown, as S3 does when a prefix is over its request
+    /// rate, to test how code copes with throttling. Retries count as requests.
+    pub fn throttle_next(&self, requests: usize) {
+        self.state().throttled = requests;
+    }
+
+    /// An S3 client whose requests are answered by this store.
+    pub fn client(&self) -> Client {
+        let connector = SharedHttpConnector::new(MockConnector {
+            state: self.state.clone(),
+        });
+        let config = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .retry_config(RetryConfig::disabled())
+            .region(Region::new("us-east-1"))
+            .endpoint_url("http://s3.mock")
+            .force_path_style(true)
+            .credentials_provider(Credentials::new("mock", "mock", None, None, "mock"))
+            .http_client(http_client_fn(move |_, _| connector.clone()))
+            .build();
+        Client::from_conf(config)
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A response as the handlers build it, converted for the client once complete.
+type MockResponse = http::Response<SdkBody>;
+
+#[derive(Debug)]
+struct MockConnector {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl HttpConnector for MockConnector {
+    fn call(&self, mut request: HttpRequest) -> HttpConnectorFuture {
+        let state = self.state.clone();
+        HttpConnectorFuture::new(async move {
+            let body = match ByteStream::new(request.take_body()).collect().await {
+                Ok(body) => body.into_bytes().to_vec(),
+                Err(_) => Vec::new(),
+            };
+            let mut state = state
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            Ok(HttpResponse::try_from(handle(&mut state, &request, body))
+                .expect("the mock only builds valid responses"))
+        })
+    }
+}
+
+/// Header names and values, in the order they were sent.
+type Headers = Vec<(String, String)>;
+
+/// A request broken into the parts S3 routes on.
+struct Call<'a> {
+    request: &'a HttpRequest,
+    bucket: String,
+    key: String,
+    query: HashMap<String, String>,
+    body: Vec<u8>,
+    /// Headers sent after an `aws-chunked` body, where the SDK puts checksums it works out while sending.
+    trailers: Headers,
+}
+
+impl Call<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Every header sent with the request, followed by any trailers.
+    fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.request.headers().iter().chain(
+            self.trailers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        )
+    }
+
+    fn has_query(&self, name: &str) -> bool {
+        self.query.contains_key(name)
+    }
+}
+
+fn handle(state: &mut MockState, request: &HttpRequest, body: Vec<u8>) -> MockResponse {
+    let uri = request.uri();
+    let after_scheme = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let path_and_query = after_scheme
+        .find('/')
+        .map_or("/", |index| &after_scheme[index..]);
+    let (path, query) = path_and_query
+        .split_once('?')
+        .unwrap_or((path_and_query, ""));
+    // Directory buckets are always addressed virtual-host style, as `<bucket>.s3.mock`.
+    let host = after_scheme.split(['/', ':']).next().unwrap_or_default();
+    let (bucket, key) = match host.strip_suffix(".s3.mock") {
+        Some(bucket) => (bucket, &path[1..]),
+        None => path[1..].split_once('/').unwrap_or((&path[1..], "")),
+    };
+
+    let chunked = request
+        .headers()
+        .get("x-amz-content-sha256")
+        .is_some_and(|sha256| sha256.starts_with("STREAMING-"));
+    let (body, trailers) = match chunked {
+        true => match decode_chunked(&body) {
+            Some(decoded) => decoded,
+            None => {
+                return error(
+                    400,
+                    "IncompleteBody",
+                    "The aws-chunked body could not be read",
+                    false,
+                )
+            }
+        },
+        false => (body, Vec::new()),
+    };
+
+    let call = Call {
+        request,
+        bucket: percent_decode(bucket),
+        key: percent_decode(key),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(name), percent_decode(value))
+            })
+            .collect(),
+        body,
+        trailers,
+    };
+    let head = request.method() == "HEAD";
+
+    if state.throttled > 0 {
+        state.throttled -= 1;
+        return error(503, "SlowDown", "Please reduce your request rate.", head);
+    }
+
+    if !state.buckets.contains_key(&call.bucket) {
+        return error(
+            404,
+            "NoSuchBucket",
+            "The specified bucket does not exist",
+            head,
+        );
+    }
+
+    match (request.method(), call.key.is_empty()) {
+        ("HEAD", true) => response(200).body(SdkBody::empty()).unwrap(),
+        ("GET", true) if call.has_query("session") => create_session(),
+        ("GET", true) if call.has_query("list-type") => list_objects(state, &call),
+        ("POST", true) if call.has_query("delete") => delete_objects(state, &call),
+        ("GET", false) if call.has_query("attributes") => object_attributes(state, &call),
+        ("GET" | "HEAD", false) => get_object(state, &call, head),
+        ("PUT", false) if call.has_query("partNumber") => upload_part(state, &call),
+        ("PUT", false) if call.header("x-amz-copy-source").is_some() => copy_object(state, &call),
+        ("PUT", false) => put_object(state, &call),
+        ("POST", false) if call.has_query("uploads") => create_upload(state, &call),
+        ("POST", false) if call.has_query("uploadId") => complete_upload(state, &call),
+        ("DELETE", false) if call.has_query("uploadId") => abort_upload(state, &call),
+        ("DELETE", false) => {
+            if let Some(objects) = state.buckets.get_mut(&call.bucket) {
+                objects.remove(&call.key);
+            }
+            response(204).body(SdkBody::empty()).unwrap()
+        }
+        _ => error(
+            501,
+            "NotImplemented",
+            "This operation is not supported by MockS3",
+            head,
+        ),
+    }
+}
+
+fn get_object(state: &MockState, call: &Call, head: bool) -> MockResponse {
+    let object = match state.buckets[&call.bucket].get(&call.key) {
+        Some(object) => object,
+        None => return error(404, "NoSuchKey", "The specified key does not exist.", head),
+    };
+    if let Some(e_tag) = call.header("if-match") {
+        if e_tag.trim_matches('"') != object.e_tag.trim_matches('"') {
+            return error(412, "PreconditionFailed", "If-Match did not match", head);
+        }
+    }
+    if let Some(e_tag) = call.header("if-none-match") {
+        if e_tag.trim_matches('"') == object.e_tag.trim_matches('"') {
+            return response(304)
+                .header("ETag", &object.e_tag)
+                .body(SdkBody::empty())
+                .unwrap();
+        }
+    }
+
+    if object.is_archived() && !head {
+        return error(
+            403,
+            "InvalidObjectState",
+            "The operation is not valid for the object's storage class",
+            head,
+        );
+    }
+
+    let size = object.data.len() as u64;
+    let mut builder = response(200)
+        .header("ETag", &object.e_tag)
+        .header("Last-Modified", http_date(object.last_modified))
+        .header("Accept-Ranges", "bytes");
+    if object.storage_class != "STANDARD" {
+        builder = builder.header("x-amz-storage-class", &object.storage_class);
+    }
+    if let Some(content_type) = &object.content_type {
+        builder = builder.header("Content-Type", content_type);
+    }
+    if let Some(content_encoding) = &object.content_encoding {
+        builder = builder.header("Content-Encoding", content_encoding);
+    }
+    for (name, value) in &object.metadata {
+        builder = builder.header(name.as_str(), value);
+    }
+    if let Some((name, value)) = &object.checksum {
+        if call.header("x-amz-checksum-mode") == Some("ENABLED") {
+            builder = builder.header(name.as_str(), value);
+        }
+    }
+
+    let range = match call.header("range") {
+        Some(range) => match parse_range(range, size) {
+            Some(range) => Some(range),
+            None => {
+                return error(
+                    416,
+                    "InvalidRange",
+                    "The requested range is not satisfiable",
+                    head,
+                )
+            }
+        },
+        None => None,
+    };
+    let (start, end) = range.unwrap_or((0, size.saturating_sub(1)));
+    let data = match size {
+        0 => &[][..],
+        _ => &object.data[start as usize..=end as usize],
+    };
+    if range.is_some() {
+        builder = builder
+            .status(206)
+            .header("Content-Range", format!("bytes {}-{}/{}", start, end, size));
+    }
+
+    builder
+        .header("Content-Length", data.len())
+        .body(match head {
+            true => SdkBody::empty(),
+            false => SdkBody::from(data.to_vec()),
+        })
+        .unwrap()
+}
+
+fn object_attributes(state: &MockState, call: &Call) -> MockResponse {
+    let object = match state.buckets[&call.bucket].get(&call.key) {
+        Some(object) => object,
+        None => return error(404, "NoSuchKey", "The specified key does not exist.", false),
+    };
+    let checksum_xml = |(name, value): &(String, String)| {
+        let element = checksum_element(name);
+        format!("<{0}>{1}</{0}>", element, xml_escape(value))
+    };
+
+    let mut body = format!(
+        "<GetObjectAttributesResponse><ETag>{}</ETag><StorageClass>{}</StorageClass><ObjectSize>{}</ObjectSize>",
+        xml_escape(object.e_tag.trim_matches('"')),
+        object.storage_class,
+        object.data.len()
+    );
+    if let Some(checksum) = &object.checksum {
+        body.push_str(&format!("<Checksum>{}</Checksum>", checksum_xml(checksum)));
+    }
+
+    if !object.parts.is_empty() {
+        let max_parts: usize = call
+            .header("x-amz-max-parts")
+            .and_then(|max| max.parse().ok())
+            .unwrap_or(1000);
+        let marker: usize = call
+            .header("x-amz-part-number-marker")
+            .and_then(|marker| marker.parse().ok())
+            .unwrap_or(0);
+        let listed: Vec<_> = object
+            .parts
+            .iter()
+            .enumerate()
+            .skip(marker)
+            .take(max_parts)
+            .collect();
+        let last = marker + listed.len();
+
+        body.push_str(&format!(
+            "<ObjectParts><PartsCount>{}</PartsCount><PartNumberMarker>{}</PartNumberMarker><NextPartNumberMarker>{}</NextPartNumberMarker><MaxParts>{}</MaxParts><IsTruncated>{}</IsTruncated>",
+            object.parts.len(),
+            marker,
+            last,
+            max_parts,
+            last < object.parts.len()
+        ));
+        // S3 only lists the parts of objects uploaded with checksums.
+        if object.checksum.is_some() {
+            for (index, (size, checksum)) in listed {
+                body.push_str(&format!(
+                    "<Part><PartNumber>{}</PartNumber><Size>{}</Size>{}</Part>",
+                    index + 1,
+                    size,
+                    checksum.as_ref().map(checksum_xml).unwrap_or_default()
+                ));
+            }
+        }
+        body.push_str("</ObjectParts>");
+    }
+
+    body.push_str("</GetObjectAttributesResponse>");
+    response(200)
+        .header("Content-Type", "application/xml")
+        .header("Last-Modified", http_date(object.last_modified))
+        .body(SdkBody::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}",
+            body
+        )))
+        .unwrap()
+}
+
+fn put_object(state: &mut MockState, call: &Call) -> MockResponse {
+    if let Some(response) = check_checksum(call) {
+        return response;
+    }
+    let objects = state.buckets.get_mut(&call.bucket).unwrap();
+    let existing = objects.get(&call.key);
+
+    if call.header("if-none-match") == Some("*") && existing.is_some() {
+        return error(
+            412,
+            "PreconditionFailed",
+            "The object already exists",
+            false,
+        );
+    }
+    if let Some(e_tag) = call.header("if-match") {
+        match existing {
+            None => return error(404, "NoSuchKey", "The specified key does not exist.", false),
+            Some(existing) if existing.e_tag.trim_matches('"') != e_tag.trim_matches('"') => {
+                return error(412, "PreconditionFailed", "If-Match did not match", false)
+            }
+            _ => {}
+        }
+    }
+
+    let mut object = MockObject::new(
+        call.body.clone(),
+        call.header("content-type").map(str::to_string),
+    );
+    // The SDK's aws-chunked framing is undone on arrival, so S3 does not store it as an encoding.
+    object.content_encoding = call
+        .header("content-encoding")
+        .map(|encodings| {
+            encodings
+                .split(',')
+                .map(str::trim)
+                .filter(|encoding| *encoding != "aws-chunked")
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .filter(|encodings| !encodings.is_empty());
+    for (name, value) in call.headers() {
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("x-amz-meta-") {
+            object.metadata.push((name, value.to_string()));
+        } else if let Some(checksum) = checksum_header(&name, value) {
+            object.checksum = Some(checksum);
+        }
+    }
+    let e_tag = object.e_tag.clone();
+    objects.insert(call.key.clone(), object);
+    response(200)
+        .header("ETag", e_tag)
+        .body(SdkBody::empty())
+        .unwrap()
+}
+
+/// The object named by an `x-amz-copy-source` header.
+fn copy_source<'a>(state: &'a MockState, call: &Call) -> Result<&'a MockObject, Box<MockResponse>> {
+    let source = percent_decode(call.header("x-amz-copy-source").unwrap_or_default());
+    let source = source.split('?').next().unwrap_or_default();
+    let (bucket, key) = source
+        .trim_start_matches('/')
+        .split_once('/')
+        .unwrap_or((source, ""));
+
+    match state.buckets.get(bucket) {
+        Some(objects) => objects.get(key).ok_or_else(|| {
+            Box::new(error(
+                404,
+                "NoSuchKey",
+                "The specified key does not exist.",
+                false,
+            ))
+        }),
+        None => Err(Box::new(error(
+            404,
+            "NoSuchBucket",
+            "The specified bucket does not exist",
+            false,
+        ))),
+    }
+}
+
+fn copy_object(state: &mut MockState, call: &Call) -> MockResponse {
+    let source = match copy_source(state, call) {
+        Ok(source) => source,
+        Err(response) => return *response,
+    };
+    let mut object = MockObject::new(source.data.clone(), source.content_type.clone());
+    object.content_encoding = source.content_encoding.clone();
+    object.metadata = source.metadata.clone();
+    let body = format!(
+        "<CopyObjectResult><ETag>{}</ETag><LastModified>{}</LastModified></CopyObjectResult>",
+        xml_escape(&object.e_tag),
+        iso_date(object.last_modified)
+    );
+
+    state
+        .buckets
+        .get_mut(&call.bucket)
+        .unwrap()
+        .insert(call.key.clone(), object);
+    xml(body)
+}
+
+fn create_upload(state: &mut MockState, call: &Call) -> MockResponse {
+    state.next_upload_id += 1;
+    let upload_id = format!("mock-upload-{}", state.next_upload_id);
+    state.uploads.insert(
+        upload_id.clone(),
+        MockUpload {
+            bucket: call.bucket.clone(),
+            key: call.key.clone(),
+            content_type: call.header("content-type").map(str::to_string),
+            parts: BTreeMap::new(),
+            part_checksums: BTreeMap::new(),
+        },
+    );
+
+    xml(format!(
+        "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+        xml_escape(&call.bucket),
+        xml_escape(&call.key),
+        upload_id
+    ))
+}
+
+fn upload_part(state: &mut MockState, call: &Call) -> MockResponse {
+    let upload_id = call.query.get("uploadId").cloned().unwrap_or_default();
+    let part_number: i32 = call
+        .query
+        .get("partNumber")
+        .and_then(|number| number.parse().ok())
+        .unwrap_or_default();
+    if let Some(response) = check_checksum(call) {
+        return response;
+    }
+
+    let (data, copied) = match call.header("x-amz-copy-source") {
+        Some(_) => {
+            let source = match copy_source(state, call) {
+                Ok(source) => source,
+                Err(response) => return *response,
+            };
+            let size = source.data.len() as u64;
+            let (start, end) = match call.header("x-amz-copy-source-range") {
+                Some(range) => match parse_range(range, size) {
+                    Some(range) => range,
+                    None => {
+                        return error(
+                            416,
+                            "InvalidRange",
+                            "The requested range is not satisfiable",
+                            false,
+                        )
+                    }
+                },
+                None => (0, size.saturating_sub(1)),
+            };
+            (source.data[start as usize..=end as usize].to_vec(), true)
+        }
+        None => (call.body.clone(), false),
+    };
+
+    let upload = match state.uploads.get_mut(&upload_id) {
+        Some(upload) => upload,
+        None => {
+            return error(
+                404,
+                "NoSuchUpload",
+                "The specified upload does not exist",
+                false,
+            )
+        }
+    };
+    let e_tag = format!("\"{}\"", to_hex(&Md5::digest(&data)));
+    upload.parts.insert(part_number, data);
+    let checksum = call
+        .request
+        .headers()
+        .iter()
+        .find_map(|(name, value)| checksum_header(&name.to_ascii_lowercase(), value));
+    match checksum {
+        Some(checksum) => upload.part_checksums.insert(part_number, checksum),
+        None => upload.part_checksums.remove(&part_number),
+    };
+
+    match copied {
+        true => xml(format!(
+            "<CopyPartResult><ETag>{}</ETag><LastModified>{}</LastModified></CopyPartResult>",
+            xml_escape(&e_tag),
+            iso_date(SystemTime::now())
+        )),
+        false => response(200)
+            .header("ETag", e_tag)
+            .body(SdkBody::empty())
+            .unwrap(),
+    }
+}
+
+fn complete_upload(state: &mut MockState, call: &Call) -> MockResponse {
+    let upload_id = call.query.get("uploadId").cloned().unwrap_or_default();
+    let upload = match state.uploads.remove(&upload_id) {
+        Some(upload) => upload,
+        None => {
+            return error(
+                404,
+                "NoSuchUpload",
+                "The specified upload does not exist",
+                false,
+            )
+        }
+    };
+
+    let mut digests = Vec::new();
+    let mut data = Vec::new();
+    for part in upload.parts.values() {
+        digests.extend_from_slice(&Md5::digest(part));
+        data.extend_from_slice(part);
+    }
+    let mut object = MockObject::new(data, upload.content_type);
+    object.e_tag = format!(
+        "\"{}-{}\"",
+        to_hex(&Md5::digest(&digests)),
+        upload.parts.len()
+    );
+    object.parts = upload
+        .parts
+        .iter()
+        .map(|(number, part)| {
+            let checksum = upload.part_checksums.get(number).cloned();
+            (part.len() as u64, checksum)
+        })
+        .collect();
+    object.checksum = composite_checksum(&object.parts);
+    let e_tag = object.e_tag.clone();
+
+    state
+        .buckets
+        .entry(upload.bucket.clone())
+        .or_default()
+        .insert(upload.key.clone(), object);
+    xml(format!(
+        "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+        xml_escape(&upload.bucket),
+        xml_escape(&upload.key),
+        xml_escape(&e_tag)
+    ))
+}
+
+fn abort_upload(state: &mut MockState, call: &Call) -> MockResponse {
+    let upload_id = call.query.get("uploadId").cloned().unwrap_or_default();
+    match state.uploads.remove(&upload_id) {
+        Some(_) => response(204).body(SdkBody::empty()).unwrap(),
+        None => error(
+            404,
+            "NoSuchUpload",
+            "The specified upload does not exist",
+            false,
+        ),
+    }
+}
+
+fn delete_objects(state: &mut MockState, call: &Call) -> MockResponse {
+    let request = String::from_utf8_lossy(&call.body);
+    let objects = state.buckets.get_mut(&call.bucket).unwrap();
+
+    let mut deleted = String::new();
+    for fragment in request.split("<Key>").skip(1) {
+        let key = xml_unescape(fragment.split("</Key>").next().unwrap_or_default());
+        objects.remove(&key);
+        let _ = write!(
+            deleted,
+            "<Deleted><Key>{}</Key></Deleted>",
+            xml_escape(&key)
+        );
+    }
+
+    xml(format!("<DeleteResult>{}</DeleteResult>", deleted))
+}
+
+fn create_session() -> MockResponse {
+    xml(format!(
+        "<CreateSessionResult><Credentials><SessionToken>mock-session</SessionToken><SecretAccessKey>mock</SecretAccessKey><AccessKeyId>mock</AccessKeyId><Expiration>{}</Expiration></Credentials></CreateSessionResult>",
+        iso_date(SystemTime::now() + Duration::from_secs(300))
+    ))
+}
+
+fn list_objects(state: &MockState, call: &Call) -> MockResponse {
+    let objects = &state.buckets[&call.bucket];
+    let prefix = call.query.get("prefix").cloned().unwrap_or_default();
+    let delimiter = call.query.get("delimiter").filter(|d| !d.is_empty());
+
+    let directory = call.bucket.ends_with("--x-s3");
+    if directory {
+        let refusal = if !prefix.is_empty() && !prefix.ends_with('/') {
+            Some("Prefixes must end in a delimiter for directory buckets")
+        } else if delimiter.is_some_and(|delimiter| delimiter != "/") {
+            Some("Only the / delimiter is supported for directory buckets")
+        } else if call.has_query("start-after") {
+            Some("StartAfter is not supported for directory buckets")
+        } else {
+            None
+        };
+        if let Some(message) = refusal {
+            return error(400, "InvalidArgument", message, false);
+        }
+    }
+    let max_keys: usize = call
+        .query
+        .get("max-keys")
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(1000);
+    let after = call
+        .query
+        .get("continuation-token")
+        .or_else(|| call.query.get("start-after"))
+        .cloned()
+        .unwrap_or_default();
+
+    let mut contents = Vec::new();
+    let mut common_prefixes = BTreeSet::new();
+    let mut count = 0;
+    let mut last = None;
+    let mut truncated = false;
+
+    for (key, object) in objects.range(prefix.clone()..) {
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        // A continuation token ending in the delimiter is a common prefix already returned.
+        let passed = key.as_str() <= after.as_str()
+            || (delimiter.is_some_and(|d| after.ends_with(d.as_str())) && key.starts_with(&after));
+        if !after.is_empty() && passed {
+            continue;
+        }
+
+        let rolled_up = delimiter.and_then(|delimiter| {
+            let rest = &key[prefix.len()..];
+            rest.find(delimiter.as_str())
+                .map(|index| key[..prefix.len() + index + delimiter.len()].to_string())
+        });
+        if let Some(common_prefix) = &rolled_up {
+            if common_prefixes.contains(common_prefix) {
+                continue;
+            }
+        }
+
+        if count == max_keys {
+            truncated = true;
+            break;
+        }
+        count += 1;
+
+        match rolled_up {
+            Some(common_prefix) => {
+                last = Some(common_prefix.clone());
+                common_prefixes.insert(common_prefix);
+            }
+            None => {
+                last = Some(key.clone());
+                contents.push(format!(
+                    "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>{}</StorageClass></Contents>",
+                    xml_escape(key),
+                    iso_date(object.last_modified),
+                    xml_escape(&object.e_tag),
+                    object.data.len(),
+                    object.storage_class
+                ));
+            }
+        }
+    }
+    // Directory buckets make no promise about order, so keys are returned backwards within each page.
+    if directory {
+        contents.reverse();
+    }
+
+    let mut body = format!(
+        "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        xml_escape(&call.bucket),
+        xml_escape(&prefix),
+        count,
+        max_keys,
+        truncated
+    );
+    body.push_str(&contents.concat());
+    for common_prefix in &common_prefixes {
+        let _ = write!(
+            body,
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            xml_escape(common_prefix)
+        );
+    }
+    if let (true, Some(last)) = (truncated, last) {
+        let _ = write!(
+            body,
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            xml_escape(&last)
+        );
+    }
+    body.push_str("</ListBucketResult>");
+    xml(body)
+}
+
+fn response(status: u16) -> http::response::Builder {
+    http::Response::builder()
+        .status(status)
+        .header("x-amz-request-id", "mock")
+}
+
+fn xml(body: String) -> MockResponse {
+    response(200)
+        .header("Content-Type", "application/xml")
+        .body(SdkBody::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}",
+            body
+        )))
+        .unwrap()
+}
+
+/// The data and trailers of a body sent with `aws-chunked` framing: each chunk is a hex length line, the
+/// data and a line break, ending with an empty chunk followed by `name:value` trailer lines.
+fn decode_chunked(body: &[u8]) -> Option<(Vec<u8>, Headers)> {
+    fn line(rest: &mut &[u8]) -> Option<String> {
+        let end = rest.windows(2).position(|pair| pair == b"\r\n")?;
+        let line = String::from_utf8(rest[..end].to_vec()).ok()?;
+        *rest = &rest[end + 2..];
+        Some(line)
+    }
+
+    let mut rest = body;
+    let mut data = Vec::new();
+    loop {
+        let header = line(&mut rest)?;
+        let size = header.split(';').next()?;
+        let size = usize::from_str_radix(size.trim(), 16).ok()?;
+        if size == 0 {
+            break;
+        }
+        data.extend_from_slice(rest.get(..size)?);
+        rest = rest.get(size + 2..)?;
+    }
+
+    let mut trailers = Vec::new();
+    while let Some(trailer) = line(&mut rest).filter(|trailer| !trailer.is_empty()) {
+        let (name, value) = trailer.split_once(':')?;
+        let name = name.trim().to_ascii_lowercase();
+        if name != "x-amz-trailer-signature" {
+            trailers.push((name, value.trim().to_string()));
+        }
+    }
+    Some((data, trailers))
+}
+
+/// The name and value of a checksum header this mock stores, if `name` is one.
+fn checksum_header(name: &str, value: &str) -> Option<(String, String)> {
+    matches!(name, "x-amz-checksum-crc32c" | "x-amz-checksum-sha256")
+        .then(|| (name.to_string(), value.to_string()))
+}
+
+/// The XML element GetObjectAttributes reports a checksum header in.
+fn checksum_element(header: &str) -> &'static str {
+    match header {
+        "x-amz-checksum-crc32c" => "ChecksumCRC32C",
+        _ => "ChecksumSHA256",
+    }
+}
+
+/// The checksum of an object uploaded in parts, if every part was sent with the same kind of checksum.
+fn composite_checksum(parts: &[(u64, Option<(String, String)>)]) -> Option<(String, String)> {
+    let (header, _) = parts.first()?.1.as_ref()?;
+    let algorithm = match header.as_str() {
+        "x-amz-checksum-crc32c" => ChecksumAlgorithm::Crc32c,
+        _ => ChecksumAlgorithm::Sha256,
+    };
+    let mut values = Vec::new();
+    for (_, checksum) in parts {
+        match checksum {
+            Some((name, value)) if name == header => values.push(value.as_str()),
+            _ => return None,
+        }
+    }
+    let checksum = ObjectChecksum::of_parts(algorithm, values)?;
+    Some((header.clone(), checksum.value))
+}
+
+/// A BadDigest error if the body does not match a checksum sent with it, as S3 answers.
+fn check_checksum(call: &Call) -> Option<MockResponse> {
+    for (header, algorithm) in [
+        ("x-amz-checksum-crc32c", ChecksumAlgorithm::Crc32c),
+        ("x-amz-checksum-sha256", ChecksumAlgorithm::Sha256),
+    ] {
+        if let Some(checksum) = call.header(header) {
+            if checksum != base64::encode(algorithm.digest(&call.body)) {
+                return Some(error(
+                    400,
+                    "BadDigest",
+                    "The checksum did not match the data received",
+                    false,
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// An S3 error response. Responses to HEAD requests have no body, as with S3.
+fn error(status: u16, code: &str, message: &str, head: bool) -> MockResponse {
+    let body = match head {
+        true => SdkBody::empty(),
+        false => SdkBody::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code><Message>{}</Message><RequestId>mock</RequestId></Error>",
+            code, message
+        )),
+    };
+    response(status)
+        .header("Content-Type", "application/xml")
+        .body(body)
+        .unwrap()
+}
+
+/// The inclusive byte range a `bytes=` Range header selects, or None if it cannot be satisfied.
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start, end) {
+        ("", suffix) => (
+            size.saturating_sub(suffix.parse().ok()?),
+            size.checked_sub(1)?,
+        ),
+        (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1)),
+    };
+    (start <= end && end < size).then_some((start, end))
+}
+
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| encoded.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn http_date(time: SystemTime) -> String {
+    DateTime::from(time)
+        .fmt(Format::HttpDate)
+        .unwrap_or_default()
+}
+
+fn iso_date(time: SystemTime) -> String {
+    DateTime::from(time)
+        .fmt(Format::DateTime)
+        .unwrap_or_default()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
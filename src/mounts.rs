@@ -0,0 +1,164 @@
+//! Several buckets configured together under one client and shared limits.
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use aws_sdk_s3::Client;
+use tokio::sync::Semaphore;
+
+use crate::{index::CacheIndex, limit::RateLimiter, CachePolicy, MetricsSink, OpenOptions};
+
+/// A set of [OpenOptions], one per bucket, looked up by a name of your choosing.
+///
+/// Every mount uses the same S3 client, and settings made on the S3Mounts apply to every mount, whether
+/// added before or after. Limits set with [S3Mounts::max_bandwidth] and
+/// [S3Mounts::max_requests_per_second], and slots set with [S3Mounts::max_concurrent_requests], are a
+/// single budget split between all the mounts rather than one each. Each bucket is still mirrored in its own folder under the mount path and keeps its own cache
+/// statistics.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::S3Mounts;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mounts = S3Mounts::new(None)
+///         .await
+///         .mount_path("data/")
+///         .max_requests_per_second(100)
+///         .add("raw", "my_raw_bucket")
+///         .add_with("reference", "my_reference_bucket", |options| options.read_only(true));
+///
+///     let raw = mounts.get("raw").unwrap();
+///     let file = raw.open_s3("some_folder/some_file.csv").await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct S3Mounts {
+    template: OpenOptions,
+    mounts: BTreeMap<String, OpenOptions>,
+}
+
+impl S3Mounts {
+    /// Create an empty set of mounts.
+    ///
+    /// Client is an optional argument - if it exists that will be the client used by every mount and if it
+    /// doesn't, one is created from your environment (the AWS CLI).
+    pub async fn new(client: Option<Client>) -> Self {
+        S3Mounts {
+            template: OpenOptions::new(String::new(), client).await,
+            mounts: BTreeMap::new(),
+        }
+    }
+
+    /// Add a bucket under `name`, configured like every other mount.
+    ///
+    /// Adding a name that is already in use replaces the earlier mount.
+    pub fn add<N, B>(self, name: N, bucket: B) -> Self
+    where
+        N: Into<String>,
+        B: Into<String>,
+    {
+        self.add_with(name, bucket, |options| options)
+    }
+
+    /// Add a bucket under `name`, adjusting its configuration with `configure`
+    ///
+    /// `configure` is given the mount as the shared settings leave it, so it can set options that only
+    /// apply to this bucket, such as [OpenOptions::read_only] or [OpenOptions::dry_run]. Shared settings
+    /// made on the S3Mounts afterwards still apply to it.
+    pub fn add_with<N, B, F>(mut self, name: N, bucket: B, configure: F) -> Self
+    where
+        N: Into<String>,
+        B: Into<String>,
+        F: FnOnce(OpenOptions) -> OpenOptions,
+    {
+        let mut options = self.template.clone();
+        options.bucket = bucket.into();
+        options.cache_index = Arc::new(CacheIndex::new(&options.mount_path, &options.bucket));
+        options.cache_counters = Arc::default();
+
+        self.mounts.insert(name.into(), configure(options));
+        self
+    }
+
+    /// The mount added under `name`.
+    pub fn get(&self, name: &str) -> Option<&OpenOptions> {
+        self.mounts.get(name)
+    }
+
+    /// Every mount with its name, in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &OpenOptions)> {
+        self.mounts
+            .iter()
+            .map(|(name, options)| (name.as_str(), options))
+    }
+
+    /// Set the folder every bucket is mirrored under, as [OpenOptions::mount_path] does for one.
+    pub fn mount_path<P>(self, folder_path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        let folder_path = folder_path.into();
+        self.apply(|options| options.mount_path(folder_path.clone()))
+    }
+
+    /// Choose when cached copies are used, as [OpenOptions::cache_policy] does for one.
+    pub fn cache_policy(self, policy: CachePolicy) -> Self {
+        self.apply(|options| options.cache_policy(policy))
+    }
+
+    /// Forbid changes to every bucket, as [OpenOptions::read_only] does for one.
+    pub fn read_only(self, read_only: bool) -> Self {
+        self.apply(|options| options.read_only(read_only))
+    }
+
+    /// Cap the combined throughput of downloads and uploads across every mount
+    ///
+    /// See [OpenOptions::max_bandwidth]. The limit is shared, so busy mounts take bandwidth from idle ones.
+    pub fn max_bandwidth(self, bytes_per_second: u64) -> Self {
+        let limiter = Arc::new(RateLimiter::new(bytes_per_second));
+        self.apply(|mut options| {
+            options.bandwidth_limiter = Some(limiter.clone());
+            options
+        })
+    }
+
+    /// Cap the combined rate of S3 API calls across every mount
+    ///
+    /// See [OpenOptions::max_requests_per_second]. The limit is shared, so several buckets in the same
+    /// account stay under its request limits together.
+    pub fn max_requests_per_second(self, requests_per_second: u64) -> Self {
+        let limiter = Arc::new(RateLimiter::new(requests_per_second));
+        self.apply(|mut options| {
+            options.request_limiter = Some(limiter.clone());
+            options
+        })
+    }
+
+    /// Cap how many S3 requests may be in flight at once across every mount
+    ///
+    /// See [OpenOptions::max_concurrent_requests]. The slots are shared, so a bulk transfer on one mount
+    /// cannot take every connection from the others.
+    pub fn max_concurrent_requests(self, requests: usize) -> Self {
+        let slots = Arc::new(Semaphore::new(requests.max(1)));
+        self.apply(|options| options.request_slots(slots.clone()))
+    }
+
+    /// Send metrics from every mount to `sink`, as [OpenOptions::metrics] does for one.
+    pub fn metrics(self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.apply(|options| options.metrics(sink.clone()))
+    }
+
+    /// Apply a shared setting to the template for future mounts and to every existing mount.
+    fn apply<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(OpenOptions) -> OpenOptions,
+    {
+        self.template = configure(self.template);
+        self.mounts = self
+            .mounts
+            .into_iter()
+            .map(|(name, options)| (name, configure(options)))
+            .collect();
+        self
+    }
+}
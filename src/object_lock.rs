@@ -0,0 +1,141 @@
+//! Writing objects into buckets with S3 Object Lock, under a retention period or legal hold.
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::{
+    primitives::DateTime,
+    types::{ObjectLockLegalHoldStatus, ObjectLockMode},
+};
+
+use crate::{ChecksumAlgorithm, DeleteOutcome, OpenOptions, WriteOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How strictly an Object Lock retention period protects an object version.
+pub enum RetentionMode {
+    /// Users with the `s3:BypassGovernanceRetention` permission can still delete the version or shorten
+    /// its retention. `GOVERNANCE`
+    Governance,
+    /// Nobody, including the root user, can delete the version until the period ends. `COMPLIANCE`
+    Compliance,
+}
+
+impl RetentionMode {
+    pub(crate) fn to_sdk(self) -> ObjectLockMode {
+        match self {
+            RetentionMode::Governance => ObjectLockMode::Governance,
+            RetentionMode::Compliance => ObjectLockMode::Compliance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The Object Lock settings for a single upload, as passed to [ObjectBackend::put](crate::ObjectBackend::put).
+///
+/// Backends without Object Lock ignore them.
+pub struct ObjectLock {
+    /// The retention mode and the time until which the new version is protected.
+    pub retention: Option<(RetentionMode, SystemTime)>,
+    /// Whether the new version is placed under a legal hold, which protects it until the hold is removed.
+    pub legal_hold: bool,
+}
+
+impl ObjectLock {
+    /// Whether any Object Lock setting is applied.
+    pub fn is_set(&self) -> bool {
+        self.retention.is_some() || self.legal_hold
+    }
+
+    pub(crate) fn mode(&self) -> Option<ObjectLockMode> {
+        self.retention.map(|(mode, _)| mode.to_sdk())
+    }
+
+    pub(crate) fn retain_until(&self) -> Option<DateTime> {
+        self.retention.map(|(_, until)| DateTime::from(until))
+    }
+
+    pub(crate) fn legal_hold_status(&self) -> Option<ObjectLockLegalHoldStatus> {
+        self.legal_hold.then_some(ObjectLockLegalHoldStatus::On)
+    }
+}
+
+impl WriteOptions {
+    /// Protect the new object version with an Object Lock retention period
+    ///
+    /// The version cannot be deleted or overwritten in place until `period` after the upload, as far as
+    /// `mode` allows. The bucket must have been created with Object Lock enabled, or S3 rejects the
+    /// upload. Writing the same key again still succeeds, as it creates a new version. S3 requires a
+    /// checksum on uploads with Object Lock settings, so a CRC-32C is sent if
+    /// [OpenOptions::upload_checksum](crate::OpenOptions::upload_checksum) is not set.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, RetentionMode, WriteOptions};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     // Audit records have to be kept for seven years.
+    ///     let options = WriteOptions::new()
+    ///         .retention(RetentionMode::Compliance, Duration::from_secs(7 * 365 * 24 * 60 * 60));
+    ///     open_options
+    ///         .write_s3_with("audit/2024-05-01.log", b"...", &options)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn retention(mut self, mode: RetentionMode, period: Duration) -> Self {
+        self.retention = Some((mode, period));
+        self
+    }
+
+    /// Place the new object version under an Object Lock legal hold
+    ///
+    /// A legal hold protects the version from deletion with no end date, until it is removed, and can be
+    /// combined with [WriteOptions::retention]. The bucket must have Object Lock enabled.
+    pub fn legal_hold(mut self, enabled: bool) -> Self {
+        self.legal_hold = enabled;
+        self
+    }
+
+    /// The Object Lock settings for an upload starting now.
+    pub(crate) fn object_lock(&self) -> ObjectLock {
+        ObjectLock {
+            retention: self
+                .retention
+                .map(|(mode, period)| (mode, SystemTime::now() + period)),
+            legal_hold: self.legal_hold,
+        }
+    }
+}
+
+impl OpenOptions {
+    /// The additional checksum to send with an upload: the one set with [OpenOptions::upload_checksum],
+    /// or CRC-32C if the upload has Object Lock settings, which S3 only accepts with a checksum.
+    pub(crate) fn checksum_algorithm(&self, object_lock: &ObjectLock) -> Option<ChecksumAlgorithm> {
+        self.upload_checksum
+            .or(object_lock.is_set().then_some(ChecksumAlgorithm::Crc32c))
+    }
+}
+
+impl DeleteOutcome {
+    /// Whether S3 refused to delete the object because it is under an Object Lock retention period or
+    /// legal hold.
+    pub fn is_object_locked(&self) -> bool {
+        match self {
+            DeleteOutcome::Failed { code, message, .. } => {
+                is_object_locked(code.as_deref(), message.as_deref())
+            }
+            DeleteOutcome::Deleted(_) => false,
+        }
+    }
+}
+
+/// Whether an S3 error code and message report that Object Lock protected an object version.
+pub(crate) fn is_object_locked(code: Option<&str>, message: Option<&str>) -> bool {
+    // S3 reports this as AccessDenied, with only the message saying why.
+    code == Some("AccessDenied")
+        && message.is_some_and(|message| message.to_ascii_lowercase().contains("object lock"))
+}
@@ -0,0 +1,167 @@
+//! Falling back to the local mirror when S3 cannot be reached.
+//!
+//! Listings are saved under `<mount_path>/.s3-filesystem/<bucket>.listings/`, one file per prefix named
+//! after the hex encoded prefix. Each line holds one entry:
+//!
+//! ```text
+//! <size>\t<1 if a folder, otherwise 0>\t<etag, or - if unknown>\t<last modified, seconds since the unix epoch, or - if unknown>\t<storage class, or - if unknown>\t<path>
+//! ```
+use std::{
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use tokio::fs::File;
+
+use crate::{index::INDEX_DIR, options::bucket_folder, DirEntry, OpenOptions, S3FilesystemError};
+
+#[derive(Debug)]
+/// A file opened by [OpenOptions::open_s3_or_cached].
+pub struct OpenedFile {
+    /// The opened file, ready to be read.
+    pub file: File,
+    /// True if S3 could not be reached and the file was served from the local mirror without
+    /// checking it was up to date.
+    pub stale: bool,
+}
+
+impl OpenOptions {
+    /// Keep working from the local mirror when S3 is unreachable
+    ///
+    /// With `offline` = true, a download that fails because S3 cannot be reached (no network, DNS
+    /// failures, timeouts) is answered from the local copy when there is one, rather than returning
+    /// an error. [OpenOptions::open_s3_or_cached] reports when this happens.
+    ///
+    /// Every successful [OpenOptions::walkdir] is also saved under the mount path, and replayed if a later
+    /// listing of the same path cannot reach S3. Enable this while connected so there is something to fall
+    /// back on once connectivity is lost.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Open a file from S3, reporting whether it came from the local mirror because S3 was unreachable
+    ///
+    /// Behaves like [OpenOptions::open_s3]. When [OpenOptions::offline] is enabled and S3 cannot be
+    /// reached, an existing local copy is returned with [OpenedFile::stale] set rather than failing.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be downloaded and opened.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{CachePolicy, OpenOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/")
+    ///         .cache_policy(CachePolicy::RevalidateEtag)
+    ///         .offline(true);
+    ///
+    ///     let opened = open_options
+    ///         .open_s3_or_cached("some_folder/some_file.csv")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     if opened.stale {
+    ///         println!("S3 is unreachable, using the cached copy");
+    ///     }
+    /// }
+    /// ```
+    pub async fn open_s3_or_cached<P>(&self, path: P) -> Result<OpenedFile, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.fetch_prefetching(path)
+            .await
+            .map_err(|e| e.with_context("GetObject", &self.bucket, Some(path)))
+    }
+
+    /// Save a listing so it can be served while offline.
+    pub(crate) async fn save_listing(&self, prefix: &str, entries: &[DirEntry]) -> io::Result<()> {
+        let path = self.listing_path(prefix);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut contents = String::new();
+        for entry in entries {
+            let _ = writeln!(
+                contents,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                entry.size,
+                u8::from(entry.folder),
+                entry.e_tag.as_deref().unwrap_or("-"),
+                entry
+                    .last_modified
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or("-".to_string(), |since| since.as_secs().to_string()),
+                entry.storage_class.as_deref().unwrap_or("-"),
+                entry.path.display()
+            );
+        }
+
+        let temporary = path.with_extension("listing.tmp");
+        tokio::fs::write(&temporary, contents).await?;
+        tokio::fs::rename(&temporary, &path).await
+    }
+
+    /// The listing last saved for `prefix`, if there is one.
+    pub(crate) async fn load_listing(&self, prefix: &str) -> io::Result<Option<Vec<DirEntry>>> {
+        let contents = match tokio::fs::read_to_string(self.listing_path(prefix)).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(contents.lines().filter_map(parse_line).collect()))
+    }
+
+    fn listing_path(&self, prefix: &str) -> PathBuf {
+        let mut file_name = prefix.bytes().fold(String::new(), |mut name, byte| {
+            let _ = write!(name, "{:02x}", byte);
+            name
+        });
+        file_name.push_str(".listing");
+
+        self.mount_path
+            .join(INDEX_DIR)
+            .join(format!("{}.listings", bucket_folder(&self.bucket)))
+            .join(file_name)
+    }
+}
+
+fn parse_line(line: &str) -> Option<DirEntry> {
+    let mut fields = line.splitn(6, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let folder = fields.next()? == "1";
+    let e_tag = match fields.next()? {
+        "-" => None,
+        e_tag => Some(e_tag.to_string()),
+    };
+    let last_modified = match fields.next()? {
+        "-" => None,
+        seconds => Some(UNIX_EPOCH + Duration::from_secs(seconds.parse().ok()?)),
+    };
+    let storage_class = match fields.next()? {
+        "-" => None,
+        storage_class => Some(storage_class.to_string()),
+    };
+    let path = PathBuf::from(fields.next()?);
+
+    Some(DirEntry {
+        path,
+        size,
+        folder,
+        e_tag,
+        last_modified,
+        storage_class,
+    })
+}
@@ -0,0 +1,2202 @@
+use aws_sdk_s3::{primitives::ByteStream, types::ObjectCannedAcl, Client};
+use bytes::{Bytes, BytesMut};
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+    sync::Semaphore,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    backend::{Backend, GetRequest, ListPage, ListRequest, ObjectHead, PutRequest},
+    backoff::{Backoff, BackoffInterceptor},
+    cache::{fit_mirror_path, hashed_name, CacheCounters, CacheLayout, CachePolicy},
+    checksum::{ChecksumAlgorithm, ObjectChecksum},
+    dry_run::{DryRunLog, DryRunOperation},
+    error::S3FilesystemError,
+    index::{CacheIndex, CachedObject},
+    journal::JournalOperation,
+    key::{s3_key, s3_prefix},
+    limit::{RateLimiter, RequestSlot},
+    metrics::Metrics,
+    mime::content_type_for,
+    object_lock::{ObjectLock, RetentionMode},
+    offline::OpenedFile,
+    prefetch::Prefetcher,
+    tls::config_loader,
+    upload::{DEFAULT_PART_SIZE, MULTIPART_THRESHOLD},
+};
+
+/// The default location files are mirrored to when no mount path is given.
+pub const DEFAULT_DATA_STORE: &str = "target/temp";
+
+/// Directory under the system temp dir that dry run writes are redirected to.
+const DRY_RUN_DIR: &str = "s3-filesystem-dry-run";
+
+/// The most keys S3 accepts in a single DeleteObjects request.
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// Holds configuration data for syncing S3 objects.
+///
+/// Bucket will specify the bucket which is mounted at mount_path. It will
+/// download the file from the bucket to the path maintaining the same folder
+/// structure. Its cache policy decides whether whatever is found on disk at that location is used or
+/// the file is downloaded from S3 again.
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    pub(crate) s3_client: Client,
+    pub(crate) backend: Backend,
+    pub(crate) bucket: String,
+    pub(crate) mount_path: PathBuf,
+    pub(crate) cache_policy: CachePolicy,
+    pub(crate) bandwidth_limiter: Option<Arc<RateLimiter>>,
+    pub(crate) request_limiter: Option<Arc<RateLimiter>>,
+    pub(crate) request_slots: Option<Arc<Semaphore>>,
+    pub(crate) backoff: Arc<Backoff>,
+    pub(crate) adaptive_backoff: bool,
+    pub(crate) dry_run: Option<Arc<DryRunLog>>,
+    pub(crate) read_only: bool,
+    pub(crate) metrics: Option<Metrics>,
+    pub(crate) cache_counters: Arc<CacheCounters>,
+    pub(crate) cache_index: Arc<CacheIndex>,
+    pub(crate) offline: bool,
+    pub(crate) download_parts: usize,
+    pub(crate) download_buffer_size: usize,
+    pub(crate) part_size: u64,
+    pub(crate) upload_concurrency: usize,
+    pub(crate) cache_layout: CacheLayout,
+    pub(crate) cache_quota: Option<u64>,
+    pub(crate) block_size: Option<u64>,
+    pub(crate) sparse_cache: bool,
+    pub(crate) decompress: bool,
+    pub(crate) decompress_gz_suffix: bool,
+    pub(crate) keep_compressed: bool,
+    pub(crate) skip_unchanged_uploads: bool,
+    pub(crate) write_back: bool,
+    pub(crate) trash: Option<String>,
+    pub(crate) upload_acl: Option<CannedAcl>,
+    pub(crate) upload_checksum: Option<ChecksumAlgorithm>,
+    pub(crate) cancellation: Option<CancellationToken>,
+    pub(crate) prefetcher: Option<Arc<Prefetcher>>,
+    pub(crate) prefix: String,
+}
+
+impl OpenOptions {
+    /// Create a new OpenOptions struct.
+    ///
+    /// This function should be used to create a new option configuration for your S3 bucket and
+    /// filesystem. If data is needed from another bucket, a new OpenOptions should be created.
+    ///
+    /// Client is an optional argument - if it exists that will be the client used
+    /// and if it doesn't, this function will automatically create an S3 client
+    /// from your environment (the AWS CLI). To create it from a named AWS profile instead, use
+    /// [OpenOptionsBuilder::profile](crate::OpenOptionsBuilder::profile).
+    ///
+    /// If non default mount paths are wanted, the function [OpenOptions::mount_path] can be
+    /// used, and if you wish to re-download or revalidate data each time, [OpenOptions::cache_policy] can
+    /// be used.
+    ///
+    /// `bucket` may also be an S3 Access Point ARN, such as
+    /// `arn:aws:s3:eu-west-2:123456789012:accesspoint/shared-data`, in which case every request goes
+    /// through that access point. Its files are mirrored under a folder named after the ARN, with `:` and
+    /// `/` replaced by `_`.
+    ///
+    /// `bucket` may also be an S3 Express One Zone directory bucket, such as `logs--use1-az4--x-s3`, as long
+    /// as the client's region is the one the zone is in. The client creates and refreshes the session
+    /// directory buckets need itself. Directory buckets list keys out of order, so listings are sorted here
+    /// after they are fetched, and [OpenOptions::walkdir_page] pages are only sorted within each page.
+    ///
+    /// # Examples
+    ///
+    ///```no_run
+    /// use s3_filesystem::{CachePolicy, OpenOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///  let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///  let open_options = OpenOptions::new(bucket, None)
+    ///     .await
+    ///     .mount_path("data/test/")
+    ///     .cache_policy(CachePolicy::AlwaysDownload);
+    /// }
+    /// ```
+    pub async fn new(bucket: String, client: Option<Client>) -> Self {
+        let s3_client = match client {
+            Some(x) => x,
+            None => {
+                let config = config_loader().load().await;
+                aws_sdk_s3::Client::new(&config)
+            }
+        };
+        let backoff = Arc::new(Backoff::default());
+        let s3_client = Client::from_conf(
+            s3_client
+                .config()
+                .to_builder()
+                .interceptor(BackoffInterceptor(backoff.clone()))
+                .build(),
+        );
+
+        let mount_path = PathBuf::from(DEFAULT_DATA_STORE);
+        let cache_index = Arc::new(CacheIndex::new(&mount_path, &bucket));
+
+        OpenOptions {
+            backend: Backend::s3(s3_client.clone()),
+            s3_client,
+            bucket,
+            mount_path,
+            cache_policy: CachePolicy::UseCacheIfPresent,
+            bandwidth_limiter: None,
+            request_limiter: None,
+            request_slots: None,
+            backoff,
+            adaptive_backoff: true,
+            dry_run: None,
+            read_only: false,
+            metrics: None,
+            cache_counters: Arc::default(),
+            cache_index,
+            offline: false,
+            download_parts: 1,
+            download_buffer_size: 0,
+            part_size: DEFAULT_PART_SIZE,
+            upload_concurrency: 1,
+            cache_layout: CacheLayout::Mirror,
+            cache_quota: None,
+            block_size: None,
+            sparse_cache: false,
+            decompress: false,
+            decompress_gz_suffix: false,
+            keep_compressed: false,
+            skip_unchanged_uploads: false,
+            write_back: false,
+            trash: None,
+            upload_acl: None,
+            upload_checksum: None,
+            cancellation: None,
+            prefetcher: None,
+            prefix: String::new(),
+        }
+    }
+
+    /// Attach a custom mount path.
+    ///
+    /// By default any data downloaded from S3 is found in target/temp. This can
+    /// be changed by using this function!
+    pub fn mount_path<P>(mut self, folder_path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.mount_path = folder_path.into();
+        self.cache_index = Arc::new(CacheIndex::new(&self.mount_path, &self.bucket));
+        self
+    }
+
+    /// Choose when cached copies are used instead of downloading
+    ///
+    /// Cache is supported by default - if a file with the same name is found on disk
+    /// then it is read in ([CachePolicy::UseCacheIfPresent]). [CachePolicy::AlwaysDownload] downloads
+    /// every time, [CachePolicy::RevalidateEtag] and [CachePolicy::MaxAge] check cached copies with S3
+    /// and only download objects which have changed, and [CachePolicy::OfflineOnly] never contacts S3.
+    pub fn cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = policy;
+        self
+    }
+
+    /// Cap the throughput of downloads and uploads
+    ///
+    /// By default transfers run as fast as the network allows. Pass a limit in bytes per second to
+    /// keep background jobs from saturating the link. The limit is shared by every clone of this
+    /// OpenOptions, so concurrent transfers split it between them rather than each getting the full
+    /// amount.
+    pub fn max_bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth_limiter = Some(Arc::new(RateLimiter::new(bytes_per_second)));
+        self
+    }
+
+    /// Cap the rate of S3 API calls
+    ///
+    /// Every request made to S3 (listing, downloading, uploading and so on) takes a token from a bucket
+    /// which refills at `requests_per_second`. Bursts of up to one second's worth of requests go straight
+    /// through, after which requests are spaced out. Like [OpenOptions::max_bandwidth], the limit is
+    /// shared by every clone of this OpenOptions, keeping bulk operations under account request limits
+    /// and avoiding 503 SlowDown responses.
+    pub fn max_requests_per_second(mut self, requests_per_second: u64) -> Self {
+        self.request_limiter = Some(Arc::new(RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    /// Cap how many S3 requests may be in flight at once
+    ///
+    /// Every request made to S3 first takes one of `requests` slots, and gives it back once the request
+    /// and any data it streams are done, so a large [OpenOptions::download_prefix_stream] or parallel upload
+    /// cannot use every connection the rest of the application needs. Requests beyond the limit wait for
+    /// a slot. The slots are shared by every clone of this OpenOptions; to share them with other
+    /// OpenOptions or with the rest of the application, use [OpenOptions::request_slots].
+    ///
+    /// # Arguments
+    /// * `requests`: How many requests may be in flight at once. At least one is always allowed.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .max_concurrent_requests(32);
+    ///
+    ///     // However much concurrency is asked for here, it runs in at most 32 requests at once...
+    ///     let bulk = open_options.download_prefix_stream("datasets/", 256);
+    ///     tokio::spawn(bulk.collect::<Vec<_>>());
+    ///
+    ///     // ...and this waits for a free slot rather than for the whole bulk download.
+    ///     let config = open_options.read_to_string("config.toml").await.unwrap();
+    /// }
+    /// ```
+    pub fn max_concurrent_requests(self, requests: usize) -> Self {
+        self.request_slots(Arc::new(Semaphore::new(requests.max(1))))
+    }
+
+    /// Take request slots from `slots`, shared with whatever else holds it
+    ///
+    /// Like [OpenOptions::max_concurrent_requests], but the semaphore is yours, so one limit can cover
+    /// several OpenOptions for different buckets, or other code that talks to S3 and takes permits from
+    /// the same semaphore. Each request holds one permit while it is in flight.
+    ///
+    /// # Arguments
+    /// * `slots`: The semaphore each request takes a permit from.
+    pub fn request_slots(mut self, slots: Arc<Semaphore>) -> Self {
+        self.request_slots = Some(slots);
+        self
+    }
+
+    /// Choose how files are arranged under the mount path
+    ///
+    /// By default ([CacheLayout::Mirror]) the mount path mirrors the folder structure of the bucket.
+    /// [CacheLayout::Hashed] instead keeps every file in one flat folder, named by a hash of its key, for
+    /// buckets whose keys nest very deeply or use characters the local filesystem cannot store. Files
+    /// cached under one layout are not found under the other, so pick one per mount path.
+    pub fn cache_layout(mut self, layout: CacheLayout) -> Self {
+        self.cache_layout = layout;
+        self
+    }
+
+    /// Apply a canned ACL to every uploaded object
+    ///
+    /// Objects written with [OpenOptions::write_s3] and its variants are stored with `acl`, such as
+    /// [CannedAcl::BucketOwnerFullControl] when delivering into a bucket owned by another account. By
+    /// default no ACL is sent and the bucket's defaults apply. [WriteOptions::acl] overrides this for a
+    /// single upload.
+    pub fn upload_acl(mut self, acl: CannedAcl) -> Self {
+        self.upload_acl = Some(acl);
+        self
+    }
+
+    /// Download large files as several concurrent Range requests
+    ///
+    /// A single GetObject stream tops out well below what S3 can deliver. With `parts` above 1, files of
+    /// at least 8 MiB are split into up to `parts` ranges which are downloaded at once, each written
+    /// straight to its offset in the destination file. This costs one extra HeadObject request per
+    /// download to learn the file's size. A download interrupted part way starts again rather than
+    /// resuming. Defaults to 1, a single request per file.
+    pub fn parallel_download(mut self, parts: usize) -> Self {
+        self.download_parts = parts.max(1);
+        self
+    }
+
+    /// Collect downloaded data into writes of up to `bytes` before writing it to disk
+    ///
+    /// S3 sends data in small chunks, and by default each is written to the destination file as it
+    /// arrives. A larger buffer means fewer, bigger writes, which helps on disks and network filesystems
+    /// where each write is costly, at the cost of `bytes` of memory per download (or per range, with
+    /// [OpenOptions::parallel_download]). Defaults to 0, no buffering.
+    pub fn download_buffer_size(mut self, bytes: usize) -> Self {
+        self.download_buffer_size = bytes;
+        self
+    }
+
+    /// Report mutations instead of performing them
+    ///
+    /// With `dry_run` = true, operations that would change the bucket (such as [OpenOptions::write_s3] and
+    /// [OpenOptions::copy_s3]) skip their S3 calls and record what they would have done, which can be
+    /// inspected with [OpenOptions::dry_run_report]. Reads and listings still go to S3 as normal, so bulk
+    /// jobs can be validated end to end.
+    ///
+    /// Dry run writes never touch the mount path, so the local mirror stays in step with S3. The file
+    /// returned by [OpenOptions::write_s3] is instead written under the system temp directory.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run.then(|| Arc::new(DryRunLog::default()));
+        self
+    }
+
+    /// Forbid any changes to the bucket
+    ///
+    /// With `read_only` = true, every method that would write to or delete from S3 returns
+    /// [S3FilesystemError::ReadOnly] straight away, before any local or remote work is done. This takes
+    /// precedence over [OpenOptions::dry_run]. Use it to guarantee production readers never mutate a bucket.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// The operations skipped so far in dry run mode, in the order they were attempted.
+    ///
+    /// The report is shared by every clone of this OpenOptions and is empty when dry run is disabled.
+    pub fn dry_run_report(&self) -> Vec<DryRunOperation> {
+        match &self.dry_run {
+            Some(log) => log.operations(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Where `key` is mirrored locally, refusing keys that would land outside the mount path.
+    pub(crate) fn local_path(&self, key: &str) -> Result<PathBuf, S3FilesystemError> {
+        let root = self.mount_path.join(bucket_folder(&self.bucket));
+        match self.cache_layout {
+            CacheLayout::Mirror => Ok(fit_mirror_path(&root, mirror_path(&root, key)?, key)),
+            CacheLayout::Hashed => Ok(root.join(hashed_name(key))),
+        }
+    }
+
+    /// Wait for a free request slot and for the request limiter, if any, to allow another S3 call.
+    ///
+    /// The returned slot holds the request's place, so keep it until the request, and any body it streams,
+    /// is done.
+    pub(crate) async fn throttle_request(&self) -> RequestSlot {
+        let permit = match &self.request_slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let in_flight = match self.adaptive_backoff {
+            true => Some(self.backoff.acquire().await),
+            false => None,
+        };
+        if let Some(limiter) = &self.request_limiter {
+            limiter.acquire(1).await;
+        }
+        RequestSlot::new(permit, in_flight)
+    }
+}
+
+impl OpenOptions {
+    /// Open a file from S3.
+    ///
+    /// This function will find the S3 file, download it and return a [tokio::fs::File] ready to be read. Doing it this way
+    /// enables large files to be downloaded in chunks as well as local caching..
+    ///
+    /// Files will be placed in the `mount_path` and all folder structure is retained. Folders will be created
+    /// if they do not exist already.
+    /// Characters Windows does not allow in file names (`:`, `*`, `?`, `"`, `<`, `>`, `|` and control
+    /// characters) are percent encoded in local file names, as is `%`, on every platform.
+    ///
+    /// Downloads are written to a `.part` file next to the destination and only renamed into place once
+    /// complete, so an interrupted download never leaves a truncated file that looks like a cached copy.
+    /// The next attempt resumes from the last byte received with a Range request, provided the object's
+    /// ETag is unchanged; otherwise it starts again from the beginning.
+    ///
+    /// Each download and write holds an advisory lock on its file, so processes sharing a mount path never
+    /// write the same file at once. A process which finds a file locked waits, then uses the copy the
+    /// other process downloaded.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be downloaded and opened.
+    ///```no_run
+    /// use s3_filesystem::{CachePolicy, OpenOptions};
+    /// use tokio::io::AsyncReadExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///  let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///  let open_options = OpenOptions::new(bucket, None)
+    ///     .await
+    ///     .mount_path("data/test/")
+    ///     .cache_policy(CachePolicy::AlwaysDownload);
+    ///
+    /// let mut file = open_options
+    ///     .open_s3("redasa1-Q1-20/manifest.txt")
+    ///     .await
+    ///     .unwrap();
+    ///
+    ///  let mut string = String::new();
+    ///
+    ///  file.read_to_string(&mut string).await.unwrap();
+    ///
+    ///  println!("String: {}", string);
+    /// }
+    /// ```
+    pub async fn open_s3<P>(&self, path: P) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(self.open_s3_or_cached(path).await?.file)
+    }
+
+    /// Download a file from S3 to a path of your choosing
+    ///
+    /// Behaves like [OpenOptions::open_s3], except the file is written to `local_path` rather than under
+    /// the mount path, for when another tool dictates where files must go. Parent folders are created
+    /// as needed, and an existing file at `local_path` is reused as the
+    /// [OpenOptions::cache_policy] allows. Files downloaded this way are not recorded in the cache index.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be downloaded and opened.
+    /// * `local_path`: Where the file should be written locally.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     open_options
+    ///         .open_s3_to("models/latest.onnx", "/opt/inference/model.onnx")
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn open_s3_to<P, Q>(&self, path: P, local_path: Q) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        Ok(self
+            .fetch(path, Some(local_path.as_ref()))
+            .await
+            .map_err(|e| e.with_context("GetObject", &self.bucket, Some(path)))?
+            .file)
+    }
+
+    /// Serve a file from `destination`, or the local mirror if None, downloading it first if needed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "open_s3",
+            level = "debug",
+            skip_all,
+            fields(bucket = %self.bucket, key = %path.display(), cache_hit, stale, bytes),
+            err
+        )
+    )]
+    pub(crate) async fn fetch(
+        &self,
+        path: &Path,
+        destination: Option<&Path>,
+    ) -> Result<OpenedFile, S3FilesystemError> {
+        let s3_data_path = s3_key(path)?;
+
+        let full_data_path = match destination {
+            Some(destination) => destination.to_path_buf(),
+            None => self.local_path(&s3_data_path)?,
+        };
+
+        let exists = std::fs::metadata(&full_data_path).is_ok();
+        // Copies which may need revalidating are looked up in the cache index for their age and ETag.
+        let revalidates = matches!(
+            self.cache_policy,
+            CachePolicy::RevalidateEtag | CachePolicy::MaxAge(_)
+        );
+        let cached = match exists && revalidates && destination.is_none() {
+            true => self.cached_entry(&s3_data_path, &full_data_path).await,
+            false => None,
+        };
+        // Writes held back by write_back are newer than S3's copy, so they are always served.
+        let pending = exists && destination.is_none() && self.is_pending(&s3_data_path).await?;
+        let fresh = pending || (exists && self.is_fresh(cached.as_ref(), &full_data_path));
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("cache_hit", fresh);
+
+        self.cache_counters.record(fresh);
+        if let Some(metrics) = &self.metrics {
+            match fresh {
+                true => metrics.cache_hit(&s3_data_path),
+                false => metrics.cache_miss(&s3_data_path),
+            }
+        }
+
+        if !exists && self.cache_policy == CachePolicy::OfflineOnly {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "The file is not in the local mirror and the cache policy is OfflineOnly",
+            )
+            .into());
+        }
+
+        if fresh {
+            return Ok(OpenedFile {
+                file: tokio::fs::OpenOptions::new()
+                    .read(true)
+                    .open(&full_data_path)
+                    .await?,
+                stale: false,
+            });
+        }
+
+        if let Some(parent_path) = full_data_path.parent() {
+            std::fs::create_dir_all(parent_path)?;
+        }
+
+        // Another process sharing the mount path may be downloading the same file, and may have finished.
+        let _lock = self.lock_cache_file(&full_data_path).await?;
+        if !exists
+            && self.cache_policy != CachePolicy::AlwaysDownload
+            && std::fs::metadata(&full_data_path).is_ok()
+        {
+            return Ok(OpenedFile {
+                file: tokio::fs::OpenOptions::new()
+                    .read(true)
+                    .open(&full_data_path)
+                    .await?,
+                stale: false,
+            });
+        }
+
+        // The download is journalled, so a crash part way through can be recovered.
+        let journalled = destination.is_none();
+        if journalled {
+            self.cache_index
+                .journal
+                .begin(&s3_data_path, JournalOperation::Download, None)
+                .await?;
+        }
+        let result = self
+            .download(&s3_data_path, &full_data_path, destination, exists, cached)
+            .await;
+        if journalled {
+            self.cache_index.journal.end(&s3_data_path).await?;
+        }
+        result
+    }
+
+    /// Download `s3_data_path` to `full_data_path`, resuming a partial download if there is one, once the
+    /// cached copy has been found to be missing or stale.
+    async fn download(
+        &self,
+        s3_data_path: &str,
+        full_data_path: &Path,
+        destination: Option<&Path>,
+        exists: bool,
+        cached: Option<CachedObject>,
+    ) -> Result<OpenedFile, S3FilesystemError> {
+        // A cached copy being revalidated is only downloaded again if the object has changed.
+        let cached_e_tag = cached.as_ref().and_then(|cached| cached.e_tag.clone());
+
+        let part_path = part_path(full_data_path);
+        let e_tag_path = part_e_tag_path(full_data_path);
+
+        // Pick up where an interrupted download left off, provided the object has not changed since.
+        let mut resume = match tokio::fs::read_to_string(&e_tag_path).await {
+            Ok(e_tag) => match tokio::fs::metadata(&part_path).await {
+                Ok(metadata) if metadata.len() > 0 => Some((metadata.len(), e_tag)),
+                _ => None,
+            },
+            Err(_) => None,
+        };
+
+        // Large objects can be fetched as several concurrent Range requests rather than one stream.
+        if self.download_parts > 1 && resume.is_none() {
+            match self.plan_ranges(s3_data_path).await {
+                Ok(Some(head)) if head.e_tag.is_some() && head.e_tag == cached_e_tag => {
+                    return self.serve_revalidated(cached, full_data_path).await;
+                }
+                Ok(Some(ObjectHead {
+                    size,
+                    e_tag,
+                    content_encoding,
+                    ..
+                })) => {
+                    discard_partial(&part_path, &e_tag_path).await?;
+                    self.reserve_space(full_data_path, destination.is_none(), size)
+                        .await?;
+                    if let Err(e) = self
+                        .cancellable(self.fetch_ranges(
+                            s3_data_path,
+                            &part_path,
+                            size,
+                            e_tag.as_deref(),
+                        ))
+                        .await
+                    {
+                        discard_partial(&part_path, &e_tag_path).await?;
+                        return Err(e);
+                    }
+                    let decoded_size = self
+                        .decode_download(
+                            s3_data_path,
+                            full_data_path,
+                            destination.is_none(),
+                            content_encoding,
+                            size,
+                        )
+                        .await?;
+                    return self
+                        .finish_fetch(
+                            s3_data_path,
+                            full_data_path,
+                            destination.is_none(),
+                            e_tag,
+                            decoded_size,
+                            size,
+                        )
+                        .await;
+                }
+                Ok(None) => {}
+                // Let the single request below decide whether to serve the stale copy.
+                Err(e) if self.offline && exists && e.is_unreachable() => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let (mut object, slot) = loop {
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self
+                .cancellable(self.backend.get(GetRequest {
+                    bucket: &self.bucket,
+                    key: s3_data_path,
+                    range: resume.as_ref().map(|(offset, _)| (*offset, None)),
+                    if_match: resume.as_ref().map(|(_, e_tag)| e_tag.as_str()),
+                    if_none_match: match resume {
+                        Some(_) => None,
+                        None => cached_e_tag.as_deref(),
+                    },
+                }))
+                .await;
+            self.record_request("GetObject", started, result.is_ok());
+
+            let err = match result {
+                Ok(object) => break (object, slot),
+                Err(e) => e,
+            };
+            drop(slot);
+
+            if err.is_not_modified() {
+                return self.serve_revalidated(cached, full_data_path).await;
+            }
+
+            // The object has changed since the partial download, or it was already complete.
+            if err.is_cancelled() {
+                discard_partial(&part_path, &e_tag_path).await?;
+                return Err(err);
+            }
+            if resume.is_some()
+                && (err.is_precondition_failed() || matches!(err.status(), Some(412 | 416)))
+            {
+                discard_partial(&part_path, &e_tag_path).await?;
+                resume = None;
+                continue;
+            }
+
+            if self.offline && exists && err.is_unreachable() {
+                #[cfg(feature = "tracing")]
+                tracing::Span::current().record("stale", true);
+                return Ok(OpenedFile {
+                    file: tokio::fs::OpenOptions::new()
+                        .read(true)
+                        .open(full_data_path)
+                        .await?,
+                    stale: true,
+                });
+            }
+            return Err(err);
+        };
+
+        let e_tag = object.e_tag.clone();
+        let content_encoding = object.content_encoding.take();
+        let resumed_from = resume.map_or(0, |(offset, _)| offset);
+        if resumed_from == 0 {
+            discard_partial(&part_path, &e_tag_path).await?;
+        }
+        self.reserve_space(
+            full_data_path,
+            destination.is_none(),
+            object.body.size_hint().0,
+        )
+        .await?;
+
+        let part_file = match resumed_from {
+            0 => {
+                let part_file = tokio::fs::File::create(&part_path).await?;
+                if let Some(e_tag) = &e_tag {
+                    tokio::fs::write(&e_tag_path, e_tag).await?;
+                }
+                part_file
+            }
+            _ => {
+                tokio::fs::OpenOptions::new()
+                    .append(true)
+                    .open(&part_path)
+                    .await?
+            }
+        };
+
+        let mut part_file = BufWriter::with_capacity(self.download_buffer_size, part_file);
+        let downloaded: Result<u64, S3FilesystemError> = self
+            .cancellable(async {
+                let mut downloaded_bytes = 0;
+                while let Some(bytes) = object.body.try_next().await? {
+                    if let Some(limiter) = &self.bandwidth_limiter {
+                        limiter.acquire(bytes.len() as u64).await;
+                    }
+                    part_file.write_all(&bytes).await?;
+                    downloaded_bytes += bytes.len() as u64;
+                }
+                part_file.flush().await?;
+                part_file.get_ref().sync_all().await?;
+                Ok(downloaded_bytes)
+            })
+            .await;
+        drop(slot);
+
+        let downloaded_bytes = match downloaded {
+            Ok(downloaded_bytes) => downloaded_bytes,
+            Err(e) => {
+                let _ = part_file.flush().await;
+                let _ = part_file.get_ref().sync_all().await;
+                drop(part_file);
+                // Without an ETag there is no way to tell whether the object changes before a retry.
+                if e_tag.is_none() || e.is_cancelled() {
+                    discard_partial(&part_path, &e_tag_path).await?;
+                }
+                return Err(e);
+            }
+        };
+        drop(part_file);
+
+        let decoded_size = self
+            .decode_download(
+                s3_data_path,
+                full_data_path,
+                destination.is_none(),
+                content_encoding,
+                resumed_from + downloaded_bytes,
+            )
+            .await?;
+        self.finish_fetch(
+            s3_data_path,
+            full_data_path,
+            destination.is_none(),
+            e_tag,
+            decoded_size,
+            downloaded_bytes,
+        )
+        .await
+    }
+
+    /// The cache index's record of the mirrored copy of `key` at `path`, if the file is still the size it
+    /// was recorded at.
+    async fn cached_entry(&self, key: &str, path: &Path) -> Option<CachedObject> {
+        let cached = self.cache_index.get(key).await.ok()??;
+        let size = tokio::fs::metadata(path).await.ok()?.len();
+        (cached.size == size).then_some(cached)
+    }
+
+    /// Whether the cache policy allows the copy at `path` to be used without contacting S3.
+    ///
+    /// The age of a copy is taken from the cache index, or for files outside the mirror from when the file
+    /// was last written.
+    fn is_fresh(&self, cached: Option<&CachedObject>, path: &Path) -> bool {
+        match self.cache_policy {
+            CachePolicy::AlwaysDownload | CachePolicy::RevalidateEtag => false,
+            CachePolicy::UseCacheIfPresent | CachePolicy::OfflineOnly => true,
+            CachePolicy::MaxAge(max_age) => {
+                let cached_at = match cached {
+                    Some(cached) => Some(cached.cached_at),
+                    None => std::fs::metadata(path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok(),
+                };
+                cached_at
+                    .and_then(|cached_at| cached_at.elapsed().ok())
+                    .is_some_and(|age| age < max_age)
+            }
+        }
+    }
+
+    /// Open the cached copy S3 reported as unchanged, restarting its age for [CachePolicy::MaxAge].
+    async fn serve_revalidated(
+        &self,
+        cached: Option<CachedObject>,
+        path: &Path,
+    ) -> Result<OpenedFile, S3FilesystemError> {
+        if let Some(cached) = cached {
+            self.cache_index
+                .insert(CachedObject {
+                    cached_at: SystemTime::now(),
+                    ..cached
+                })
+                .await?;
+        }
+
+        Ok(OpenedFile {
+            file: tokio::fs::OpenOptions::new().read(true).open(path).await?,
+            stale: false,
+        })
+    }
+
+    /// Move a completed download into place, record it in the cache index if `index` is set and open it.
+    async fn finish_fetch(
+        &self,
+        key: &str,
+        full_data_path: &Path,
+        index: bool,
+        e_tag: Option<String>,
+        size: u64,
+        downloaded_bytes: u64,
+    ) -> Result<OpenedFile, S3FilesystemError> {
+        tokio::fs::rename(part_path(full_data_path), full_data_path).await?;
+        discard_partial(&part_path(full_data_path), &part_e_tag_path(full_data_path)).await?;
+        if index {
+            self.cache_index
+                .insert(CachedObject {
+                    key: key.to_string(),
+                    e_tag,
+                    checksum: None,
+                    size,
+                    cached_at: SystemTime::now(),
+                })
+                .await?;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("bytes", downloaded_bytes);
+        if let Some(metrics) = &self.metrics {
+            metrics.downloaded(key, downloaded_bytes);
+        }
+
+        Ok(OpenedFile {
+            file: tokio::fs::OpenOptions::new()
+                .read(true)
+                .open(full_data_path)
+                .await?,
+            stale: false,
+        })
+    }
+
+    /// Read the entire contents of a file from S3 into a bytes vector
+    ///
+    /// A shorthand for [OpenOptions::open_s3] followed by reading the whole file, so the usual caching
+    /// applies.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be read.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let data = open_options.read("some_folder/some_file.csv").await.unwrap();
+    ///
+    ///     println!("Read {} bytes", data.len());
+    /// }
+    /// ```
+    pub async fn read<P>(&self, path: P) -> Result<Vec<u8>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = self.open_s3(path).await?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).await?;
+
+        Ok(contents)
+    }
+
+    /// Read the entire contents of a file from S3 into a string
+    ///
+    /// A shorthand for [OpenOptions::open_s3] followed by reading the whole file, so the usual caching
+    /// applies. An [io::ErrorKind::InvalidData] error is returned if the file is not valid UTF-8.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be read.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let manifest = open_options.read_to_string("manifest.txt").await.unwrap();
+    ///
+    ///     println!("Manifest: {}", manifest);
+    /// }
+    /// ```
+    pub async fn read_to_string<P>(&self, path: P) -> Result<String, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = self.open_s3(path).await?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await?;
+
+        Ok(contents)
+    }
+
+    /// Read a file from S3 straight into memory
+    ///
+    /// Unlike [OpenOptions::open_s3], nothing is written to or read from the mount path, so there is no
+    /// disk round trip. Best suited to small objects such as configuration files; large objects are better
+    /// streamed through [OpenOptions::open_s3] or [OpenOptions::open_s3_lazy].
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be read.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let config = open_options.read_s3("config/settings.json").await.unwrap();
+    ///
+    ///     println!("Config is {} bytes", config.len());
+    /// }
+    /// ```
+    pub async fn read_s3<P>(&self, path: P) -> Result<Bytes, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.download_to_memory(path)
+            .await
+            .map_err(|e| e.with_context("GetObject", &self.bucket, Some(path)))
+    }
+
+    async fn download_to_memory(&self, path: &Path) -> Result<Bytes, S3FilesystemError> {
+        let s3_data_path = s3_key(path)?;
+        if self.is_pending(&s3_data_path).await? {
+            let contents = tokio::fs::read(self.local_path(&s3_data_path)?).await?;
+            return Ok(contents.into());
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .cancellable(self.backend.get(GetRequest {
+                bucket: &self.bucket,
+                key: &s3_data_path,
+                range: None,
+                if_match: None,
+                if_none_match: None,
+            }))
+            .await;
+        self.record_request("GetObject", started, result.is_ok());
+        let mut object = result?;
+
+        let contents = self
+            .cancellable(async {
+                let mut contents = BytesMut::new();
+                while let Some(bytes) = object.body.try_next().await? {
+                    if let Some(limiter) = &self.bandwidth_limiter {
+                        limiter.acquire(bytes.len() as u64).await;
+                    }
+                    contents.extend_from_slice(&bytes);
+                }
+                Ok(contents)
+            })
+            .await?;
+        drop(slot);
+
+        if let Some(metrics) = &self.metrics {
+            metrics.downloaded(&s3_data_path, contents.len() as u64);
+        }
+        Ok(contents.freeze())
+    }
+
+    /// Write a file to S3
+    ///
+    /// Enter a path relative to the bucket and this function will create a file in S3 and on your local system under
+    /// the mount path chosen in [OpenOptions]. This will overwrite any files that exist with the same name and will
+    /// return the file that has been written to.
+    ///
+    /// The object's Content-Type is guessed from the file extension. To set it yourself, use
+    /// [OpenOptions::write_s3_with].
+    ///
+    /// Data of 64 MiB or more is uploaded in parts. If such an upload is interrupted it can be finished later with
+    /// [OpenOptions::resume_upload] rather than starting again; see [OpenOptions::pending_uploads].
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, where you wish to store the data.
+    /// * `buf`: The data you wish to store.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{CachePolicy, OpenOptions};
+    /// use tokio::fs;
+    ///
+    /// const BUCKET: &str = "test-bucket";
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = BUCKET.to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/")
+    ///         .cache_policy(CachePolicy::AlwaysDownload);
+    ///
+    ///     let data = fs::read("data/manifest.txt").await.unwrap();
+    ///
+    ///     open_options.write_s3("manifest.txt", &data).await.unwrap();
+    ///
+    ///     println!("Data uploaded successfully");
+    /// }
+    pub async fn write_s3<P>(&self, path: P, buf: &[u8]) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.put_s3(path, buf, &WriteOptions::new()).await
+    }
+
+    /// Write a file to S3 only if no object exists at that path
+    ///
+    /// Behaves like [OpenOptions::write_s3], except the upload is sent with `If-None-Match: *`. If another
+    /// writer has already created the object, S3 rejects the upload and
+    /// an error for which [S3FilesystemError::is_precondition_failed] is true is returned, so concurrent writers cannot silently overwrite
+    /// each other.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, where you wish to store the data.
+    /// * `buf`: The data you wish to store.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     match open_options.write_s3_if_absent("results/run-1.csv", b"a,b,c").await {
+    ///         Ok(_) => println!("Results written"),
+    ///         Err(e) if e.is_precondition_failed() => println!("Another worker got there first"),
+    ///         Err(e) => panic!("{}", e),
+    ///     }
+    /// }
+    /// ```
+    pub async fn write_s3_if_absent<P>(
+        &self,
+        path: P,
+        buf: &[u8],
+    ) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.put_s3(
+            path,
+            buf,
+            &WriteOptions::new().precondition(WritePrecondition::IfAbsent),
+        )
+        .await
+    }
+
+    /// Write a file to S3 only if a precondition on the existing object holds
+    ///
+    /// Behaves like [OpenOptions::write_s3], except the upload is rejected by S3 with
+    /// [S3FilesystemError::PreconditionFailed] if `precondition` does not hold. Use
+    /// [WritePrecondition::IfMatch] with the ETag from a previous read or listing to implement
+    /// optimistic concurrency: the write only succeeds if nobody else has changed the object since.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, where you wish to store the data.
+    /// * `buf`: The data you wish to store.
+    /// * `precondition`: The condition the existing object must meet.
+    pub async fn write_s3_conditional<P>(
+        &self,
+        path: P,
+        buf: &[u8],
+        precondition: WritePrecondition,
+    ) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.put_s3(path, buf, &WriteOptions::new().precondition(precondition))
+            .await
+    }
+
+    /// Write a file to S3 with settings for this upload
+    ///
+    /// Behaves like [OpenOptions::write_s3], with `options` overriding how the object is stored: for
+    /// example its Content-Type, or a precondition as [OpenOptions::write_s3_conditional] takes.
+    ///
+    /// With [OpenOptions::write_back] enabled, only writes with the default [WriteOptions::new] are held
+    /// until flushed. Pending writes are recorded by key alone, so any other options could not be applied
+    /// by the flush, and the write is uploaded straight away instead.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, where you wish to store the data.
+    /// * `buf`: The data you wish to store.
+    /// * `options`: Settings for this upload.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, WriteOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     open_options
+    ///         .write_s3_with(
+    ///             "site/feed",
+    ///             b"<rss></rss>",
+    ///             &WriteOptions::new().content_type("application/rss+xml"),
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn write_s3_with<P>(
+        &self,
+        path: P,
+        buf: &[u8],
+        options: &WriteOptions,
+    ) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.put_s3(path, buf, options).await
+    }
+
+    /// Upload data to S3 without writing a copy under the mount path
+    ///
+    /// [OpenOptions::write_s3] writes every upload to the mount path first, which fails when there is little
+    /// local disk, such as in a container with a small writable layer. This sends `buf` straight from memory
+    /// instead. Any copy of the file already in the mount path is removed, as it no longer matches S3.
+    ///
+    /// The Content-Type is guessed from the file extension and [OpenOptions::upload_acl] is applied, as for
+    /// [OpenOptions::write_s3]. Data of 64 MiB or more is still uploaded in parts, but with nothing staged
+    /// on disk an interrupted upload cannot be resumed and is aborted instead. Pass a `Vec<u8>` or [Bytes] to
+    /// hand the buffer over without copying it.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, where you wish to store the data.
+    /// * `buf`: The data you wish to store.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let report = format!("{} rows processed", 1024).into_bytes();
+    ///     open_options
+    ///         .write_s3_direct("reports/latest.txt", report)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn write_s3_direct<P, B>(&self, path: P, buf: B) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+        B: Into<Bytes>,
+    {
+        let path = path.as_ref();
+        self.upload_direct(path, buf.into())
+            .await
+            .map_err(|e| e.with_context("PutObject", &self.bucket, Some(path)))
+    }
+
+    async fn upload_direct(&self, path: &Path, data: Bytes) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let s3_data_path = s3_key(path)?;
+        // Rejects keys which could not be mirrored, as write_s3 does.
+        self.local_path(&s3_data_path)?;
+        let size = data.len() as u64;
+
+        if self.unchanged_remote(&s3_data_path, &data).await?.is_some() {
+            return match self.dry_run {
+                Some(_) => Ok(()),
+                None => self.evict(&s3_data_path).await,
+            };
+        }
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Upload {
+                key: s3_data_path,
+                size,
+            });
+            return Ok(());
+        }
+
+        let content_type = content_type_for(&s3_data_path);
+        let acl = self.upload_acl;
+
+        if size >= MULTIPART_THRESHOLD {
+            self.upload_multipart_from_memory(
+                &s3_data_path,
+                data,
+                content_type,
+                acl.map(CannedAcl::to_sdk),
+            )
+            .await?;
+        } else {
+            let checksum = self
+                .upload_checksum
+                .map(|algorithm| ObjectChecksum::of(algorithm, &data));
+            let mut byte_stream = ByteStream::from(data);
+            if let Some(limiter) = &self.bandwidth_limiter {
+                byte_stream = limiter.throttle_body(byte_stream);
+            }
+
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self
+                .backend
+                .put(PutRequest {
+                    bucket: &self.bucket,
+                    key: &s3_data_path,
+                    body: byte_stream,
+                    content_type,
+                    acl,
+                    precondition: None,
+                    checksum: checksum.as_ref(),
+                    object_lock: ObjectLock::default(),
+                })
+                .await;
+            self.record_request("PutObject", started, result.is_ok());
+            drop(slot);
+            result?;
+
+            if let Some(metrics) = &self.metrics {
+                metrics.uploaded(&s3_data_path, size);
+            }
+        }
+
+        self.evict(&s3_data_path).await
+    }
+
+    /// Write to the local mirror and upload with the given settings.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "write_s3",
+            level = "debug",
+            skip_all,
+            fields(bucket = %self.bucket, key = %path.as_ref().display(), bytes = buf.len()),
+            err
+        )
+    )]
+    async fn put_s3<P>(
+        &self,
+        path: P,
+        buf: &[u8],
+        options: &WriteOptions,
+    ) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.journalled_upload(path, buf, options)
+            .await
+            .map_err(|e| e.with_context("PutObject", &self.bucket, Some(path)))
+    }
+
+    /// Upload as [OpenOptions::upload] does, journalling the upload while it changes the mirror.
+    async fn journalled_upload(
+        &self,
+        path: &Path,
+        buf: &[u8],
+        options: &WriteOptions,
+    ) -> Result<File, S3FilesystemError> {
+        if self.read_only || self.dry_run.is_some() {
+            return self.upload(path, buf, options).await;
+        }
+
+        let key = s3_key(path)?;
+        self.cache_index
+            .journal
+            .begin(&key, JournalOperation::Upload, Some(buf.len() as u64))
+            .await?;
+        let result = self.upload(path, buf, options).await;
+        self.cache_index.journal.end(&key).await?;
+        result
+    }
+
+    async fn upload(
+        &self,
+        path: &Path,
+        buf: &[u8],
+        options: &WriteOptions,
+    ) -> Result<File, S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let s3_data_path = s3_key(path)?;
+        let full_data_path = match &self.dry_run {
+            Some(_) => mirror_path(
+                &std::env::temp_dir()
+                    .join(DRY_RUN_DIR)
+                    .join(bucket_folder(&self.bucket)),
+                &s3_data_path,
+            )?,
+            None => self.local_path(&s3_data_path)?,
+        };
+        if let Some(parent_path) = full_data_path.parent() {
+            std::fs::create_dir_all(parent_path)?;
+        }
+        let _lock = match self.dry_run {
+            Some(_) => None,
+            None => Some(self.lock_cache_file(&full_data_path).await?),
+        };
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&full_data_path)
+            .await?;
+
+        file.write_all(buf).await?;
+
+        if self.write_back && self.dry_run.is_none() && *options == WriteOptions::new() {
+            self.cache_index
+                .insert(CachedObject {
+                    key: s3_data_path.clone(),
+                    e_tag: None,
+                    checksum: None,
+                    size: buf.len() as u64,
+                    cached_at: SystemTime::now(),
+                })
+                .await?;
+            self.cache_index.pending.insert(&s3_data_path).await?;
+            return Ok(file);
+        }
+
+        // Skipping an upload would leave the existing version without the requested Object Lock.
+        let object_lock = options.object_lock();
+        if options.precondition.is_none() && !object_lock.is_set() {
+            if let Some(head) = self.unchanged_remote(&s3_data_path, buf).await? {
+                if self.dry_run.is_none() {
+                    self.cache_index
+                        .insert(CachedObject {
+                            key: s3_data_path,
+                            e_tag: head.e_tag,
+                            checksum: head.checksum,
+                            size: head.size,
+                            cached_at: SystemTime::now(),
+                        })
+                        .await?;
+                }
+                return Ok(file);
+            }
+        }
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Upload {
+                key: s3_data_path,
+                size: buf.len() as u64,
+            });
+            return Ok(file);
+        }
+
+        let content_type = options
+            .content_type
+            .clone()
+            .or_else(|| content_type_for(&s3_data_path).map(str::to_string));
+        let acl = options.acl.or(self.upload_acl);
+
+        // Large unconditional writes go up in parts so an interruption can be resumed with resume_upload.
+        if options.precondition.is_none() && buf.len() as u64 >= MULTIPART_THRESHOLD {
+            return match self
+                .upload_multipart(
+                    &s3_data_path,
+                    buf,
+                    content_type.as_deref(),
+                    acl.map(CannedAcl::to_sdk),
+                    object_lock,
+                )
+                .await
+            {
+                Ok((e_tag, checksum)) => {
+                    self.cache_index
+                        .insert(CachedObject {
+                            key: s3_data_path,
+                            e_tag,
+                            checksum,
+                            size: buf.len() as u64,
+                            cached_at: SystemTime::now(),
+                        })
+                        .await?;
+                    Ok(file)
+                }
+                Err(e) => {
+                    self.evict(&s3_data_path).await?;
+                    Err(e)
+                }
+            };
+        }
+
+        let checksum = self
+            .checksum_algorithm(&object_lock)
+            .map(|algorithm| ObjectChecksum::of(algorithm, buf));
+        let mut byte_stream = ByteStream::from_path(&full_data_path).await?;
+        if let Some(limiter) = &self.bandwidth_limiter {
+            byte_stream = limiter.throttle_body(byte_stream);
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .put(PutRequest {
+                bucket: &self.bucket,
+                key: &s3_data_path,
+                body: byte_stream,
+                content_type: content_type.as_deref(),
+                acl,
+                precondition: options.precondition.as_ref(),
+                checksum: checksum.as_ref(),
+                object_lock,
+            })
+            .await;
+        self.record_request("PutObject", started, result.is_ok());
+        drop(slot);
+
+        match result {
+            Ok(e_tag) => {
+                self.cache_index
+                    .insert(CachedObject {
+                        key: s3_data_path.clone(),
+                        e_tag,
+                        checksum,
+                        size: buf.len() as u64,
+                        cached_at: SystemTime::now(),
+                    })
+                    .await?;
+                if let Some(metrics) = &self.metrics {
+                    metrics.uploaded(&s3_data_path, buf.len() as u64);
+                }
+                Ok(file)
+            }
+            Err(e) => {
+                self.evict(&s3_data_path).await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Copy an object within the bucket
+    ///
+    /// The copy happens server-side with CopyObject, so the data never passes through your machine. Any
+    /// locally mirrored copy of the destination is removed so the next [OpenOptions::open_s3] fetches the
+    /// new contents rather than serving a stale file.
+    ///
+    /// # Arguments
+    /// * `from`: The path, including filename, of the object to copy.
+    /// * `to`: The path, including filename, the object should be copied to.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     open_options
+    ///         .copy_s3("some_folder/some_file.csv", "backup/some_file.csv")
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn copy_s3<P, Q>(&self, from: P, to: Q) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let to = to.as_ref();
+        self.copy_object(from.as_ref(), to)
+            .await
+            .map_err(|e| e.with_context("CopyObject", &self.bucket, Some(to)))
+    }
+
+    async fn copy_object(&self, from: &Path, to: &Path) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let source_key = s3_key(from)?;
+        let destination_key = s3_key(to)?;
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Copy {
+                from: source_key,
+                to: destination_key,
+            });
+            return Ok(());
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(copy_source(&self.bucket, &self.remote_key(&source_key)))
+            .key(self.remote_key(&destination_key))
+            .send()
+            .await;
+        self.record_request("CopyObject", started, result.is_ok());
+        drop(slot);
+        result?;
+
+        self.evict(&destination_key).await
+    }
+
+    /// Delete many objects from the bucket
+    ///
+    /// Keys are sent in batches of up to 1000 with DeleteObjects, rather than one request per object. Each
+    /// key gets a [DeleteOutcome] saying whether S3 deleted it, and any locally mirrored copies of deleted
+    /// objects are removed. Deleting a key which does not exist counts as a success, as it does in S3.
+    /// With [OpenOptions::trash] set, each object is copied to the trash first.
+    ///
+    /// An error is only returned if a whole batch request fails; in that case earlier batches will already
+    /// have been deleted.
+    ///
+    /// # Arguments
+    /// * `paths`: The paths, including filenames, of the objects to delete.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{DeleteOutcome, OpenOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let stale: Vec<_> = open_options
+    ///         .walkdir("tmp/")
+    ///         .await
+    ///         .unwrap()
+    ///         .into_iter()
+    ///         .map(|entry| entry.path)
+    ///         .collect();
+    ///
+    ///     for outcome in open_options.delete_many(&stale).await.unwrap() {
+    ///         if let DeleteOutcome::Failed { key, message, .. } = outcome {
+    ///             println!("Could not delete {}: {:?}", key, message);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn delete_many<I, P>(&self, paths: I) -> Result<Vec<DeleteOutcome>, S3FilesystemError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let paths = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect::<Vec<_>>();
+        self.delete_objects(&paths)
+            .await
+            .map_err(|e| e.with_context("DeleteObjects", &self.bucket, None))
+    }
+
+    async fn delete_objects(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<Vec<DeleteOutcome>, S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let keys = paths
+            .iter()
+            .map(|path| s3_key(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut outcomes = Vec::with_capacity(keys.len());
+        let trash_folder = self.trash_folder();
+
+        for batch in keys.chunks(DELETE_BATCH_SIZE) {
+            if let Some(log) = &self.dry_run {
+                for key in batch {
+                    let trash_key = trash_folder
+                        .as_ref()
+                        .and_then(|folder| self.trash_key(folder, key));
+                    if let Some(trash_key) = trash_key {
+                        log.record(DryRunOperation::Copy {
+                            from: key.clone(),
+                            to: trash_key,
+                        });
+                    }
+                    log.record(DryRunOperation::Delete { key: key.clone() });
+                    outcomes.push(DeleteOutcome::Deleted(key.clone()));
+                }
+                continue;
+            }
+
+            let batch = match &trash_folder {
+                Some(folder) => {
+                    let (deletable, failed) = self.copy_to_trash(batch, folder).await?;
+                    outcomes.extend(failed);
+                    if deletable.is_empty() {
+                        continue;
+                    }
+                    deletable
+                }
+                None => batch.to_vec(),
+            };
+
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self.backend.delete(&self.bucket, &batch).await;
+            self.record_request("DeleteObjects", started, result.is_ok());
+            drop(slot);
+
+            for outcome in result? {
+                if let DeleteOutcome::Deleted(key) = &outcome {
+                    self.evict(key).await?;
+                }
+                outcomes.push(outcome);
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Return a list of S3 objects within the bucket
+    ///
+    /// This function returns the files and folders (S3 objects) in the bucket defined in [OpenOptions]. A sub path
+    /// can be specified to return a subset of the items - for the entire bucket provide an empty string: "".
+    ///
+    /// Listing is paginated internally, so every object under the path is returned regardless of how
+    /// many requests that takes.
+    ///
+    /// It returns their path, size, and whether or not it's a directory, but be wary - directories do not exist in S3.
+    /// This function will return any directories that have been created as a dummy object ending in "/" within S3. It is not
+    /// guaranteed to find all directories. This may change in upcoming versions.
+    ///
+    /// To limit how deep the listing goes, use [OpenOptions::walk] instead.
+    ///
+    /// # Arguments
+    /// * `path`: A path to search within the S3 bucket. If you want the entire bucket, just specify an empty string: "".
+    ///
+    /// # Examples
+    /// ```rust no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let data = open_options.walkdir("").await.unwrap();
+    ///
+    ///     for dat in data {
+    ///         println!("Data: {:?}", dat);
+    ///     }
+    /// }
+    /// ```
+    pub async fn walkdir<P>(&self, path: P) -> Result<Vec<DirEntry>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.walk(path).list().await
+    }
+
+    /// List one page of the objects under a path
+    ///
+    /// A lower level version of [OpenOptions::walkdir] which makes a single listing request and returns
+    /// its entries along with [ListPage::continuation_token]. Pass that token back in to get the next page;
+    /// it is None once the listing is finished. As the token is a plain string it can be saved, letting a
+    /// long running job checkpoint its progress through a large bucket and carry on after a restart.
+    ///
+    /// # Arguments
+    /// * `path`: A path to search within the S3 bucket. If you want the entire bucket, just specify an empty string: "".
+    /// * `continuation`: None for the first page, then the token from the previous page.
+    /// * `max_keys`: The most entries to return. S3 returns at most 1000 per page whatever is asked for.
+    ///
+    /// Each page of a directory bucket is sorted, but keys are not in order from one page to the next, and a
+    /// page may hold fewer than `max_keys` entries before the listing is finished.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     // Picked up from a checkpoint written by an earlier run, if there was one.
+    ///     let mut continuation = std::fs::read_to_string("listing.checkpoint").ok();
+    ///
+    ///     loop {
+    ///         let page = open_options
+    ///             .walkdir_page("datasets/", continuation, 1000)
+    ///             .await
+    ///             .unwrap();
+    ///
+    ///         for entry in &page.entries {
+    ///             println!("{}", entry.path.display());
+    ///         }
+    ///
+    ///         continuation = page.continuation_token;
+    ///         match &continuation {
+    ///             Some(token) => std::fs::write("listing.checkpoint", token).unwrap(),
+    ///             None => break,
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn walkdir_page<P>(
+        &self,
+        path: P,
+        continuation: Option<String>,
+        max_keys: usize,
+    ) -> Result<ListPage, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.list_page(path, continuation, max_keys)
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(path)))
+    }
+
+    async fn list_page(
+        &self,
+        path: &Path,
+        continuation: Option<String>,
+        max_keys: usize,
+    ) -> Result<ListPage, S3FilesystemError> {
+        let prefix = s3_prefix(path)?;
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .list(ListRequest {
+                bucket: &self.bucket,
+                prefix: &prefix,
+                delimiter: None,
+                start_after: None,
+                continuation_token: continuation,
+                max_keys: Some(max_keys),
+            })
+            .await;
+        self.record_request("ListObjectsV2", started, result.is_ok());
+        drop(slot);
+        result
+    }
+
+    /// Page through every object under `prefix`.
+    ///
+    /// With `delimited`, only objects directly under the prefix are returned as entries, along with the
+    /// sub-prefixes ("folders") S3 rolled up at the next `/`. Objects `keep` rejects are dropped page by
+    /// page rather than collected.
+    pub(crate) async fn list_objects(
+        &self,
+        prefix: &str,
+        delimited: bool,
+        keep: &(dyn Fn(&DirEntry) -> bool + Sync),
+    ) -> Result<(Vec<DirEntry>, Vec<String>), S3FilesystemError> {
+        self.list_objects_window(prefix, delimited, keep, None, None)
+            .await
+    }
+
+    /// Page through the objects under `prefix` as [OpenOptions::list_objects] does, starting after the
+    /// key `start_after` and stopping once `max_keys` objects have been kept.
+    pub(crate) async fn list_objects_window(
+        &self,
+        prefix: &str,
+        delimited: bool,
+        keep: &(dyn Fn(&DirEntry) -> bool + Sync),
+        start_after: Option<&str>,
+        max_keys: Option<usize>,
+    ) -> Result<(Vec<DirEntry>, Vec<String>), S3FilesystemError> {
+        let directory = is_directory_bucket(&self.bucket);
+        let mut data_to_return = Vec::new();
+        let mut common_prefixes = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self
+                .cancellable(self.backend.list(ListRequest {
+                    bucket: &self.bucket,
+                    prefix,
+                    delimiter: delimited.then_some("/"),
+                    start_after,
+                    continuation_token,
+                    max_keys: None,
+                }))
+                .await;
+            self.record_request("ListObjectsV2", started, result.is_ok());
+            drop(slot);
+            let page = result?;
+
+            data_to_return.extend(page.entries.into_iter().filter(|entry| keep(entry)));
+            common_prefixes.extend(page.common_prefixes);
+
+            // Directory buckets list out of key order, so the first keys are only known once every page is in.
+            if max_keys.is_some_and(|max_keys| !directory && data_to_return.len() >= max_keys) {
+                break;
+            }
+
+            continuation_token = page.continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        if directory {
+            data_to_return.sort_by(|a, b| a.path.cmp(&b.path));
+            common_prefixes.sort();
+            common_prefixes.dedup();
+        }
+        if let Some(max_keys) = max_keys {
+            data_to_return.truncate(max_keys);
+        }
+
+        Ok((data_to_return, common_prefixes))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A condition the existing object must meet for a conditional write to go ahead.
+pub enum WritePrecondition {
+    /// Only write if there is no object at the path yet.
+    IfAbsent,
+    /// Only write if the existing object's ETag matches this one.
+    IfMatch(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Settings for a single upload with [OpenOptions::write_s3_with].
+pub struct WriteOptions {
+    pub(crate) content_type: Option<String>,
+    pub(crate) acl: Option<CannedAcl>,
+    pub(crate) precondition: Option<WritePrecondition>,
+    pub(crate) retention: Option<(RetentionMode, Duration)>,
+    pub(crate) legal_hold: bool,
+}
+
+impl WriteOptions {
+    /// Settings matching a plain [OpenOptions::write_s3].
+    pub fn new() -> Self {
+        WriteOptions::default()
+    }
+
+    /// Store the object with this Content-Type
+    ///
+    /// By default the Content-Type is guessed from the file extension, such as `text/csv` for `.csv`
+    /// files, and left for S3 to default to `binary/octet-stream` for extensions it does not recognise.
+    /// Objects served through CloudFront or S3 website hosting are sent with this header.
+    pub fn content_type<C>(mut self, content_type: C) -> Self
+    where
+        C: Into<String>,
+    {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Store the object with this canned ACL, overriding any set with [OpenOptions::upload_acl].
+    pub fn acl(mut self, acl: CannedAcl) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    /// Only write if `precondition` holds, as [OpenOptions::write_s3_conditional] does.
+    pub fn precondition(mut self, precondition: WritePrecondition) -> Self {
+        self.precondition = Some(precondition);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A canned ACL applied to uploaded objects, for buckets that still grant access through ACLs.
+///
+/// Buckets with S3 Object Ownership set to "bucket owner enforced", the default for new buckets, have ACLs
+/// disabled and reject uploads with any ACL other than [CannedAcl::BucketOwnerFullControl].
+pub enum CannedAcl {
+    /// Only the object owner has access. `private`
+    Private,
+    /// Anyone can read the object. `public-read`
+    PublicRead,
+    /// Anyone can read and write the object. `public-read-write`
+    PublicReadWrite,
+    /// Any authenticated AWS user can read the object. `authenticated-read`
+    AuthenticatedRead,
+    /// EC2 can read the object to launch AMIs. `aws-exec-read`
+    AwsExecRead,
+    /// The bucket owner can read the object. `bucket-owner-read`
+    BucketOwnerRead,
+    /// The bucket owner has full control of the object, as cross-account delivery usually requires.
+    /// `bucket-owner-full-control`
+    BucketOwnerFullControl,
+}
+
+impl CannedAcl {
+    pub(crate) fn to_sdk(self) -> ObjectCannedAcl {
+        match self {
+            CannedAcl::Private => ObjectCannedAcl::Private,
+            CannedAcl::PublicRead => ObjectCannedAcl::PublicRead,
+            CannedAcl::PublicReadWrite => ObjectCannedAcl::PublicReadWrite,
+            CannedAcl::AuthenticatedRead => ObjectCannedAcl::AuthenticatedRead,
+            CannedAcl::AwsExecRead => ObjectCannedAcl::AwsExecRead,
+            CannedAcl::BucketOwnerRead => ObjectCannedAcl::BucketOwnerRead,
+            CannedAcl::BucketOwnerFullControl => ObjectCannedAcl::BucketOwnerFullControl,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The result of deleting a single key with [OpenOptions::delete_many].
+pub enum DeleteOutcome {
+    /// The object was deleted, or did not exist.
+    Deleted(String),
+    /// S3 refused to delete the object, or it could not be copied to the [trash](OpenOptions::trash).
+    Failed {
+        /// The key which could not be deleted.
+        key: String,
+        /// The S3 error code, for instance "AccessDenied".
+        code: Option<String>,
+        /// A description of the error from S3.
+        message: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Holds information describing a file or folder.
+pub struct DirEntry {
+    /// Path data is located at in S3.
+    pub path: PathBuf,
+    /// Size of the data in bytes. Folders = 0 bytes.
+    pub size: i64,
+    /// Whether the S3 object is a folder or not.
+    pub folder: bool,
+    /// The entity tag S3 holds for the object, which changes whenever its contents do.
+    pub e_tag: Option<String>,
+    /// When the object was last written. Unknown for folders S3 rolled up from longer keys.
+    pub last_modified: Option<SystemTime>,
+    /// The storage class S3 reported for the object, such as "STANDARD" or "GLACIER".
+    pub storage_class: Option<String>,
+}
+
+impl DirEntry {
+    /// Whether the object is in Glacier Flexible Retrieval or Deep Archive, and so has to be restored with
+    /// [OpenOptions::restore](crate::OpenOptions::restore) before it can be downloaded.
+    ///
+    /// Intelligent-Tiering objects moved to an archive access tier are listed as "INTELLIGENT_TIERING"
+    /// and cannot be told apart here; downloading one fails with an error for which
+    /// [S3FilesystemError::is_archived] is true.
+    pub fn is_archived(&self) -> bool {
+        matches!(
+            self.storage_class.as_deref(),
+            Some("GLACIER" | "DEEP_ARCHIVE")
+        )
+    }
+}
+
+/// Join `key` onto `root`, rejecting keys with `..`, absolute or drive prefixed segments which would
+/// escape it.
+///
+/// Characters Windows does not allow in file names are percent encoded (see [escape_key]) on every
+/// platform, so a mirror can be copied between machines.
+pub(crate) fn mirror_path(root: &Path, key: &str) -> Result<PathBuf, S3FilesystemError> {
+    let escaped = escape_key(key);
+    let escapes = escaped.starts_with('/')
+        || Path::new(&escaped)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+
+    match escapes {
+        true => Err(S3FilesystemError::PathTraversal(key.to_string())),
+        false => Ok(root.join(escaped)),
+    }
+}
+
+/// Percent encode the characters in a key that are invalid in Windows file names, along with `%` itself
+/// so the mapping can be reversed by [unescape_key].
+pub(crate) fn escape_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for character in key.chars() {
+        match character {
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' | '%' | '\u{0}'..='\u{1f}' => {
+                escaped.push_str(&format!("%{:02X}", character as u32))
+            }
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Turn a path relative to the mirror back into the key it was escaped from.
+pub(crate) fn unescape_key(escaped: &str) -> String {
+    let mut key = String::with_capacity(escaped.len());
+    let mut rest = escaped;
+    while let Some(index) = rest.find('%') {
+        key.push_str(&rest[..index]);
+        let code = rest
+            .get(index + 1..index + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match code {
+            Some(code) => {
+                key.push(code as char);
+                rest = &rest[index + 3..];
+            }
+            None => {
+                key.push('%');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    key.push_str(rest);
+    key
+}
+
+/// Decode the form URL encoded keys used by S3 event notifications and inventory reports.
+#[cfg(any(feature = "sqs", feature = "inventory"))]
+pub(crate) fn decode_key(key: &str) -> String {
+    let bytes = key.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' => match key
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    index += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The temporary path a download to `path` is written to before being renamed into place.
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+/// Where the ETag of the object being downloaded to `path` is kept, so an interrupted download can be
+/// resumed only if the object is unchanged. Ends in `.part` so it is treated like the partial download.
+pub(crate) fn part_e_tag_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".etag.part");
+    path.with_file_name(file_name)
+}
+
+/// Remove a partial download and its ETag, if there are any.
+pub(crate) async fn discard_partial(part_path: &Path, e_tag_path: &Path) -> io::Result<()> {
+    for path in [part_path, e_tag_path] {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The name of the folder holding `bucket`'s files under the mount path.
+///
+/// Access point ARNs contain `:` and `/`, which are swapped for `_` so each access point gets a single
+/// folder whose name is valid on every platform.
+pub(crate) fn bucket_folder(bucket: &str) -> String {
+    match is_access_point(bucket) {
+        true => bucket.replace([':', '/'], "_"),
+        false => bucket.to_string(),
+    }
+}
+
+/// Whether `bucket` is an access point ARN rather than a bucket name.
+pub(crate) fn is_access_point(bucket: &str) -> bool {
+    bucket.starts_with("arn:")
+}
+
+/// Whether `bucket` is an S3 Express One Zone directory bucket, which are named `<name>--<zone id>--x-s3`.
+///
+/// Directory buckets only list prefixes ending in `/`, have no `start-after` and return keys out of order.
+pub(crate) fn is_directory_bucket(bucket: &str) -> bool {
+    bucket.ends_with("--x-s3")
+}
+
+/// Build the URL encoded `bucket/key` value CopyObject expects as its source.
+///
+/// Objects reached through an access point are named `<access point ARN>/object/<key>` instead.
+pub(crate) fn copy_source(bucket: &str, key: &str) -> String {
+    let mut encoded = match is_access_point(bucket) {
+        true => format!("{}/object/", bucket),
+        false => format!("{}/", bucket),
+    };
+
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,330 @@
+//! Layering several buckets or prefixes into one namespace.
+use std::{
+    collections::{BTreeMap, HashSet},
+    future::Future,
+    io,
+    path::Path,
+};
+
+use bytes::Bytes;
+use tokio::fs::File;
+
+use crate::{
+    key::{s3_key, s3_prefix},
+    stat::Metadata,
+    DeleteOutcome, DirEntry, OpenOptions, S3FilesystemError,
+};
+
+/// Start of the name of an empty object marking a file deleted through an overlay.
+const WHITEOUT: &str = ".wh.";
+
+/// Several [OpenOptions] layered into one namespace, such as a per-user scratch bucket over a shared
+/// dataset bucket.
+///
+/// Reads go to each layer in turn, top first, and are served by the first layer holding the key, so a file
+/// written to an upper layer hides the file of the same name below it. Listings merge every layer in the
+/// same way. Writes and deletes all go to one layer, the top one unless [S3Overlay::write_to] picks
+/// another, and lower layers are never changed through the overlay.
+///
+/// Deleting a file which a layer beneath the write layer also holds leaves a whiteout in the write layer:
+/// an empty object named after the file with `.wh.` in front, as in `reports/.wh.summary.csv`. A whiteout
+/// hides the file in every layer beneath it from reads and listings, and is removed again when the file is
+/// next written through the overlay. Whiteouts are never listed, so keys starting with `.wh.` cannot be
+/// used through an overlay.
+///
+/// Each layer keeps its own settings, so a layer can be another bucket, a [prefix](OpenOptions::prefix) of
+/// the same bucket, or [read_only](OpenOptions::read_only) to guard a shared dataset. Give layers on the
+/// same bucket separate mount paths, as their mirrored files would otherwise share names.
+///
+/// # Examples
+/// ```no_run
+/// use s3_filesystem::{OpenOptions, S3Overlay};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bucket = "my_aws_s3_bucket".to_string();
+///
+///     let scratch = OpenOptions::new("my_scratch_bucket".to_string(), None)
+///         .await
+///         .mount_path("data/scratch/")
+///         .prefix("users/alice/");
+///     let base = OpenOptions::new(bucket, None)
+///         .await
+///         .mount_path("data/base/")
+///         .read_only(true);
+///
+///     let overlay = S3Overlay::new(scratch).layer(base);
+///
+///     // Edits land in the scratch bucket, and are read back in place of the base dataset's copy.
+///     let config = overlay.read_to_string("config.json").await.unwrap();
+///     overlay.write_s3("config.json", config.replace("v1", "v2").as_bytes()).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct S3Overlay {
+    /// The layers, top first.
+    layers: Vec<OpenOptions>,
+    write_layer: usize,
+}
+
+impl S3Overlay {
+    /// Create an overlay with a single layer, which writes go to.
+    pub fn new(top: OpenOptions) -> Self {
+        S3Overlay {
+            layers: vec![top],
+            write_layer: 0,
+        }
+    }
+
+    /// Add a layer beneath every layer added so far, read only when none of them hold a key.
+    pub fn layer(mut self, lower: OpenOptions) -> Self {
+        self.layers.push(lower);
+        self
+    }
+
+    /// Send writes and deletes to the layer at `index`, counting from 0 for the top layer
+    ///
+    /// Defaults to 0. Writing to a lower layer suits an overlay whose upper layers are pinned overrides,
+    /// while new files still go to the dataset beneath them. Returns an [io::ErrorKind::InvalidInput] error
+    /// if there is no layer at `index`.
+    pub fn write_to(mut self, index: usize) -> Result<Self, S3FilesystemError> {
+        if index >= self.layers.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The overlay has no layer {}, only {}",
+                    index,
+                    self.layers.len()
+                ),
+            )
+            .into());
+        }
+        self.write_layer = index;
+        Ok(self)
+    }
+
+    /// Every layer, top first.
+    pub fn layers(&self) -> &[OpenOptions] {
+        &self.layers
+    }
+
+    /// The layer writes and deletes go to.
+    pub fn write_layer(&self) -> &OpenOptions {
+        &self.layers[self.write_layer]
+    }
+
+    /// The top layer holding `path`, as found by [OpenOptions::stat]. Returns a not found error if no
+    /// layer holds it.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to look for.
+    pub async fn resolve<P>(&self, path: P) -> Result<&OpenOptions, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.first_match(path, |layer| async move {
+            layer.stat(path).await.map(|_| layer)
+        })
+        .await
+    }
+
+    /// Open a file from the top layer holding it, as [OpenOptions::open_s3] does.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be opened.
+    pub async fn open_s3<P>(&self, path: P) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.first_match(path, |layer| layer.open_s3(path)).await
+    }
+
+    /// Read a file from the top layer holding it into memory, as [OpenOptions::read_s3] does.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be read.
+    pub async fn read_s3<P>(&self, path: P) -> Result<Bytes, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.first_match(path, |layer| layer.read_s3(path)).await
+    }
+
+    /// Read a file from the top layer holding it as a string, as [OpenOptions::read_to_string] does.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be read.
+    pub async fn read_to_string<P>(&self, path: P) -> Result<String, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.first_match(path, |layer| layer.read_to_string(path))
+            .await
+    }
+
+    /// Metadata for a file or folder from the top layer holding it, as [OpenOptions::stat] gives.
+    ///
+    /// # Arguments
+    /// * `path`: The path of the file, or a folder ending in `/`.
+    pub async fn stat<P>(&self, path: P) -> Result<Metadata, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.first_match(path, |layer| layer.stat(path)).await
+    }
+
+    /// List the files under a path in every layer, as [OpenOptions::walkdir] does
+    ///
+    /// Where several layers hold the same path, the entry from the top one is returned. Files hidden by a
+    /// whiteout are left out. Entries are sorted by path.
+    ///
+    /// # Arguments
+    /// * `path`: A path to search within every layer. If you want everything, just specify an empty string: "".
+    pub async fn walkdir<P>(&self, path: P) -> Result<Vec<DirEntry>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut entries = BTreeMap::new();
+        let mut hidden = HashSet::new();
+        for layer in &self.layers {
+            let mut whiteouts = Vec::new();
+            for entry in layer.walkdir(path).await? {
+                let key = entry.path.to_string_lossy().into_owned();
+                match whited_out_key(&key) {
+                    Some(whited_out) => whiteouts.push(whited_out),
+                    None if !hidden.contains(&key) => {
+                        entries.entry(key).or_insert(entry);
+                    }
+                    None => {}
+                }
+            }
+            hidden.extend(whiteouts);
+        }
+        Ok(entries.into_values().collect())
+    }
+
+    /// Write a file to the write layer, as [OpenOptions::write_s3] does, removing any whiteout of it there.
+    ///
+    /// The whiteout is looked up first, so writing a file which was never deleted costs no DeleteObjects
+    /// request and leaves nothing in the write layer's [OpenOptions::trash].
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, to write the file to.
+    /// * `buf`: The contents of the file.
+    pub async fn write_s3<P>(&self, path: P, buf: &[u8]) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = self.write_layer().write_s3(path, buf).await?;
+        let below = self.write_layer + 1 < self.layers.len();
+        if below && has_whiteout(self.write_layer(), path).await? {
+            let whiteout = whiteout_key(&s3_key(path)?);
+            self.write_layer().delete_many([whiteout]).await?;
+        }
+        Ok(file)
+    }
+
+    /// Delete files from the write layer, as [OpenOptions::delete_many] does
+    ///
+    /// Only the write layer is changed. A deleted file which a layer beneath it also holds is hidden with a
+    /// whiteout in the write layer, so it stays deleted as seen through the overlay.
+    ///
+    /// # Arguments
+    /// * `paths`: The paths, including filenames, of the files to delete.
+    pub async fn delete_many<I, P>(&self, paths: I) -> Result<Vec<DeleteOutcome>, S3FilesystemError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let write_layer = self.write_layer();
+        let outcomes = write_layer.delete_many(paths).await?;
+
+        for outcome in &outcomes {
+            let key = match outcome {
+                DeleteOutcome::Deleted(key) => key,
+                DeleteOutcome::Failed { .. } => continue,
+            };
+            for lower in &self.layers[self.write_layer + 1..] {
+                match lower.stat(key).await {
+                    Ok(_) => {
+                        write_layer
+                            .write_s3_direct(whiteout_key(key), Vec::new())
+                            .await?;
+                        break;
+                    }
+                    Err(e) if e.is_not_found() => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Run `read` against each layer in turn, top first, returning the first result other than not found.
+    /// A layer holding a whiteout of `path` ends the search.
+    async fn first_match<'a, T, F, Fut>(
+        &'a self,
+        path: &Path,
+        read: F,
+    ) -> Result<T, S3FilesystemError>
+    where
+        F: Fn(&'a OpenOptions) -> Fut,
+        Fut: Future<Output = Result<T, S3FilesystemError>>,
+    {
+        let mut not_found = None;
+        for (index, layer) in self.layers.iter().enumerate() {
+            match read(layer).await {
+                Err(e) if e.is_not_found() => {
+                    let below = index + 1 < self.layers.len();
+                    if below && has_whiteout(layer, path).await? {
+                        return Err(e);
+                    }
+                    not_found = Some(e);
+                }
+                result => return result,
+            }
+        }
+        Err(not_found.expect("an overlay has at least one layer"))
+    }
+}
+
+/// Whether `layer` holds a whiteout hiding `path` in the layers beneath it.
+async fn has_whiteout(layer: &OpenOptions, path: &Path) -> Result<bool, S3FilesystemError> {
+    let key = s3_prefix(path)?;
+    if key.is_empty() || key.ends_with('/') {
+        return Ok(false);
+    }
+    match layer.stat(whiteout_key(&key)).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.is_not_found() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The key of the whiteout hiding `key`.
+fn whiteout_key(key: &str) -> String {
+    match key.rsplit_once('/') {
+        Some((folder, name)) => format!("{}/{}{}", folder, WHITEOUT, name),
+        None => format!("{}{}", WHITEOUT, key),
+    }
+}
+
+/// The key a whiteout hides, or None if `key` is not a whiteout.
+fn whited_out_key(key: &str) -> Option<String> {
+    let (folder, name) = match key.rsplit_once('/') {
+        Some((folder, name)) => (Some(folder), name),
+        None => (None, key),
+    };
+    let name = name.strip_prefix(WHITEOUT)?;
+    Some(match folder {
+        Some(folder) => format!("{}/{}", folder, name),
+        None => name.to_string(),
+    })
+}
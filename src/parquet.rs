@@ -0,0 +1,171 @@
+//! Reading Parquet objects as Arrow record batches with ranged GETs.
+use std::{io, ops::Range, path::Path, sync::Arc};
+
+use arrow_array::RecordBatch;
+use bytes::Bytes;
+use parquet::{
+    arrow::{
+        arrow_reader::ArrowReaderOptions,
+        async_reader::{AsyncFileReader, ParquetRecordBatchStreamBuilder},
+    },
+    errors::ParquetError,
+    file::metadata::{PageIndexPolicy, ParquetMetaData, ParquetMetaDataReader},
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{OpenOptions, S3File, S3FilesystemError};
+
+/// How much of the end of the object is fetched at first, in the hope the whole footer is in it.
+const FOOTER_PREFETCH: usize = 64 * 1024;
+
+type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+/// A Parquet object read through Range requests, for [ParquetRecordBatchStreamBuilder].
+///
+/// Created by [OpenOptions::parquet_reader]. Every byte range the Parquet reader asks for is fetched on its
+/// own, so only the footer and the column chunks of the row groups being read are downloaded. Reads go
+/// through an [S3File], so they are pinned to the ETag seen when the object was opened and use the block or
+/// sparse cache when one is set.
+#[derive(Debug)]
+pub struct ParquetObject {
+    file: S3File,
+}
+
+impl AsyncFileReader for ParquetObject {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        Box::pin(async move { Ok(self.file.read_range(range).await?) })
+    }
+
+    fn get_metadata<'a>(
+        &'a mut self,
+        options: Option<&'a ArrowReaderOptions>,
+    ) -> BoxFuture<'a, parquet::errors::Result<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            let size = self.file.size();
+            let metadata = ParquetMetaDataReader::new()
+                .with_page_index_policy(PageIndexPolicy::from(
+                    options.is_some_and(|options| options.page_index()),
+                ))
+                .with_prefetch_hint(Some(FOOTER_PREFETCH))
+                .load_and_finish(&mut *self, size)
+                .await?;
+            Ok(Arc::new(metadata))
+        })
+    }
+}
+
+impl OpenOptions {
+    /// Open a Parquet object for reading as Arrow record batches
+    ///
+    /// Returns a [ParquetRecordBatchStreamBuilder] with the object's footer already read, so its schema and
+    /// row group statistics can be inspected and the columns, row groups or row filter chosen before the
+    /// data is read. Only the byte ranges needed for those are then fetched, each with its own Range
+    /// request, and nothing is written to the mount path unless [OpenOptions::block_cache] or
+    /// [OpenOptions::sparse_cache] is set. Use [OpenOptions::read_parquet] to read every column of every
+    /// row group.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the Parquet object.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use parquet::arrow::ProjectionMask;
+    /// use s3_filesystem::OpenOptions;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let builder = open_options
+    ///         .parquet_reader("lake/trips/2024-05.parquet")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     // Only the first two columns of the last row group are downloaded.
+    ///     let last = builder.metadata().num_row_groups() - 1;
+    ///     let columns = ProjectionMask::roots(builder.parquet_schema(), [0, 1]);
+    ///     let mut batches = builder
+    ///         .with_row_groups(vec![last])
+    ///         .with_projection(columns)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     while let Some(batch) = batches.next().await {
+    ///         println!("{} rows", batch.unwrap().num_rows());
+    ///     }
+    /// }
+    /// ```
+    pub async fn parquet_reader<P>(
+        &self,
+        path: P,
+    ) -> Result<ParquetRecordBatchStreamBuilder<ParquetObject>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = self.open_s3_lazy(path).await?;
+        ParquetRecordBatchStreamBuilder::new(ParquetObject { file })
+            .await
+            .map_err(parquet_error)
+    }
+
+    /// Read every row of a Parquet object as a stream of Arrow record batches
+    ///
+    /// A shorthand for [OpenOptions::parquet_reader] with every column and row group selected. Row groups
+    /// are fetched one at a time as the stream is polled, so the whole object is never held in memory at
+    /// once.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the Parquet object.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let mut batches = open_options
+    ///         .read_parquet("lake/trips/2024-05.parquet")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let mut rows = 0;
+    ///     while let Some(batch) = batches.next().await {
+    ///         rows += batch.unwrap().num_rows();
+    ///     }
+    ///     println!("{} rows", rows);
+    /// }
+    /// ```
+    pub async fn read_parquet<P>(
+        &self,
+        path: P,
+    ) -> Result<impl Stream<Item = Result<RecordBatch, S3FilesystemError>>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let batches = self
+            .parquet_reader(path)
+            .await?
+            .build()
+            .map_err(parquet_error)?;
+        Ok(batches.map(|batch| batch.map_err(parquet_error)))
+    }
+}
+
+/// Surface a failed range request as the I/O error it was, and anything else as invalid data.
+fn parquet_error(error: ParquetError) -> S3FilesystemError {
+    match error {
+        ParquetError::External(external) => match external.downcast::<io::Error>() {
+            Ok(io_error) => (*io_error).into(),
+            Err(external) => io::Error::new(io::ErrorKind::InvalidData, external).into(),
+        },
+        error => io::Error::new(io::ErrorKind::InvalidData, error).into(),
+    }
+}
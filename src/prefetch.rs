@@ -0,0 +1,193 @@
+//! Downloading the objects after the one just opened before they are asked for.
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{key::s3_key, CachePolicy, OpenOptions, OpenedFile, S3FilesystemError};
+
+/// Shared between clones of an [OpenOptions] so they prefetch from one listing and never download the
+/// same object twice at once.
+#[derive(Debug, Default)]
+pub(crate) struct Prefetcher {
+    count: usize,
+    /// The most recently listed prefix and the keys directly under it, sorted.
+    listing: Mutex<Option<(String, Arc<Vec<String>>)>>,
+    /// Keys being prefetched, each locked until its download finishes.
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl Prefetcher {
+    /// Mark `key` as being prefetched, or None if it already is.
+    fn claim(&self, key: &str) -> Option<OwnedMutexGuard<()>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.contains_key(key) {
+            return None;
+        }
+        let lock = Arc::new(tokio::sync::Mutex::new(()));
+        let guard = lock.clone().try_lock_owned().ok()?;
+        in_flight.insert(key.to_string(), lock);
+        Some(guard)
+    }
+
+    fn release(&self, key: &str) {
+        self.in_flight.lock().unwrap().remove(key);
+    }
+}
+
+impl OpenOptions {
+    /// Download the next `count` objects in the same folder in the background whenever a file is opened
+    ///
+    /// When [OpenOptions::open_s3] or [OpenOptions::open_s3_or_cached] is called for a key, the keys directly
+    /// under the same prefix are listed and the `count` which follow it in listing order are downloaded
+    /// into the mount path, unless they are already cached. Code which reads a dataset file by file then
+    /// finds each file waiting for it rather than paying S3's latency every time. Opening a file which is
+    /// still being prefetched waits for that download rather than starting another.
+    ///
+    /// The listing is reused for later files in the same prefix, and taken again when moving to another
+    /// prefix or opening a key it does not include. Prefetch failures are ignored; the error surfaces if
+    /// the file is opened. Nothing is prefetched under [CachePolicy::AlwaysDownload], as every open would
+    /// download again anyway, or [CachePolicy::OfflineOnly]. Defaults to 0, which turns prefetching off.
+    ///
+    /// # Arguments
+    /// * `count`: How many of the following objects to keep downloaded ahead.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/")
+    ///         .prefetch(4);
+    ///
+    ///     for shard in 0..100 {
+    ///         let data = open_options
+    ///             .read(format!("training/shard-{:05}.bin", shard))
+    ///             .await
+    ///             .unwrap();
+    ///         println!("Shard {} is {} bytes", shard, data.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn prefetch(mut self, count: usize) -> Self {
+        self.prefetcher = (count > 0).then(|| {
+            Arc::new(Prefetcher {
+                count,
+                ..Prefetcher::default()
+            })
+        });
+        self
+    }
+
+    /// Download `path` into the mount path as `fetch` does, first starting prefetches of the
+    /// objects after it. A prefetch of `path` itself is waited for, and none is started while this runs.
+    pub(crate) async fn fetch_prefetching(
+        &self,
+        path: &Path,
+    ) -> Result<OpenedFile, S3FilesystemError> {
+        let prefetcher = match &self.prefetcher {
+            Some(prefetcher) => prefetcher,
+            None => return self.fetch(path, None).await,
+        };
+        let key = s3_key(path)?;
+        self.prefetch_after(&key);
+
+        let guard = loop {
+            if let Some(guard) = prefetcher.claim(&key) {
+                break guard;
+            }
+            let lock = prefetcher.in_flight.lock().unwrap().get(&key).cloned();
+            if let Some(lock) = lock {
+                let _ = lock.lock().await;
+            }
+        };
+        let opened = self.fetch(path, None).await;
+        prefetcher.release(&key);
+        drop(guard);
+        opened
+    }
+
+    /// Start downloading the objects which follow `key` in the background.
+    fn prefetch_after(&self, key: &str) {
+        let prefetcher = match &self.prefetcher {
+            Some(prefetcher)
+                if !matches!(
+                    self.cache_policy,
+                    CachePolicy::AlwaysDownload | CachePolicy::OfflineOnly
+                ) =>
+            {
+                prefetcher.clone()
+            }
+            _ => return,
+        };
+        let open_options = self.clone();
+        let key = key.to_string();
+
+        tokio::spawn(async move {
+            let siblings = match open_options.siblings(&prefetcher, &key).await {
+                Ok(siblings) => siblings,
+                Err(_) => return,
+            };
+            let next = siblings.partition_point(|sibling| sibling.as_str() <= key.as_str());
+
+            for sibling in siblings[next..].iter().take(prefetcher.count) {
+                let cached = open_options
+                    .local_path(sibling)
+                    .map_or(true, |path| path.exists());
+                if cached {
+                    continue;
+                }
+                let guard = match prefetcher.claim(sibling) {
+                    Some(guard) => guard,
+                    None => continue,
+                };
+
+                let open_options = open_options.clone();
+                let prefetcher = prefetcher.clone();
+                let sibling = sibling.clone();
+                tokio::spawn(async move {
+                    let _ = open_options.fetch(Path::new(&sibling), None).await;
+                    prefetcher.release(&sibling);
+                    drop(guard);
+                });
+            }
+        });
+    }
+
+    /// The sorted keys directly under the same prefix as `key`, listed again unless the last listing
+    /// was of that prefix and included `key`.
+    async fn siblings(
+        &self,
+        prefetcher: &Prefetcher,
+        key: &str,
+    ) -> Result<Arc<Vec<String>>, S3FilesystemError> {
+        let prefix = &key[..key.rfind('/').map_or(0, |slash| slash + 1)];
+
+        if let Some((listed, keys)) = prefetcher.listing.lock().unwrap().as_ref() {
+            if listed == prefix && keys.binary_search_by(|k| k.as_str().cmp(key)).is_ok() {
+                return Ok(keys.clone());
+            }
+        }
+
+        let (objects, _) = self
+            .list_objects(prefix, true, &|entry| !entry.folder)
+            .await?;
+        let mut keys: Vec<String> = objects
+            .into_iter()
+            .map(|entry| entry.path.to_string_lossy().into_owned())
+            .collect();
+        keys.sort();
+
+        let keys = Arc::new(keys);
+        *prefetcher.listing.lock().unwrap() = Some((prefix.to_string(), keys.clone()));
+        Ok(keys)
+    }
+}
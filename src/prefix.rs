@@ -0,0 +1,171 @@
+//! Treating a prefix within the bucket as its root.
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    backend::{
+        BackendFuture, GetRequest, ListPage, ListRequest, ObjectBody, ObjectHead, PutRequest,
+    },
+    DeleteOutcome, ObjectBackend, OpenOptions,
+};
+
+impl OpenOptions {
+    /// Use a prefix within the bucket as the root
+    ///
+    /// Every key given to this OpenOptions, as in [OpenOptions::open_s3], [OpenOptions::write_s3] and
+    /// [OpenOptions::walkdir], is taken to be relative to `prefix`, and listings return keys relative to it.
+    /// Files are mirrored without the prefix too, so `datasets/v2/train.csv` is cached as `train.csv` under
+    /// the bucket's folder in the mount path. Code written against a logical root can then be pointed at
+    /// another dataset version by changing only the prefix. Use a separate mount path for each prefix, as
+    /// their files would otherwise share names.
+    ///
+    /// A `/` is added if `prefix` does not end in one. Methods which take a bucket as well as a key, such as
+    /// [OpenOptions::copy_between], use the key as given. Defaults to "", the root of the bucket.
+    ///
+    /// # Arguments
+    /// * `prefix`: The prefix to treat as the root, such as `datasets/v2/`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/v2/")
+    ///         .prefix("datasets/v2/");
+    ///
+    ///     // Reads s3://my_aws_s3_bucket/datasets/v2/train.csv
+    ///     let train = open_options.read("train.csv").await.unwrap();
+    /// }
+    /// ```
+    pub fn prefix<P>(mut self, prefix: P) -> Self
+    where
+        P: Into<String>,
+    {
+        let mut prefix = prefix.into();
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        self.backend = self.backend.with_prefix(&prefix);
+        self.prefix = prefix;
+        self
+    }
+
+    /// The key in the bucket for `key`, which is relative to the prefix.
+    pub(crate) fn remote_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// The key relative to the prefix for `key` in the bucket, or None if it lies outside the prefix.
+    pub(crate) fn relative_key<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(self.prefix.as_str())
+    }
+}
+
+/// Wraps a backend so keys are given relative to `prefix` and listings report them that way.
+pub(crate) struct Prefixed {
+    backend: Arc<dyn ObjectBackend>,
+    prefix: String,
+}
+
+impl Prefixed {
+    pub(crate) fn new(backend: Arc<dyn ObjectBackend>, prefix: &str) -> Self {
+        Prefixed {
+            backend,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn remote_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn relative_key(&self, key: String) -> String {
+        match key.strip_prefix(self.prefix.as_str()) {
+            Some(relative) => relative.to_string(),
+            None => key,
+        }
+    }
+}
+
+impl ObjectBackend for Prefixed {
+    fn list<'a>(&'a self, request: ListRequest<'a>) -> BackendFuture<'a, ListPage> {
+        Box::pin(async move {
+            let prefix = self.remote_key(request.prefix);
+            let start_after = request.start_after.map(|key| self.remote_key(key));
+            let mut page = self
+                .backend
+                .list(ListRequest {
+                    prefix: &prefix,
+                    start_after: start_after.as_deref(),
+                    ..request
+                })
+                .await?;
+
+            for entry in &mut page.entries {
+                let key = entry.path.to_string_lossy().into_owned();
+                entry.path = PathBuf::from(self.relative_key(key));
+            }
+            page.common_prefixes = page
+                .common_prefixes
+                .into_iter()
+                .map(|common_prefix| self.relative_key(common_prefix))
+                .collect();
+            Ok(page)
+        })
+    }
+
+    fn get<'a>(&'a self, request: GetRequest<'a>) -> BackendFuture<'a, ObjectBody> {
+        Box::pin(async move {
+            let key = self.remote_key(request.key);
+            self.backend
+                .get(GetRequest {
+                    key: &key,
+                    ..request
+                })
+                .await
+        })
+    }
+
+    fn head<'a>(&'a self, bucket: &'a str, key: &'a str) -> BackendFuture<'a, ObjectHead> {
+        Box::pin(async move { self.backend.head(bucket, &self.remote_key(key)).await })
+    }
+
+    fn put<'a>(&'a self, request: PutRequest<'a>) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let key = self.remote_key(request.key);
+            self.backend
+                .put(PutRequest {
+                    key: &key,
+                    ..request
+                })
+                .await
+        })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        bucket: &'a str,
+        keys: &'a [String],
+    ) -> BackendFuture<'a, Vec<DeleteOutcome>> {
+        Box::pin(async move {
+            let keys: Vec<String> = keys.iter().map(|key| self.remote_key(key)).collect();
+            let outcomes = self.backend.delete(bucket, &keys).await?;
+
+            Ok(outcomes
+                .into_iter()
+                .map(|outcome| match outcome {
+                    DeleteOutcome::Deleted(key) => DeleteOutcome::Deleted(self.relative_key(key)),
+                    DeleteOutcome::Failed { key, code, message } => DeleteOutcome::Failed {
+                        key: self.relative_key(key),
+                        code,
+                        message,
+                    },
+                })
+                .collect())
+        })
+    }
+}
@@ -0,0 +1,181 @@
+//! Warming the local mirror with everything under a prefix before it is asked for.
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    sync::Semaphore,
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{key::s3_prefix, OpenOptions, S3FilesystemError};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How far a [Preload] has got.
+pub struct PreloadProgress {
+    /// How many objects are under the prefix, or None until the listing has finished.
+    pub total: Option<usize>,
+    /// Objects downloaded into the mount path.
+    pub downloaded: usize,
+    /// Objects which were already cached, so were not downloaded again.
+    pub cached: usize,
+    /// Objects which could not be downloaded. They are downloaded as usual when next opened.
+    pub failed: usize,
+    /// The combined size in bytes of the objects downloaded.
+    pub bytes: u64,
+}
+
+impl PreloadProgress {
+    /// Whether every object under the prefix has been dealt with.
+    pub fn is_complete(&self) -> bool {
+        self.total == Some(self.downloaded + self.cached + self.failed)
+    }
+}
+
+/// A preload running in the background, returned by [OpenOptions::preload].
+///
+/// Dropping the handle leaves the preload running; call [Preload::wait] to wait for it to finish.
+#[derive(Debug)]
+pub struct Preload {
+    progress: Arc<Mutex<PreloadProgress>>,
+    task: JoinHandle<Result<(), S3FilesystemError>>,
+}
+
+impl Preload {
+    /// How far the preload has got so far.
+    pub fn progress(&self) -> PreloadProgress {
+        *self.progress.lock().unwrap()
+    }
+
+    /// Whether the preload has stopped, either because it finished or because it failed.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Wait for the preload to finish, returning the final progress
+    ///
+    /// Objects which fail to download are counted in [PreloadProgress::failed] rather than returned as an
+    /// error. An error is only returned if the prefix could not be listed, or the preload was stopped by
+    /// [OpenOptions::cancel_on].
+    pub async fn wait(self) -> Result<PreloadProgress, S3FilesystemError> {
+        match self.task.await {
+            Ok(result) => result?,
+            Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+        }
+        Ok(*self.progress.lock().unwrap())
+    }
+}
+
+impl OpenOptions {
+    /// Download every object under a prefix into the mount path in the background
+    ///
+    /// The prefix is listed and each object is fetched as [OpenOptions::open_s3] would fetch it, up to
+    /// `concurrency` at once, so a service can warm its working set at startup and have it cached before
+    /// the first request arrives. Objects already cached, and fresh under the
+    /// [CachePolicy](crate::CachePolicy), are not downloaded again. Returns straight away with a [Preload]
+    /// handle, which can be polled for its progress or waited on. This has to be called from within a
+    /// Tokio runtime.
+    ///
+    /// # Arguments
+    /// * `prefix`: The prefix to preload. If you want the entire bucket, just specify an empty string: "".
+    /// * `concurrency`: The most downloads to run at once. 0 is treated as 1.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/cache/");
+    ///
+    ///     let preload = open_options.preload("models/", 8);
+    ///
+    ///     // Report readiness once the models are on disk.
+    ///     let progress = preload.wait().await.unwrap();
+    ///     println!(
+    ///         "{} models downloaded ({} bytes), {} already cached",
+    ///         progress.downloaded, progress.bytes, progress.cached
+    ///     );
+    /// }
+    /// ```
+    pub fn preload<P>(&self, prefix: P, concurrency: usize) -> Preload
+    where
+        P: AsRef<Path>,
+    {
+        let progress = Arc::new(Mutex::new(PreloadProgress::default()));
+        let open_options = self.clone();
+        let prefix = prefix.as_ref().to_path_buf();
+        let task_progress = progress.clone();
+
+        let task = tokio::spawn(async move {
+            open_options
+                .preload_prefix(&prefix, concurrency.max(1), &task_progress)
+                .await
+        });
+
+        Preload { progress, task }
+    }
+
+    async fn preload_prefix(
+        &self,
+        prefix: &Path,
+        concurrency: usize,
+        progress: &Arc<Mutex<PreloadProgress>>,
+    ) -> Result<(), S3FilesystemError> {
+        let prefix_key = s3_prefix(prefix)?;
+        let (objects, _) = self
+            .list_objects(&prefix_key, false, &|entry| !entry.folder)
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(prefix)))?;
+        progress.lock().unwrap().total = Some(objects.len());
+
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        let mut tasks = JoinSet::new();
+
+        for object in objects {
+            let open_options = self.clone();
+            let progress = progress.clone();
+            let semaphore = semaphore.clone();
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let local_path = open_options.local_path(&object.path.to_string_lossy());
+                let modified = || {
+                    let local_path = local_path.as_ref().ok()?;
+                    std::fs::metadata(local_path).ok()?.modified().ok()
+                };
+
+                // A cached copy which was fresh, or revalidated unchanged, is left as it was.
+                let before = modified();
+                let fetched = open_options.fetch(&object.path, None).await;
+                let cached = before.is_some() && before == modified();
+
+                let mut progress = progress.lock().unwrap();
+                match fetched {
+                    Ok(_) if cached => progress.cached += 1,
+                    Ok(_) => {
+                        progress.downloaded += 1;
+                        progress.bytes += object.size.max(0) as u64;
+                    }
+                    Err(e) if e.is_cancelled() => return Err(e),
+                    Err(_) => progress.failed += 1,
+                }
+                Ok(())
+            });
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(fetched) => fetched?,
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Downloading one large object as several concurrent Range requests.
+use std::{io::SeekFrom, path::Path, time::Instant};
+
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
+    task::JoinSet,
+};
+
+use crate::{backend::GetRequest, ObjectHead, OpenOptions, S3FilesystemError};
+
+/// Objects are never split into ranges smaller than this, so small files stay a single request.
+const MIN_RANGE_SIZE: u64 = 8 * 1024 * 1024;
+
+impl OpenOptions {
+    /// The size, ETag and encoding of `key` if it is large enough to be downloaded in more than one range.
+    pub(crate) async fn plan_ranges(
+        &self,
+        key: &str,
+    ) -> Result<Option<ObjectHead>, S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self.backend.head(&self.bucket, key).await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+        let head = result?;
+
+        Ok((head.size >= 2 * MIN_RANGE_SIZE).then_some(head))
+    }
+
+    /// Download `size` bytes of `key` into `part_path`, each range written at its own offset.
+    ///
+    /// Every range is requested with If-Match on `e_tag`, so an object replaced part way fails the download
+    /// rather than producing a file stitched together from two versions.
+    pub(crate) async fn fetch_ranges(
+        &self,
+        key: &str,
+        part_path: &Path,
+        size: u64,
+        e_tag: Option<&str>,
+    ) -> Result<(), S3FilesystemError> {
+        let part_file = tokio::fs::File::create(part_path).await?;
+        part_file.set_len(size).await?;
+        drop(part_file);
+
+        let parts = (self.download_parts as u64)
+            .min(size / MIN_RANGE_SIZE)
+            .max(1);
+        let range_size = size.div_ceil(parts);
+        let mut tasks = JoinSet::new();
+
+        for start in (0..size).step_by(range_size as usize) {
+            let open_options = self.clone();
+            let key = key.to_string();
+            let part_path = part_path.to_path_buf();
+            let e_tag = e_tag.map(str::to_string);
+            let end = (start + range_size).min(size) - 1;
+
+            tasks.spawn(async move {
+                open_options
+                    .fetch_range(&key, &part_path, start, end, e_tag)
+                    .await
+            });
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(fetched) => fetched?,
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            }
+        }
+
+        tokio::fs::File::open(part_path).await?.sync_all().await?;
+        Ok(())
+    }
+
+    /// Download bytes `start..=end` of `key` into the same bytes of `part_path`.
+    async fn fetch_range(
+        &self,
+        key: &str,
+        part_path: &Path,
+        start: u64,
+        end: u64,
+        e_tag: Option<String>,
+    ) -> Result<(), S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .get(GetRequest {
+                bucket: &self.bucket,
+                key,
+                range: Some((start, Some(end))),
+                if_match: e_tag.as_deref(),
+                if_none_match: None,
+            })
+            .await;
+        self.record_request("GetObject", started, result.is_ok());
+        let mut object = result?;
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(part_path)
+            .await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut file = BufWriter::with_capacity(self.download_buffer_size, file);
+
+        while let Some(bytes) = object.body.try_next().await? {
+            if let Some(limiter) = &self.bandwidth_limiter {
+                limiter.acquire(bytes.len() as u64).await;
+            }
+            file.write_all(&bytes).await?;
+        }
+        drop(slot);
+        file.flush().await?;
+        Ok(())
+    }
+}
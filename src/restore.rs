@@ -0,0 +1,203 @@
+//! Restoring objects archived in Glacier or Deep Archive so they can be read again.
+use std::{path::Path, time::Instant, time::SystemTime};
+
+use aws_sdk_s3::types::{GlacierJobParameters, RestoreRequest, StorageClass, Tier};
+use aws_smithy_types::{date_time::Format, DateTime};
+
+use crate::{
+    dry_run::DryRunOperation, error::S3Error, key::s3_key, OpenOptions, S3FilesystemError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How quickly, and at what cost, [OpenOptions::restore] brings an archived object back.
+pub enum RestoreTier {
+    /// Minutes for Glacier Flexible Retrieval. Not available for Deep Archive.
+    Expedited,
+    /// Hours, and the default when restoring through the console.
+    Standard,
+    /// The cheapest and slowest tier, taking up to two days for Deep Archive.
+    Bulk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Whether an object can be read, as reported by [OpenOptions::restore_status].
+pub enum RestoreStatus {
+    /// The object is not archived and can be read straight away.
+    Available,
+    /// The object is archived and has no restored copy. Start one with [OpenOptions::restore].
+    Archived,
+    /// A restore has been requested and has not finished yet.
+    InProgress,
+    /// A restored copy can be read until it expires.
+    Restored {
+        /// When the restored copy is removed again, if S3 said.
+        expires: Option<SystemTime>,
+    },
+}
+
+impl OpenOptions {
+    /// Request a temporary readable copy of an object archived in Glacier or Deep Archive
+    ///
+    /// Archived objects fail to download with an error for which
+    /// [S3FilesystemError::is_archived] is true. Restoring makes a copy readable for `days` days, after
+    /// which it is removed again; the object itself stays archived. Restores take minutes to days depending
+    /// on `tier`, so poll [OpenOptions::restore_status] until it reports [RestoreStatus::Restored] before
+    /// opening the file.
+    ///
+    /// Requesting a restore that is already in progress succeeds without starting another. Requesting one
+    /// for an already restored object extends how long the copy is kept.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the archived object.
+    /// * `tier`: How quickly the restore should happen.
+    /// * `days`: How many days the restored copy should be kept for.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, RestoreStatus, RestoreTier};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let path = "archive/2019/data.csv";
+    ///
+    ///     if let Err(err) = open_options.open_s3(path).await {
+    ///         if err.is_archived() {
+    ///             open_options.restore(path, RestoreTier::Bulk, 7).await.unwrap();
+    ///
+    ///             while open_options.restore_status(path).await.unwrap() == RestoreStatus::InProgress {
+    ///                 tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    ///             }
+    ///
+    ///             let file = open_options.open_s3(path).await.unwrap();
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn restore<P>(
+        &self,
+        path: P,
+        tier: RestoreTier,
+        days: i32,
+    ) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.restore_object(path, tier, days)
+            .await
+            .map_err(|e| e.with_context("RestoreObject", &self.bucket, Some(path)))
+    }
+
+    async fn restore_object(
+        &self,
+        path: &Path,
+        tier: RestoreTier,
+        days: i32,
+    ) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let key = s3_key(path)?;
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Restore { key, days });
+            return Ok(());
+        }
+
+        let tier = match tier {
+            RestoreTier::Expedited => Tier::Expedited,
+            RestoreTier::Standard => Tier::Standard,
+            RestoreTier::Bulk => Tier::Bulk,
+        };
+        let parameters = GlacierJobParameters::builder()
+            .tier(tier)
+            .build()
+            .map_err(S3Error::construction_failure)?;
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .restore_object()
+            .bucket(&self.bucket)
+            .key(self.remote_key(&key))
+            .restore_request(
+                RestoreRequest::builder()
+                    .days(days)
+                    .glacier_job_parameters(parameters)
+                    .build(),
+            )
+            .send()
+            .await;
+        self.record_request("RestoreObject", started, result.is_ok());
+        drop(slot);
+
+        match result.map_err(S3FilesystemError::from) {
+            Ok(_) => Ok(()),
+            Err(e) if e.code() == Some("RestoreAlreadyInProgress") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check whether an object is archived, being restored, or can be read
+    ///
+    /// Makes a single HeadObject request.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the object to check.
+    pub async fn restore_status<P>(&self, path: P) -> Result<RestoreStatus, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.head_restore(path)
+            .await
+            .map_err(|e| e.with_context("HeadObject", &self.bucket, Some(path)))
+    }
+
+    async fn head_restore(&self, path: &Path) -> Result<RestoreStatus, S3FilesystemError> {
+        let key = s3_key(path)?;
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.remote_key(&key))
+            .send()
+            .await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+        let head = result?;
+
+        // The x-amz-restore header looks like `ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT"`.
+        if let Some(restore) = head.restore() {
+            if restore.contains("ongoing-request=\"true\"") {
+                return Ok(RestoreStatus::InProgress);
+            }
+            let expires = restore
+                .split_once("expiry-date=\"")
+                .and_then(|(_, rest)| rest.split('"').next())
+                .and_then(|date| DateTime::from_str(date, Format::HttpDate).ok())
+                .and_then(|date| SystemTime::try_from(date).ok());
+            return Ok(RestoreStatus::Restored { expires });
+        }
+
+        let archived = head.archive_status().is_some()
+            || matches!(
+                head.storage_class(),
+                Some(StorageClass::Glacier | StorageClass::DeepArchive)
+            );
+        Ok(match archived {
+            true => RestoreStatus::Archived,
+            false => RestoreStatus::Available,
+        })
+    }
+}
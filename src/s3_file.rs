@@ -0,0 +1,368 @@
+//! Reading objects on demand with ranged GETs instead of downloading them whole.
+use std::{
+    future::Future,
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf};
+
+use crate::{
+    backend::GetRequest, blocks::BlockCache, key::s3_key, sparse::SparseCache, OpenOptions,
+    S3FilesystemError,
+};
+
+/// How much is fetched per request when no buffer size is given.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+type PendingRange = Pin<Box<dyn Future<Output = Result<Bytes, io::Error>> + Send>>;
+
+/// An S3 object read lazily through Range requests.
+///
+/// Created by [OpenOptions::open_s3_lazy]. Implements [AsyncRead] and [AsyncSeek], fetching one buffer's
+/// worth of the object at a time from wherever the file is positioned, so formats that seek around (zip,
+/// Parquet and the like) only download the parts they read. Nothing is written to the mount path.
+///
+/// Every request is pinned to the ETag seen when the file was opened, so if the object is replaced part
+/// way through, reads fail rather than mixing old and new contents.
+///
+/// With [OpenOptions::block_cache] set, whole blocks are fetched instead and kept in the mount path, so
+/// parts of the object read before are not downloaded again. With [OpenOptions::sparse_cache] set, the
+/// ranges fetched are kept in a sparse file in the mount path for the same reason.
+///
+/// A file written with [OpenOptions::write_back] and not yet flushed is read from the mount path instead,
+/// as its copy there is newer than S3's.
+pub struct S3File {
+    open_options: OpenOptions,
+    key: String,
+    size: u64,
+    e_tag: Option<String>,
+    position: u64,
+    buffer: Bytes,
+    buffer_start: u64,
+    buffer_size: usize,
+    blocks: Option<BlockCache>,
+    sparse: Option<SparseCache>,
+    /// The mirrored copy to read from, for a file with a pending write.
+    local: Option<PathBuf>,
+    pending: Option<(u64, PendingRange)>,
+}
+
+impl std::fmt::Debug for S3File {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("S3File")
+            .field("bucket", &self.open_options.bucket)
+            .field("key", &self.key)
+            .field("size", &self.size)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl S3File {
+    /// Set how many bytes each Range request fetches
+    ///
+    /// Larger buffers mean fewer requests for sequential reads; smaller ones waste less when seeking
+    /// around. Defaults to 8 MiB. Ignored with [OpenOptions::block_cache], where each request fetches one
+    /// block.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes.max(1);
+        self
+    }
+
+    /// The size of the object in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The object's ETag when it was opened.
+    pub fn e_tag(&self) -> Option<&str> {
+        self.e_tag.as_deref()
+    }
+
+    /// Where the range holding `position` starts: the start of its block with a block cache, or
+    /// `position` itself.
+    fn range_start(&self, position: u64) -> u64 {
+        match &self.blocks {
+            Some(blocks) => position - position % blocks.block_size,
+            None => position,
+        }
+    }
+
+    /// Read exactly the bytes in `range`, fetching no more than that unless a block cache rounds it up.
+    #[cfg(feature = "parquet")]
+    pub(crate) async fn read_range(&mut self, range: std::ops::Range<u64>) -> io::Result<Bytes> {
+        let length = (range.end - range.start) as usize;
+        self.buffer_size = length.max(1);
+        self.seek(SeekFrom::Start(range.start)).await?;
+        let mut bytes = vec![0; length];
+        self.read_exact(&mut bytes).await?;
+        Ok(bytes.into())
+    }
+
+    fn fetch_range(&self, start: u64) -> PendingRange {
+        let open_options = self.open_options.clone();
+        let key = self.key.clone();
+        let e_tag = self.e_tag.clone();
+        let blocks = self.blocks.clone();
+        let sparse = self.sparse.clone();
+        let length = match &blocks {
+            Some(blocks) => blocks.block_size,
+            None => self.buffer_size as u64,
+        };
+        let mut end = (start + length).min(self.size);
+
+        if let Some(sparse) = &sparse {
+            if let Some(cached_end) = sparse.cached_end(start, end - start) {
+                let sparse = sparse.clone();
+                return Box::pin(async move { sparse.read(start, cached_end).await });
+            }
+            // Stop at the next range already on disk rather than fetching it again.
+            end = sparse.uncached_end(start, end - start);
+        }
+        let end = end - 1;
+
+        if let Some(local) = self.local.clone() {
+            return Box::pin(async move {
+                let mut file = tokio::fs::File::open(local).await?;
+                file.seek(SeekFrom::Start(start)).await?;
+                let mut bytes = vec![0; (end + 1 - start) as usize];
+                file.read_exact(&mut bytes).await?;
+                Ok(bytes.into())
+            });
+        }
+
+        Box::pin(async move {
+            if let Some(blocks) = &blocks {
+                if let Some(block) = blocks.read(start).await? {
+                    return Ok(block);
+                }
+            }
+
+            let slot = open_options.throttle_request().await;
+            let started = Instant::now();
+            let result = open_options
+                .backend
+                .get(GetRequest {
+                    bucket: &open_options.bucket,
+                    key: &key,
+                    range: Some((start, Some(end))),
+                    if_match: e_tag.as_deref(),
+                    if_none_match: None,
+                })
+                .await;
+            open_options.record_request("GetObject", started, result.is_ok());
+
+            let to_io = |e: S3FilesystemError| {
+                io::Error::from(e.with_context(
+                    "GetObject",
+                    &open_options.bucket,
+                    Some(Path::new(&key)),
+                ))
+            };
+            let object = result.map_err(to_io)?;
+            let bytes = object
+                .body
+                .collect()
+                .await
+                .map_err(|e| to_io(e.into()))?
+                .into_bytes();
+            drop(slot);
+
+            if let Some(limiter) = &open_options.bandwidth_limiter {
+                limiter.acquire(bytes.len() as u64).await;
+            }
+            if let Some(metrics) = &open_options.metrics {
+                metrics.downloaded(&key, bytes.len() as u64);
+            }
+            if let Some(blocks) = &blocks {
+                open_options
+                    .reserve_space(&blocks.block_path(start), false, bytes.len() as u64)
+                    .await
+                    .map_err(to_io)?;
+                blocks.write(start, &bytes).await?;
+            }
+            if let Some(sparse) = &sparse {
+                sparse.write(start, &bytes).await?;
+            }
+            Ok(bytes)
+        })
+    }
+}
+
+impl AsyncRead for S3File {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if self.position >= self.size || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let buffer_end = self.buffer_start + self.buffer.len() as u64;
+            if self.position >= self.buffer_start && self.position < buffer_end {
+                let offset = (self.position - self.buffer_start) as usize;
+                let available = &self.buffer[offset..];
+                let count = available.len().min(buf.remaining());
+                buf.put_slice(&available[..count]);
+                self.position += count as u64;
+                return Poll::Ready(Ok(()));
+            }
+
+            if self.pending.is_none() {
+                let start = self.range_start(self.position);
+                let range = self.fetch_range(start);
+                self.pending = Some((start, range));
+            }
+
+            let (start, pending) = self.pending.as_mut().expect("a range was just requested");
+            let start = *start;
+            match pending.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => {
+                    self.pending = None;
+                    self.buffer = result?;
+                    self.buffer_start = start;
+                    if self.buffer.is_empty() {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "S3 returned an empty range",
+                        )));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl AsyncSeek for S3File {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match target {
+            Some(target) => {
+                if self.range_start(target) != self.range_start(self.position) {
+                    self.pending = None;
+                }
+                self.position = target;
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.position))
+    }
+}
+
+impl OpenOptions {
+    /// Open a file from S3 without downloading it
+    ///
+    /// Returns an [S3File] which reads the object on demand with Range requests, for formats where only
+    /// part of a large object is needed. A single HeadObject request is made up front to find the size;
+    /// data is only fetched as it is read. Unlike [OpenOptions::open_s3], nothing is cached locally, unless
+    /// [OpenOptions::block_cache] or [OpenOptions::sparse_cache] is set.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be opened.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::io::SeekFrom;
+    /// use tokio::io::{AsyncReadExt, AsyncSeekExt};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let mut file = open_options
+    ///         .open_s3_lazy("some_folder/data.parquet")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     // Parquet keeps its footer length in the last 8 bytes.
+    ///     let mut footer = [0; 8];
+    ///     file.seek(SeekFrom::End(-8)).await.unwrap();
+    ///     file.read_exact(&mut footer).await.unwrap();
+    /// }
+    /// ```
+    pub async fn open_s3_lazy<P>(&self, path: P) -> Result<S3File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.head(path)
+            .await
+            .map_err(|e| e.with_context("HeadObject", &self.bucket, Some(path)))
+    }
+
+    async fn head(&self, path: &Path) -> Result<S3File, S3FilesystemError> {
+        let key = s3_key(path)?;
+
+        if self.is_pending(&key).await? {
+            let local = self.local_path(&key)?;
+            return Ok(S3File {
+                size: tokio::fs::metadata(&local).await?.len(),
+                e_tag: None,
+                blocks: None,
+                sparse: None,
+                local: Some(local),
+                ..self.lazy_file(key)
+            });
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self.backend.head(&self.bucket, &key).await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+        let head = result?;
+        let blocks = self.open_blocks(&key, head.e_tag.as_deref()).await?;
+        let sparse = self
+            .open_sparse(&key, head.size, head.e_tag.as_deref())
+            .await?;
+
+        Ok(S3File {
+            size: head.size,
+            e_tag: head.e_tag,
+            blocks,
+            sparse,
+            ..self.lazy_file(key)
+        })
+    }
+
+    /// An [S3File] for `key` positioned at the start, holding nothing yet.
+    fn lazy_file(&self, key: String) -> S3File {
+        S3File {
+            open_options: self.clone(),
+            key,
+            size: 0,
+            e_tag: None,
+            position: 0,
+            buffer: Bytes::new(),
+            buffer_start: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            blocks: None,
+            sparse: None,
+            local: None,
+            pending: None,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Locks held as objects in the bucket, for workers coordinating through S3.
+//!
+//! A lock is an object whose body is the time it expires, in milliseconds since the unix epoch:
+//!
+//! ```text
+//! <expires at>
+//! ```
+//!
+//! It is created with `If-None-Match: *`, so only one worker can create it, and renewed or taken over
+//! with `If-Match` on its ETag, so only one worker can change it.
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use aws_sdk_s3::primitives::ByteStream;
+
+use crate::{
+    backend::{GetRequest, PutRequest},
+    key::s3_key,
+    DeleteOutcome, DryRunOperation, ObjectChecksum, ObjectLock, OpenOptions, S3FilesystemError,
+    WritePrecondition,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A lock held by this worker, as returned by [OpenOptions::lock].
+pub struct S3Lock {
+    key: String,
+    e_tag: Option<String>,
+    expires_at: SystemTime,
+}
+
+impl S3Lock {
+    /// The key of the lock object.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// When the lock lapses and another worker may take it, unless renewed first.
+    pub fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+
+    /// Whether the lock has lapsed, going by this machine's clock.
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires_at
+    }
+}
+
+impl OpenOptions {
+    /// Try to take a lock shared with every other worker using the bucket
+    ///
+    /// The lock is an object at `path`, created only if it does not already exist, so however many workers
+    /// race for it exactly one gets it. Returns None if another worker holds the lock and it has not
+    /// expired. A lock held past its `ttl` is assumed to belong to a worker that died, and is taken over.
+    ///
+    /// Keep the lock by calling [OpenOptions::renew_lock] well within `ttl`, and give it up with
+    /// [OpenOptions::unlock]. Expiry is judged by each worker's own clock, so keep `ttl` well above the
+    /// clock skew between them. Lock objects are never written to the mount path.
+    ///
+    /// # Arguments
+    /// * `path`: The key of the lock object, such as `locks/job-42`.
+    /// * `ttl`: How long the lock is held for before it must be renewed.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     for job in ["job-1", "job-2", "job-3"] {
+    ///         let lock = match open_options
+    ///             .lock(format!("locks/{}", job), Duration::from_secs(300))
+    ///             .await
+    ///             .unwrap()
+    ///         {
+    ///             Some(lock) => lock,
+    ///             None => continue,
+    ///         };
+    ///
+    ///         println!("Processing {}", job);
+    ///
+    ///         open_options.unlock(lock).await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub async fn lock<P>(&self, path: P, ttl: Duration) -> Result<Option<S3Lock>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.acquire_lock(path, ttl)
+            .await
+            .map_err(|e| e.with_context("PutObject", &self.bucket, Some(path)))
+    }
+
+    /// Extend a lock so it expires `ttl` from now
+    ///
+    /// Fails with an error for which [S3FilesystemError::is_precondition_failed] is true if the lock has
+    /// been taken over by another worker since it was taken or last renewed, in which case stop work
+    /// guarded by it.
+    ///
+    /// # Arguments
+    /// * `lock`: A lock taken with [OpenOptions::lock].
+    /// * `ttl`: How long from now the lock is held for.
+    pub async fn renew_lock(
+        &self,
+        lock: &mut S3Lock,
+        ttl: Duration,
+    ) -> Result<(), S3FilesystemError> {
+        self.renew(lock, ttl)
+            .await
+            .map_err(|e| e.with_context("PutObject", &self.bucket, Some(lock.key.as_ref())))
+    }
+
+    /// Give up a lock so another worker can take it straight away
+    ///
+    /// A lock which has already been taken over by another worker is left alone, and an error for which
+    /// [S3FilesystemError::is_precondition_failed] is true is returned.
+    ///
+    /// # Arguments
+    /// * `lock`: A lock taken with [OpenOptions::lock].
+    pub async fn unlock(&self, lock: S3Lock) -> Result<(), S3FilesystemError> {
+        self.release(&lock)
+            .await
+            .map_err(|e| e.with_context("DeleteObject", &self.bucket, Some(lock.key.as_ref())))
+    }
+
+    async fn acquire_lock(
+        &self,
+        path: &Path,
+        ttl: Duration,
+    ) -> Result<Option<S3Lock>, S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+        let key = s3_key(path)?;
+
+        loop {
+            match self.put_lock(&key, ttl, &WritePrecondition::IfAbsent).await {
+                Err(e) if lost_race(&e) => {}
+                acquired => return acquired.map(Some),
+            }
+
+            let (held_until, e_tag) = match self.read_lock(&key).await? {
+                Some(held) => held,
+                // Released since, so try to create it again.
+                None => continue,
+            };
+            if SystemTime::now() < held_until {
+                return Ok(None);
+            }
+
+            return match self
+                .put_lock(&key, ttl, &WritePrecondition::IfMatch(e_tag))
+                .await
+            {
+                Err(e) if lost_race(&e) => Ok(None),
+                acquired => acquired.map(Some),
+            };
+        }
+    }
+
+    async fn renew(&self, lock: &mut S3Lock, ttl: Duration) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+        let precondition = match &lock.e_tag {
+            Some(e_tag) => WritePrecondition::IfMatch(e_tag.clone()),
+            None => WritePrecondition::IfAbsent,
+        };
+
+        *lock = self.put_lock(&lock.key, ttl, &precondition).await?;
+        Ok(())
+    }
+
+    async fn release(&self, lock: &S3Lock) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Delete {
+                key: lock.key.clone(),
+            });
+            return Ok(());
+        }
+
+        // DeleteObject cannot be made conditional here, so check the lock is still ours first.
+        match self.read_lock(&lock.key).await? {
+            Some((_, e_tag)) if Some(&e_tag) == lock.e_tag.as_ref() => {}
+            Some(_) => return Err(S3FilesystemError::PreconditionFailed),
+            None => return Ok(()),
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .delete(&self.bucket, std::slice::from_ref(&lock.key))
+            .await;
+        self.record_request("DeleteObject", started, result.is_ok());
+        drop(slot);
+
+        match result?.pop() {
+            Some(DeleteOutcome::Failed { code, message, .. }) => Err(io::Error::other(format!(
+                "the lock could not be deleted: {}",
+                message.or(code).unwrap_or_default()
+            ))
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the lock object for `key`, expiring `ttl` from now, if `precondition` holds.
+    async fn put_lock(
+        &self,
+        key: &str,
+        ttl: Duration,
+        precondition: &WritePrecondition,
+    ) -> Result<S3Lock, S3FilesystemError> {
+        let expires_at = SystemTime::now() + ttl;
+        let body = format!(
+            "{}\n",
+            expires_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+        );
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Upload {
+                key: key.to_string(),
+                size: body.len() as u64,
+            });
+            return Ok(S3Lock {
+                key: key.to_string(),
+                e_tag: None,
+                expires_at,
+            });
+        }
+
+        let checksum = self
+            .upload_checksum
+            .map(|algorithm| ObjectChecksum::of(algorithm, body.as_bytes()));
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .put(PutRequest {
+                bucket: &self.bucket,
+                key,
+                body: ByteStream::from(body.into_bytes()),
+                content_type: Some("text/plain"),
+                acl: self.upload_acl,
+                precondition: Some(precondition),
+                checksum: checksum.as_ref(),
+                object_lock: ObjectLock::default(),
+            })
+            .await;
+        self.record_request("PutObject", started, result.is_ok());
+        drop(slot);
+
+        Ok(S3Lock {
+            key: key.to_string(),
+            e_tag: result?,
+            expires_at,
+        })
+    }
+
+    /// When the lock at `key` expires and its ETag, or None if nobody holds it.
+    async fn read_lock(
+        &self,
+        key: &str,
+    ) -> Result<Option<(SystemTime, String)>, S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .get(GetRequest {
+                bucket: &self.bucket,
+                key,
+                range: None,
+                if_match: None,
+                if_none_match: None,
+            })
+            .await;
+        self.record_request("GetObject", started, result.is_ok());
+
+        let object = match result {
+            Ok(object) => object,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let e_tag = object.e_tag.unwrap_or_default();
+        let body = object.body.collect().await?.into_bytes();
+        drop(slot);
+
+        let expires_at = std::str::from_utf8(&body)
+            .ok()
+            .and_then(|body| body.trim().parse().ok())
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a lock object"))?;
+        Ok(Some((expires_at, e_tag)))
+    }
+}
+
+/// Whether a conditional write failed because another worker created or changed the lock first.
+///
+/// S3 answers 409 rather than 412 when two conditional writes to one key are in flight at once.
+fn lost_race(err: &S3FilesystemError) -> bool {
+    err.is_precondition_failed() || err.status() == Some(409)
+}
@@ -0,0 +1,193 @@
+//! Keeping the local mirror of a prefix up to date with S3 on a schedule.
+use std::{path::PathBuf, time::Duration};
+
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{OpenOptions, S3FilesystemError, SyncFailure};
+
+/// How many cycle reports are kept for a [SyncTask] before it waits for them to be read.
+const CYCLE_BUFFER: usize = 64;
+
+#[derive(Debug, Default)]
+/// What one cycle of a [SyncTask] changed in the mount path. Paths are keys within the bucket.
+pub struct SyncCycle {
+    /// Objects new in S3 which were downloaded.
+    pub downloaded: Vec<PathBuf>,
+    /// Cached files which differed from S3 and were downloaded again.
+    pub updated: Vec<PathBuf>,
+    /// Cached files whose object was deleted from S3, which were removed from the mount path.
+    pub removed: Vec<PathBuf>,
+    /// Files which could not be downloaded or removed. They are tried again next cycle.
+    pub failed: Vec<SyncFailure>,
+}
+
+impl SyncCycle {
+    /// Whether the cycle found nothing to change.
+    pub fn is_empty(&self) -> bool {
+        self.downloaded.is_empty()
+            && self.updated.is_empty()
+            && self.removed.is_empty()
+            && self.failed.is_empty()
+    }
+}
+
+/// A sync running in the background, returned by [OpenOptions::spawn_sync].
+///
+/// Syncing stops when the handle is dropped or [SyncTask::stop] is called.
+#[derive(Debug)]
+pub struct SyncTask {
+    cycles: mpsc::Receiver<Result<SyncCycle, S3FilesystemError>>,
+    task: JoinHandle<()>,
+}
+
+impl SyncTask {
+    /// Wait for the next cycle to finish, returning what it changed
+    ///
+    /// A cycle which could not compare the mount path with S3, for instance because the prefix could not
+    /// be listed, is returned as an error and the next cycle runs as usual. Returns None once the task
+    /// has stopped and every report has been read. Every report is kept, so once 64 go unread the sync
+    /// waits for them to be read before starting another cycle.
+    pub async fn next_cycle(&mut self) -> Option<Result<SyncCycle, S3FilesystemError>> {
+        self.cycles.recv().await
+    }
+
+    /// Stop syncing. A cycle part way through is abandoned; files it already downloaded are kept.
+    pub fn stop(self) {}
+}
+
+impl Drop for SyncTask {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl OpenOptions {
+    /// Keep the mount path up to date with a prefix in S3, in the background
+    ///
+    /// Straight away, and then every `interval`, the cached files under `prefix` are compared with the
+    /// objects in S3 as [OpenOptions::diff] compares them. Objects not yet cached are downloaded, cached
+    /// files which differ from their object are downloaded again, and cached files whose object has been
+    /// deleted are removed. Each cycle's changes are reported through the returned [SyncTask].
+    ///
+    /// Files written with [OpenOptions::write_back] and not yet flushed are left alone, though S3 does not
+    /// have them yet or has an older version, so a sync never loses a pending write. So are files changed
+    /// in the mount path since they were last downloaded or uploaded, which
+    /// [OpenOptions::spawn_auto_upload] may not have uploaded yet.
+    ///
+    /// Only the mount path is changed; nothing is uploaded to or deleted from S3. A cycle which fails is
+    /// reported and the next one carries on as usual. Cancelling [OpenOptions::cancel_on]'s token stops the
+    /// sync, whether during a cycle or between them. This has to be called from within a Tokio runtime.
+    ///
+    /// # Arguments
+    /// * `prefix`: Only keys starting with this are synced. Use "" for the whole bucket.
+    /// * `interval`: How long to wait after one cycle finishes before starting the next.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/reference/");
+    ///
+    ///     let mut sync = open_options.spawn_sync("reference/", Duration::from_secs(300));
+    ///
+    ///     while let Some(cycle) = sync.next_cycle().await {
+    ///         match cycle {
+    ///             Ok(cycle) => println!(
+    ///                 "{} downloaded, {} updated, {} removed",
+    ///                 cycle.downloaded.len(),
+    ///                 cycle.updated.len(),
+    ///                 cycle.removed.len()
+    ///             ),
+    ///             Err(e) => println!("Sync failed: {}", e),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn spawn_sync(&self, prefix: &str, interval: Duration) -> SyncTask {
+        let (sender, cycles) = mpsc::channel(CYCLE_BUFFER);
+        let open_options = self.clone();
+        let prefix = prefix.to_string();
+
+        let task = tokio::spawn(async move {
+            loop {
+                let cycle = open_options.sync_cycle(&prefix).await;
+                let cancelled = cycle.as_ref().is_err_and(|e| e.is_cancelled());
+                if sender.send(cycle).await.is_err() || cancelled {
+                    return;
+                }
+
+                let wait = async {
+                    tokio::time::sleep(interval).await;
+                    Ok(())
+                };
+                if open_options.cancellable(wait).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        SyncTask { cycles, task }
+    }
+
+    /// Bring the mount path in line with S3 under `prefix` once.
+    async fn sync_cycle(&self, prefix: &str) -> Result<SyncCycle, S3FilesystemError> {
+        let report = self.diff(prefix).await?;
+        let mut cycle = SyncCycle::default();
+
+        for path in report.remote_only {
+            match self.fetch(&path, None).await {
+                Ok(_) => cycle.downloaded.push(path),
+                Err(error) if error.is_cancelled() => return Err(error),
+                Err(error) => cycle.failed.push(SyncFailure { path, error }),
+            }
+        }
+
+        for change in report.changed {
+            let path = change.path;
+            let key = path.to_string_lossy();
+            if self.has_unsent_changes(&key).await? {
+                continue;
+            }
+            // The stale copy may still count as fresh under the cache policy, so it is removed first.
+            let updated = match self.evict(&key).await {
+                Ok(()) => self.fetch(&path, None).await.map(|_| ()),
+                Err(error) => Err(error),
+            };
+            match updated {
+                Ok(()) => cycle.updated.push(path),
+                Err(error) if error.is_cancelled() => return Err(error),
+                Err(error) => cycle.failed.push(SyncFailure { path, error }),
+            }
+        }
+
+        for path in report.local_only {
+            if self.has_unsent_changes(&path.to_string_lossy()).await? {
+                continue;
+            }
+            match self.evict(&path.to_string_lossy()).await {
+                Ok(()) => cycle.removed.push(path),
+                Err(error) => cycle.failed.push(SyncFailure { path, error }),
+            }
+        }
+
+        Ok(cycle)
+    }
+    /// Whether the mirrored copy of `key` holds writes S3 does not have yet, either pending for
+    /// [OpenOptions::flush] or made in the mount path by another program, so syncing must not replace it.
+    async fn has_unsent_changes(&self, key: &str) -> Result<bool, S3FilesystemError> {
+        if self.is_pending(key).await? {
+            return Ok(true);
+        }
+        Ok(self
+            .local_change(key, &self.local_path(key)?)
+            .await?
+            .is_some())
+    }
+}
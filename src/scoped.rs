@@ -0,0 +1,106 @@
+//! Making calls with other credentials, such as short-lived STS tokens scoped to one tenant.
+use std::path::Path;
+
+use aws_sdk_s3::{config::Credentials, Client};
+use tokio::fs::File;
+
+use crate::{backend::Backend, OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// A view of this OpenOptions which signs its requests with other credentials
+    ///
+    /// The client is rebuilt with `credentials`, keeping its endpoint, timeouts, [OpenOptions::tls] settings
+    /// and request hooks, and everything else is shared with this OpenOptions: the mount path, cache index,
+    /// request limits and metrics. It is cheap enough to make one per request, so per-tenant STS credentials
+    /// can be used without configuring a whole OpenOptions for each.
+    ///
+    /// The local mirror is shared too. A file cached through one set of credentials is served to the next
+    /// caller without S3 checking theirs, unless the [CachePolicy](crate::CachePolicy) revalidates it, so
+    /// give each tenant its own mount path if they may not read each other's objects.
+    ///
+    /// # Arguments
+    /// * `credentials`: The credentials to sign requests with.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aws_sdk_s3::config::Credentials;
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/tenant-a/");
+    ///
+    ///     // Credentials from an STS AssumeRole scoped to tenant-a/.
+    ///     let tenant = Credentials::new("ASIA...", "secret", Some("token".to_string()), None, "sts");
+    ///     let scoped = open_options.with_credentials(tenant);
+    ///
+    ///     scoped.write_s3("tenant-a/report.csv", b"a,b,c").await.unwrap();
+    ///     scoped.open_s3("tenant-a/config.json").await.unwrap();
+    /// }
+    /// ```
+    pub fn with_credentials(&self, credentials: Credentials) -> Self {
+        self.clone()
+            .reconfigure_client(|config| config.credentials_provider(credentials))
+    }
+
+    /// A view of this OpenOptions which sends its requests with another client
+    ///
+    /// As [OpenOptions::with_credentials], but replacing the whole client, for credentials which need a
+    /// provider of their own or a client set up differently. The new client is used as it is: interceptors
+    /// this OpenOptions added to its own client, for [OpenOptions::adaptive_backoff],
+    /// [OpenOptions::request_hook] and the timeouts, do not carry over. A backend installed with
+    /// [OpenOptions::backend] is kept, so only requests made with the S3 client go through `client`.
+    ///
+    /// # Arguments
+    /// * `client`: The client to send requests with.
+    pub fn with_client(&self, client: Client) -> Self {
+        let mut scoped = self.clone();
+        scoped.s3_client = client;
+        if scoped.backend.is_s3() {
+            scoped.backend = Backend::s3(scoped.s3_client.clone()).with_prefix(&scoped.prefix);
+        }
+        scoped
+    }
+
+    /// Open a file from S3, as [OpenOptions::open_s3] does, signing the requests with other credentials
+    ///
+    /// Shorthand for [OpenOptions::with_credentials] followed by [OpenOptions::open_s3], for a single call.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to be opened.
+    /// * `credentials`: The credentials to sign this call's requests with.
+    pub async fn open_s3_as<P>(
+        &self,
+        path: P,
+        credentials: Credentials,
+    ) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.with_credentials(credentials).open_s3(path).await
+    }
+
+    /// Write a file to S3, as [OpenOptions::write_s3] does, signing the requests with other credentials
+    ///
+    /// Shorthand for [OpenOptions::with_credentials] followed by [OpenOptions::write_s3], for a single call.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, to write the file to.
+    /// * `buf`: The contents of the file.
+    /// * `credentials`: The credentials to sign this call's requests with.
+    pub async fn write_s3_as<P>(
+        &self,
+        path: P,
+        buf: &[u8],
+        credentials: Credentials,
+    ) -> Result<File, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.with_credentials(credentials).write_s3(path, buf).await
+    }
+}
@@ -0,0 +1,217 @@
+//! Running S3 Select queries so filtering happens in S3 rather than after a download.
+use std::{io, path::Path, time::Instant};
+
+use aws_sdk_s3::types::{
+    CsvInput, CsvOutput, ExpressionType, FileHeaderInfo, InputSerialization, JsonInput, JsonOutput,
+    JsonType, OutputSerialization, ParquetInput, SelectObjectContentEventStream,
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+use crate::{key::s3_key, OpenOptions, S3FilesystemError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The format of an object queried with [OpenOptions::select].
+pub enum SelectInput {
+    /// Comma separated values. With `header` = true the first line names the columns, so queries can
+    /// refer to them by name; otherwise columns are `_1`, `_2` and so on.
+    Csv {
+        /// Whether the first line of the file holds column names.
+        header: bool,
+    },
+    /// One JSON object per line.
+    JsonLines,
+    /// A single JSON document.
+    JsonDocument,
+    /// An Apache Parquet file.
+    Parquet,
+}
+
+impl OpenOptions {
+    /// Run an S3 Select query against a file, streaming back the matching rows
+    ///
+    /// The query runs inside S3, so only the rows it returns are transferred rather than the whole object
+    /// as with [OpenOptions::open_s3]. Each item on the stream is one row without its trailing newline: a
+    /// CSV line for [SelectInput::Csv] files, and a JSON object for JSON and Parquet files.
+    ///
+    /// Errors starting the query are returned straight away. Errors part way through are yielded on the
+    /// stream, after which it ends. This must be called from within a Tokio runtime, as the results are
+    /// read on a spawned task.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the file to query.
+    /// * `sql`: The query, which selects from `S3Object`.
+    /// * `input_format`: The format of the file.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, SelectInput};
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let mut rows = open_options
+    ///         .select(
+    ///             "some_folder/some_file.csv",
+    ///             "SELECT s.name FROM S3Object s WHERE CAST(s.age AS INT) > 30",
+    ///             SelectInput::Csv { header: true },
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     while let Some(row) = rows.next().await {
+    ///         println!("Row: {}", row.unwrap());
+    ///     }
+    /// }
+    /// ```
+    pub async fn select<P, S>(
+        &self,
+        path: P,
+        sql: S,
+        input_format: SelectInput,
+    ) -> Result<impl Stream<Item = Result<String, S3FilesystemError>>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+        S: Into<String>,
+    {
+        let path = path.as_ref();
+        self.start_select(path, sql.into(), input_format)
+            .await
+            .map_err(|e| e.with_context("SelectObjectContent", &self.bucket, Some(path)))
+    }
+
+    async fn start_select(
+        &self,
+        path: &Path,
+        sql: String,
+        input_format: SelectInput,
+    ) -> Result<impl Stream<Item = Result<String, S3FilesystemError>>, S3FilesystemError> {
+        let key = s3_key(path)?;
+
+        let (input, output) = match input_format {
+            SelectInput::Csv { header } => (
+                InputSerialization::builder()
+                    .csv(
+                        CsvInput::builder()
+                            .file_header_info(match header {
+                                true => FileHeaderInfo::Use,
+                                false => FileHeaderInfo::None,
+                            })
+                            .build(),
+                    )
+                    .build(),
+                OutputSerialization::builder()
+                    .csv(CsvOutput::builder().record_delimiter("\n").build())
+                    .build(),
+            ),
+            SelectInput::JsonLines | SelectInput::JsonDocument => (
+                InputSerialization::builder()
+                    .json(
+                        JsonInput::builder()
+                            .r#type(match input_format {
+                                SelectInput::JsonLines => JsonType::Lines,
+                                _ => JsonType::Document,
+                            })
+                            .build(),
+                    )
+                    .build(),
+                json_output(),
+            ),
+            SelectInput::Parquet => (
+                InputSerialization::builder()
+                    .parquet(ParquetInput::builder().build())
+                    .build(),
+                json_output(),
+            ),
+        };
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .select_object_content()
+            .bucket(&self.bucket)
+            .key(self.remote_key(&key))
+            .expression(sql)
+            .expression_type(ExpressionType::Sql)
+            .input_serialization(input)
+            .output_serialization(output)
+            .send()
+            .await;
+        self.record_request("SelectObjectContent", started, result.is_ok());
+        let mut events = result?.payload;
+
+        let (sender, receiver) = mpsc::channel(64);
+        let open_options = self.clone();
+
+        tokio::spawn(async move {
+            // The request is in flight until its results have all arrived.
+            let _slot = slot;
+            let mut pending = Vec::new();
+
+            loop {
+                let records = match events.recv().await {
+                    Ok(Some(SelectObjectContentEventStream::Records(records))) => records,
+                    Ok(Some(SelectObjectContentEventStream::End(_))) => {
+                        if !pending.is_empty() {
+                            let row = String::from_utf8_lossy(&pending).into_owned();
+                            let _ = sender.send(Ok(row)).await;
+                        }
+                        return;
+                    }
+                    Ok(Some(_)) => continue,
+                    failed => {
+                        // S3 always finishes a successful query with an End event.
+                        let err = match failed {
+                            Err(err) => io::Error::other(aws_sdk_s3::Error::from(err)),
+                            _ => io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "S3 Select results ended before the query finished",
+                            ),
+                        };
+                        let err = S3FilesystemError::from(err).with_context(
+                            "SelectObjectContent",
+                            &open_options.bucket,
+                            Some(Path::new(&key)),
+                        );
+                        let _ = sender.send(Err(err)).await;
+                        return;
+                    }
+                };
+
+                let chunk = records
+                    .payload()
+                    .map(|blob| blob.as_ref())
+                    .unwrap_or_default();
+                if let Some(limiter) = &open_options.bandwidth_limiter {
+                    limiter.acquire(chunk.len() as u64).await;
+                }
+                if let Some(metrics) = &open_options.metrics {
+                    metrics.downloaded(&key, chunk.len() as u64);
+                }
+                pending.extend_from_slice(chunk);
+
+                while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+                    let row = String::from_utf8_lossy(&pending[..end]).into_owned();
+                    pending.drain(..=end);
+                    if sender.send(Ok(row)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(ReceiverStream::new(receiver))
+    }
+}
+
+/// Results as one JSON object per line.
+fn json_output() -> OutputSerialization {
+    OutputSerialization::builder()
+        .json(JsonOutput::builder().record_delimiter("\n").build())
+        .build()
+}
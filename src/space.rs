@@ -0,0 +1,157 @@
+//! Checking there is room for a download before it starts, and keeping the local mirror within a quota.
+use std::{io, path::Path, time::SystemTime};
+
+use crate::{cache::MirroredFile, OpenOptions, S3FilesystemError};
+
+impl OpenOptions {
+    /// Cap how much disk the local mirror of the bucket may use
+    ///
+    /// Before each download into the mount path, the files already mirrored for this bucket are totalled,
+    /// and if the new file would take them over `bytes` the oldest downloads are evicted until it fits, as
+    /// [OpenOptions::purge_cache] would remove them. A file larger than the whole quota is refused with
+    /// [S3FilesystemError::InsufficientSpace] before anything is written. Files downloaded elsewhere with
+    /// [OpenOptions::open_s3_to] do not count towards the quota.
+    ///
+    /// Whether or not a quota is set, each download first checks the free space on the disk holding its
+    /// destination, and fails with [S3FilesystemError::InsufficientSpace] rather than running out part way
+    /// through. Free space is only checked on Unix-like systems.
+    ///
+    /// # Arguments
+    /// * `bytes`: The most bytes the mirror of this bucket may hold.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/")
+    ///         .cache_quota(10 * 1024 * 1024 * 1024);
+    ///
+    ///     match open_options.open_s3("datasets/huge.parquet").await {
+    ///         Err(e) if e.is_insufficient_space() => println!("No room for the file: {}", e),
+    ///         result => {
+    ///             result.unwrap();
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn cache_quota(mut self, bytes: u64) -> Self {
+        self.cache_quota = Some(bytes);
+        self
+    }
+
+    /// Make room for `needed` more bytes at `destination`, evicting old downloads if it is in the mirror and
+    /// a quota is set.
+    pub(crate) async fn reserve_space(
+        &self,
+        destination: &Path,
+        mirrored: bool,
+        needed: u64,
+    ) -> Result<(), S3FilesystemError> {
+        if let (Some(quota), true) = (self.cache_quota, mirrored) {
+            self.evict_for(destination, needed, quota).await?;
+        }
+
+        let folder = destination.parent().unwrap_or(destination);
+        if let Some(available) = available_space(folder)? {
+            if needed > available {
+                return Err(S3FilesystemError::InsufficientSpace { needed, available });
+            }
+        }
+        Ok(())
+    }
+
+    /// Evict old downloads until `size` bytes already written to `written` in the mirror fit within the
+    /// quota, for a file which turned out larger than the room made for it.
+    pub(crate) async fn fit_in_quota(
+        &self,
+        written: &Path,
+        size: u64,
+    ) -> Result<(), S3FilesystemError> {
+        match self.cache_quota {
+            Some(quota) => self.evict_for(written, size, quota).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Evict the oldest complete downloads until `needed` more bytes fit within `quota`. The file at
+    /// `destination` is about to be replaced, so it is neither counted nor evicted.
+    async fn evict_for(
+        &self,
+        destination: &Path,
+        needed: u64,
+        quota: u64,
+    ) -> Result<(), S3FilesystemError> {
+        if needed > quota {
+            return Err(S3FilesystemError::InsufficientSpace {
+                needed,
+                available: quota,
+            });
+        }
+
+        let mut files: Vec<(SystemTime, MirroredFile)> = Vec::new();
+        let mut used = 0;
+        for file in self.mirrored_files(true).await? {
+            if file.path == destination {
+                continue;
+            }
+            used += file.size;
+            // Partial downloads may still be in progress, and pending writes are not in S3 yet, so they
+            // count but are left alone.
+            if let Ok(modified) = std::fs::metadata(&file.path).and_then(|m| m.modified()) {
+                if !file.key.ends_with(".part") && !self.is_pending(&file.key).await? {
+                    files.push((modified, file));
+                }
+            }
+        }
+        files.sort_by_key(|(modified, _)| *modified);
+
+        let mut oldest = files.into_iter();
+        while used + needed > quota {
+            let (_, file) = match oldest.next() {
+                Some(file) => file,
+                None => break,
+            };
+            self.evict(&file.key).await?;
+            used -= file.size;
+        }
+
+        match used + needed > quota {
+            true => Err(S3FilesystemError::InsufficientSpace {
+                needed,
+                available: quota.saturating_sub(used),
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+/// The bytes free for unprivileged use on the filesystem holding `path`.
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stats` is only read after statvfs reports it was filled in.
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stats.assume_init()
+    };
+    #[allow(clippy::unnecessary_cast)]
+    Ok(Some(stats.f_bavail as u64 * stats.f_frsize as u64))
+}
+
+/// Free space is not checked on this platform.
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
@@ -0,0 +1,297 @@
+//! Caching the ranges of large objects read through [S3File](crate::S3File) in sparse files.
+//!
+//! Each object gets a file the full size of the object in `<mount_path>/<bucket>/%sparse/`, named by the
+//! SHA-256 of its key, which only has the ranges read so far written into it. Filesystems which support
+//! sparse files store nothing for the rest. Beside it, `<name>.extents` records which ranges are present:
+//!
+//! ```text
+//! <object size>\t<etag, or - if unknown>\t<start>-<end>,<start>-<end>,...\t<key>
+//! ```
+//!
+//! where each `<start>-<end>` is a half-open range of bytes held in the sparse file.
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+    cache::{hashed_name, PrefixStats},
+    options::bucket_folder,
+    OpenOptions, S3FilesystemError,
+};
+
+/// The folder, below the bucket's folder in the mount path, holding the sparse files. `%` is always
+/// escaped in mirrored paths, so no key is mirrored into it.
+pub(crate) const SPARSE_FOLDER: &str = "%sparse";
+
+/// A half-open range of bytes held in a sparse file.
+type Extent = (u64, u64);
+
+/// The sparse file of one version of an object, and the ranges of it which have been written.
+#[derive(Debug, Clone)]
+pub(crate) struct SparseCache {
+    path: PathBuf,
+    /// Everything in the extent map before the extents themselves.
+    header: String,
+    key: String,
+    /// Half-open byte ranges held in the file, sorted and never touching.
+    extents: Arc<Mutex<Vec<Extent>>>,
+}
+
+impl SparseCache {
+    /// Where the cached range starting at `start` ends, reading at most `length` bytes, if `start` has been
+    /// cached.
+    pub(crate) fn cached_end(&self, start: u64, length: u64) -> Option<u64> {
+        let extents = self.extents.lock().unwrap();
+        extents
+            .iter()
+            .find(|(from, to)| *from <= start && start < *to)
+            .map(|(_, to)| (*to).min(start + length))
+    }
+
+    /// Where a range fetched from `start` should stop: after `length` bytes, or at the next cached range.
+    pub(crate) fn uncached_end(&self, start: u64, length: u64) -> u64 {
+        let extents = self.extents.lock().unwrap();
+        extents
+            .iter()
+            .map(|(from, _)| *from)
+            .find(|from| *from > start)
+            .map_or(start + length, |from| from.min(start + length))
+    }
+
+    /// Bytes `start..end` from the sparse file.
+    pub(crate) async fn read(&self, start: u64, end: u64) -> io::Result<Bytes> {
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut range = vec![0; (end - start) as usize];
+        file.read_exact(&mut range).await?;
+        Ok(range.into())
+    }
+
+    /// Write `range`, fetched from `start`, into the sparse file and record it in the extent map. The range
+    /// is only recorded once it is on disk, so a write cut short is fetched again rather than read back.
+    pub(crate) async fn write(&self, start: u64, range: &[u8]) -> io::Result<()> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        file.write_all(range).await?;
+        file.sync_data().await?;
+
+        let map = {
+            let mut extents = self.extents.lock().unwrap();
+            add_extent(&mut extents, (start, start + range.len() as u64));
+            format!(
+                "{}\t{}\t{}",
+                self.header,
+                format_extents(&extents),
+                self.key
+            )
+        };
+        write_map(&self.path, &map).await
+    }
+}
+
+impl OpenOptions {
+    /// Cache the ranges of objects read with [OpenOptions::open_s3_lazy] in sparse files
+    ///
+    /// Each range an [S3File](crate::S3File) fetches is written at its own offset into a sparse file the
+    /// size of the object, kept in the mount path, with a map of which ranges it holds. Reading a range
+    /// again, from this or a later S3File, is served from disk instead of S3, and the full object is never
+    /// stored; on filesystems with sparse file support, only the ranges read take up space.
+    ///
+    /// Sparse files are kept in a `%sparse` folder below the bucket's folder in the mount path. They are
+    /// reused only while the object's ETag, checked by the HeadObject request made on opening, is unchanged;
+    /// otherwise the object's sparse file is started again. They are removed by [OpenOptions::purge_cache],
+    /// but are not counted by [OpenOptions::cache_stats] or towards [OpenOptions::cache_quota]. Ignored if
+    /// [OpenOptions::block_cache] is set.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::io::SeekFrom;
+    /// use tokio::io::{AsyncReadExt, AsyncSeekExt};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/cache/")
+    ///         .sparse_cache(true);
+    ///
+    ///     // Every run reads the same footer, which is only fetched from S3 the first time.
+    ///     let mut file = open_options
+    ///         .open_s3_lazy("some_folder/data.parquet")
+    ///         .await
+    ///         .unwrap()
+    ///         .buffer_size(64 * 1024);
+    ///     let mut footer = [0; 8];
+    ///     file.seek(SeekFrom::End(-8)).await.unwrap();
+    ///     file.read_exact(&mut footer).await.unwrap();
+    /// }
+    /// ```
+    pub fn sparse_cache(mut self, enabled: bool) -> Self {
+        self.sparse_cache = enabled;
+        self
+    }
+
+    /// The sparse file `key` is cached in.
+    fn sparse_path(&self, key: &str) -> PathBuf {
+        self.mount_path
+            .join(bucket_folder(&self.bucket))
+            .join(SPARSE_FOLDER)
+            .join(hashed_name(key))
+    }
+
+    /// The sparse cache for the version of `key` with `e_tag` and `size`, if sparse caching is on. A sparse
+    /// file cached from another version is started again.
+    pub(crate) async fn open_sparse(
+        &self,
+        key: &str,
+        size: u64,
+        e_tag: Option<&str>,
+    ) -> Result<Option<SparseCache>, S3FilesystemError> {
+        if !self.sparse_cache || self.block_size.is_some() {
+            return Ok(None);
+        }
+        let path = self.sparse_path(key);
+        let header = format!("{}\t{}", size, e_tag.unwrap_or("-"));
+
+        let map = tokio::fs::read_to_string(map_path(&path)).await.ok();
+        let extents = match map.as_deref().and_then(parse_map) {
+            Some((cached_header, extents, _)) if cached_header == header => extents,
+            _ => {
+                let folder = path.parent().expect("sparse files are in a folder");
+                tokio::fs::create_dir_all(folder).await?;
+                let file = tokio::fs::File::create(&path).await?;
+                file.set_len(size).await?;
+                write_map(&path, &format!("{}\t\t{}", header, key)).await?;
+                Vec::new()
+            }
+        };
+
+        Ok(Some(SparseCache {
+            path,
+            header,
+            key: key.to_string(),
+            extents: Arc::new(Mutex::new(extents)),
+        }))
+    }
+
+    /// Remove the sparse file of `key`.
+    pub(crate) async fn evict_sparse(&self, key: &str) -> io::Result<()> {
+        let path = self.sparse_path(key);
+        remove_file(&map_path(&path)).await?;
+        remove_file(&path).await
+    }
+
+    /// Remove the sparse files of every object whose key starts with `prefix`, returning how many files
+    /// and cached bytes were removed.
+    pub(crate) async fn purge_sparse(&self, prefix: &str) -> io::Result<PrefixStats> {
+        let folder = self
+            .mount_path
+            .join(bucket_folder(&self.bucket))
+            .join(SPARSE_FOLDER);
+        let mut purged = PrefixStats::default();
+
+        let mut entries = match tokio::fs::read_dir(&folder).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(purged),
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some() {
+                continue;
+            }
+            // A sparse file with no extent map is left over from an interrupted open, and goes too.
+            let map = tokio::fs::read_to_string(map_path(&path))
+                .await
+                .unwrap_or_default();
+            let (extents, key) = match parse_map(&map) {
+                Some((_, extents, key)) => (extents, Some(key)),
+                None => (Vec::new(), None),
+            };
+            if key.is_some_and(|key| !key.starts_with(prefix)) {
+                continue;
+            }
+
+            purged.bytes += extents.iter().map(|(start, end)| end - start).sum::<u64>();
+            purged.files += 1;
+            remove_file(&map_path(&path)).await?;
+            remove_file(&path).await?;
+        }
+
+        Ok(purged)
+    }
+}
+
+/// The extent map of the sparse file at `path`.
+fn map_path(path: &Path) -> PathBuf {
+    path.with_extension("extents")
+}
+
+/// Replace the extent map of the sparse file at `path` with `map`.
+async fn write_map(path: &Path, map: &str) -> io::Result<()> {
+    let temporary = path.with_extension("extents.tmp");
+    tokio::fs::write(&temporary, map).await?;
+    tokio::fs::rename(&temporary, map_path(path)).await
+}
+
+/// The header, extents and key of an extent map, or None if it is not one.
+fn parse_map(map: &str) -> Option<(String, Vec<Extent>, &str)> {
+    let mut fields = map.splitn(4, '\t');
+    let size = fields.next()?;
+    let e_tag = fields.next()?;
+    let extents = fields
+        .next()?
+        .split(',')
+        .filter(|extent| !extent.is_empty())
+        .map(|extent| {
+            let (start, end) = extent.split_once('-')?;
+            Some((start.parse().ok()?, end.parse().ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let key = fields.next()?;
+
+    Some((format!("{}\t{}", size, e_tag), extents, key))
+}
+
+/// `extents` as they are written in an extent map.
+fn format_extents(extents: &[Extent]) -> String {
+    extents
+        .iter()
+        .map(|(start, end)| format!("{}-{}", start, end))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Add `extent` to the sorted `extents`, merging any it overlaps or touches.
+fn add_extent(extents: &mut Vec<Extent>, extent: Extent) {
+    let (mut start, mut end) = extent;
+    extents.retain(|(from, to)| {
+        let separate = *to < start || end < *from;
+        if !separate {
+            start = start.min(*from);
+            end = end.max(*to);
+        }
+        separate
+    });
+    let index = extents.partition_point(|(from, _)| *from < start);
+    extents.insert(index, (start, end));
+}
+
+/// Remove the file at `path`, if it exists.
+async fn remove_file(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
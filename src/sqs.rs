@@ -0,0 +1,339 @@
+//! Cache invalidation driven by S3 event notifications delivered through SQS.
+use serde_json::Value;
+use std::path::PathBuf;
+
+use crate::{
+    error::S3FilesystemError, options::decode_key, tls::config_loader, CachePolicy, OpenOptions,
+};
+
+/// The longest SQS allows a receive to wait for messages.
+const MAX_WAIT_SECONDS: i32 = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What happened to a locally cached file in response to an event notification.
+pub enum CacheUpdate {
+    /// The cached copy at this path was removed, so the next open downloads it again.
+    Evicted(PathBuf),
+    /// The cached copy at this path was re-downloaded from S3.
+    Refreshed(PathBuf),
+}
+
+/// Keeps the files cached under a mount path in step with S3 event notifications.
+///
+/// Created with [OpenOptions::sqs_invalidator]. The queue can receive notifications directly from S3,
+/// through an SNS topic, or from EventBridge. Events for other buckets, or for keys outside
+/// [OpenOptions::prefix], are ignored.
+#[derive(Debug, Clone)]
+pub struct SqsInvalidator {
+    open_options: OpenOptions,
+    sqs_client: aws_sdk_sqs::Client,
+    queue_url: String,
+    refresh: bool,
+}
+
+impl OpenOptions {
+    /// Create an [SqsInvalidator] that keeps this mount's cache up to date from an SQS queue.
+    ///
+    /// Client is an optional argument - if it exists that will be the SQS client used and if it doesn't,
+    /// one is created from your environment (the AWS CLI).
+    ///
+    /// # Arguments
+    /// * `queue_url`: The URL of the queue S3 event notifications are delivered to.
+    /// * `client`: An optional SQS client.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let invalidator = open_options
+    ///         .sqs_invalidator("https://sqs.eu-west-2.amazonaws.com/123456789012/my-queue", None)
+    ///         .await
+    ///         .refresh(true);
+    ///
+    ///     invalidator.run().await.unwrap();
+    /// }
+    /// ```
+    pub async fn sqs_invalidator<Q>(
+        &self,
+        queue_url: Q,
+        client: Option<aws_sdk_sqs::Client>,
+    ) -> SqsInvalidator
+    where
+        Q: Into<String>,
+    {
+        let sqs_client = match client {
+            Some(x) => x,
+            None => {
+                let config = config_loader().load().await;
+                aws_sdk_sqs::Client::new(&config)
+            }
+        };
+
+        SqsInvalidator {
+            open_options: self.clone(),
+            sqs_client,
+            queue_url: queue_url.into(),
+            refresh: false,
+        }
+    }
+}
+
+impl SqsInvalidator {
+    /// Re-download created or overwritten objects instead of only evicting them
+    ///
+    /// By default cached files are simply removed, and are downloaded again the next time they are opened.
+    /// Pass `refresh` = true to download new contents straight away. Deleted objects are always evicted.
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Receive one batch of messages and apply them to the cache.
+    ///
+    /// Waits up to 20 seconds for messages to arrive. Every received message is deleted from the queue once
+    /// it has been handled, including messages that are not S3 object events (such as the test event S3
+    /// sends when notifications are configured).
+    pub async fn poll(&self) -> Result<Vec<CacheUpdate>, S3FilesystemError> {
+        let received = self
+            .sqs_client
+            .receive_message()
+            .queue_url(&self.queue_url)
+            .max_number_of_messages(10)
+            .wait_time_seconds(MAX_WAIT_SECONDS)
+            .send()
+            .await
+            .map_err(|e| {
+                S3FilesystemError::Sqs(Box::new(e.map_service_error(aws_sdk_sqs::Error::from)))
+            })?;
+
+        let mut updates = Vec::new();
+
+        for message in received.messages() {
+            for event in message.body().map(parse_events).unwrap_or_default() {
+                if event.bucket != self.open_options.bucket
+                    || self.open_options.relative_key(&event.key).is_none()
+                {
+                    continue;
+                }
+                updates.push(self.apply(event).await?);
+            }
+
+            if let Some(receipt_handle) = message.receipt_handle() {
+                self.sqs_client
+                    .delete_message()
+                    .queue_url(&self.queue_url)
+                    .receipt_handle(receipt_handle)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        S3FilesystemError::Sqs(Box::new(
+                            e.map_service_error(aws_sdk_sqs::Error::from),
+                        ))
+                    })?;
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Poll the queue until an error occurs.
+    pub async fn run(&self) -> Result<(), S3FilesystemError> {
+        loop {
+            self.poll().await?;
+        }
+    }
+
+    async fn apply(&self, event: ObjectEvent) -> Result<CacheUpdate, S3FilesystemError> {
+        let key = self
+            .open_options
+            .relative_key(&event.key)
+            .unwrap_or_default();
+        let local_path = self.open_options.local_path(key)?;
+
+        if event.created && self.refresh {
+            let refreshed = self
+                .open_options
+                .clone()
+                .cache_policy(CachePolicy::AlwaysDownload)
+                .open_s3(key)
+                .await;
+            if refreshed.is_ok() {
+                return Ok(CacheUpdate::Refreshed(local_path));
+            }
+        }
+
+        self.open_options.evict(key).await?;
+        Ok(CacheUpdate::Evicted(local_path))
+    }
+}
+
+/// An object created or removed, extracted from a notification.
+struct ObjectEvent {
+    bucket: String,
+    key: String,
+    created: bool,
+}
+
+/// Extract object events from a message body in S3, SNS-wrapped or EventBridge format.
+fn parse_events(body: &str) -> Vec<ObjectEvent> {
+    let value: Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(_) => return Vec::new(),
+    };
+
+    if let (Some("Notification"), Some(message)) =
+        (value["Type"].as_str(), value["Message"].as_str())
+    {
+        return parse_events(message);
+    }
+
+    if let Some(records) = value["Records"].as_array() {
+        return records
+            .iter()
+            .filter_map(|record| {
+                let event_name = record["eventName"].as_str()?;
+                let created = if event_name.starts_with("ObjectCreated") {
+                    true
+                } else if event_name.starts_with("ObjectRemoved")
+                    || event_name.starts_with("LifecycleExpiration")
+                {
+                    false
+                } else {
+                    return None;
+                };
+
+                Some(ObjectEvent {
+                    bucket: record["s3"]["bucket"]["name"].as_str()?.to_string(),
+                    key: decode_key(record["s3"]["object"]["key"].as_str()?),
+                    created,
+                })
+            })
+            .collect();
+    }
+
+    let created = match value["detail-type"].as_str() {
+        Some("Object Created") => true,
+        Some("Object Deleted") => false,
+        _ => return Vec::new(),
+    };
+
+    let detail = &value["detail"];
+    match (
+        detail["bucket"]["name"].as_str(),
+        detail["object"]["key"].as_str(),
+    ) {
+        (Some(bucket), Some(key)) => vec![ObjectEvent {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            created,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(events: Vec<ObjectEvent>) -> Vec<(String, String, bool)> {
+        events
+            .into_iter()
+            .map(|event| (event.bucket, event.key, event.created))
+            .collect()
+    }
+
+    #[test]
+    fn test_parses_s3_notifications() {
+        let body = r#"{"Records": [
+            {"eventName": "ObjectCreated:Put",
+             "s3": {"bucket": {"name": "reports"}, "object": {"key": "daily/2024+01%2F02.csv"}}},
+            {"eventName": "ObjectRemoved:Delete",
+             "s3": {"bucket": {"name": "reports"}, "object": {"key": "old.csv"}}},
+            {"eventName": "ObjectRestore:Completed",
+             "s3": {"bucket": {"name": "reports"}, "object": {"key": "archived.csv"}}}
+        ]}"#;
+        assert_eq!(
+            summary(parse_events(body)),
+            [
+                ("reports".into(), "daily/2024 01/02.csv".into(), true),
+                ("reports".into(), "old.csv".into(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parses_sns_wrapped_and_eventbridge_notifications() {
+        let inner = r#"{"Records": [{"eventName": "LifecycleExpiration:Delete",
+            "s3": {"bucket": {"name": "reports"}, "object": {"key": "expired.csv"}}}]}"#;
+        let sns = serde_json::json!({"Type": "Notification", "Message": inner}).to_string();
+        assert_eq!(
+            summary(parse_events(&sns)),
+            [("reports".into(), "expired.csv".into(), false)]
+        );
+
+        let eventbridge = r#"{"detail-type": "Object Created",
+            "detail": {"bucket": {"name": "reports"}, "object": {"key": "new file.csv"}}}"#;
+        assert_eq!(
+            summary(parse_events(eventbridge)),
+            [("reports".into(), "new file.csv".into(), true)]
+        );
+    }
+
+    #[test]
+    fn test_ignores_messages_which_are_not_object_events() {
+        let test_event =
+            r#"{"Service": "Amazon S3", "Event": "s3:TestEvent", "Bucket": "reports"}"#;
+        assert!(parse_events(test_event).is_empty());
+        assert!(parse_events("not json").is_empty());
+        assert!(parse_events(r#"{"detail-type": "Object Restore Completed"}"#).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_evict_the_key_they_name() {
+        let mount_path = "target/test-sqs-evict/";
+        let _ = tokio::fs::remove_dir_all(mount_path).await;
+        tokio::fs::create_dir_all(format!("{}sqs_bucket/daily", mount_path))
+            .await
+            .unwrap();
+        let cached = format!("{}sqs_bucket/daily/today.csv", mount_path);
+        tokio::fs::write(&cached, "stale").await.unwrap();
+
+        let s3_config = aws_sdk_s3::Config::builder()
+            .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+            .region(aws_sdk_s3::config::Region::new("eu-west-2"))
+            .endpoint_url("http://127.0.0.1:1")
+            .build();
+        let sqs_config = aws_sdk_sqs::Config::builder()
+            .behavior_version(aws_sdk_sqs::config::BehaviorVersion::latest())
+            .region(aws_sdk_sqs::config::Region::new("eu-west-2"))
+            .endpoint_url("http://127.0.0.1:1")
+            .build();
+        let invalidator = OpenOptions::new(
+            "sqs_bucket".to_string(),
+            Some(aws_sdk_s3::Client::from_conf(s3_config)),
+        )
+        .await
+        .mount_path(mount_path)
+        .sqs_invalidator(
+            "http://127.0.0.1:1/queue",
+            Some(aws_sdk_sqs::Client::from_conf(sqs_config)),
+        )
+        .await;
+
+        let body = r#"{"Records": [{"eventName": "ObjectRemoved:Delete",
+            "s3": {"bucket": {"name": "sqs_bucket"}, "object": {"key": "daily/today.csv"}}}]}"#;
+        let event = parse_events(body).pop().unwrap();
+        let update = invalidator.apply(event).await.unwrap();
+
+        assert_eq!(update, CacheUpdate::Evicted(PathBuf::from(&cached)));
+        assert!(!std::path::Path::new(&cached).exists());
+    }
+}
@@ -0,0 +1,209 @@
+//! Looking up an object or folder the way [std::fs::metadata] looks up a file or directory.
+use std::{
+    collections::HashMap,
+    io,
+    path::Path,
+    time::{Instant, SystemTime},
+};
+
+use crate::{
+    backend::ListRequest, key::s3_prefix, ObjectChecksum, ObjectHead, OpenOptions,
+    S3FilesystemError,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// What [OpenOptions::stat] found at a path, shaped like [std::fs::Metadata].
+///
+/// Folders have no size or ETag. S3 has no real folders, so one only exists while there are objects
+/// under it, or a folder marker object ending in `/`; its modification time is the marker's, if there is one.
+pub struct Metadata {
+    pub(crate) len: u64,
+    pub(crate) is_dir: bool,
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) e_tag: Option<String>,
+    pub(crate) storage_class: Option<String>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) user_metadata: HashMap<String, String>,
+    pub(crate) checksum: Option<ObjectChecksum>,
+}
+
+impl Metadata {
+    /// The size of the object in bytes. Folders are 0 bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the object holds no data. Always true for folders.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the path is a folder: a prefix with objects under it.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Whether the path is an object.
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    /// When the object was last written.
+    ///
+    /// As with [std::fs::Metadata::modified] this is an error when the time is not known, which is the case
+    /// for folders without a marker object and for backends which do not record it.
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        self.modified.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "The last modified time is not known",
+            )
+        })
+    }
+
+    /// The entity tag S3 holds for the object, which changes whenever its contents do.
+    pub fn e_tag(&self) -> Option<&str> {
+        self.e_tag.as_deref()
+    }
+
+    /// The storage class S3 reported for the object, such as "STANDARD" or "GLACIER".
+    pub fn storage_class(&self) -> Option<&str> {
+        self.storage_class.as_deref()
+    }
+
+    /// The Content-Type the object was stored with.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// User-defined metadata, the `x-amz-meta-*` headers S3 stores with an object, without the prefix.
+    pub fn user_metadata(&self) -> &HashMap<String, String> {
+        &self.user_metadata
+    }
+
+    /// The additional CRC-32C or SHA-256 checksum the object was uploaded with, if any.
+    pub fn checksum(&self) -> Option<&ObjectChecksum> {
+        self.checksum.as_ref()
+    }
+}
+
+impl From<ObjectHead> for Metadata {
+    fn from(head: ObjectHead) -> Self {
+        Self {
+            len: head.size,
+            is_dir: false,
+            modified: head.last_modified,
+            e_tag: head.e_tag,
+            storage_class: head.storage_class,
+            content_type: head.content_type,
+            user_metadata: head.metadata,
+            checksum: head.checksum,
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Look up an object or folder in S3, like [std::fs::metadata]
+    ///
+    /// An object at the path is looked up with a HeadObject request. If there is none, the path is checked
+    /// for objects under it with a single-key listing, and reported as a folder if it has any, so code
+    /// written against [std::fs] can keep asking [Metadata::is_dir] and [Metadata::is_file]. A path ending
+    /// in `/`, or an empty path for the root of the bucket, is only looked up as a folder. Nothing is
+    /// downloaded.
+    ///
+    /// If nothing is found the error is HeadObject's, for which [S3FilesystemError::is_not_found] is true,
+    /// or for a folder path an I/O error of kind [io::ErrorKind::NotFound].
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, of the object, or the path of a folder.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let metadata = open_options.stat("redasa1-Q1-20/manifest.txt").await.unwrap();
+    ///
+    ///     if metadata.is_file() {
+    ///         println!("{} bytes, modified {:?}", metadata.len(), metadata.modified());
+    ///     }
+    /// }
+    /// ```
+    pub async fn stat<P>(&self, path: P) -> Result<Metadata, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let key = s3_prefix(path)?;
+
+        if key.is_empty() || key.ends_with('/') {
+            return match self.stat_folder(&key).await {
+                Ok(Some(metadata)) => Ok(metadata),
+                Ok(None) => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "No objects under the folder",
+                )
+                .into()),
+                Err(e) => Err(e),
+            }
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(path)));
+        }
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .cancellable(self.backend.head(&self.bucket, &key))
+            .await;
+        self.record_request("HeadObject", started, result.is_ok());
+        drop(slot);
+
+        match result {
+            Ok(head) => Ok(head.into()),
+            Err(e) if e.is_not_found() => match self.stat_folder(&format!("{}/", key)).await {
+                Ok(Some(metadata)) => Ok(metadata),
+                Ok(None) => Err(e.with_context("HeadObject", &self.bucket, Some(path))),
+                Err(e) => Err(e.with_context("ListObjectsV2", &self.bucket, Some(path))),
+            },
+            Err(e) => Err(e.with_context("HeadObject", &self.bucket, Some(path))),
+        }
+    }
+
+    /// Report `prefix` as a folder if any object's key starts with it. The root of the bucket always exists.
+    async fn stat_folder(&self, prefix: &str) -> Result<Option<Metadata>, S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .cancellable(self.backend.list(ListRequest {
+                bucket: &self.bucket,
+                prefix,
+                delimiter: None,
+                start_after: None,
+                continuation_token: None,
+                max_keys: Some(1),
+            }))
+            .await;
+        self.record_request("ListObjectsV2", started, result.is_ok());
+        drop(slot);
+        let page = result?;
+
+        if page.entries.is_empty() && !prefix.is_empty() {
+            return Ok(None);
+        }
+
+        // A folder marker object sorts before every other key under the folder.
+        let marker = page
+            .entries
+            .first()
+            .filter(|entry| entry.path.to_str() == Some(prefix));
+        Ok(Some(Metadata {
+            is_dir: true,
+            modified: marker.and_then(|marker| marker.last_modified),
+            ..Default::default()
+        }))
+    }
+}
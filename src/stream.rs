@@ -0,0 +1,141 @@
+//! Listing a prefix and downloading what it holds at the same time.
+use std::{path::Path, sync::Arc};
+
+use tokio::{
+    fs::File,
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+use crate::{DirEntry, OpenOptions, S3FilesystemError};
+
+/// How many keys to ask for in each page of the listing.
+const PAGE_SIZE: usize = 1000;
+
+type Downloaded = Result<(DirEntry, File), S3FilesystemError>;
+
+impl OpenOptions {
+    /// Download every object under a prefix, yielding each file as it arrives
+    ///
+    /// A replacement for listing with [OpenOptions::walkdir] and then calling [OpenOptions::open_s3] on each
+    /// entry in turn. Downloads start as soon as the first page of the listing arrives, while later pages
+    /// are still being fetched, and up to `concurrency` run at once. Files are yielded in the order their
+    /// downloads finish, not key order, and go through the mount path as for [OpenOptions::open_s3], so
+    /// cached copies are used as the [OpenOptions::cache_policy] allows. Folder marker objects are skipped.
+    ///
+    /// An object which fails to download is yielded as an error and the rest carry on. If the listing fails
+    /// its error is yielded once the downloads already started have finished, and the stream ends. Work
+    /// stops when the stream is dropped. This must be called from within a Tokio runtime, as the listing
+    /// and downloads happen on spawned tasks.
+    ///
+    /// # Arguments
+    /// * `prefix`: The path within the bucket to download. Use an empty string for the entire bucket.
+    /// * `concurrency`: The most downloads to run at once. Zero is treated as one.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let mut files = open_options.download_prefix_stream("redasa1-Q1-20/", 8);
+    ///
+    ///     while let Some(downloaded) = files.next().await {
+    ///         let (entry, file) = downloaded.unwrap();
+    ///         let size = file.metadata().await.unwrap().len();
+    ///         println!("{}: {} bytes", entry.path.display(), size);
+    ///     }
+    /// }
+    /// ```
+    pub fn download_prefix_stream<P>(
+        &self,
+        prefix: P,
+        concurrency: usize,
+    ) -> impl Stream<Item = Downloaded>
+    where
+        P: AsRef<Path>,
+    {
+        let concurrency = concurrency.max(1);
+        let (sender, receiver) = mpsc::channel(concurrency);
+        let open_options = self.clone();
+        let prefix = prefix.as_ref().to_path_buf();
+
+        tokio::spawn(async move {
+            if let Err(err) = open_options
+                .stream_prefix(&prefix, concurrency, &sender)
+                .await
+            {
+                let _ = sender.send(Err(err)).await;
+            }
+        });
+
+        ReceiverStream::new(receiver)
+    }
+
+    /// List the prefix page by page, starting a download for each object as soon as it is listed.
+    async fn stream_prefix(
+        &self,
+        prefix: &Path,
+        concurrency: usize,
+        sender: &mpsc::Sender<Downloaded>,
+    ) -> Result<(), S3FilesystemError> {
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        let mut tasks = JoinSet::new();
+        let mut continuation = None;
+
+        let listed = loop {
+            let page = match self
+                .cancellable(self.walkdir_page(prefix, continuation, PAGE_SIZE))
+                .await
+            {
+                Ok(page) => page,
+                Err(err) => break Err(err),
+            };
+
+            for entry in page.entries.into_iter().filter(|entry| !entry.folder) {
+                let permit = semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the semaphore is never closed");
+                if sender.is_closed() {
+                    return Ok(());
+                }
+
+                let open_options = self.clone();
+                let sender = sender.clone();
+                tasks.spawn(async move {
+                    let downloaded = open_options
+                        .open_s3(&entry.path)
+                        .await
+                        .map(|file| (entry, file));
+                    // Holding the permit until the file is taken keeps a slow consumer from piling up
+                    // finished downloads.
+                    let _ = sender.send(downloaded).await;
+                    drop(permit);
+                });
+            }
+
+            continuation = page.continuation_token;
+            if continuation.is_none() {
+                break Ok(());
+            }
+        };
+
+        while let Some(joined) = tasks.join_next().await {
+            if let Err(join_error) = joined {
+                std::panic::resume_unwind(join_error.into_panic());
+            }
+        }
+
+        listed
+    }
+}
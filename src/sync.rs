@@ -0,0 +1,207 @@
+//! Pushing a local folder to S3, uploading only what has changed.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{manifest::checksum_of, Checksum, OpenOptions, S3FilesystemError};
+
+#[derive(Debug)]
+/// A file [OpenOptions::sync_up] could not upload, or a [SyncTask](crate::SyncTask) could not download or
+/// remove.
+pub struct SyncFailure {
+    /// Key the file was being synced with.
+    pub path: PathBuf,
+    /// What went wrong.
+    pub error: S3FilesystemError,
+}
+
+#[derive(Debug, Default)]
+/// The outcome of [OpenOptions::sync_up]. Paths are the keys the files map to.
+pub struct SyncReport {
+    /// Files which were new or changed and have been uploaded.
+    pub uploaded: Vec<PathBuf>,
+    /// Files which already matched their object in S3 and were skipped.
+    pub unchanged: Vec<PathBuf>,
+    /// Files which could not be read or uploaded.
+    pub failed: Vec<SyncFailure>,
+}
+
+/// What S3 holds for a key, as listed.
+struct RemoteObject {
+    size: u64,
+    e_tag: Option<String>,
+    last_modified: Option<SystemTime>,
+}
+
+impl OpenOptions {
+    /// Upload the files in a local folder which are new or differ from S3
+    ///
+    /// Every file under `local_dir` is mapped to the key `prefix` + its path relative to `local_dir`, and
+    /// uploaded with [OpenOptions::write_s3] unless the object already matches, rather than re-uploading
+    /// everything. A file matches when its size is the same and, for objects whose ETag is the MD5 of their
+    /// contents, so is its MD5. Objects uploaded in parts have no such ETag, so they match when the local
+    /// file was not modified after the object was.
+    ///
+    /// Objects in S3 with no local file are left alone. A file which fails to upload is recorded in the
+    /// report and the rest carry on; an error is only returned if the prefix cannot be listed.
+    ///
+    /// # Arguments
+    /// * `local_dir`: The folder to upload.
+    /// * `prefix`: Where in the bucket the folder goes. A `/` is added if it does not end in one; use "" for
+    ///   the root of the bucket.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let report = open_options.sync_up("site/build", "www").await.unwrap();
+    ///
+    ///     println!(
+    ///         "{} uploaded, {} unchanged, {} failed",
+    ///         report.uploaded.len(),
+    ///         report.unchanged.len(),
+    ///         report.failed.len()
+    ///     );
+    /// }
+    /// ```
+    pub async fn sync_up<P>(
+        &self,
+        local_dir: P,
+        prefix: &str,
+    ) -> Result<SyncReport, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let prefix = match prefix.is_empty() || prefix.ends_with('/') {
+            true => prefix.to_string(),
+            false => format!("{}/", prefix),
+        };
+
+        let remote = self
+            .remote_objects(&prefix)
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(prefix.as_ref())))?;
+        let files = local_files(local_dir.as_ref()).await?;
+
+        let mut report = SyncReport::default();
+        for (relative, local_path) in files {
+            let key = format!("{}{}", prefix, relative);
+            let path = PathBuf::from(&key);
+
+            let synced = async {
+                if self.matches_remote(&local_path, remote.get(&key)).await? {
+                    return Ok(false);
+                }
+                let data = tokio::fs::read(&local_path).await?;
+                self.write_s3(&key, &data).await?;
+                Ok(true)
+            }
+            .await;
+
+            match synced {
+                Ok(true) => report.uploaded.push(path),
+                Ok(false) => report.unchanged.push(path),
+                Err(error) => report.failed.push(SyncFailure { path, error }),
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn remote_objects(
+        &self,
+        prefix: &str,
+    ) -> Result<HashMap<String, RemoteObject>, S3FilesystemError> {
+        let (objects, _) = self
+            .list_objects(prefix, false, &|entry| !entry.folder)
+            .await?;
+
+        Ok(objects
+            .into_iter()
+            .map(|entry| {
+                let object = RemoteObject {
+                    size: entry.size.max(0) as u64,
+                    e_tag: entry.e_tag,
+                    last_modified: entry.last_modified,
+                };
+                (entry.path.to_string_lossy().into_owned(), object)
+            })
+            .collect())
+    }
+
+    /// Whether the file at `local_path` is already what S3 holds.
+    async fn matches_remote(
+        &self,
+        local_path: &Path,
+        remote: Option<&RemoteObject>,
+    ) -> Result<bool, S3FilesystemError> {
+        let remote = match remote {
+            Some(remote) => remote,
+            None => return Ok(false),
+        };
+        let metadata = tokio::fs::metadata(local_path).await?;
+        if metadata.len() != remote.size {
+            return Ok(false);
+        }
+
+        // ETags of multipart uploads end in -<part count> and are not a digest of the whole object.
+        let md5 = remote
+            .e_tag
+            .as_deref()
+            .map(|e_tag| e_tag.trim_matches('"').to_lowercase())
+            .filter(|md5| md5.len() == 32 && md5.bytes().all(|byte| byte.is_ascii_hexdigit()));
+
+        match md5 {
+            Some(md5) => {
+                let expected = Checksum::Md5(md5);
+                let mut file = tokio::fs::File::open(local_path).await?;
+                Ok(checksum_of(&mut file, &expected).await? == expected)
+            }
+            None => Ok(match (metadata.modified().ok(), remote.last_modified) {
+                (Some(local), Some(remote)) => local <= remote,
+                _ => false,
+            }),
+        }
+    }
+}
+
+/// Every file under `root`, as its `/` separated path relative to `root`, sorted.
+async fn local_files(root: &Path) -> Result<Vec<(String, PathBuf)>, S3FilesystemError> {
+    let mut files = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+
+    while let Some(folder) = folders.pop() {
+        let mut read_dir = tokio::fs::read_dir(&folder).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                folders.push(path);
+                continue;
+            }
+
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((relative, path));
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,82 @@
+//! Bounding how long S3 requests may take.
+use std::time::Duration;
+
+use aws_sdk_s3::Client;
+use aws_smithy_types::timeout::{TimeoutConfig, TimeoutConfigBuilder};
+
+use crate::{backend::Backend, OpenOptions};
+
+impl OpenOptions {
+    /// Give up on connecting to S3 after `timeout`
+    ///
+    /// Applies to every S3 request, including each retry. Defaults to the client's own setting, which for
+    /// clients loaded from your environment is 3.1 seconds.
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        self.with_timeouts(|timeouts| timeouts.connect_timeout(timeout))
+    }
+
+    /// Give up on a response from S3 when no data has arrived for `timeout`
+    ///
+    /// Covers waiting for the response to start and every read of its body after that, so a download
+    /// which stalls part way fails rather than hanging. By default there is no limit.
+    pub fn read_timeout(self, timeout: Duration) -> Self {
+        self.with_timeouts(|timeouts| timeouts.read_timeout(timeout))
+    }
+
+    /// Give up on any single S3 operation that has not been answered within `timeout`
+    ///
+    /// The deadline covers the whole operation, including retries, up until S3's response starts to arrive;
+    /// pair it with [OpenOptions::read_timeout] to also bound downloading the body. An operation which runs
+    /// out of time fails with an error for which [S3FilesystemError::is_unreachable](crate::S3FilesystemError::is_unreachable)
+    /// is true. By default there is no limit.
+    ///
+    /// Timeouts are set on the S3 client, so they do not apply to a backend installed with
+    /// [OpenOptions::backend].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .connect_timeout(Duration::from_secs(2))
+    ///         .read_timeout(Duration::from_secs(10))
+    ///         .operation_timeout(Duration::from_secs(30));
+    /// }
+    /// ```
+    pub fn operation_timeout(self, timeout: Duration) -> Self {
+        self.with_timeouts(|timeouts| timeouts.operation_timeout(timeout))
+    }
+
+    /// Rebuild the client with its timeouts changed by `configure`.
+    fn with_timeouts<F>(self, configure: F) -> Self
+    where
+        F: FnOnce(TimeoutConfigBuilder) -> TimeoutConfigBuilder,
+    {
+        let timeouts = self
+            .s3_client
+            .config()
+            .timeout_config()
+            .map(TimeoutConfig::to_builder)
+            .unwrap_or_default();
+
+        self.reconfigure_client(|config| config.timeout_config(configure(timeouts).build()))
+    }
+
+    /// Rebuild the client with its config changed by `configure`, and the default backend with it.
+    pub(crate) fn reconfigure_client<F>(mut self, configure: F) -> Self
+    where
+        F: FnOnce(aws_sdk_s3::config::Builder) -> aws_sdk_s3::config::Builder,
+    {
+        self.s3_client = Client::from_conf(configure(self.s3_client.config().to_builder()).build());
+        if self.backend.is_s3() {
+            self.backend = Backend::s3(self.s3_client.clone()).with_prefix(&self.prefix);
+        }
+        self
+    }
+}
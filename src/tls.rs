@@ -0,0 +1,258 @@
+//! Trusting private certificate authorities, for S3 compatible stores behind internal TLS certificates, and
+//! choosing the TLS library connections are made with.
+use std::{io, path::Path};
+
+use aws_config::{BehaviorVersion, ConfigLoader};
+use aws_sdk_s3::config::SharedHttpClient;
+use aws_smithy_runtime::client::http::hyper_014::HyperClientBuilder;
+
+use crate::{OpenOptions, S3FilesystemError};
+
+#[cfg(all(feature = "rustls", feature = "native-tls"))]
+compile_error!(
+    "the rustls and native-tls features cannot both be enabled; use native-tls with default-features = false"
+);
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("one of the rustls or native-tls features must be enabled to make TLS connections");
+
+/// The root certificates connections to S3 are verified against, passed to [OpenOptions::tls].
+///
+/// Connections are made with rustls by default, or with the platform's TLS library (OpenSSL, Secure
+/// Transport or SChannel) when the `native-tls` feature is enabled instead of `rustls`. Either way only the
+/// certificate authorities added here are trusted.
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// Each trusted certificate, in DER form, checked when it was added.
+    roots: Vec<Vec<u8>>,
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("roots", &self.roots.len())
+            .finish()
+    }
+}
+
+impl TlsConfig {
+    /// Trust no certificate authorities yet, so only those added are trusted
+    ///
+    /// Use this for an endpoint which should only ever present certificates from a private authority.
+    pub fn new() -> Self {
+        TlsConfig { roots: Vec::new() }
+    }
+
+    /// Trust the certificate authorities in the platform's store, as the SDK's default client does
+    ///
+    /// Add a private authority on top with [TlsConfig::add_pem] to reach both AWS and an on-premises
+    /// endpoint. The `SSL_CERT_FILE` environment variable is used instead of the platform's store if it is
+    /// set. Certificates in the store which cannot be parsed are skipped.
+    pub fn native_roots() -> Result<Self, S3FilesystemError> {
+        let roots = rustls_native_certs::load_native_certs()?
+            .into_iter()
+            .map(|certificate| certificate.0)
+            .filter(|der| check_certificate(der).is_ok())
+            .collect();
+        Ok(TlsConfig { roots })
+    }
+
+    /// Trust every certificate in a PEM bundle
+    ///
+    /// Returns an [io::ErrorKind::InvalidData] error if `pem` holds no certificates or one which cannot be
+    /// parsed. Anything in the bundle other than certificates, such as private keys, is ignored.
+    ///
+    /// # Arguments
+    /// * `pem`: The contents of a PEM file, such as a company's root CA bundle.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, TlsConfig};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     // The CA bundle is handed to the service through its environment.
+    ///     let pem = std::env::var("STORAGE_CA_BUNDLE").unwrap();
+    ///     let tls = TlsConfig::new().add_pem(pem.as_bytes()).unwrap();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await.tls(tls);
+    /// }
+    /// ```
+    pub fn add_pem(mut self, pem: &[u8]) -> Result<Self, S3FilesystemError> {
+        let certificates = rustls_pemfile::certs(&mut &pem[..])?;
+        if certificates.is_empty() {
+            return Err(invalid_certificate("no certificates were found in the PEM"));
+        }
+
+        for certificate in certificates {
+            check_certificate(&certificate)?;
+            self.roots.push(certificate);
+        }
+        Ok(self)
+    }
+
+    /// Trust every certificate in a PEM file, as [TlsConfig::add_pem] does.
+    pub fn add_pem_file<P>(self, path: P) -> Result<Self, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        self.add_pem(&std::fs::read(path)?)
+    }
+
+    /// Trust a single certificate in DER form. Returns an [io::ErrorKind::InvalidData] error if it cannot
+    /// be parsed.
+    pub fn add_der(mut self, der: &[u8]) -> Result<Self, S3FilesystemError> {
+        check_certificate(der)?;
+        self.roots.push(der.to_vec());
+        Ok(self)
+    }
+
+    /// How many certificate authorities are trusted.
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Whether no certificate authorities are trusted, in which case every connection fails.
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig::new()
+    }
+}
+
+impl OpenOptions {
+    /// Verify S3's TLS certificates against the given root certificates
+    ///
+    /// For on-premises S3 compatible stores, such as MinIO or Ceph, whose endpoints present certificates
+    /// signed by a private authority. The client is rebuilt with an HTTPS connector trusting only the
+    /// authorities in `config`, keeping the rest of its configuration, so there is no need to assemble an
+    /// SDK client by hand. Connections use TLS 1.2 or later, with HTTP/1.1, or HTTP/2 except with the
+    /// `native-tls` feature, and plain `http://` endpoints still work.
+    ///
+    /// # Arguments
+    /// * `config`: The certificate authorities to trust.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, TlsConfig};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     // Reach AWS and the data centre's own store, whose CA bundle is deployed alongside the app.
+    ///     let tls = TlsConfig::native_roots()
+    ///         .unwrap()
+    ///         .add_pem_file("/etc/ssl/internal/ca-bundle.pem")
+    ///         .unwrap();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await.tls(tls);
+    /// }
+    /// ```
+    pub fn tls(self, config: TlsConfig) -> Self {
+        let http_client = https_client(Some(&config.roots));
+        self.reconfigure_client(|config| config.http_client(http_client))
+    }
+}
+
+/// The loader every client this crate creates is configured by, which with the `native-tls` feature makes
+/// its connections with the platform's TLS library.
+pub(crate) fn config_loader() -> ConfigLoader {
+    let loader = aws_config::defaults(BehaviorVersion::latest());
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    let loader = loader.http_client(https_client(None));
+    loader
+}
+
+/// An HTTPS client trusting only `roots`, or the platform's certificate authorities if None.
+#[cfg(feature = "rustls")]
+fn https_client(roots: Option<&[Vec<u8>]>) -> SharedHttpClient {
+    use rustls::{Certificate, ClientConfig, RootCertStore};
+
+    let mut store = RootCertStore::empty();
+    match roots {
+        Some(roots) => {
+            for der in roots {
+                // Checked when the certificate was added.
+                let _ = store.add(&Certificate(der.clone()));
+            }
+        }
+        None => {
+            if let Ok(config) = TlsConfig::native_roots() {
+                for der in &config.roots {
+                    let _ = store.add(&Certificate(der.clone()));
+                }
+            }
+        }
+    }
+    let tls = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(store)
+        .with_no_client_auth();
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls)
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .build();
+
+    HyperClientBuilder::new().build(connector)
+}
+
+/// An HTTPS client trusting only `roots`, or the platform's certificate authorities if None.
+///
+/// # Panics
+/// If the platform's TLS library cannot create a connector at all.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn https_client(roots: Option<&[Vec<u8>]>) -> SharedHttpClient {
+    use tokio_native_tls::native_tls::{Certificate, Protocol, TlsConnector};
+
+    let mut tls = TlsConnector::builder();
+    tls.min_protocol_version(Some(Protocol::Tlsv12));
+    if let Some(roots) = roots {
+        tls.disable_built_in_roots(true);
+        for der in roots {
+            // Checked when the certificate was added.
+            if let Ok(certificate) = Certificate::from_der(der) {
+                tls.add_root_certificate(certificate);
+            }
+        }
+    }
+    let tls = tls
+        .build()
+        .expect("the platform's TLS library could not create a connector");
+
+    let mut http = hyper::client::HttpConnector::new();
+    http.enforce_http(false);
+    let connector = hyper_tls::HttpsConnector::from((http, tls.into()));
+
+    HyperClientBuilder::new().build(connector)
+}
+
+/// Check that the TLS library in use can trust `der`.
+fn check_certificate(der: &[u8]) -> Result<(), S3FilesystemError> {
+    #[cfg(feature = "rustls")]
+    let checked = rustls::RootCertStore::empty()
+        .add(&rustls::Certificate(der.to_vec()))
+        .map_err(|e| e.to_string());
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    let checked = tokio_native_tls::native_tls::Certificate::from_der(der)
+        .map(drop)
+        .map_err(|e| e.to_string());
+
+    checked.map_err(invalid_certificate)
+}
+
+/// An error for a certificate which could not be trusted.
+fn invalid_certificate<E>(error: E) -> S3FilesystemError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error).into()
+}
@@ -0,0 +1,259 @@
+//! Keeping copies of deleted objects under a trash prefix, so deletions can be undone.
+use std::{
+    io,
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    archive::civil_from_days, dry_run::DryRunOperation, key::s3_key, DeleteOutcome, OpenOptions,
+    S3FilesystemError,
+};
+
+impl OpenOptions {
+    /// Copy objects under a trash prefix before deleting them
+    ///
+    /// With a trash prefix set, [OpenOptions::delete_many] first copies each object to
+    /// `<prefix><timestamp>/<key>`, with one timestamp for every key deleted in the same call, such as
+    /// ".trash/2024-05-01T12-30-05.123Z/data/a.csv". An object which cannot be copied is not deleted and
+    /// is reported as [DeleteOutcome::Failed]. Bring an object back with [OpenOptions::restore_from_trash].
+    ///
+    /// Keys already under the trash prefix are deleted without being copied, which is how the trash is
+    /// emptied. Nothing is removed from the trash automatically; a lifecycle rule expiring objects under
+    /// the prefix after a few days keeps it from growing forever. Copies are made server-side, but each
+    /// one costs a HeadObject and a CopyObject request on top of the delete.
+    ///
+    /// # Arguments
+    /// * `prefix`: Where deleted objects are kept. A `/` is added if it does not end in one.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await.trash(".trash/");
+    ///
+    ///     open_options.delete_many(["reports/q1.csv"]).await.unwrap();
+    ///
+    ///     // That was the wrong quarter.
+    ///     open_options
+    ///         .restore_from_trash("reports/q1.csv")
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn trash<T>(mut self, prefix: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let mut prefix = prefix.into();
+        if !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        self.trash = Some(prefix);
+        self
+    }
+
+    /// Restore the most recently deleted copy of an object from the trash
+    ///
+    /// The newest copy of `path` under the [trash](OpenOptions::trash) prefix is copied back to `path`,
+    /// replacing anything written there since, and then removed from the trash. Any locally mirrored copy
+    /// of `path` is removed so the next [OpenOptions::open_s3] fetches the restored contents. Returns an
+    /// [io::ErrorKind::NotFound] error if the trash holds no copy of `path`, and an
+    /// [io::ErrorKind::InvalidInput] error if no trash prefix is set.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including filename, the object was deleted from.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await.trash(".trash/");
+    ///
+    ///     open_options
+    ///         .restore_from_trash("reports/q1.csv")
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn restore_from_trash<P>(&self, path: P) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.restore_trashed(path)
+            .await
+            .map_err(|e| e.with_context("CopyObject", &self.bucket, Some(path)))
+    }
+
+    async fn restore_trashed(&self, path: &Path) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+        let trash = match &self.trash {
+            Some(trash) => trash,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "No trash prefix is set to restore from",
+                )
+                .into())
+            }
+        };
+        let key = s3_key(path)?;
+
+        let trashed = match self.find_trashed(trash, &key).await? {
+            Some(trashed) => trashed,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the trash", key),
+                )
+                .into())
+            }
+        };
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Copy {
+                from: trashed.clone(),
+                to: key,
+            });
+            log.record(DryRunOperation::Delete { key: trashed });
+            return Ok(());
+        }
+
+        self.copy_across(
+            &self.bucket,
+            Path::new(&self.remote_key(&trashed)),
+            &self.bucket,
+            Path::new(&self.remote_key(&key)),
+        )
+        .await?;
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .delete(&self.bucket, std::slice::from_ref(&trashed))
+            .await;
+        self.record_request("DeleteObjects", started, result.is_ok());
+        drop(slot);
+        // The object is back either way; a copy left in the trash is harmless.
+        result?;
+
+        Ok(())
+    }
+
+    /// The key of the newest copy of `key` in the trash, if there is one.
+    async fn find_trashed(
+        &self,
+        trash: &str,
+        key: &str,
+    ) -> Result<Option<String>, S3FilesystemError> {
+        // Each delete call has its own folder, and timestamps sort in the order they were taken.
+        let (_, mut folders) = self.list_objects(trash, true, &|_| false).await?;
+        folders.sort();
+
+        for folder in folders.into_iter().rev() {
+            let trashed = format!("{}{}", folder, key);
+
+            let slot = self.throttle_request().await;
+            let started = Instant::now();
+            let result = self
+                .cancellable(self.backend.head(&self.bucket, &trashed))
+                .await;
+            self.record_request("HeadObject", started, result.is_ok());
+            drop(slot);
+            match result {
+                Ok(_) => return Ok(Some(trashed)),
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The trash folder for keys deleted now, if a trash prefix is set.
+    pub(crate) fn trash_folder(&self) -> Option<String> {
+        let trash = self.trash.as_ref()?;
+        Some(format!("{}{}/", trash, timestamp(SystemTime::now())))
+    }
+
+    /// Where `key` is copied to in `folder` before being deleted, or None if it is already in the trash.
+    pub(crate) fn trash_key(&self, folder: &str, key: &str) -> Option<String> {
+        let trash = self.trash.as_deref()?;
+        (!key.starts_with(trash)).then(|| format!("{}{}", folder, key))
+    }
+
+    /// Copy each key in `keys` into the trash `folder`, returning the keys which may now be deleted and
+    /// a failed outcome for each which could not be copied.
+    pub(crate) async fn copy_to_trash(
+        &self,
+        keys: &[String],
+        folder: &str,
+    ) -> Result<(Vec<String>, Vec<DeleteOutcome>), S3FilesystemError> {
+        let mut deletable = Vec::with_capacity(keys.len());
+        let mut failed = Vec::new();
+
+        for key in keys {
+            let trash_key = match self.trash_key(folder, key) {
+                Some(trash_key) => trash_key,
+                None => {
+                    deletable.push(key.clone());
+                    continue;
+                }
+            };
+
+            let copied = self
+                .copy_across(
+                    &self.bucket,
+                    Path::new(&self.remote_key(key)),
+                    &self.bucket,
+                    Path::new(&self.remote_key(&trash_key)),
+                )
+                .await;
+            match copied {
+                // Deleting a key which does not exist succeeds, and there is nothing to keep.
+                Ok(()) => deletable.push(key.clone()),
+                Err(e) if e.is_not_found() => deletable.push(key.clone()),
+                Err(e) if e.is_cancelled() => return Err(e),
+                Err(e) => failed.push(DeleteOutcome::Failed {
+                    key: key.clone(),
+                    code: e.code().map(str::to_string),
+                    message: Some(format!("Could not copy to the trash: {}", e)),
+                }),
+            }
+        }
+
+        Ok((deletable, failed))
+    }
+}
+
+/// `time` as a UTC timestamp which sorts in time order and is safe in a key, such as
+/// "2024-05-01T12-30-05.123Z".
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let second_of_day = seconds % 86_400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day % 3600 / 60,
+        second_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
@@ -0,0 +1,745 @@
+//! Multipart uploads which survive interruption.
+//!
+//! Large writes are staged under `<mount_path>/.s3-filesystem/<bucket>.uploads/`, one pair of files per key
+//! named after the hex encoded key: `<hex>.data` holds the bytes being uploaded and `<hex>.upload` records
+//! progress. The first line of the progress file describes the upload, and a line is appended for each
+//! part as S3 accepts it:
+//!
+//! ```text
+//! <upload id>\t<part size>\t<total size>\t<checksum algorithm, or ->\t<key>
+//! <part number>\t<etag>[\t<checksum>]
+//! ```
+//!
+//! Parts only have a checksum when the upload was started with [OpenOptions::upload_checksum] set.
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart, ObjectCannedAcl},
+};
+use aws_smithy_types::byte_stream::Length;
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Semaphore,
+    task::JoinSet,
+};
+
+use crate::{
+    checksum::{ChecksumAlgorithm, ObjectChecksum},
+    dry_run::DryRunOperation,
+    index::INDEX_DIR,
+    key::s3_key,
+    options::bucket_folder,
+    CachedObject, ObjectLock, OpenOptions, S3FilesystemError,
+};
+
+/// Writes at least this large are uploaded in parts.
+pub(crate) const MULTIPART_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// The part size used unless [OpenOptions::part_size] is set.
+pub(crate) const DEFAULT_PART_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The smallest part S3 accepts, other than the last.
+const S3_MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// The most parts S3 allows in a multipart upload.
+const MAX_PARTS: u64 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A multipart upload that was interrupted, as returned by [OpenOptions::pending_uploads].
+pub struct PendingUpload {
+    /// The key being uploaded to.
+    pub key: String,
+    /// The total size of the upload in bytes.
+    pub size: u64,
+    /// How many bytes S3 has already accepted.
+    pub uploaded: u64,
+}
+
+/// The progress of one multipart upload.
+struct UploadState {
+    upload_id: String,
+    part_size: u64,
+    size: u64,
+    key: String,
+    checksum: Option<ChecksumAlgorithm>,
+    parts: Vec<CompletedPart>,
+}
+
+/// Where the parts of a multipart upload are read from.
+#[derive(Clone)]
+enum PartSource {
+    /// Staged under the mount path, with progress recorded so the upload can be resumed.
+    Staged {
+        state_path: PathBuf,
+        data_path: PathBuf,
+    },
+    /// Held in memory. Nothing is recorded, so the upload cannot be resumed.
+    Memory(Bytes),
+}
+
+impl PartSource {
+    /// The `length` bytes from `offset` as a request body.
+    async fn body(&self, offset: u64, length: u64) -> Result<ByteStream, S3FilesystemError> {
+        match self {
+            PartSource::Staged { data_path, .. } => Ok(ByteStream::read_from()
+                .path(data_path)
+                .offset(offset)
+                .length(Length::Exact(length))
+                .build()
+                .await?),
+            PartSource::Memory(data) => Ok(ByteStream::from(
+                data.slice(offset as usize..(offset + length) as usize),
+            )),
+        }
+    }
+
+    /// The `length` bytes from `offset` as a request body, with their base64 encoded checksum.
+    ///
+    /// Staged parts are read into memory so they only need reading from disk once.
+    async fn checksummed_body(
+        &self,
+        offset: u64,
+        length: u64,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<(ByteStream, String), S3FilesystemError> {
+        let data = match self {
+            PartSource::Staged { data_path, .. } => {
+                let mut file = tokio::fs::File::open(data_path).await?;
+                file.seek(io::SeekFrom::Start(offset)).await?;
+                let mut data = vec![0; length as usize];
+                file.read_exact(&mut data).await?;
+                Bytes::from(data)
+            }
+            PartSource::Memory(data) => data.slice(offset as usize..(offset + length) as usize),
+        };
+        let checksum = ObjectChecksum::of(algorithm, &data).value;
+        Ok((ByteStream::from(data), checksum))
+    }
+}
+
+impl UploadState {
+    fn part_count(&self) -> u64 {
+        self.size.div_ceil(self.part_size)
+    }
+
+    /// The offset and length of part `part_number`, counting from 1.
+    fn part_range(&self, part_number: i32) -> (u64, u64) {
+        let offset = (part_number as u64 - 1) * self.part_size;
+        (offset, self.part_size.min(self.size - offset))
+    }
+
+    /// The checksum of the whole object, once every part has been uploaded with one.
+    fn object_checksum(&self) -> Option<ObjectChecksum> {
+        let algorithm = self.checksum?;
+        let parts: Option<Vec<&str>> = self
+            .parts
+            .iter()
+            .map(|part| match algorithm {
+                ChecksumAlgorithm::Crc32c => part.checksum_crc32_c(),
+                ChecksumAlgorithm::Sha256 => part.checksum_sha256(),
+            })
+            .collect();
+        ObjectChecksum::of_parts(algorithm, parts?)
+    }
+
+    fn uploaded(&self) -> u64 {
+        self.parts
+            .iter()
+            .filter_map(|part| part.part_number())
+            .map(|part_number| self.part_range(part_number).1)
+            .sum()
+    }
+}
+
+impl OpenOptions {
+    /// Upload large writes in parts of this many bytes
+    ///
+    /// Writes of 64 MiB or more are split into parts, which are retried and resumed individually. Larger
+    /// parts mean fewer requests for very large objects, smaller ones less to send again after a failure.
+    /// Sizes below S3's minimum of 5 MiB are raised to it, and parts grow beyond `bytes` when needed to
+    /// stay within S3's limit of 10,000 parts. Uploads already in progress keep the size they started
+    /// with. Defaults to 16 MiB.
+    pub fn part_size(mut self, bytes: u64) -> Self {
+        self.part_size = bytes.max(S3_MIN_PART_SIZE);
+        self
+    }
+
+    /// Send up to `parts` parts of a multipart upload at once
+    ///
+    /// A single part upload rarely uses all the bandwidth available, so uploading several at a time
+    /// finishes large writes sooner. Parts are streamed from the staged copy on disk, so this costs
+    /// connections rather than memory. Defaults to 1, one part after another.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .part_size(64 * 1024 * 1024)
+    ///         .upload_concurrency(8)
+    ///         .download_buffer_size(1024 * 1024);
+    ///
+    ///     let data = vec![0; 1024 * 1024 * 1024];
+    ///     open_options.write_s3("large/object.bin", &data).await.unwrap();
+    /// }
+    /// ```
+    pub fn upload_concurrency(mut self, parts: usize) -> Self {
+        self.upload_concurrency = parts.max(1);
+        self
+    }
+
+    /// Multipart uploads that were interrupted and can be resumed
+    ///
+    /// Writes of 64 MiB or more through [OpenOptions::write_s3] are uploaded in parts, and their progress is
+    /// kept under the mount path. If the process stops or the network fails part way, the upload is left
+    /// here to be finished with [OpenOptions::resume_upload] or cancelled with
+    /// [OpenOptions::abort_upload].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     for pending in open_options.pending_uploads().await.unwrap() {
+    ///         println!("Resuming {} from byte {}", pending.key, pending.uploaded);
+    ///         open_options.resume_upload(&pending.key).await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub async fn pending_uploads(&self) -> Result<Vec<PendingUpload>, S3FilesystemError> {
+        let mut entries = match tokio::fs::read_dir(self.uploads_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut pending = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension == "upload")
+            {
+                if let Some(state) = read_state(&entry.path()).await? {
+                    pending.push(PendingUpload {
+                        uploaded: state.uploaded(),
+                        key: state.key,
+                        size: state.size,
+                    });
+                }
+            }
+        }
+
+        pending.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(pending)
+    }
+
+    /// Finish an interrupted multipart upload
+    ///
+    /// Only the parts S3 has not yet accepted are sent. Once complete, the uploaded file is placed in the
+    /// mount path as [OpenOptions::write_s3] would have left it.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, that was being written.
+    pub async fn resume_upload<P>(&self, path: P) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.resume(path)
+            .await
+            .map_err(|e| e.with_context("UploadPart", &self.bucket, Some(path)))
+    }
+
+    /// Cancel an interrupted multipart upload
+    ///
+    /// S3 is told to discard the parts already uploaded, which otherwise go on being billed, and the staged
+    /// data under the mount path is removed. Cancelling a path with no pending upload does nothing.
+    ///
+    /// # Arguments
+    /// * `path`: The path, including the filename, that was being written.
+    pub async fn abort_upload<P>(&self, path: P) -> Result<(), S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.abort(path)
+            .await
+            .map_err(|e| e.with_context("AbortMultipartUpload", &self.bucket, Some(path)))
+    }
+
+    async fn resume(&self, path: &Path) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let key = s3_key(path)?;
+        let (state_path, data_path) = self.upload_paths(&key);
+        let state = read_state(&state_path).await?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "there is no pending upload for this path",
+            )
+        })?;
+
+        if let Some(log) = &self.dry_run {
+            log.record(DryRunOperation::Upload {
+                key,
+                size: state.size - state.uploaded(),
+            });
+            return Ok(());
+        }
+
+        let source = PartSource::Staged {
+            state_path: state_path.clone(),
+            data_path: data_path.clone(),
+        };
+        let (e_tag, checksum) = self.upload_parts(state, source).await?;
+
+        let full_data_path = self.local_path(&key)?;
+        if let Some(parent_path) = full_data_path.parent() {
+            tokio::fs::create_dir_all(parent_path).await?;
+        }
+        let _lock = self.lock_cache_file(&full_data_path).await?;
+        let size = tokio::fs::metadata(&data_path).await?.len();
+        tokio::fs::rename(&data_path, &full_data_path).await?;
+        tokio::fs::remove_file(&state_path).await?;
+
+        self.cache_index
+            .insert(CachedObject {
+                key,
+                e_tag,
+                checksum,
+                size,
+                cached_at: SystemTime::now(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn abort(&self, path: &Path) -> Result<(), S3FilesystemError> {
+        if self.read_only {
+            return Err(S3FilesystemError::ReadOnly);
+        }
+
+        let key = s3_key(path)?;
+        let (state_path, data_path) = self.upload_paths(&key);
+        let state = match read_state(&state_path).await? {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        if self.dry_run.is_some() {
+            return Ok(());
+        }
+
+        self.abort_multipart(&state).await?;
+        remove_if_present(&state_path).await?;
+        remove_if_present(&data_path).await?;
+        Ok(())
+    }
+
+    /// Upload `buf` to `key` in parts, returning the new object's ETag and additional checksum.
+    ///
+    /// The data is staged first so the upload can be resumed if it fails part way. Any earlier pending
+    /// upload to the same key is abandoned.
+    pub(crate) async fn upload_multipart(
+        &self,
+        key: &str,
+        buf: &[u8],
+        content_type: Option<&str>,
+        acl: Option<ObjectCannedAcl>,
+        object_lock: ObjectLock,
+    ) -> Result<(Option<String>, Option<ObjectChecksum>), S3FilesystemError> {
+        let (state_path, data_path) = self.upload_paths(key);
+        if let Some(previous) = read_state(&state_path).await? {
+            let _ = self.abort_multipart(&previous).await;
+        }
+        if let Some(parent) = state_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&data_path, buf).await?;
+
+        let state = match self
+            .create_multipart(key, buf.len() as u64, content_type, acl, object_lock)
+            .await
+        {
+            Ok(state) => state,
+            Err(e) => {
+                remove_if_present(&data_path).await?;
+                return Err(e);
+            }
+        };
+        tokio::fs::write(
+            &state_path,
+            format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                state.upload_id,
+                state.part_size,
+                state.size,
+                state
+                    .checksum
+                    .map_or_else(|| "-".to_string(), |algorithm| algorithm.to_string()),
+                state.key
+            ),
+        )
+        .await?;
+
+        let source = PartSource::Staged {
+            state_path: state_path.clone(),
+            data_path: data_path.clone(),
+        };
+        let uploaded = self.upload_parts(state, source).await?;
+        tokio::fs::remove_file(&state_path).await?;
+        tokio::fs::remove_file(&data_path).await?;
+        Ok(uploaded)
+    }
+
+    /// Upload `data` to `key` in parts straight from memory, returning the new object's ETag and
+    /// additional checksum.
+    ///
+    /// Nothing is staged on disk, so the upload cannot be resumed; it is aborted if any part fails.
+    pub(crate) async fn upload_multipart_from_memory(
+        &self,
+        key: &str,
+        data: Bytes,
+        content_type: Option<&str>,
+        acl: Option<ObjectCannedAcl>,
+    ) -> Result<(Option<String>, Option<ObjectChecksum>), S3FilesystemError> {
+        let state = self
+            .create_multipart(
+                key,
+                data.len() as u64,
+                content_type,
+                acl,
+                ObjectLock::default(),
+            )
+            .await?;
+        self.upload_parts(state, PartSource::Memory(data)).await
+    }
+
+    /// Start a multipart upload of `size` bytes to `key`.
+    async fn create_multipart(
+        &self,
+        key: &str,
+        size: u64,
+        content_type: Option<&str>,
+        acl: Option<ObjectCannedAcl>,
+        object_lock: ObjectLock,
+    ) -> Result<UploadState, S3FilesystemError> {
+        let checksum = self.checksum_algorithm(&object_lock);
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(self.remote_key(key))
+            .set_content_type(content_type.map(str::to_string))
+            .set_acl(acl)
+            .set_checksum_algorithm(checksum.map(ChecksumAlgorithm::to_sdk))
+            .set_object_lock_mode(object_lock.mode())
+            .set_object_lock_retain_until_date(object_lock.retain_until())
+            .set_object_lock_legal_hold_status(object_lock.legal_hold_status())
+            .send()
+            .await;
+        self.record_request("CreateMultipartUpload", started, result.is_ok());
+        drop(slot);
+
+        Ok(UploadState {
+            upload_id: result?.upload_id().unwrap_or_default().to_string(),
+            part_size: self.part_size.max(size.div_ceil(MAX_PARTS)),
+            size,
+            key: key.to_string(),
+            checksum,
+            parts: Vec::new(),
+        })
+    }
+
+    /// Upload every part not yet recorded in `state` from `source`, then complete the upload, returning the
+    /// new object's ETag and additional checksum.
+    ///
+    /// Up to [OpenOptions::upload_concurrency] parts are sent at once. Staged uploads record each part in
+    /// the progress file as soon as S3 accepts it.
+    async fn upload_parts(
+        &self,
+        mut state: UploadState,
+        source: PartSource,
+    ) -> Result<(Option<String>, Option<ObjectChecksum>), S3FilesystemError> {
+        let mut progress = match &source {
+            PartSource::Staged { state_path, .. } => Some(
+                tokio::fs::OpenOptions::new()
+                    .append(true)
+                    .open(state_path)
+                    .await?,
+            ),
+            PartSource::Memory(_) => None,
+        };
+
+        let uploaded: HashSet<i32> = state
+            .parts
+            .iter()
+            .filter_map(|part| part.part_number())
+            .collect();
+        let semaphore = Arc::new(Semaphore::new(self.upload_concurrency));
+        let mut tasks = JoinSet::new();
+
+        for part_number in (1..=state.part_count() as i32).filter(|n| !uploaded.contains(n)) {
+            let open_options = self.clone();
+            let semaphore = semaphore.clone();
+            let key = state.key.clone();
+            let upload_id = state.upload_id.clone();
+            let source = source.clone();
+            let algorithm = state.checksum;
+            let (offset, length) = state.part_range(part_number);
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let (mut body, checksum) = match algorithm {
+                    Some(algorithm) => {
+                        let (body, checksum) =
+                            source.checksummed_body(offset, length, algorithm).await?;
+                        (body, Some(checksum))
+                    }
+                    None => (source.body(offset, length).await?, None),
+                };
+                if let Some(limiter) = &open_options.bandwidth_limiter {
+                    body = limiter.throttle_body(body);
+                }
+
+                let request = open_options
+                    .s3_client
+                    .upload_part()
+                    .bucket(&open_options.bucket)
+                    .key(open_options.remote_key(&key))
+                    .upload_id(&upload_id)
+                    .part_number(part_number)
+                    .content_length(length as i64)
+                    .body(body);
+                let request = match (algorithm, &checksum) {
+                    (Some(ChecksumAlgorithm::Crc32c), Some(checksum)) => {
+                        request.checksum_crc32_c(checksum)
+                    }
+                    (Some(ChecksumAlgorithm::Sha256), Some(checksum)) => {
+                        request.checksum_sha256(checksum)
+                    }
+                    _ => request,
+                };
+
+                let slot = open_options.throttle_request().await;
+                let started = Instant::now();
+                let result = open_options
+                    .cancellable(async { Ok(request.send().await?) })
+                    .await;
+                open_options.record_request("UploadPart", started, result.is_ok());
+                drop(slot);
+
+                let e_tag = result?.e_tag().unwrap_or_default().to_string();
+                Ok::<_, S3FilesystemError>((part_number, e_tag, checksum, length))
+            });
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            let (part_number, e_tag, checksum, length) = match joined {
+                Ok(Ok(part)) => part,
+                // A cancelled upload is abandoned rather than left to be resumed.
+                Ok(Err(e)) if e.is_cancelled() => {
+                    tasks.shutdown().await;
+                    drop(progress);
+                    self.abort_multipart(&state).await?;
+                    if let PartSource::Staged {
+                        state_path,
+                        data_path,
+                    } = &source
+                    {
+                        remove_if_present(state_path).await?;
+                        remove_if_present(data_path).await?;
+                    }
+                    return Err(e);
+                }
+                // Parts held in memory go with the caller's buffer, so the upload could never be resumed.
+                Ok(Err(e)) if matches!(source, PartSource::Memory(_)) => {
+                    tasks.shutdown().await;
+                    let _ = self.abort_multipart(&state).await;
+                    return Err(e);
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            };
+
+            if let Some(progress) = &mut progress {
+                let line = match &checksum {
+                    Some(checksum) => format!("{}\t{}\t{}\n", part_number, e_tag, checksum),
+                    None => format!("{}\t{}\n", part_number, e_tag),
+                };
+                progress.write_all(line.as_bytes()).await?;
+                progress.sync_data().await?;
+            }
+            state.parts.push(completed_part(
+                part_number,
+                e_tag,
+                state.checksum.zip(checksum),
+            ));
+
+            if let Some(metrics) = &self.metrics {
+                metrics.uploaded(&state.key, length);
+            }
+        }
+        state.parts.sort_by_key(|part| part.part_number());
+        let checksum = state.object_checksum();
+
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(self.remote_key(&state.key))
+            .upload_id(&state.upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(state.parts))
+                    .build(),
+            )
+            .send()
+            .await;
+        self.record_request("CompleteMultipartUpload", started, result.is_ok());
+        drop(slot);
+
+        Ok((result?.e_tag().map(str::to_string), checksum))
+    }
+
+    async fn abort_multipart(&self, state: &UploadState) -> Result<(), S3FilesystemError> {
+        let slot = self.throttle_request().await;
+        let started = Instant::now();
+        let result = self
+            .s3_client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(self.remote_key(&state.key))
+            .upload_id(&state.upload_id)
+            .send()
+            .await;
+        self.record_request("AbortMultipartUpload", started, result.is_ok());
+        drop(slot);
+
+        match result.map_err(S3FilesystemError::from) {
+            Err(e) if !e.is_not_found() => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn uploads_dir(&self) -> PathBuf {
+        self.mount_path
+            .join(INDEX_DIR)
+            .join(format!("{}.uploads", bucket_folder(&self.bucket)))
+    }
+
+    /// The progress and staged data files for an upload to `key`.
+    fn upload_paths(&self, key: &str) -> (PathBuf, PathBuf) {
+        let name = key.bytes().fold(String::new(), |mut name, byte| {
+            let _ = write!(name, "{:02x}", byte);
+            name
+        });
+        let dir = self.uploads_dir();
+        (
+            dir.join(format!("{}.upload", name)),
+            dir.join(format!("{}.data", name)),
+        )
+    }
+}
+
+/// Read an upload's progress, or None if there is no upload in progress.
+async fn read_state(path: &Path) -> io::Result<Option<UploadState>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "corrupt upload progress file");
+
+    let mut lines = contents.lines();
+    let mut header = lines.next().ok_or_else(invalid)?.splitn(4, '\t');
+    let mut field = || header.next().ok_or_else(invalid);
+    let upload_id = field()?.to_string();
+    let part_size = field()?.parse().map_err(|_| invalid())?;
+    let size = field()?.parse().map_err(|_| invalid())?;
+    let rest = field()?;
+
+    // Uploads started before checksums were recorded have no checksum field.
+    let (checksum, key) = match rest.split_once('\t') {
+        Some(("-", key)) => (None, key),
+        Some((algorithm, key)) => match algorithm.parse() {
+            Ok(algorithm) => (Some(algorithm), key),
+            Err(_) => (None, rest),
+        },
+        None => (None, rest),
+    };
+
+    // A line cut short by a crash is ignored, and that part uploaded again.
+    let parts = lines
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let part_number = fields.next()?.parse().ok()?;
+            let e_tag = fields.next()?.to_string();
+            let part_checksum = match checksum {
+                Some(algorithm) => Some((algorithm, fields.next()?.to_string())),
+                None => None,
+            };
+            Some(completed_part(part_number, e_tag, part_checksum))
+        })
+        .collect();
+
+    Ok(Some(UploadState {
+        upload_id,
+        part_size,
+        size,
+        key: key.to_string(),
+        checksum,
+        parts,
+    }))
+}
+
+/// A part S3 has accepted, with the checksum it was sent with if any.
+fn completed_part(
+    part_number: i32,
+    e_tag: String,
+    checksum: Option<(ChecksumAlgorithm, String)>,
+) -> CompletedPart {
+    let part = CompletedPart::builder()
+        .part_number(part_number)
+        .e_tag(e_tag);
+    match checksum {
+        Some((ChecksumAlgorithm::Crc32c, checksum)) => part.checksum_crc32_c(checksum),
+        Some((ChecksumAlgorithm::Sha256, checksum)) => part.checksum_sha256(checksum),
+        None => part,
+    }
+    .build()
+}
+
+async fn remove_if_present(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,165 @@
+//! Auditing the local mirror against the objects in S3.
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{manifest::checksum_of, Checksum, OpenOptions, S3FilesystemError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What is wrong with a cached file, as found by [OpenOptions::verify].
+pub enum VerifyProblem {
+    /// The object has been deleted from S3 since it was cached.
+    Missing,
+    /// The cached file is not the size of the object in S3.
+    SizeMismatch {
+        /// Size of the cached file.
+        local: u64,
+        /// Size of the object in S3.
+        remote: u64,
+    },
+    /// The object has been replaced in S3 since it was cached.
+    Changed {
+        /// The ETag recorded in the cache index when the file was cached.
+        cached: String,
+        /// The object's ETag in S3 now.
+        remote: String,
+    },
+    /// The cached file's contents do not match the object's ETag, so the file is corrupt.
+    ChecksumMismatch {
+        /// The MD5 digest given by the object's ETag.
+        expected: Checksum,
+        /// The MD5 digest of the cached file.
+        actual: Checksum,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A cached file which does not match S3.
+pub struct VerifyMismatch {
+    /// Path of the object within the bucket.
+    pub path: PathBuf,
+    /// What is wrong with it.
+    pub problem: VerifyProblem,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The outcome of [OpenOptions::verify].
+pub struct VerifyReport {
+    /// Paths of cached files which match S3.
+    pub verified: Vec<PathBuf>,
+    /// Cached files which do not match S3.
+    pub mismatches: Vec<VerifyMismatch>,
+}
+
+impl OpenOptions {
+    /// Check the cached files under a prefix against S3
+    ///
+    /// Every file in the mount path under `prefix` is compared with the object it mirrors: its size must
+    /// match, the ETag recorded in the cache index (if any) must still be the object's ETag, and for objects
+    /// not uploaded in parts, whose ETag is the MD5 of their contents, the file must hash to it. Nothing is
+    /// downloaded or removed, so long-lived caches can be audited without re-downloading them; evict or
+    /// re-download the reported files as suits.
+    ///
+    /// The objects are found with a single listing of the prefix, but every cached file with an MD5 ETag is
+    /// read in full to hash it.
+    ///
+    /// # Arguments
+    /// * `prefix`: Only cached files whose keys start with this are checked. Use "" for the whole bucket.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/test/");
+    ///
+    ///     let report = open_options.verify("redasa1-Q1-20/").await.unwrap();
+    ///
+    ///     for mismatch in report.mismatches {
+    ///         println!("{}: {:?}", mismatch.path.display(), mismatch.problem);
+    ///     }
+    /// }
+    /// ```
+    pub async fn verify(&self, prefix: &str) -> Result<VerifyReport, S3FilesystemError> {
+        self.verify_prefix(prefix)
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &self.bucket, Some(prefix.as_ref())))
+    }
+
+    async fn verify_prefix(&self, prefix: &str) -> Result<VerifyReport, S3FilesystemError> {
+        let mut cached: Vec<(String, u64)> = self
+            .mirrored_files(false)
+            .await?
+            .into_iter()
+            .filter(|file| file.key.starts_with(prefix))
+            .map(|file| (file.key, file.size))
+            .collect();
+        cached.sort();
+
+        let (objects, _) = self.list_objects(prefix, false, &|_| true).await?;
+        let remote: HashMap<String, (u64, Option<String>)> = objects
+            .into_iter()
+            .map(|entry| {
+                let key = entry.path.to_string_lossy().into_owned();
+                (key, (entry.size.max(0) as u64, entry.e_tag))
+            })
+            .collect();
+
+        let mut report = VerifyReport::default();
+        for (key, local_size) in cached {
+            let path = PathBuf::from(&key);
+            match self.verify_file(&key, local_size, remote.get(&key)).await? {
+                Some(problem) => report.mismatches.push(VerifyMismatch { path, problem }),
+                None => report.verified.push(path),
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn verify_file(
+        &self,
+        key: &str,
+        local_size: u64,
+        remote: Option<&(u64, Option<String>)>,
+    ) -> Result<Option<VerifyProblem>, S3FilesystemError> {
+        let (remote_size, remote_e_tag) = match remote {
+            Some(remote) => remote,
+            None => return Ok(Some(VerifyProblem::Missing)),
+        };
+
+        if local_size != *remote_size {
+            return Ok(Some(VerifyProblem::SizeMismatch {
+                local: local_size,
+                remote: *remote_size,
+            }));
+        }
+
+        let remote_e_tag = match remote_e_tag {
+            Some(remote_e_tag) => remote_e_tag,
+            None => return Ok(None),
+        };
+        if let Some(cached) = self.cache_index.get(key).await? {
+            if let Some(cached_e_tag) = cached.e_tag.filter(|e_tag| e_tag != remote_e_tag) {
+                return Ok(Some(VerifyProblem::Changed {
+                    cached: cached_e_tag,
+                    remote: remote_e_tag.clone(),
+                }));
+            }
+        }
+
+        // ETags of multipart uploads end in -<part count> and are not a digest of the whole object.
+        let md5 = remote_e_tag.trim_matches('"').to_lowercase();
+        if md5.len() != 32 || !md5.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        let expected = Checksum::Md5(md5);
+        let mut file = tokio::fs::File::open(self.local_path(key)?).await?;
+        let actual = checksum_of(&mut file, &expected).await?;
+
+        Ok((actual != expected).then_some(VerifyProblem::ChecksumMismatch { expected, actual }))
+    }
+}
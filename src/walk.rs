@@ -0,0 +1,660 @@
+//! Configurable listings of the objects under a prefix.
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{key::s3_prefix, DirEntry, OpenOptions, S3FilesystemError};
+
+/// A listing of the objects under a prefix, configured before it is run.
+///
+/// Created by [OpenOptions::walk]. [OpenOptions::walkdir] is the same as `walk(path).list()` with no
+/// options set.
+#[derive(Debug, Clone)]
+pub struct WalkDir<'a> {
+    open_options: &'a OpenOptions,
+    path: PathBuf,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    sort: Option<(SortKey, SortOrder)>,
+    filters: Filters,
+    start_after: Option<String>,
+    max_keys: Option<usize>,
+    concurrency: usize,
+    #[cfg(feature = "inventory")]
+    inventory: Option<(String, String)>,
+}
+
+/// The filters set on a [WalkDir], kept apart so sharded listings can take their own copy.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Filters {
+    extensions: Vec<String>,
+    min_size: Option<u64>,
+    modified_after: Option<SystemTime>,
+    skip_archived: bool,
+}
+
+/// Everything a [WalkDir] checks entries against, owned so inventory report streams can carry a copy.
+#[derive(Debug, Clone)]
+pub(crate) struct Selection {
+    /// The prefix being listed, which depths are counted from.
+    pub(crate) prefix: String,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    pub(crate) filters: Filters,
+    start_after: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What [WalkDir::sort_by] orders entries by.
+pub enum SortKey {
+    /// The entry's path, compared byte by byte.
+    Name,
+    /// The entry's size in bytes.
+    Size,
+    /// When the entry was last written. Entries with no known time, such as folders, sort first.
+    LastModified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Which way [WalkDir::sort_by] orders entries.
+pub enum SortOrder {
+    /// Smallest, earliest or alphabetically first entries first.
+    Ascending,
+    /// Largest, latest or alphabetically last entries first.
+    Descending,
+}
+
+impl OpenOptions {
+    /// Start building a listing of the objects under `path`
+    ///
+    /// Set any options on the returned [WalkDir], then call [WalkDir::list] to run it.
+    ///
+    /// # Arguments
+    /// * `path`: A path to search within the S3 bucket. If you want the entire bucket, just specify an empty string: "".
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     // Only the top two levels under datasets/, however deep the tree goes.
+    ///     let entries = open_options
+    ///         .walk("datasets/")
+    ///         .max_depth(2)
+    ///         .list()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     for entry in entries {
+    ///         println!("Entry: {:?}", entry);
+    ///     }
+    /// }
+    /// ```
+    pub fn walk<P>(&self, path: P) -> WalkDir<'_>
+    where
+        P: AsRef<Path>,
+    {
+        WalkDir {
+            open_options: self,
+            path: path.as_ref().to_path_buf(),
+            min_depth: 0,
+            max_depth: None,
+            sort: None,
+            filters: Filters::default(),
+            start_after: None,
+            max_keys: None,
+            concurrency: 1,
+            #[cfg(feature = "inventory")]
+            inventory: None,
+        }
+    }
+}
+
+impl WalkDir<'_> {
+    /// Skip entries fewer than `depth` levels below the path.
+    ///
+    /// Depth counts the `/` separated segments of a key after the path, so with a path of "data/",
+    /// "data/a.csv" and the folder "data/2024/" are at depth 1 and "data/2024/a.csv" is at depth 2.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Stop descending more than `depth` levels below the path
+    ///
+    /// Deeper objects are never listed: S3 is asked to roll everything beneath the last level up into
+    /// folder entries, so the cost depends on how many entries are returned rather than how many
+    /// objects sit beneath them. Depth is counted as in [WalkDir::min_depth].
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Return entries sorted by `key` in the given `order`
+    ///
+    /// Entries that compare equal on `key` are ordered by path, so results are the same from run to run.
+    /// Without this, entries come back in the order S3 lists them.
+    pub fn sort_by(mut self, key: SortKey, order: SortOrder) -> Self {
+        self.sort = Some((key, order));
+        self
+    }
+
+    /// Only return entries whose file extension is `extension`
+    ///
+    /// The comparison ignores ASCII case and a leading `.`, so "csv", ".csv" and "CSV" all match
+    /// "data/a.csv". Call this more than once to accept any of several extensions. Folders have no
+    /// extension, so they are left out once this is set.
+    pub fn filter_extension<E>(mut self, extension: E) -> Self
+    where
+        E: AsRef<str>,
+    {
+        let extension = extension.as_ref();
+        self.filters
+            .extensions
+            .push(extension.strip_prefix('.').unwrap_or(extension).to_string());
+        self
+    }
+
+    /// Only return entries of at least `bytes` in size.
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.filters.min_size = Some(bytes);
+        self
+    }
+
+    /// Only return entries last written after `time`
+    ///
+    /// Entries with no known modification time, such as folders S3 rolled up from longer keys, are left
+    /// out once this is set.
+    pub fn modified_after(mut self, time: SystemTime) -> Self {
+        self.filters.modified_after = Some(time);
+        self
+    }
+
+    /// Leave out objects that have to be restored before they can be downloaded
+    ///
+    /// Useful when the listing drives a bulk download, so the download does not fail part way through on
+    /// archived objects. See [DirEntry::is_archived] for which objects are left out.
+    pub fn skip_archived(mut self, skip: bool) -> Self {
+        self.filters.skip_archived = skip;
+        self
+    }
+
+    /// Only return entries whose key sorts after `key`
+    ///
+    /// S3 is asked to start the listing after `key` with ListObjectsV2's `start-after`, so keys before
+    /// it are never fetched. `key` is a full key, such as "logs/2024-05-01/000123.json", and need not
+    /// exist. With [WalkDir::max_depth] or an inventory report the full listing is fetched and filtered
+    /// instead. Together with [WalkDir::max_keys] this lets a job work through a huge prefix a window at
+    /// a time, carrying on from the last key it processed, even in a later run. Directory buckets return
+    /// keys out of order, so for them the whole prefix is still listed to find the window.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     // Picked up from where yesterday's run stopped, if it got anywhere.
+    ///     let mut last_key = std::fs::read_to_string("last_key.txt").unwrap_or_default();
+    ///
+    ///     loop {
+    ///         let window = open_options
+    ///             .walk("logs/")
+    ///             .start_after(&last_key)
+    ///             .max_keys(500)
+    ///             .list()
+    ///             .await
+    ///             .unwrap();
+    ///
+    ///         let Some(last) = window.last() else {
+    ///             break;
+    ///         };
+    ///         for entry in &window {
+    ///             println!("Processing {}", entry.path.display());
+    ///         }
+    ///
+    ///         last_key = last.path.to_string_lossy().into_owned();
+    ///         std::fs::write("last_key.txt", &last_key).unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn start_after<K>(mut self, key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        self.start_after = Some(key.into()).filter(|key| !key.is_empty());
+        self
+    }
+
+    /// Return at most `max_keys` entries
+    ///
+    /// The entries returned are the first matching ones in key order, and listing stops as soon as that
+    /// many are found rather than paging through the rest of the prefix. The cap is applied before
+    /// [WalkDir::sort_by], so it always picks the same window whatever order it is returned in.
+    ///
+    /// A listing with a window set is made as a single sequential pagination, so [WalkDir::concurrency]
+    /// has no effect, and it is not saved for [offline](OpenOptions::offline) use.
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    /// Split the listing into shards and run up to `concurrency` of them at once
+    ///
+    /// A single ListObjectsV2 pagination returns at most 1000 keys per request, one request after
+    /// another, which is slow for prefixes holding millions of objects. With `concurrency` above 1 the
+    /// path is first listed with a `/` delimiter, and each sub-prefix ("folder") found directly beneath it
+    /// is then paginated separately and concurrently. Results are merged back into the order S3 would list
+    /// them in.
+    ///
+    /// This only helps when the keys are spread across several sub-prefixes, and has no effect when
+    /// [WalkDir::max_depth] is set. Defaults to 1, a single sequential pagination.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// List from an S3 Inventory report rather than with ListObjectsV2
+    ///
+    /// `manifest_key` is the `manifest.json` of one inventory delivery, such as
+    /// "inventories/my-bucket/daily/2024-01-01T01-00Z/manifest.json", in `inventory_bucket`. The report
+    /// files it names are downloaded (up to [WalkDir::concurrency] at once) and read in place of a live
+    /// listing, which for buckets holding hundreds of millions of objects is far quicker and cheaper.
+    ///
+    /// Entries are only as fresh as the report, and folders are not rolled up when [WalkDir::max_depth]
+    /// is set. Noncurrent versions and delete markers are skipped. [WalkDir::list] still gathers every
+    /// entry into one `Vec`; for inventories too large for that, stream each report file with
+    /// [WalkDir::inventory_reports] instead.
+    ///
+    /// The inventory's source bucket must be the bucket being listed, unless it is reached through an
+    /// access point, whose ARN or alias cannot be compared with the bucket name.
+    ///
+    /// Only inventories delivered as CSV can be read. Configure the inventory with the CSV output format, as
+    /// listing from an ORC or Parquet inventory fails with an [std::io::ErrorKind::Unsupported] error.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let entries = open_options
+    ///         .walk("datasets/")
+    ///         .from_inventory(
+    ///             "my_inventory_bucket",
+    ///             "inventories/my_aws_s3_bucket/daily/2024-01-01T01-00Z/manifest.json",
+    ///         )
+    ///         .concurrency(8)
+    ///         .list()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     println!("{} objects", entries.len());
+    /// }
+    /// ```
+    #[cfg(feature = "inventory")]
+    pub fn from_inventory<B, K>(mut self, inventory_bucket: B, manifest_key: K) -> Self
+    where
+        B: Into<String>,
+        K: Into<String>,
+    {
+        self.inventory = Some((inventory_bucket.into(), manifest_key.into()));
+        self
+    }
+
+    /// Read the manifest of the inventory set with [WalkDir::from_inventory], returning its report files
+    ///
+    /// Each [InventoryReport](crate::InventoryReport) streams the entries of one report file as it is
+    /// downloaded, so an inventory of hundreds of millions of objects can be worked through without
+    /// holding it in memory, and several report files can be read at once. The path, depth limits,
+    /// filters and [WalkDir::start_after] apply to the streamed entries; [WalkDir::sort_by],
+    /// [WalkDir::max_keys] and [WalkDir::concurrency] do not.
+    ///
+    /// Fails with an [std::io::ErrorKind::InvalidInput] error if no inventory was set, and with an
+    /// [std::io::ErrorKind::Unsupported] error for ORC and Parquet inventories.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let reports = open_options
+    ///         .walk("datasets/")
+    ///         .from_inventory(
+    ///             "my_inventory_bucket",
+    ///             "inventories/my_aws_s3_bucket/daily/2024-01-01T01-00Z/manifest.json",
+    ///         )
+    ///         .inventory_reports()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let mut total = 0;
+    ///     for report in reports {
+    ///         let mut entries = report.entries();
+    ///         while let Some(entry) = entries.next().await {
+    ///             total += entry.unwrap().size;
+    ///         }
+    ///     }
+    ///     println!("{} bytes", total);
+    /// }
+    /// ```
+    #[cfg(feature = "inventory")]
+    pub async fn inventory_reports(self) -> Result<Vec<crate::InventoryReport>, S3FilesystemError> {
+        let Some((inventory_bucket, manifest_key)) = &self.inventory else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No inventory was set with from_inventory",
+            )
+            .into());
+        };
+
+        let prefix = s3_prefix(&self.path)?;
+        self.open_options
+            .inventory_reports(inventory_bucket, manifest_key, self.selection(&prefix))
+            .await
+    }
+
+    /// Run the listing
+    ///
+    /// Listing is paginated internally, so every matching object is returned regardless of how many
+    /// requests that takes.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "walkdir",
+            level = "debug",
+            skip_all,
+            fields(bucket = %self.open_options.bucket, prefix = %self.path.display(), objects),
+            err
+        )
+    )]
+    pub async fn list(self) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        let open_options = self.open_options;
+        self.run()
+            .await
+            .map_err(|e| e.with_context("ListObjectsV2", &open_options.bucket, Some(&self.path)))
+    }
+
+    async fn run(&self) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        let prefix = s3_prefix(&self.path)?;
+
+        let entries = match self.list_source(&prefix).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                let open_options = self.open_options;
+                if !(open_options.offline && err.is_unreachable()) {
+                    return Err(err);
+                }
+                match open_options.load_listing(&prefix).await? {
+                    Some(saved) => saved,
+                    None => return Err(err),
+                }
+            }
+        };
+
+        let selection = self.selection(&prefix);
+        let mut entries: Vec<DirEntry> = entries
+            .into_iter()
+            .filter(|entry| selection.keeps(entry))
+            .collect();
+
+        if let Some(max_keys) = self.max_keys {
+            entries.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+            entries.truncate(max_keys);
+        }
+
+        if let Some((key, order)) = self.sort {
+            entries.sort_by(|a, b| {
+                let ordering = compare(key, a, b).then_with(|| a.path.cmp(&b.path));
+                match order {
+                    SortOrder::Ascending => ordering,
+                    SortOrder::Descending => ordering.reverse(),
+                }
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("objects", entries.len());
+
+        Ok(entries)
+    }
+
+    /// List the prefix from wherever this walk is configured to read.
+    async fn list_source(&self, prefix: &str) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        #[cfg(feature = "inventory")]
+        if let Some((inventory_bucket, manifest_key)) = &self.inventory {
+            return self
+                .open_options
+                .list_inventory(
+                    inventory_bucket,
+                    manifest_key,
+                    self.selection(prefix),
+                    self.concurrency,
+                )
+                .await;
+        }
+
+        match self.max_depth {
+            Some(max_depth) => self.list_to_depth(prefix, max_depth).await,
+            None => self.list_everything(prefix).await,
+        }
+    }
+
+    /// List every object under the prefix, saving the listing for offline use if enabled.
+    ///
+    /// A saved listing has to be complete to answer later walks with different filters, so filtering is
+    /// left until afterwards when one is being saved.
+    async fn list_everything(&self, prefix: &str) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        let windowed = self.start_after.is_some() || self.max_keys.is_some();
+        let save = self.open_options.offline && !windowed;
+        let filters = match save {
+            true => Filters::default(),
+            false => self.filters.clone(),
+        };
+
+        let entries = match self.concurrency {
+            _ if windowed => {
+                // Everything run() filters on is checked here too, so the cap counts only entries kept.
+                let keep = |entry: &DirEntry| {
+                    filters.matches(entry) && depth(prefix, &entry.path) >= self.min_depth
+                };
+                self.open_options
+                    .list_objects_window(
+                        prefix,
+                        false,
+                        &keep,
+                        self.start_after.as_deref(),
+                        self.max_keys,
+                    )
+                    .await?
+                    .0
+            }
+            1 => {
+                self.open_options
+                    .list_objects(prefix, false, &|entry| filters.matches(entry))
+                    .await?
+                    .0
+            }
+            concurrency => self.list_sharded(prefix, concurrency, filters).await?,
+        };
+
+        if save {
+            self.open_options.save_listing(prefix, &entries).await?;
+        }
+
+        Ok(entries)
+    }
+
+    /// List each sub-prefix directly under the prefix as its own concurrent pagination.
+    async fn list_sharded(
+        &self,
+        prefix: &str,
+        concurrency: usize,
+        filters: Filters,
+    ) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        let (mut entries, shards) = self
+            .open_options
+            .list_objects(prefix, true, &|entry| filters.matches(entry))
+            .await?;
+
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        let mut tasks = JoinSet::new();
+
+        for shard in shards {
+            let open_options = self.open_options.clone();
+            let filters = filters.clone();
+            let semaphore = semaphore.clone();
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                open_options
+                    .list_objects(&shard, false, &|entry| filters.matches(entry))
+                    .await
+            });
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(listed) => entries.extend(listed?.0),
+                Err(join_error) => std::panic::resume_unwind(join_error.into_panic()),
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+        Ok(entries)
+    }
+
+    /// Whether an entry's key sorts after [WalkDir::start_after], if it was set.
+    fn selection(&self, prefix: &str) -> Selection {
+        Selection {
+            prefix: prefix.to_string(),
+            min_depth: self.min_depth,
+            max_depth: self.max_depth,
+            filters: self.filters.clone(),
+            start_after: self.start_after.clone(),
+        }
+    }
+
+    /// List level by level with a delimiter, so nothing below `max_depth` is fetched.
+    async fn list_to_depth(
+        &self,
+        prefix: &str,
+        max_depth: usize,
+    ) -> Result<Vec<DirEntry>, S3FilesystemError> {
+        let mut entries = Vec::new();
+        let mut level = vec![prefix.to_string()];
+
+        while let Some(current) = level.pop() {
+            let (objects, common_prefixes) = self
+                .open_options
+                .list_objects(&current, true, &|entry| self.filters.matches(entry))
+                .await?;
+
+            // A folder marker object for the prefix being listed was already added as a folder entry.
+            entries.extend(
+                objects
+                    .into_iter()
+                    .filter(|object| current == prefix || object.path != Path::new(&current)),
+            );
+
+            for common_prefix in common_prefixes {
+                if depth(prefix, Path::new(&common_prefix)) < max_depth {
+                    level.push(common_prefix.clone());
+                }
+                entries.push(DirEntry {
+                    path: PathBuf::from(common_prefix),
+                    size: 0,
+                    folder: true,
+                    e_tag: None,
+                    last_modified: None,
+                    storage_class: None,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl Filters {
+    /// Whether an entry passes the extension, size and modification time filters.
+    pub(crate) fn matches(&self, entry: &DirEntry) -> bool {
+        let extension_matches = self.extensions.is_empty()
+            || (!entry.folder
+                && entry
+                    .path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| {
+                        self.extensions
+                            .iter()
+                            .any(|wanted| wanted.eq_ignore_ascii_case(extension))
+                    }));
+
+        extension_matches
+            && !(self.skip_archived && entry.is_archived())
+            && self
+                .min_size
+                .is_none_or(|min_size| entry.size.max(0) as u64 >= min_size)
+            && self
+                .modified_after
+                .is_none_or(|after| entry.last_modified.is_some_and(|modified| modified > after))
+    }
+}
+
+impl Selection {
+    /// Whether an entry is within the depth limits, passes the filters and sorts after the start key.
+    pub(crate) fn keeps(&self, entry: &DirEntry) -> bool {
+        let depth = depth(&self.prefix, &entry.path);
+        depth >= self.min_depth
+            && self.max_depth.is_none_or(|max| depth <= max)
+            && self.filters.matches(entry)
+            && self
+                .start_after
+                .as_deref()
+                .is_none_or(|start_after| entry.path.to_string_lossy().as_ref() > start_after)
+    }
+}
+
+/// Compare two entries on a single sort key.
+fn compare(key: SortKey, a: &DirEntry, b: &DirEntry) -> Ordering {
+    match key {
+        SortKey::Name => Ordering::Equal,
+        SortKey::Size => a.size.cmp(&b.size),
+        SortKey::LastModified => a.last_modified.cmp(&b.last_modified),
+    }
+}
+
+/// How many `/` separated segments `key` has beyond `prefix`.
+fn depth(prefix: &str, key: &Path) -> usize {
+    let key = key.to_string_lossy();
+    key.get(prefix.len()..)
+        .unwrap_or_default()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .count()
+}
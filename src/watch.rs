@@ -0,0 +1,138 @@
+//! Polling for changes to objects under a prefix.
+use std::{collections::BTreeMap, path::Path, path::PathBuf, time::Duration};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+use crate::{error::S3FilesystemError, key::s3_prefix, DirEntry, OpenOptions};
+
+#[derive(Debug, Clone)]
+/// A change to an S3 object noticed by [OpenOptions::watch].
+pub enum WatchEvent {
+    /// An object appeared that was not present in the previous listing.
+    Created(DirEntry),
+    /// An object's ETag or size differs from the previous listing.
+    Modified(DirEntry),
+    /// An object present in the previous listing has gone. The entry is as it was last seen.
+    Deleted(DirEntry),
+}
+
+impl OpenOptions {
+    /// Watch a prefix for remote changes
+    ///
+    /// Every `interval` the prefix is listed with [OpenOptions::walkdir] and compared against the previous
+    /// listing, yielding a [WatchEvent] for each object created, modified or deleted in between. The events
+    /// from one listing are yielded in path order. The first listing only establishes a baseline and
+    /// produces no events.
+    ///
+    /// Listing errors are yielded on the stream and polling carries on, so a transient failure does not end
+    /// the watch. Polling stops when the stream is dropped. This must be called from within a Tokio runtime,
+    /// as the polling happens on a spawned task.
+    ///
+    /// # Arguments
+    /// * `prefix`: The path within the bucket to watch. Use an empty string for the entire bucket.
+    /// * `interval`: How long to wait between listings.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::{OpenOptions, WatchEvent};
+    /// use std::time::Duration;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None).await;
+    ///
+    ///     let mut events = open_options.watch("incoming/", Duration::from_secs(30));
+    ///
+    ///     while let Some(event) = events.next().await {
+    ///         if let Ok(WatchEvent::Created(entry)) = event {
+    ///             println!("New file: {:?}", entry.path);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn watch<P>(
+        &self,
+        prefix: P,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<WatchEvent, S3FilesystemError>>
+    where
+        P: AsRef<Path>,
+    {
+        let (sender, receiver) = mpsc::channel(64);
+        let open_options = self.clone();
+        let prefix = s3_prefix(prefix.as_ref());
+
+        tokio::spawn(async move {
+            let prefix = match prefix {
+                Ok(prefix) => prefix,
+                Err(err) => {
+                    let _ = sender.send(Err(err)).await;
+                    return;
+                }
+            };
+
+            let mut previous: Option<BTreeMap<PathBuf, DirEntry>> = None;
+
+            loop {
+                match open_options.walkdir(&prefix).await {
+                    Ok(entries) => {
+                        let current: BTreeMap<PathBuf, DirEntry> = entries
+                            .into_iter()
+                            .map(|entry| (entry.path.clone(), entry))
+                            .collect();
+
+                        if let Some(previous) = &previous {
+                            for event in diff_listings(previous, &current) {
+                                if sender.send(Ok(event)).await.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+
+                        previous = Some(current);
+                    }
+                    Err(e) => {
+                        if sender.send(Err(e)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = sender.closed() => return,
+                }
+            }
+        });
+
+        ReceiverStream::new(receiver)
+    }
+}
+
+/// Work out what changed between two listings keyed by path, in path order.
+fn diff_listings(
+    previous: &BTreeMap<PathBuf, DirEntry>,
+    current: &BTreeMap<PathBuf, DirEntry>,
+) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+    let mut previous_entries = previous.iter().peekable();
+
+    for (path, entry) in current {
+        while let Some((_, old)) = previous_entries.next_if(|(old_path, _)| *old_path < path) {
+            events.push(WatchEvent::Deleted(old.clone()));
+        }
+        match previous_entries.next_if(|(old_path, _)| *old_path == path) {
+            None => events.push(WatchEvent::Created(entry.clone())),
+            Some((_, old)) if old.e_tag != entry.e_tag || old.size != entry.size => {
+                events.push(WatchEvent::Modified(entry.clone()))
+            }
+            Some(_) => (),
+        }
+    }
+
+    events.extend(previous_entries.map(|(_, old)| WatchEvent::Deleted(old.clone())));
+    events
+}
@@ -0,0 +1,167 @@
+//! Holding writes in the local mirror until they are flushed to S3.
+//!
+//! Keys written but not yet uploaded are kept in `<mount_path>/.s3-filesystem/<bucket>.pending`, a
+//! [KeyedLog] read again before every use, so writes left behind by a run which stopped before flushing can
+//! still be flushed by the next, and processes sharing a mount path see each other's pending writes.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    index::INDEX_DIR, key::s3_prefix, keyed_log::KeyedLog, options::bucket_folder, OpenOptions,
+    S3FilesystemError, WriteOptions,
+};
+
+/// The value every pending key is recorded with.
+const PENDING: &str = "+";
+
+/// The keys waiting to be flushed for one bucket.
+#[derive(Debug)]
+pub(crate) struct PendingWrites {
+    log: KeyedLog,
+}
+
+impl PendingWrites {
+    pub(crate) fn new(mount_path: &Path, bucket: &str) -> Self {
+        let path = mount_path
+            .join(INDEX_DIR)
+            .join(format!("{}.pending", bucket_folder(bucket)));
+        PendingWrites {
+            log: KeyedLog::new(path, "pending"),
+        }
+    }
+
+    pub(crate) async fn contains(&self, key: &str) -> io::Result<bool> {
+        self.log.read(|keys| keys.contains_key(key)).await
+    }
+
+    /// Every pending key starting with `prefix`, in key order.
+    pub(crate) async fn starting_with(&self, prefix: &str) -> io::Result<Vec<String>> {
+        self.log
+            .read(|keys| {
+                keys.range(prefix.to_string()..)
+                    .take_while(|(key, _)| key.starts_with(prefix))
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .await
+    }
+
+    pub(crate) async fn insert(&self, key: &str) -> io::Result<()> {
+        self.log.set(key, PENDING).await
+    }
+
+    pub(crate) async fn remove(&self, key: &str) -> io::Result<()> {
+        self.log.remove(key).await?;
+        Ok(())
+    }
+}
+
+impl OpenOptions {
+    /// Hold writes in the local mirror until they are flushed
+    ///
+    /// With `enabled` = true, [OpenOptions::write_s3] only writes the file under the mount path and records
+    /// it as pending, making no request to S3. [OpenOptions::flush_all] or [OpenOptions::flush] then uploads
+    /// everything pending in one go, so a batch job can produce many small outputs and push them to S3 once
+    /// it has finished.
+    ///
+    /// Until they are flushed, pending files are served from the mirror by [OpenOptions::open_s3] and
+    /// [OpenOptions::read_s3] whatever the [CachePolicy](crate::CachePolicy), and are never evicted to stay
+    /// within [OpenOptions::cache_quota]. Listings, [OpenOptions::stat] and other clients still see S3 as it
+    /// was. Pending writes are recorded under the mount path, so they survive a restart, but are lost if
+    /// their files are removed, as by [OpenOptions::purge_cache] or [OpenOptions::delete_many].
+    ///
+    /// Only a file's key is recorded as pending, and flushing uploads it with the default [WriteOptions]. So
+    /// writes made with [OpenOptions::write_s3_with] and anything other than [WriteOptions::new], such as a
+    /// [WritePrecondition](crate::WritePrecondition), an Object Lock or a Content-Type, skip write-back and
+    /// are uploaded straight away, as are writes with [OpenOptions::write_s3_direct]. Ignored when
+    /// [OpenOptions::dry_run] is set. Defaults to false.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use s3_filesystem::OpenOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let bucket = "my_aws_s3_bucket".to_string();
+    ///
+    ///     let open_options = OpenOptions::new(bucket, None)
+    ///         .await
+    ///         .mount_path("data/job/")
+    ///         .write_back(true);
+    ///
+    ///     for shard in 0..1000 {
+    ///         let output = format!("shard {} done", shard);
+    ///         let path = format!("outputs/shard-{}.txt", shard);
+    ///         open_options.write_s3(path, output.as_bytes()).await.unwrap();
+    ///     }
+    ///
+    ///     let flushed = open_options.flush_all().await.unwrap();
+    ///     println!("Uploaded {} files", flushed.len());
+    /// }
+    /// ```
+    pub fn write_back(mut self, enabled: bool) -> Self {
+        self.write_back = enabled;
+        self
+    }
+
+    /// Every file written with [OpenOptions::write_back] which has not been flushed yet, in key order.
+    pub async fn pending_writes(&self) -> Result<Vec<PathBuf>, S3FilesystemError> {
+        let pending = self.cache_index.pending.starting_with("").await?;
+        Ok(pending.into_iter().map(PathBuf::from).collect())
+    }
+
+    /// Upload every pending write, as [OpenOptions::flush] does for a prefix.
+    pub async fn flush_all(&self) -> Result<Vec<PathBuf>, S3FilesystemError> {
+        self.flush("").await
+    }
+
+    /// Upload the pending writes under a prefix
+    ///
+    /// Each file written with [OpenOptions::write_back] whose key starts with `prefix` is uploaded from the
+    /// mount path as [OpenOptions::write_s3] would have, one after another in key order, and is no longer
+    /// pending once S3 has it. The flush stops at the first upload which fails, returning its error; files
+    /// already uploaded stay flushed and the rest stay pending, so calling it again carries on.
+    ///
+    /// Returns the paths uploaded. Files which have left the mount path since they were written are dropped
+    /// from the pending writes without being uploaded. With [OpenOptions::dry_run] set, the uploads are
+    /// recorded instead and every write stays pending.
+    ///
+    /// # Arguments
+    /// * `prefix`: The prefix to flush. For everything, just specify an empty string: "".
+    pub async fn flush<P>(&self, prefix: P) -> Result<Vec<PathBuf>, S3FilesystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let prefix = s3_prefix(prefix.as_ref())?;
+        let mut upload = self.clone();
+        upload.write_back = false;
+
+        let mut flushed = Vec::new();
+        for key in self.cache_index.pending.starting_with(&prefix).await? {
+            let buf = match tokio::fs::read(self.local_path(&key)?).await {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    self.cache_index.pending.remove(&key).await?;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            self.cancellable(upload.write_s3_with(&key, &buf, &WriteOptions::new()))
+                .await?;
+            if self.dry_run.is_none() {
+                self.cache_index.pending.remove(&key).await?;
+            }
+            flushed.push(PathBuf::from(key));
+        }
+        Ok(flushed)
+    }
+
+    /// Whether `key` was written with [OpenOptions::write_back] and has not been flushed, so its mirrored
+    /// copy is newer than S3's.
+    pub(crate) async fn is_pending(&self, key: &str) -> io::Result<bool> {
+        self.cache_index.pending.contains(key).await
+    }
+}
@@ -2,11 +2,27 @@
 ///
 /// IMPORTANT: for the tests to work you will need to be signed into AWS via the CLI. If your AWS client is not connected to eu-west2 it will fail,
 /// as this is where the free data is stored.
-use s3_filesystem::OpenOptions;
-use tokio::io::AsyncReadExt;
+use arrow_array::{Array, ArrayRef, Int64Array, RecordBatch, StringArray};
+use parquet::{
+    arrow::{ArrowWriter, ProjectionMask},
+    file::properties::WriterProperties,
+};
+use s3_filesystem::{
+    ArchiveFormat, CacheLayout, CachePolicy, CancellationToken, Checksum, ChecksumAlgorithm,
+    DirEntry, HttpRequest, HttpResponse, Manifest, MetricsSink, MockS3, OpenOptions,
+    OpenOptionsBuilder, RequestHook, S3FilesystemError, S3Key, S3Mounts, S3Overlay, SelectInput,
+    SortKey, SortOrder, TlsConfig, WatchEvent, WriteOptions,
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_stream::StreamExt;
 
 // eu-west2 public data.
-const BUCKET: &'static str = "pansurg-curation-workflo-kendraqueryresults50d0eb-open-data";
+const BUCKET: &str = "pansurg-curation-workflo-kendraqueryresults50d0eb-open-data";
 
 #[tokio::test]
 async fn test_open_file() {
@@ -15,7 +31,7 @@ async fn test_open_file() {
     let open_options = OpenOptions::new(bucket, None)
         .await
         .mount_path("data/test/")
-        .force_download(true);
+        .cache_policy(CachePolicy::AlwaysDownload);
 
     let mut file = open_options
         .open_s3("redasa1-Q1-20/manifest.txt")
@@ -29,6 +45,54 @@ async fn test_open_file() {
     println!("String: {}", string);
 }
 
+#[tokio::test]
+async fn test_open_lazy_matches_download() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("data/test/")
+        .cache_policy(CachePolicy::AlwaysDownload);
+
+    let mut downloaded = Vec::new();
+    open_options
+        .open_s3("redasa1-Q1-20/manifest.txt")
+        .await
+        .unwrap()
+        .read_to_end(&mut downloaded)
+        .await
+        .unwrap();
+
+    let mut lazy = open_options
+        .open_s3_lazy("redasa1-Q1-20/manifest.txt")
+        .await
+        .unwrap()
+        .buffer_size(16);
+    assert_eq!(lazy.size(), downloaded.len() as u64);
+
+    let mut tail = Vec::new();
+    lazy.seek(std::io::SeekFrom::Start(10)).await.unwrap();
+    lazy.read_to_end(&mut tail).await.unwrap();
+    assert_eq!(tail, downloaded[10..]);
+}
+
+#[tokio::test]
+async fn test_read_s3_into_memory() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("target/test-read-s3/");
+
+    let contents = open_options
+        .read_s3("redasa1-Q1-20/manifest.txt")
+        .await
+        .unwrap();
+
+    assert!(!contents.is_empty());
+    assert!(!std::path::Path::new("target/test-read-s3").exists());
+}
+
 #[tokio::test]
 async fn test_walk_dir() {
     let bucket = BUCKET.to_string();
@@ -41,6 +105,116 @@ async fn test_walk_dir() {
     }
 }
 
+#[tokio::test]
+async fn test_select_matches_download() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await;
+
+    let downloaded = open_options
+        .read_s3("redasa1-Q1-20/manifest.txt")
+        .await
+        .unwrap();
+
+    let rows: Vec<String> = open_options
+        .select(
+            "redasa1-Q1-20/manifest.txt",
+            "SELECT * FROM S3Object",
+            SelectInput::Csv { header: false },
+        )
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert_eq!(
+        rows.len(),
+        String::from_utf8_lossy(&downloaded).lines().count()
+    );
+}
+
+#[tokio::test]
+async fn test_walk_max_depth() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await;
+
+    let entries = open_options
+        .walk("redasa1-Q1-20/")
+        .max_depth(1)
+        .list()
+        .await
+        .unwrap();
+
+    assert!(!entries.is_empty());
+    for entry in entries {
+        let relative = entry.path.strip_prefix("redasa1-Q1-20").unwrap();
+        assert_eq!(relative.components().count(), 1, "{:?}", entry.path);
+    }
+}
+
+#[tokio::test]
+async fn test_walk_sorted_by_size() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await;
+
+    let entries = open_options
+        .walk("redasa1-Q1-20/")
+        .sort_by(SortKey::Size, SortOrder::Descending)
+        .list()
+        .await
+        .unwrap();
+
+    assert!(!entries.is_empty());
+    assert!(entries.windows(2).all(|pair| pair[0].size >= pair[1].size));
+}
+
+#[tokio::test]
+async fn test_walk_filter_extension() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await;
+
+    let entries = open_options
+        .walk("redasa1-Q1-20/")
+        .filter_extension("csv")
+        .min_size(1)
+        .list()
+        .await
+        .unwrap();
+
+    assert!(!entries.is_empty());
+    for entry in entries {
+        assert_eq!(entry.path.extension().unwrap(), "csv");
+        assert!(entry.size >= 1);
+    }
+}
+
+#[tokio::test]
+async fn test_walk_concurrent_matches_sequential() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await;
+
+    let sequential = open_options.walkdir("redasa1-Q1-20/").await.unwrap();
+    let concurrent = open_options
+        .walk("redasa1-Q1-20/")
+        .concurrency(4)
+        .list()
+        .await
+        .unwrap();
+
+    let paths = |entries: Vec<s3_filesystem::DirEntry>| {
+        entries
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(paths(sequential), paths(concurrent));
+}
+
 #[tokio::test]
 async fn combine_walkdir_and_download() {
     let bucket = BUCKET.to_string();
@@ -48,7 +222,7 @@ async fn combine_walkdir_and_download() {
     let open_options = OpenOptions::new(bucket, None)
         .await
         .mount_path("data/test/")
-        .force_download(false);
+        .cache_policy(CachePolicy::UseCacheIfPresent);
 
     let data = open_options.walkdir("redasa1-Q1-20").await.unwrap();
 
@@ -61,3 +235,2443 @@ async fn combine_walkdir_and_download() {
         println!("entry: {:?} downloaded", entry.path);
     }
 }
+
+#[tokio::test]
+async fn test_fs_read_to_string() {
+    let string = s3_filesystem::fs::read_to_string(BUCKET, "redasa1-Q1-20/manifest.txt")
+        .await
+        .unwrap();
+
+    println!("String: {}", string);
+}
+
+#[test]
+fn test_parse_manifest() {
+    let manifest: Manifest = "# comment\n\
+        redasa1-Q1-20/a.csv 12 md5:0CC175B9C0F1B6A831C399E269772661\n\
+        \n\
+        redasa1-Q1-20/b.csv 34\n\
+        redasa1-Q1-20/c.csv\n"
+        .parse()
+        .unwrap();
+
+    assert_eq!(manifest.entries.len(), 3);
+    assert_eq!(manifest.entries[0].size, Some(12));
+    assert_eq!(
+        manifest.entries[0].checksum,
+        Some(Checksum::Md5(
+            "0cc175b9c0f1b6a831c399e269772661".to_string()
+        ))
+    );
+    assert_eq!(manifest.entries[1].checksum, None);
+    assert_eq!(manifest.entries[2].size, None);
+
+    assert!("a.csv not-a-size".parse::<Manifest>().is_err());
+}
+
+#[tokio::test]
+async fn test_directory_bucket_lists_in_key_order() {
+    let bucket = "express--use1-az4--x-s3";
+    let mount_path = "target/test-directory-bucket/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+    let mock = MockS3::new().with_bucket(bucket);
+    for key in [
+        "logs/a.json",
+        "logs/b.json",
+        "logs/c.json",
+        "logs/old/d.json",
+        "other.txt",
+    ] {
+        mock.put_object(bucket, key, key);
+    }
+    let open_options = OpenOptions::new(bucket.to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cache_policy(CachePolicy::AlwaysDownload);
+
+    assert_eq!(
+        open_options.read_to_string("logs/b.json").await.unwrap(),
+        "logs/b.json"
+    );
+    open_options.write_s3("logs/e.json", b"e").await.unwrap();
+    assert_eq!(mock.get_object(bucket, "logs/e.json").unwrap(), b"e");
+
+    let paths = |entries: Vec<DirEntry>| {
+        entries
+            .into_iter()
+            .map(|entry| entry.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths(open_options.walkdir("logs/").await.unwrap()),
+        [
+            "logs/a.json",
+            "logs/b.json",
+            "logs/c.json",
+            "logs/e.json",
+            "logs/old/d.json"
+        ]
+    );
+    assert_eq!(
+        paths(open_options.walkdir("logs/b").await.unwrap()),
+        ["logs/b.json"]
+    );
+
+    let window = open_options
+        .walk("logs/")
+        .start_after("logs/a.json")
+        .max_keys(2)
+        .list()
+        .await
+        .unwrap();
+    assert_eq!(paths(window), ["logs/b.json", "logs/c.json"]);
+
+    let page = open_options.walkdir_page("logs/", None, 10).await.unwrap();
+    assert_eq!(page.entries.len(), 5);
+    assert!(page
+        .entries
+        .windows(2)
+        .all(|pair| pair[0].path < pair[1].path));
+}
+
+#[derive(Default)]
+struct DownloadedBytes(Mutex<u64>);
+
+impl MetricsSink for DownloadedBytes {
+    fn downloaded(&self, _key: &str, bytes: u64) {
+        *self.0.lock().unwrap() += bytes;
+    }
+}
+
+/// 30,000 rows of `id` and `name` in three row groups of 10,000.
+fn trips_parquet() -> Vec<u8> {
+    let ids = Int64Array::from_iter_values(0..30_000);
+    let names = StringArray::from_iter_values((0..30_000).map(|id| format!("trip-{}", id)));
+    let batch = RecordBatch::try_from_iter([
+        ("id", Arc::new(ids) as ArrayRef),
+        ("name", Arc::new(names) as ArrayRef),
+    ])
+    .unwrap();
+
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(10_000)
+        .build();
+    let mut data = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut data, batch.schema(), Some(properties)).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+    data
+}
+
+#[tokio::test]
+async fn test_parquet_reads_only_the_row_groups_asked_for() {
+    let mock = MockS3::new().with_bucket("lake");
+    let data = trips_parquet();
+    mock.put_object("lake", "trips.parquet", data.clone());
+    let open_options = OpenOptions::new("lake".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-parquet/");
+
+    let mut batches = open_options.read_parquet("trips.parquet").await.unwrap();
+    let mut rows = 0;
+    while let Some(batch) = batches.next().await {
+        let batch = batch.unwrap();
+        assert_eq!(batch.num_columns(), 2);
+        rows += batch.num_rows();
+    }
+    assert_eq!(rows, 30_000);
+
+    let downloaded = Arc::new(DownloadedBytes::default());
+    let open_options = open_options.metrics(downloaded.clone());
+    let builder = open_options.parquet_reader("trips.parquet").await.unwrap();
+    assert_eq!(builder.metadata().num_row_groups(), 3);
+    let ids = ProjectionMask::roots(builder.parquet_schema(), [0]);
+    let batches = builder
+        .with_row_groups(vec![1])
+        .with_projection(ids)
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .await
+        .unwrap();
+    let ids = batches
+        .iter()
+        .flat_map(|batch| {
+            let ids = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            ids.values().to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, (10_000..20_000).collect::<Vec<_>>());
+    let downloaded = *downloaded.0.lock().unwrap();
+    assert!(
+        downloaded < data.len() as u64 / 2,
+        "{} of {} bytes downloaded",
+        downloaded,
+        data.len()
+    );
+
+    mock.put_object("lake", "not.parquet", "id,name\n1,trip-1\n");
+    let error = open_options
+        .read_parquet("not.parquet")
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.io_error_kind(), std::io::ErrorKind::InvalidData);
+    let error = open_options
+        .read_parquet("missing.parquet")
+        .await
+        .err()
+        .unwrap();
+    assert!(error.is_not_found());
+}
+
+#[tokio::test]
+async fn test_archived_objects_are_skipped_and_reported() {
+    let mount_path = "target/test-archived/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+    let mock = MockS3::new().with_bucket("archived");
+    mock.put_object("archived", "data/warm.csv", "a,b");
+    mock.put_object("archived", "data/frozen.csv", "c,d");
+    mock.set_storage_class("archived", "data/frozen.csv", "GLACIER");
+    let open_options = OpenOptions::new("archived".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+
+    let listed = open_options.walkdir("data/").await.unwrap();
+    let listed = listed
+        .iter()
+        .map(|entry| {
+            (
+                entry.path.to_str().unwrap(),
+                entry.storage_class.as_deref().unwrap(),
+                entry.is_archived(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        listed,
+        [
+            ("data/frozen.csv", "GLACIER", true),
+            ("data/warm.csv", "STANDARD", false)
+        ]
+    );
+
+    let skipped = open_options
+        .walk("data/")
+        .skip_archived(true)
+        .list()
+        .await
+        .unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].path, PathBuf::from("data/warm.csv"));
+
+    let error = open_options.read_s3("data/frozen.csv").await.unwrap_err();
+    assert!(error.is_archived());
+
+    let manifest: Manifest = "data/warm.csv 3\ndata/frozen.csv\n".parse().unwrap();
+    let report = open_options.download_manifest(&manifest, 2).await;
+    assert_eq!(report.succeeded, [PathBuf::from("data/warm.csv")]);
+    assert_eq!(report.archived, [PathBuf::from("data/frozen.csv")]);
+    assert!(report.failed.is_empty());
+}
+
+#[tokio::test]
+async fn test_open_missing_file_is_not_found() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("data/test/")
+        .cache_policy(CachePolicy::AlwaysDownload);
+
+    let err = open_options
+        .open_s3("redasa1-Q1-20/does-not-exist.txt")
+        .await
+        .unwrap_err();
+
+    assert!(err.is_not_found());
+    assert!(!err.is_throttled());
+}
+
+#[derive(Default)]
+struct CacheCounts {
+    hits: Mutex<Vec<String>>,
+    misses: Mutex<Vec<String>>,
+}
+
+impl MetricsSink for CacheCounts {
+    fn cache_hit(&self, key: &str) {
+        self.hits.lock().unwrap().push(key.to_string());
+    }
+
+    fn cache_miss(&self, key: &str) {
+        self.misses.lock().unwrap().push(key.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_cache_hit_metrics() {
+    let bucket = BUCKET.to_string();
+    let counts = Arc::new(CacheCounts::default());
+
+    let cached = format!("target/test/{}/metrics/cached.txt", BUCKET);
+    tokio::fs::create_dir_all(std::path::Path::new(&cached).parent().unwrap())
+        .await
+        .unwrap();
+    tokio::fs::write(&cached, b"already here").await.unwrap();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("target/test/")
+        .metrics(counts.clone());
+
+    open_options.open_s3("metrics/cached.txt").await.unwrap();
+
+    assert_eq!(*counts.hits.lock().unwrap(), vec!["metrics/cached.txt"]);
+    assert!(counts.misses.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_cache_stats() {
+    let mirror = "target/test-cache-stats/stats-bucket";
+    let _ = tokio::fs::remove_dir_all(mirror).await;
+    tokio::fs::create_dir_all(format!("{}/a/b", mirror))
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/a/1.txt", mirror), b"one")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/a/b/2.txt", mirror), b"two!")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/a/3.txt.part", mirror), b"partial")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/root.txt", mirror), b"root!")
+        .await
+        .unwrap();
+
+    let open_options = OpenOptions::new("stats-bucket".to_string(), None)
+        .await
+        .mount_path("target/test-cache-stats/");
+
+    open_options.open_s3("root.txt").await.unwrap();
+
+    let stats = open_options.cache_stats().await.unwrap();
+
+    assert_eq!(stats.file_count, 3);
+    assert_eq!(stats.total_bytes, 12);
+    assert_eq!((stats.hits, stats.misses), (1, 0));
+    assert_eq!(stats.prefixes["a"].bytes, 7);
+    assert_eq!(stats.prefixes["a"].files, 2);
+    assert_eq!(stats.prefixes[""].bytes, 5);
+}
+
+#[tokio::test]
+async fn test_cached_object_from_index() {
+    let index_dir = "target/test-cache-index/.s3-filesystem";
+    tokio::fs::create_dir_all(index_dir).await.unwrap();
+    tokio::fs::write(
+        format!("{}/index-bucket.index", index_dir),
+        "1700000000\t12\t\"abc123\"\tsome_folder/some file.csv\nnot a record\n",
+    )
+    .await
+    .unwrap();
+
+    let open_options = OpenOptions::new("index-bucket".to_string(), None)
+        .await
+        .mount_path("target/test-cache-index/");
+
+    let cached = open_options
+        .cached_object("some_folder/some file.csv")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(cached.size, 12);
+    assert_eq!(cached.e_tag.as_deref(), Some("\"abc123\""));
+    assert_eq!(
+        cached.cached_at,
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)
+    );
+    assert!(open_options
+        .cached_object("missing.csv")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(open_options.cached_objects().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_cache_index_is_shared_and_escapes_keys() {
+    let mount_path = "target/test-shared-index/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("shared_index");
+    let odd_key = "reports/tab\there/line\nbreak.txt";
+    mock.put_object("shared_index", odd_key, "odd");
+    mock.put_object("shared_index", "reports/plain.txt", "plain");
+
+    let first = OpenOptions::new("shared_index".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    let second = OpenOptions::new("shared_index".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+
+    first.open_s3(odd_key).await.unwrap();
+    second.open_s3("reports/plain.txt").await.unwrap();
+
+    let keys: Vec<String> = first
+        .cached_objects()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|cached| cached.key)
+        .collect();
+    assert_eq!(keys, vec!["reports/plain.txt", odd_key]);
+    assert_eq!(
+        second.cached_object(odd_key).await.unwrap().unwrap().size,
+        3
+    );
+
+    second.purge_cache("reports/tab").await.unwrap();
+    assert!(first.cached_object(odd_key).await.unwrap().is_none());
+    assert!(first
+        .cached_object("reports/plain.txt")
+        .await
+        .unwrap()
+        .is_some());
+}
+
+/// A client pointed at a port nothing listens on, so every request fails to connect.
+fn unreachable_client() -> aws_sdk_s3::Client {
+    let config = aws_sdk_s3::Config::builder()
+        .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+        .region(aws_sdk_s3::config::Region::new("eu-west-2"))
+        .endpoint_url("http://127.0.0.1:1")
+        .force_path_style(true)
+        .credentials_provider(aws_sdk_s3::config::Credentials::new(
+            "access", "secret", None, None, "test",
+        ))
+        .build();
+    aws_sdk_s3::Client::from_conf(config)
+}
+
+#[tokio::test]
+async fn test_offline_open_falls_back_to_cache() {
+    let cached = "target/test-offline/offline-bucket/offline/cached.txt";
+    tokio::fs::create_dir_all("target/test-offline/offline-bucket/offline")
+        .await
+        .unwrap();
+    tokio::fs::write(cached, b"from the cache").await.unwrap();
+
+    let open_options = OpenOptions::new("offline-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-offline/")
+        .cache_policy(CachePolicy::AlwaysDownload);
+
+    let err = open_options
+        .open_s3("offline/cached.txt")
+        .await
+        .unwrap_err();
+    assert!(err.is_unreachable());
+
+    let mut opened = open_options
+        .clone()
+        .offline(true)
+        .open_s3_or_cached("offline/cached.txt")
+        .await
+        .unwrap();
+    assert!(opened.stale);
+
+    let mut contents = String::new();
+    opened.file.read_to_string(&mut contents).await.unwrap();
+    assert_eq!(contents, "from the cache");
+}
+
+#[tokio::test]
+async fn test_read_to_string_from_cache() {
+    tokio::fs::create_dir_all("target/test-read-cached/read-bucket")
+        .await
+        .unwrap();
+    tokio::fs::write(
+        "target/test-read-cached/read-bucket/notes.txt",
+        b"cached notes",
+    )
+    .await
+    .unwrap();
+
+    let open_options = OpenOptions::new("read-bucket".to_string(), None)
+        .await
+        .mount_path("target/test-read-cached/");
+
+    assert_eq!(
+        open_options.read_to_string("notes.txt").await.unwrap(),
+        "cached notes"
+    );
+    assert_eq!(
+        open_options.read("notes.txt").await.unwrap(),
+        b"cached notes"
+    );
+}
+
+#[tokio::test]
+async fn test_mounts_share_mount_path() {
+    for (bucket, contents) in [("first-bucket", "first"), ("second-bucket", "second")] {
+        let folder = format!("target/test-mounts/{}", bucket);
+        tokio::fs::create_dir_all(&folder).await.unwrap();
+        tokio::fs::write(format!("{}/notes.txt", folder), contents)
+            .await
+            .unwrap();
+    }
+
+    let mounts = S3Mounts::new(Some(unreachable_client()))
+        .await
+        .add("first", "first-bucket")
+        .mount_path("target/test-mounts/")
+        .add_with("second", "second-bucket", |options| options.read_only(true));
+
+    assert!(mounts.get("third").is_none());
+    assert_eq!(
+        mounts.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        ["first", "second"]
+    );
+
+    let first = mounts.get("first").unwrap();
+    let second = mounts.get("second").unwrap();
+    assert_eq!(first.read_to_string("notes.txt").await.unwrap(), "first");
+    assert_eq!(second.read_to_string("notes.txt").await.unwrap(), "second");
+    assert!(matches!(
+        second.write_s3("notes.txt", b"rejected").await,
+        Err(s3_filesystem::S3FilesystemError::ReadOnly)
+    ));
+}
+
+#[tokio::test]
+async fn test_overlay_reads_top_layer_first_and_writes_to_one_layer() {
+    let _ = tokio::fs::remove_dir_all("target/test-overlay/").await;
+    let mock = MockS3::new()
+        .with_bucket("overlay_base")
+        .with_bucket("overlay_scratch");
+    mock.put_object("overlay_base", "config.json", "base");
+    mock.put_object("overlay_base", "data.csv", "a,b");
+    mock.put_object("overlay_scratch", "users/alice/config.json", "alice");
+
+    let scratch = OpenOptions::new("overlay_scratch".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-overlay/scratch/")
+        .prefix("users/alice/");
+    let base = OpenOptions::new("overlay_base".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-overlay/base/")
+        .read_only(true);
+    let overlay = S3Overlay::new(scratch).layer(base);
+
+    assert_eq!(
+        overlay.read_to_string("config.json").await.unwrap(),
+        "alice"
+    );
+    assert_eq!(overlay.read_to_string("data.csv").await.unwrap(), "a,b");
+    let layer = overlay.resolve("data.csv").await.unwrap();
+    assert!(std::ptr::eq(layer, &overlay.layers()[1]));
+    let missing = overlay.read_s3("missing.csv").await.unwrap_err();
+    assert!(missing.is_not_found());
+
+    let listed = overlay.walkdir("").await.unwrap();
+    let listed = listed
+        .iter()
+        .map(|entry| (entry.path.to_str().unwrap(), entry.size))
+        .collect::<Vec<_>>();
+    assert_eq!(listed, [("config.json", 5), ("data.csv", 3)]);
+
+    overlay.write_s3("data.csv", b"c,d").await.unwrap();
+    assert_eq!(overlay.read_to_string("data.csv").await.unwrap(), "c,d");
+    assert_eq!(mock.keys("overlay_base"), ["config.json", "data.csv"]);
+    assert_eq!(
+        mock.keys("overlay_scratch"),
+        ["users/alice/config.json", "users/alice/data.csv"]
+    );
+
+    // Deleting a file the base dataset also holds leaves a whiteout hiding the base copy.
+    overlay.delete_many(["data.csv"]).await.unwrap();
+    assert!(overlay
+        .read_s3("data.csv")
+        .await
+        .unwrap_err()
+        .is_not_found());
+    assert!(overlay
+        .resolve("data.csv")
+        .await
+        .unwrap_err()
+        .is_not_found());
+    assert_eq!(
+        mock.keys("overlay_scratch"),
+        ["users/alice/.wh.data.csv", "users/alice/config.json"]
+    );
+    let listed = overlay.walkdir("").await.unwrap();
+    let listed = listed
+        .iter()
+        .map(|entry| entry.path.to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(listed, ["config.json"]);
+
+    // Writing the file again removes the whiteout.
+    overlay.write_s3("data.csv", b"g,h").await.unwrap();
+    assert_eq!(overlay.read_to_string("data.csv").await.unwrap(), "g,h");
+    assert_eq!(
+        mock.keys("overlay_scratch"),
+        ["users/alice/config.json", "users/alice/data.csv"]
+    );
+
+    assert!(matches!(
+        overlay.clone().write_to(2),
+        Err(S3FilesystemError::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput
+    ));
+    let writes_to_base = overlay.clone().write_to(1).unwrap();
+    assert!(matches!(
+        writes_to_base.write_s3("new.csv", b"e,f").await,
+        Err(S3FilesystemError::ReadOnly)
+    ));
+}
+
+#[derive(Default)]
+struct Operations(Mutex<Vec<String>>);
+
+impl MetricsSink for Operations {
+    fn request(&self, operation: &str, _latency: Duration, _succeeded: bool) {
+        self.0.lock().unwrap().push(operation.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_overlay_write_only_deletes_an_existing_whiteout() {
+    let _ = tokio::fs::remove_dir_all("target/test-overlay-whiteout/").await;
+    let mock = MockS3::new()
+        .with_bucket("whiteout_base")
+        .with_bucket("whiteout_scratch");
+    mock.put_object("whiteout_base", "data.csv", "a,b");
+    let operations = Arc::new(Operations::default());
+
+    let scratch = OpenOptions::new("whiteout_scratch".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-overlay-whiteout/scratch/")
+        .trash(".trash/")
+        .metrics(operations.clone());
+    let base = OpenOptions::new("whiteout_base".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-overlay-whiteout/base/")
+        .read_only(true);
+    let overlay = S3Overlay::new(scratch).layer(base);
+
+    overlay.write_s3("new.csv", b"c,d").await.unwrap();
+    overlay.write_s3("data.csv", b"e,f").await.unwrap();
+    assert!(!operations
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|operation| operation == "DeleteObjects"));
+    assert_eq!(mock.keys("whiteout_scratch"), ["data.csv", "new.csv"]);
+
+    overlay.delete_many(["data.csv"]).await.unwrap();
+    operations.0.lock().unwrap().clear();
+    overlay.write_s3("data.csv", b"g,h").await.unwrap();
+    let deletes = operations
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|operation| *operation == "DeleteObjects")
+        .count();
+    assert_eq!(deletes, 1);
+    assert!(!mock
+        .keys("whiteout_scratch")
+        .contains(&".wh.data.csv".to_string()));
+    assert_eq!(overlay.read_to_string("data.csv").await.unwrap(), "g,h");
+}
+
+/// `data/b%2Cc.csv` (GLACIER) and `data/old.csv` rows of an S3 Inventory CSV report, gzipped.
+const GZIPPED_INVENTORY_REPORT: &[u8] = b"\
+\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x53\xca\xcc\x2b\x4b\xcd\x2b\xc9\x2f\xaa\x8c\x2f\xce\x2f\x2d\
+\x4a\x4e\x55\xd2\x51\x4a\x49\x2c\x49\xd4\x4f\x52\x35\x72\x4e\xd6\x4b\x2e\x2e\x03\x0a\x98\x03\xb1\x91\
+\x81\x91\x89\xae\x81\xa1\xae\x81\x51\x88\x81\x81\x15\x18\xe9\x19\x18\x18\x44\x01\xa5\x52\x8d\x80\x84\
+\xbb\x8f\xa3\xb3\xa7\x6b\x90\x12\x97\x12\x2e\x13\xf3\x73\x52\xa0\xe6\x19\x22\x9b\x67\x88\x69\x9e\x31\
+\x90\x08\x0e\x71\xf4\x73\x71\x0c\x72\x51\xe2\x02\x00\xce\xe9\x97\x36\xa3\x00\x00\x00";
+
+/// A mock holding an inventory of `inventory_source`, delivered to `inventory_reports` as one plain and
+/// one gzipped CSV report.
+fn inventory_mock(source_bucket: &str, file_format: &str) -> MockS3 {
+    let mock = MockS3::new()
+        .with_bucket("inventory_source")
+        .with_bucket("inventory_reports");
+    let manifest = format!(
+        r#"{{
+            "sourceBucket": "{}",
+            "fileFormat": "{}",
+            "fileSchema": "Bucket, Key, Size, LastModifiedDate, ETag, StorageClass",
+            "files": [{{"key": "daily/data/1.csv"}}, {{"key": "daily/data/2.csv.gz"}}]
+        }}"#,
+        source_bucket, file_format
+    );
+    mock.put_object("inventory_reports", "daily/manifest.json", manifest);
+    mock.put_object(
+        "inventory_reports",
+        "daily/data/1.csv",
+        "\"inventory_source\",\"data/a.csv\",\"3\",\"2024-01-01T00:00:00.000Z\",\"e1\",\"STANDARD\"\r\n\
+         \"inventory_source\",\"other/x.csv\",\"5\",\"2024-01-01T00:00:00.000Z\",\"e4\",\"STANDARD\"\r\n\
+         \"inventory_source\",\"data/deep/d.csv\",\"2\",\"2024-01-01T00:00:00.000Z\",\"e5\",\"STANDARD\"",
+    );
+    mock.put_object(
+        "inventory_reports",
+        "daily/data/2.csv.gz",
+        GZIPPED_INVENTORY_REPORT,
+    );
+    mock
+}
+
+#[tokio::test]
+async fn test_walk_from_inventory_reads_plain_and_gzipped_reports() {
+    let mock = inventory_mock("inventory_source", "CSV");
+    let open_options = OpenOptions::new("inventory_source".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-inventory/");
+
+    let entries = open_options
+        .walk("data/")
+        .from_inventory("inventory_reports", "daily/manifest.json")
+        .list()
+        .await
+        .unwrap();
+    let listed = entries
+        .iter()
+        .map(|entry| (entry.path.to_str().unwrap(), entry.size))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        listed,
+        [
+            ("data/a.csv", 3),
+            ("data/b,c.csv", 7),
+            ("data/deep/d.csv", 2),
+            ("data/old.csv", 1)
+        ]
+    );
+    assert_eq!(entries[0].e_tag.as_deref(), Some("\"e1\""));
+    assert_eq!(entries[1].storage_class.as_deref(), Some("GLACIER"));
+
+    let shallow = open_options
+        .walk("data/")
+        .from_inventory("inventory_reports", "daily/manifest.json")
+        .max_depth(1)
+        .start_after("data/a.csv")
+        .list()
+        .await
+        .unwrap();
+    let listed = shallow
+        .iter()
+        .map(|entry| entry.path.to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(listed, ["data/b,c.csv", "data/old.csv"]);
+}
+
+#[tokio::test]
+async fn test_inventory_reports_stream_each_report_file() {
+    let mock = inventory_mock("inventory_source", "CSV");
+    let open_options = OpenOptions::new("inventory_source".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-inventory-reports/");
+
+    let reports = open_options
+        .walk("data/")
+        .from_inventory("inventory_reports", "daily/manifest.json")
+        .inventory_reports()
+        .await
+        .unwrap();
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| report.key())
+            .collect::<Vec<_>>(),
+        ["daily/data/1.csv", "daily/data/2.csv.gz"]
+    );
+
+    let mut listed = Vec::new();
+    for report in &reports {
+        let mut entries = report.entries();
+        while let Some(entry) = entries.next().await {
+            listed.push(entry.unwrap().path);
+        }
+    }
+    assert_eq!(
+        listed,
+        [
+            PathBuf::from("data/a.csv"),
+            PathBuf::from("data/deep/d.csv"),
+            PathBuf::from("data/b,c.csv"),
+            PathBuf::from("data/old.csv")
+        ]
+    );
+
+    // Dropping a stream part way through stops reading the report.
+    let first = reports[0].entries().next().await.unwrap().unwrap();
+    assert_eq!(first.path, PathBuf::from("data/a.csv"));
+
+    let missing = OpenOptions::new("inventory_source".to_string(), Some(mock.client()))
+        .await
+        .walk("data/")
+        .inventory_reports()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        missing,
+        S3FilesystemError::Io(e) if e.kind() == std::io::ErrorKind::InvalidInput
+    ));
+}
+
+#[tokio::test]
+async fn test_inventory_checks_the_manifest() {
+    let mock = inventory_mock("another_bucket", "CSV");
+    let open_options = OpenOptions::new("inventory_source".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-inventory-manifest/");
+    let mismatched = open_options
+        .walk("")
+        .from_inventory("inventory_reports", "daily/manifest.json")
+        .list()
+        .await
+        .unwrap_err();
+    assert_eq!(mismatched.io_error_kind(), std::io::ErrorKind::InvalidData);
+
+    // An access point is not named after the bucket behind it.
+    let access_point = "arn:aws:s3:us-east-1:123456789012:accesspoint/inventory";
+    let mock = inventory_mock("inventory_source", "CSV");
+    let through_access_point = OpenOptions::new(access_point.to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-inventory-manifest/");
+    let entries = through_access_point
+        .walk("")
+        .from_inventory("inventory_reports", "daily/manifest.json")
+        .list()
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 5);
+
+    let mock = inventory_mock("inventory_source", "Parquet");
+    let open_options = OpenOptions::new("inventory_source".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-inventory-manifest/");
+    let parquet = open_options
+        .walk("")
+        .from_inventory("inventory_reports", "daily/manifest.json")
+        .list()
+        .await
+        .unwrap_err();
+    assert_eq!(parquet.io_error_kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[tokio::test]
+async fn test_partial_download_kept_for_resume() {
+    let folder = "target/test-resume/resume-bucket";
+    tokio::fs::create_dir_all(folder).await.unwrap();
+    tokio::fs::write(format!("{}/big.bin.part", folder), b"first half")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/big.bin.etag.part", folder), "\"abc\"")
+        .await
+        .unwrap();
+
+    let open_options = OpenOptions::new("resume-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-resume/");
+
+    let err = open_options.open_s3("big.bin").await.unwrap_err();
+    assert!(err.is_unreachable());
+
+    // The bytes already received are kept so the next attempt can continue from them.
+    assert_eq!(
+        tokio::fs::read(format!("{}/big.bin.part", folder))
+            .await
+            .unwrap(),
+        b"first half"
+    );
+    assert!(!std::path::Path::new(&format!("{}/big.bin", folder)).exists());
+}
+
+#[tokio::test]
+async fn test_open_s3_to_reuses_existing_destination() {
+    tokio::fs::create_dir_all("target/test-open-to")
+        .await
+        .unwrap();
+    tokio::fs::write("target/test-open-to/model.bin", b"already placed")
+        .await
+        .unwrap();
+
+    let open_options = OpenOptions::new("open-to-bucket".to_string(), None)
+        .await
+        .mount_path("target/test-open-to-mirror/");
+
+    let mut file = open_options
+        .open_s3_to("models/latest.bin", "target/test-open-to/model.bin")
+        .await
+        .unwrap();
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await.unwrap();
+    assert_eq!(contents, "already placed");
+    assert!(!std::path::Path::new("target/test-open-to-mirror").exists());
+}
+
+#[tokio::test]
+async fn test_windows_invalid_characters_are_escaped() {
+    tokio::fs::create_dir_all("target/test-escape/escape-bucket/2024%3A01")
+        .await
+        .unwrap();
+    tokio::fs::write(
+        "target/test-escape/escape-bucket/2024%3A01/what%3F%2A.txt",
+        b"escaped",
+    )
+    .await
+    .unwrap();
+
+    let open_options = OpenOptions::new("escape-bucket".to_string(), None)
+        .await
+        .mount_path("target/test-escape/");
+
+    assert_eq!(
+        open_options
+            .read_to_string("2024:01/what?*.txt")
+            .await
+            .unwrap(),
+        "escaped"
+    );
+
+    let stats = open_options.cache_stats().await.unwrap();
+    assert!(stats.prefixes.contains_key("2024:01"));
+}
+
+#[tokio::test]
+async fn test_parallel_download_offline_falls_back_to_cache() {
+    tokio::fs::create_dir_all("target/test-parallel/parallel-bucket/large")
+        .await
+        .unwrap();
+    tokio::fs::write(
+        "target/test-parallel/parallel-bucket/large/cached.bin",
+        b"from the cache",
+    )
+    .await
+    .unwrap();
+
+    let open_options = OpenOptions::new("parallel-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-parallel/")
+        .cache_policy(CachePolicy::AlwaysDownload)
+        .parallel_download(4);
+
+    let err = open_options.open_s3("large/cached.bin").await.unwrap_err();
+    assert!(err.is_unreachable());
+    assert_eq!(err.context().unwrap().operation(), "GetObject");
+
+    let opened = open_options
+        .offline(true)
+        .open_s3_or_cached("large/cached.bin")
+        .await
+        .unwrap();
+    assert!(opened.stale);
+}
+
+#[tokio::test]
+async fn test_verify_error_has_context() {
+    let open_options = OpenOptions::new("verify-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-verify/");
+
+    let err = open_options.verify("cached/").await.unwrap_err();
+    assert!(err.is_unreachable());
+
+    let context = err.context().unwrap();
+    assert_eq!(context.operation(), "ListObjectsV2");
+    assert_eq!(context.key(), Some("cached/"));
+}
+
+#[tokio::test]
+async fn test_purge_cache_prefix() {
+    let mirror = "target/test-purge/purge-bucket";
+    let _ = tokio::fs::remove_dir_all(mirror).await;
+    tokio::fs::create_dir_all(format!("{}/old/nested", mirror))
+        .await
+        .unwrap();
+    tokio::fs::create_dir_all(format!("{}/keep", mirror))
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/old/1.txt", mirror), b"one")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/old/nested/2.txt", mirror), b"two!")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/old/3.txt.part", mirror), b"partial")
+        .await
+        .unwrap();
+    tokio::fs::write(format!("{}/keep/4.txt", mirror), b"kept")
+        .await
+        .unwrap();
+
+    let open_options = OpenOptions::new("purge-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-purge/")
+        .read_only(true);
+
+    let purged = open_options.purge_cache("old/").await.unwrap();
+    assert_eq!(purged.files, 3);
+    assert_eq!(purged.bytes, 14);
+
+    assert!(!std::path::Path::new(&format!("{}/old", mirror)).exists());
+    let stats = open_options.cache_stats().await.unwrap();
+    assert_eq!(stats.file_count, 1);
+    assert_eq!(stats.total_bytes, 4);
+}
+
+#[tokio::test]
+async fn test_hashed_layout_opens_flat_file() {
+    use sha2::{Digest, Sha256};
+
+    let key = "deeply/nested/key:with?odd*chars.txt";
+    let name: String = Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    tokio::fs::create_dir_all("target/test-hashed/hashed-bucket")
+        .await
+        .unwrap();
+    tokio::fs::write(
+        format!("target/test-hashed/hashed-bucket/{}", name),
+        b"flat file",
+    )
+    .await
+    .unwrap();
+
+    let open_options = OpenOptions::new("hashed-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-hashed/")
+        .cache_layout(CacheLayout::Hashed);
+
+    let contents = open_options.read_to_string(key).await.unwrap();
+    assert_eq!(contents, "flat file");
+}
+
+#[tokio::test]
+async fn test_mirror_layout_stores_overlong_keys_hashed() {
+    let mount_path = "target/test-long-keys/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let key = format!("logs/{}.txt", "a".repeat(300));
+    let mock = MockS3::new().with_bucket("long_bucket");
+    mock.put_object("long_bucket", &key, "too long to mirror");
+
+    let open_options = OpenOptions::new("long_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    let contents = open_options.read_to_string(&key).await.unwrap();
+    assert_eq!(contents, "too long to mirror");
+
+    let mut long_keys = tokio::fs::read_dir("target/test-long-keys/long_bucket/%long")
+        .await
+        .unwrap();
+    assert!(long_keys.next_entry().await.unwrap().is_some());
+
+    // The key is still recognised in the mirror through the cache index.
+    let stats = open_options.cache_stats().await.unwrap();
+    assert_eq!(stats.prefixes["logs"].files, 1);
+}
+
+#[tokio::test]
+async fn test_block_cache_keeps_only_blocks_read() {
+    let mount_path = "target/test-block-cache/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("block_bucket");
+    mock.put_object("block_bucket", "huge.bin", "0123456789abcdef");
+
+    let open_options = OpenOptions::new("block_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .block_cache(4);
+    let read_at = |start: u64| {
+        let open_options = open_options.clone();
+        async move {
+            let mut file = open_options.open_s3_lazy("huge.bin").await.unwrap();
+            file.seek(std::io::SeekFrom::Start(start)).await.unwrap();
+            let mut read = [0; 3];
+            file.read_exact(&mut read).await.unwrap();
+            String::from_utf8(read.to_vec()).unwrap()
+        }
+    };
+    assert_eq!(read_at(5).await, "567");
+
+    // Only the second block was read, so only it is kept.
+    let mut folders = tokio::fs::read_dir("target/test-block-cache/block_bucket/%blocks")
+        .await
+        .unwrap();
+    let folder = folders.next_entry().await.unwrap().unwrap().path();
+    let mut blocks = Vec::new();
+    let mut entries = tokio::fs::read_dir(&folder).await.unwrap();
+    while let Some(entry) = entries.next_entry().await.unwrap() {
+        blocks.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    blocks.sort();
+    assert_eq!(blocks, ["1", "object"]);
+
+    // Replacing the object throws away the blocks of the old version.
+    mock.put_object("block_bucket", "huge.bin", "ghijklmnopqrstuv");
+    assert_eq!(read_at(5).await, "lmn");
+
+    let purged = open_options.purge_cache("").await.unwrap();
+    assert_eq!(purged.files, 1);
+    assert_eq!(purged.bytes, 4);
+}
+
+#[derive(Default)]
+struct CountGets(Mutex<usize>);
+
+impl RequestHook for CountGets {
+    fn before_send(&self, request: &mut HttpRequest) {
+        if request.method() == "GET" && request.headers().get("range").is_some() {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_sparse_cache_serves_repeated_ranges_from_disk() {
+    let mount_path = "target/test-sparse-cache/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("sparse_bucket");
+    mock.put_object("sparse_bucket", "huge.bin", "0123456789abcdefghij");
+    let gets = Arc::new(CountGets::default());
+
+    let open_options = OpenOptions::new("sparse_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .sparse_cache(true)
+        .request_hook(gets.clone());
+    let read = |start: u64, length: usize| {
+        let open_options = open_options.clone();
+        async move {
+            let mut file = open_options
+                .open_s3_lazy("huge.bin")
+                .await
+                .unwrap()
+                .buffer_size(4);
+            file.seek(std::io::SeekFrom::Start(start)).await.unwrap();
+            let mut read = vec![0; length];
+            file.read_exact(&mut read).await.unwrap();
+            String::from_utf8(read).unwrap()
+        }
+    };
+
+    assert_eq!(read(2, 4).await, "2345");
+    assert_eq!(*gets.0.lock().unwrap(), 1);
+
+    // The same range again comes from disk, and a wider one only fetches what is missing.
+    assert_eq!(read(2, 4).await, "2345");
+    assert_eq!(*gets.0.lock().unwrap(), 1);
+    assert_eq!(read(0, 8).await, "01234567");
+    assert_eq!(*gets.0.lock().unwrap(), 3);
+
+    // Only the ranges fetched, 0..10 with a 4 byte buffer, are held, never the whole object.
+    let purged = open_options.purge_cache("").await.unwrap();
+    assert_eq!(purged.files, 1);
+    assert_eq!(purged.bytes, 10);
+}
+
+/// A self-signed CA certificate, standing in for a company's private root.
+const PRIVATE_CA: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBlzCCAT2gAwIBAgIUMXTygpkl64k8XkDQNh0RynSf/oAwCgYIKoZIzj0EAwIw
+IDEeMBwGA1UEAwwVczMtZmlsZXN5c3RlbSB0ZXN0IENBMCAXDTI2MTAxNTA5MTIy
+OVoYDzIxMjYwOTIxMDkxMjI5WjAgMR4wHAYDVQQDDBVzMy1maWxlc3lzdGVtIHRl
+c3QgQ0EwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAS/1F9mGjSWy1eq/5KLoUnH
+52YrLIdb2TR23TzK5xV5UUUR2HYHRc03YiwSYD39/Mv4veRpjHTzFcolOcpcOsFN
+o1MwUTAdBgNVHQ4EFgQUHr33Bp5zqlFbxPr8NUvZNyMzBtwwHwYDVR0jBBgwFoAU
+Hr33Bp5zqlFbxPr8NUvZNyMzBtwwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQD
+AgNIADBFAiEAuNi4RaliM1s2bcCdqvpwAXmJ+JD1E4lvYgw/G0pKqwECIClGc/9V
+Pj0z0ECH1trIIgbDCKRhYukEnvqe+febzSvX
+-----END CERTIFICATE-----
+";
+
+#[tokio::test]
+async fn test_tls_config_trusts_private_ca() {
+    let tls = TlsConfig::new().add_pem(PRIVATE_CA.as_bytes()).unwrap();
+    assert_eq!(tls.len(), 1);
+
+    let err = TlsConfig::new().add_pem(b"not a certificate").unwrap_err();
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    // The rebuilt client still sends requests, here to a plain http endpoint with nothing listening.
+    let open_options = OpenOptions::new("tls-bucket".to_string(), Some(unreachable_client()))
+        .await
+        .mount_path("target/test-tls/")
+        .tls(tls);
+    let err = open_options.read_s3("any.txt").await.unwrap_err();
+    assert!(err.is_unreachable());
+}
+
+#[tokio::test]
+async fn test_new_checked_reports_unreachable_bucket() {
+    let err = OpenOptions::new_checked(
+        "checked-bucket".to_string(),
+        Some(unreachable_client()),
+        true,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.is_unreachable());
+
+    let context = err.context().unwrap();
+    assert_eq!(context.operation(), "HeadBucket");
+    assert_eq!(context.bucket(), "checked-bucket");
+}
+
+#[tokio::test]
+async fn test_builder_applies_settings() {
+    tokio::fs::create_dir_all("target/test-builder/builder-bucket")
+        .await
+        .unwrap();
+    tokio::fs::write("target/test-builder/builder-bucket/notes.txt", b"built")
+        .await
+        .unwrap();
+
+    let builder = OpenOptionsBuilder::new("builder-bucket")
+        .client(unreachable_client())
+        .mount_path("target/test-builder/")
+        .read_only(true);
+    let open_options = builder.connect().await;
+
+    assert_eq!(
+        open_options.read_to_string("notes.txt").await.unwrap(),
+        "built"
+    );
+    assert!(matches!(
+        open_options.write_s3("notes.txt", b"changed").await,
+        Err(S3FilesystemError::ReadOnly)
+    ));
+}
+
+#[tokio::test]
+async fn test_walkdir_to_json_round_trips() {
+    let mock = MockS3::new().with_bucket("listing_bucket");
+    mock.put_object("listing_bucket", "data/a.csv", "1,2");
+    mock.put_object("listing_bucket", "data/b.csv", "3,4,5");
+
+    let open_options = OpenOptions::new("listing_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-walkdir-json/");
+
+    let mut json = Vec::new();
+    let written = open_options
+        .walkdir_to_json("data/", &mut json)
+        .await
+        .unwrap();
+
+    let listing: Vec<DirEntry> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(written, 2);
+    assert_eq!(
+        listing
+            .iter()
+            .map(|entry| (entry.path.to_str().unwrap(), entry.size))
+            .collect::<Vec<_>>(),
+        vec![("data/a.csv", 3), ("data/b.csv", 5)]
+    );
+    assert!(listing.iter().all(|entry| entry.e_tag.is_some()));
+}
+
+#[tokio::test]
+async fn test_read_json_and_csv_deserialize_objects() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        replicas: u32,
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Reading {
+        station: String,
+        temperature: f64,
+        note: Option<String>,
+    }
+
+    let mock = MockS3::new().with_bucket("typed_bucket");
+    mock.put_object(
+        "typed_bucket",
+        "config.json",
+        r#"{"name": "ingest", "replicas": 3}"#,
+    );
+    mock.put_object(
+        "typed_bucket",
+        "readings.csv",
+        "station,temperature,note\r\nHeathrow,12.5,\r\n\"Kew, Gardens\",-1,\"said \"\"cold\"\"\"\r\n",
+    );
+    mock.put_object(
+        "typed_bucket",
+        "broken.csv",
+        "station,temperature\nHeathrow,warm\n",
+    );
+
+    let open_options = OpenOptions::new("typed_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-read-typed/");
+
+    let config: Config = open_options.read_json("config.json").await.unwrap();
+    assert_eq!(
+        config,
+        Config {
+            name: "ingest".to_string(),
+            replicas: 3
+        }
+    );
+
+    let readings: Vec<Reading> = open_options.read_csv("readings.csv").await.unwrap();
+    assert_eq!(
+        readings,
+        vec![
+            Reading {
+                station: "Heathrow".to_string(),
+                temperature: 12.5,
+                note: None
+            },
+            Reading {
+                station: "Kew, Gardens".to_string(),
+                temperature: -1.0,
+                note: Some("said \"cold\"".to_string())
+            },
+        ]
+    );
+
+    let rows: Vec<(String, String, String)> = open_options.read_csv("readings.csv").await.unwrap();
+    assert_eq!(rows[0].0, "Heathrow");
+
+    let error = open_options
+        .read_csv::<Reading, _>("broken.csv")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("row 2"));
+    assert!(open_options
+        .read_json::<Config, _>("readings.csv")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_walkdir_page_resumes_from_token() {
+    let mock = MockS3::new().with_bucket("paged_bucket");
+    for index in 0..5 {
+        mock.put_object("paged_bucket", format!("logs/{}.txt", index), "log");
+    }
+    mock.put_object("paged_bucket", "other/skip.txt", "skip");
+
+    let open_options = OpenOptions::new("paged_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-walkdir-page/");
+
+    let mut keys = Vec::new();
+    let mut pages = 0;
+    let mut continuation = None;
+    loop {
+        let page = open_options
+            .walkdir_page("logs/", continuation, 2)
+            .await
+            .unwrap();
+        pages += 1;
+        assert!(page.entries.len() <= 2);
+        keys.extend(page.entries.into_iter().map(|entry| entry.path));
+
+        continuation = page.continuation_token;
+        if continuation.is_none() {
+            break;
+        }
+    }
+
+    assert_eq!(pages, 3);
+    assert_eq!(
+        keys,
+        (0..5)
+            .map(|index| std::path::PathBuf::from(format!("logs/{}.txt", index)))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_diff_reports_local_remote_and_changed() {
+    let mount_path = "target/test-diff/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("diff_bucket");
+    mock.put_object("diff_bucket", "data/same.txt", "same");
+    mock.put_object("diff_bucket", "data/edited.txt", "before");
+    mock.put_object("diff_bucket", "data/remote.txt", "remote");
+
+    let open_options = OpenOptions::new("diff_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+
+    open_options.read("data/same.txt").await.unwrap();
+    open_options.read("data/edited.txt").await.unwrap();
+    mock.put_object("diff_bucket", "data/edited.txt", "after!");
+    tokio::fs::write("target/test-diff/diff_bucket/data/local.txt", "local")
+        .await
+        .unwrap();
+
+    let report = open_options.diff("data/").await.unwrap();
+
+    assert!(!report.is_current());
+    assert_eq!(report.local_only, vec![PathBuf::from("data/local.txt")]);
+    assert_eq!(report.remote_only, vec![PathBuf::from("data/remote.txt")]);
+    assert_eq!(report.unchanged, vec![PathBuf::from("data/same.txt")]);
+    assert_eq!(report.changed.len(), 1);
+    assert_eq!(report.changed[0].path, PathBuf::from("data/edited.txt"));
+    assert_ne!(
+        report.changed[0].cached_e_tag,
+        report.changed[0].remote_e_tag
+    );
+}
+
+#[tokio::test]
+async fn test_operation_timeout_stops_hung_request() {
+    // Accepts connections but never answers them.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+
+    let config = aws_sdk_s3::Config::builder()
+        .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+        .region(aws_sdk_s3::config::Region::new("eu-west-2"))
+        .endpoint_url(format!("http://{}", address))
+        .force_path_style(true)
+        .credentials_provider(aws_sdk_s3::config::Credentials::new(
+            "access", "secret", None, None, "test",
+        ))
+        .build();
+
+    let open_options = OpenOptions::new(
+        "hung-bucket".to_string(),
+        Some(aws_sdk_s3::Client::from_conf(config)),
+    )
+    .await
+    .mount_path("target/test-timeout/")
+    .operation_timeout(std::time::Duration::from_millis(200));
+
+    let started = std::time::Instant::now();
+    let err = open_options.read_s3("hung/file.txt").await.unwrap_err();
+
+    assert!(err.is_unreachable(), "{:?}", err);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_cancelled_token_stops_reads_and_listings() {
+    let mount_path = "target/test-cancel/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("cancel_bucket");
+    mock.put_object("cancel_bucket", "data/file.txt", "contents");
+
+    let token = CancellationToken::new();
+    let open_options = OpenOptions::new("cancel_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cancel_on(token.clone());
+
+    assert_eq!(
+        open_options.read_to_string("data/file.txt").await.unwrap(),
+        "contents"
+    );
+
+    token.cancel();
+    open_options.purge_cache("data/").await.unwrap();
+
+    let err = open_options.open_s3("data/file.txt").await.unwrap_err();
+    assert!(err.is_cancelled(), "{}", err);
+    assert!(open_options
+        .walkdir("data/")
+        .await
+        .unwrap_err()
+        .is_cancelled());
+    assert!(open_options
+        .read_s3("data/file.txt")
+        .await
+        .unwrap_err()
+        .is_cancelled());
+
+    let folder = PathBuf::from(mount_path).join("cancel_bucket/data");
+    let leftovers = std::fs::read_dir(&folder).map_or(0, |entries| entries.count());
+    assert_eq!(
+        leftovers,
+        0,
+        "partial download left in {}",
+        folder.display()
+    );
+}
+
+#[tokio::test]
+async fn test_prefetch_downloads_following_objects() {
+    let mount_path = "target/test-prefetch/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("prefetch_bucket");
+    for shard in 0..5 {
+        mock.put_object(
+            "prefetch_bucket",
+            format!("shards/{}.bin", shard),
+            format!("shard {}", shard),
+        );
+    }
+    mock.put_object("prefetch_bucket", "shards/nested/0.bin", "nested");
+
+    let open_options = OpenOptions::new("prefetch_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .prefetch(2);
+
+    assert_eq!(
+        open_options.read_to_string("shards/0.bin").await.unwrap(),
+        "shard 0"
+    );
+
+    let folder = PathBuf::from(mount_path).join("prefetch_bucket/shards");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while !(folder.join("1.bin").exists() && folder.join("2.bin").exists()) {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "shards were not prefetched"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    assert!(!folder.join("3.bin").exists());
+    assert!(!folder.join("nested").exists());
+    assert_eq!(
+        open_options.read_to_string("shards/2.bin").await.unwrap(),
+        "shard 2"
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_downloads_share_one_copy() {
+    let mount_path = "target/test-cache-lock/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("lock_bucket");
+    let data: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    mock.put_object("lock_bucket", "shared/data.bin", data.clone());
+
+    // Separately constructed OpenOptions stand in for separate processes sharing the mount path.
+    let mut readers = Vec::new();
+    for _ in 0..8 {
+        let open_options = OpenOptions::new("lock_bucket".to_string(), Some(mock.client()))
+            .await
+            .mount_path(mount_path);
+        readers.push(tokio::spawn(async move {
+            open_options.read("shared/data.bin").await.unwrap()
+        }));
+    }
+    for reader in readers {
+        assert!(reader.await.unwrap() == data);
+    }
+
+    let folder = PathBuf::from(mount_path).join("lock_bucket/shared");
+    let files: Vec<_> = std::fs::read_dir(folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, vec!["data.bin"]);
+}
+
+#[tokio::test]
+async fn test_transfer_acceleration_uses_accelerate_endpoint() {
+    let mock = MockS3::new().with_bucket("accelerate_bucket");
+    mock.put_object("accelerate_bucket", "data.txt", "fast");
+
+    let open_options = OpenOptions::new("accelerate_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-accelerate/")
+        .cache_policy(CachePolicy::AlwaysDownload)
+        .transfer_acceleration(true);
+
+    // The mock's custom endpoint cannot be accelerated, so the request is refused before it is sent.
+    let err = open_options.read_to_string("data.txt").await.unwrap_err();
+    assert!(format!("{:?}", err).contains("S3 Accelerate"));
+
+    let open_options = open_options.transfer_acceleration(false);
+    assert_eq!(
+        open_options.read_to_string("data.txt").await.unwrap(),
+        "fast"
+    );
+}
+
+#[derive(Default)]
+struct PinToStaleETag {
+    statuses: Mutex<Vec<u16>>,
+}
+
+impl RequestHook for PinToStaleETag {
+    fn before_send(&self, request: &mut HttpRequest) {
+        if request.method() == "GET" {
+            request.headers_mut().insert("if-match", "\"stale\"");
+        }
+    }
+
+    fn after_response(&self, response: &HttpResponse) {
+        self.statuses
+            .lock()
+            .unwrap()
+            .push(response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn test_request_hook_changes_requests_and_sees_responses() {
+    let mock = MockS3::new().with_bucket("hook_bucket");
+    mock.put_object("hook_bucket", "data.txt", "hooked");
+
+    let hook = Arc::new(PinToStaleETag::default());
+    let open_options = OpenOptions::new("hook_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-request-hook/")
+        .cache_policy(CachePolicy::AlwaysDownload)
+        .request_hook(hook.clone());
+
+    // The header added by the hook reaches S3, which refuses the stale ETag.
+    let err = open_options.read_to_string("data.txt").await.unwrap_err();
+    assert_eq!(err.status(), Some(412));
+    assert!(hook.statuses.lock().unwrap().contains(&412));
+}
+
+#[tokio::test]
+async fn test_download_archive_tar_and_zip() {
+    let mock = MockS3::new().with_bucket("archive_bucket");
+    let long_name = format!("export/{}.csv", "n".repeat(120));
+    mock.put_object("archive_bucket", "export/a.csv", "a,b");
+    mock.put_object("archive_bucket", &long_name, "long");
+    mock.put_object("archive_bucket", "other/c.csv", "not exported");
+
+    let open_options = OpenOptions::new("archive_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-archive/");
+
+    let mut tar = Vec::new();
+    let archived = open_options
+        .download_archive("export/", &mut tar, ArchiveFormat::Tar)
+        .await
+        .unwrap();
+    assert_eq!(archived, 2);
+    assert_eq!(tar.len() % 512, 0);
+    assert_eq!(&tar[..7], b"export/");
+    assert_eq!(&tar[512..515], b"a,b");
+    // The long key does not fit in a ustar header, so a pax header names it.
+    assert_eq!(tar[1024 + 156], b'x');
+    assert!(tar
+        .windows(long_name.len())
+        .any(|window| window == long_name.as_bytes()));
+    assert!(tar.ends_with(&[0; 1024]));
+
+    let mut zip = Vec::new();
+    open_options
+        .download_archive("export/", &mut zip, ArchiveFormat::Zip)
+        .await
+        .unwrap();
+    assert_eq!(&zip[..4], b"PK\x03\x04");
+    assert_eq!(&zip[30..42], b"export/a.csv");
+    assert_eq!(&zip[42..45], b"a,b");
+    // The CRC-32 of "a,b" follows in the data descriptor.
+    assert_eq!(&zip[45..53], b"PK\x07\x08\xdf\x13\xd9\x2c");
+    let end = &zip[zip.len() - 22..];
+    assert_eq!(&end[..4], b"PK\x05\x06");
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+
+    assert!(!PathBuf::from("target/test-archive/archive_bucket").exists());
+}
+
+#[tokio::test]
+async fn test_download_prefix_stream_yields_every_object() {
+    let mock = MockS3::new().with_bucket("stream_bucket");
+    for i in 0..12 {
+        mock.put_object("stream_bucket", format!("data/{:02}.txt", i), i.to_string());
+    }
+    mock.put_object("stream_bucket", "data/folder/", "");
+    mock.put_object("stream_bucket", "elsewhere.txt", "skipped");
+
+    let open_options = OpenOptions::new("stream_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-prefix-stream/")
+        .cache_policy(CachePolicy::AlwaysDownload);
+
+    let mut files = open_options.download_prefix_stream("data/", 4);
+    let mut downloaded = Vec::new();
+    while let Some(result) = files.next().await {
+        let (entry, mut file) = result.unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await.unwrap();
+        downloaded.push((entry.path, contents));
+    }
+
+    downloaded.sort();
+    let expected: Vec<_> = (0..12)
+        .map(|i| (PathBuf::from(format!("data/{:02}.txt", i)), i.to_string()))
+        .collect();
+    assert_eq!(downloaded, expected);
+}
+
+struct TagOwner;
+
+impl RequestHook for TagOwner {
+    fn before_send(&self, request: &mut HttpRequest) {
+        if request.method() == "PUT" {
+            request.headers_mut().insert("x-amz-meta-owner", "ingest");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dir_entry_metadata_fetches_head() {
+    let mock = MockS3::new().with_bucket("metadata_bucket");
+
+    let open_options = OpenOptions::new("metadata_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-entry-metadata/")
+        .upload_checksum(ChecksumAlgorithm::Crc32c)
+        .request_hook(Arc::new(TagOwner));
+    open_options
+        .write_s3_with(
+            "reports/summary.json",
+            b"{}",
+            &WriteOptions::new().content_type("application/json"),
+        )
+        .await
+        .unwrap();
+
+    let entries = open_options.walkdir("reports/").await.unwrap();
+    let entry = entries.iter().find(|entry| !entry.folder).unwrap();
+    let metadata = entry.metadata(&open_options).await.unwrap();
+
+    assert_eq!(metadata.size, 2);
+    assert_eq!(metadata.e_tag, entry.e_tag);
+    assert_eq!(metadata.content_type.as_deref(), Some("application/json"));
+    assert_eq!(
+        metadata.metadata.get("owner").map(String::as_str),
+        Some("ingest")
+    );
+    let cached = open_options
+        .cached_object("reports/summary.json")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        metadata
+            .checksum
+            .as_ref()
+            .map(|checksum| checksum.algorithm),
+        Some(ChecksumAlgorithm::Crc32c)
+    );
+    assert_eq!(metadata.checksum, cached.checksum);
+
+    let missing = DirEntry {
+        path: PathBuf::from("reports/missing.json"),
+        ..entry.clone()
+    };
+    let err = missing.metadata(&open_options).await.unwrap_err();
+    assert!(err.is_not_found(), "{}", err);
+}
+
+#[tokio::test]
+async fn test_stat_reports_files_and_folders() {
+    let mock = MockS3::new().with_bucket("stat_bucket");
+    mock.put_object("stat_bucket", "reports/2024/summary.csv", "a,b,c");
+    mock.put_object("stat_bucket", "marked/", "");
+
+    let open_options = OpenOptions::new("stat_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-stat/");
+
+    let file = open_options.stat("reports/2024/summary.csv").await.unwrap();
+    assert!(file.is_file() && !file.is_dir());
+    assert_eq!(file.len(), 5);
+    assert!(file.modified().is_ok());
+    assert!(file.e_tag().is_some());
+    assert_eq!(file.storage_class(), Some("STANDARD"));
+
+    for folder in ["reports", "reports/", "reports/2024", ""] {
+        let metadata = open_options.stat(folder).await.unwrap();
+        assert!(metadata.is_dir(), "{}", folder);
+        assert_eq!(metadata.len(), 0);
+        assert!(metadata.modified().is_err());
+    }
+    assert!(open_options
+        .stat("marked")
+        .await
+        .unwrap()
+        .modified()
+        .is_ok());
+
+    let err = open_options.stat("reports/2023").await.unwrap_err();
+    assert!(err.is_not_found(), "{}", err);
+    assert!(open_options.stat("reports/2023/").await.is_err());
+    let err = open_options.stat("rep").await.unwrap_err();
+    assert!(err.is_not_found(), "{}", err);
+}
+
+#[derive(Default)]
+struct RecordStatuses {
+    statuses: Mutex<Vec<u16>>,
+}
+
+impl RequestHook for RecordStatuses {
+    fn after_response(&self, response: &HttpResponse) {
+        self.statuses
+            .lock()
+            .unwrap()
+            .push(response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn test_revalidate_etag_policy_skips_unchanged_objects() {
+    let mount_path = "target/test-conditional-get/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("conditional_bucket");
+    mock.put_object("conditional_bucket", "data.txt", "first");
+
+    let hook = Arc::new(RecordStatuses::default());
+    let open_options = OpenOptions::new("conditional_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cache_policy(CachePolicy::RevalidateEtag)
+        .request_hook(hook.clone());
+
+    assert_eq!(
+        open_options.read_to_string("data.txt").await.unwrap(),
+        "first"
+    );
+    assert_eq!(
+        open_options.read_to_string("data.txt").await.unwrap(),
+        "first"
+    );
+    assert_eq!(*hook.statuses.lock().unwrap(), [200, 304]);
+
+    mock.put_object("conditional_bucket", "data.txt", "second");
+    assert_eq!(
+        open_options.read_to_string("data.txt").await.unwrap(),
+        "second"
+    );
+    assert_eq!(*hook.statuses.lock().unwrap(), [200, 304, 200]);
+}
+
+#[tokio::test]
+async fn test_cache_policies_choose_when_to_contact_s3() {
+    let mount_path = "target/test-cache-policy/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("policy_bucket");
+    mock.put_object("policy_bucket", "data.txt", "first");
+
+    let hook = Arc::new(RecordStatuses::default());
+    let open_options = OpenOptions::new("policy_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .request_hook(hook.clone());
+    let read = |policy: CachePolicy| {
+        let open_options = open_options.clone().cache_policy(policy);
+        async move { open_options.read_to_string("data.txt").await }
+    };
+
+    let err = read(CachePolicy::OfflineOnly).await.unwrap_err();
+    assert!(matches!(
+        err.without_context(),
+        S3FilesystemError::Io(e) if e.kind() == std::io::ErrorKind::NotFound
+    ));
+    assert!(hook.statuses.lock().unwrap().is_empty());
+
+    assert_eq!(read(CachePolicy::UseCacheIfPresent).await.unwrap(), "first");
+    mock.put_object("policy_bucket", "data.txt", "second");
+
+    assert_eq!(read(CachePolicy::OfflineOnly).await.unwrap(), "first");
+    assert_eq!(read(CachePolicy::UseCacheIfPresent).await.unwrap(), "first");
+    assert_eq!(
+        read(CachePolicy::MaxAge(Duration::from_secs(3600)))
+            .await
+            .unwrap(),
+        "first"
+    );
+    assert_eq!(*hook.statuses.lock().unwrap(), [200]);
+
+    assert_eq!(
+        read(CachePolicy::MaxAge(Duration::ZERO)).await.unwrap(),
+        "second"
+    );
+    assert_eq!(read(CachePolicy::RevalidateEtag).await.unwrap(), "second");
+    assert_eq!(read(CachePolicy::AlwaysDownload).await.unwrap(), "second");
+    assert_eq!(*hook.statuses.lock().unwrap(), [200, 200, 304, 200]);
+}
+
+#[tokio::test]
+async fn test_cache_quota_evicts_oldest_downloads() {
+    let mount_path = "target/test-cache-quota/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("quota_bucket");
+    for key in ["a.bin", "b.bin", "c.bin"] {
+        mock.put_object("quota_bucket", key, vec![0u8; 10]);
+    }
+    mock.put_object("quota_bucket", "huge.bin", vec![0u8; 30]);
+
+    let open_options = OpenOptions::new("quota_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cache_quota(25);
+
+    for key in ["a.bin", "b.bin", "c.bin"] {
+        open_options.open_s3(key).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(open_options.cached_object("a.bin").await.unwrap().is_none());
+    assert!(open_options.cached_object("b.bin").await.unwrap().is_some());
+    assert!(open_options.cached_object("c.bin").await.unwrap().is_some());
+    assert_eq!(open_options.cache_stats().await.unwrap().total_bytes, 20);
+
+    let err = open_options.open_s3("huge.bin").await.unwrap_err();
+    assert!(err.is_insufficient_space(), "{}", err);
+    assert!(!PathBuf::from(mount_path)
+        .join("quota_bucket/huge.bin")
+        .exists());
+    assert_eq!(open_options.cache_stats().await.unwrap().total_bytes, 20);
+}
+
+/// "plain,text\n1,2\n", gzipped.
+const GZIPPED_CSV: &[u8] = b"\
+\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x2b\xc8\x49\xcc\xcc\xd3\x29\x49\xad\x28\xe1\x32\xd4\x31\
+\xe2\x02\x00\x7e\xa4\x5f\x02\x0f\x00\x00\x00";
+
+struct GzipEncoding;
+
+impl RequestHook for GzipEncoding {
+    fn before_send(&self, request: &mut HttpRequest) {
+        if request.method() == "PUT" {
+            request.headers_mut().insert("content-encoding", "gzip");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_gzip_objects_are_decompressed_into_the_mirror() {
+    let mount_path = "target/test-decompress/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("gzip_bucket");
+    mock.put_object("gzip_bucket", "plain.csv.gz", GZIPPED_CSV);
+    OpenOptions::new("gzip_bucket".to_string(), Some(mock.client()))
+        .await
+        .request_hook(Arc::new(GzipEncoding))
+        .write_s3_direct("encoded.csv", GZIPPED_CSV)
+        .await
+        .unwrap();
+
+    let open_options = OpenOptions::new("gzip_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .decompress(true);
+    assert_eq!(
+        open_options.read_to_string("encoded.csv").await.unwrap(),
+        "plain,text\n1,2\n"
+    );
+    let cached = open_options
+        .cached_object("encoded.csv")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(cached.size, 15);
+    assert_eq!(
+        open_options.read("plain.csv.gz").await.unwrap(),
+        GZIPPED_CSV
+    );
+
+    let suffixed = open_options
+        .clone()
+        .cache_policy(CachePolicy::AlwaysDownload)
+        .decompress_gz_suffix(true)
+        .keep_compressed(true);
+    assert_eq!(
+        suffixed.read_to_string("plain.csv.gz").await.unwrap(),
+        "plain,text\n1,2\n"
+    );
+    let kept = PathBuf::from(mount_path).join("gzip_bucket/plain.csv.gz.compressed");
+    assert_eq!(tokio::fs::read(kept).await.unwrap(), GZIPPED_CSV);
+
+    // Objects are kept as stored unless decompression is asked for.
+    let stored = OpenOptions::new("gzip_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cache_policy(CachePolicy::AlwaysDownload);
+    assert_eq!(stored.read("encoded.csv").await.unwrap(), GZIPPED_CSV);
+}
+
+/// 1000 zeros gzipped, then "!" gzipped as a second member, so the trailer only counts the last byte.
+const GZIPPED_ZEROS: &[u8] = b"\
+\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x33\x30\x18\x05\xa3\x60\x14\x0c\x77\x00\x00\x83\x99\x5f\
+\xa8\xe8\x03\x00\x00\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x53\x04\x00\xd3\xff\x6b\x9e\x01\x00\
+\x00\x00";
+
+#[tokio::test]
+async fn test_decompressed_size_is_held_to_the_cache_quota() {
+    let mount_path = "target/test-decompress-quota/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("gzip_quota");
+    mock.put_object("gzip_quota", "zeros.gz", GZIPPED_ZEROS);
+    mock.put_object("gzip_quota", "one.gz", &GZIPPED_ZEROS[..29]);
+
+    let open_options = OpenOptions::new("gzip_quota".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .decompress_gz_suffix(true)
+        .cache_quota(100);
+
+    for key in ["one.gz", "zeros.gz"] {
+        let err = open_options.open_s3(key).await.unwrap_err();
+        assert!(err.is_insufficient_space(), "{}", err);
+        assert!(!PathBuf::from(mount_path)
+            .join("gzip_quota")
+            .join(key)
+            .exists());
+    }
+
+    let roomy = open_options.cache_quota(2000);
+    let zeros = roomy.read_to_string("zeros.gz").await.unwrap();
+    assert_eq!(zeros, format!("{}!", "0".repeat(1000)));
+    assert_eq!(roomy.cache_stats().await.unwrap().total_bytes, 1001);
+}
+
+#[derive(Default)]
+struct CountInFlight {
+    in_flight: Mutex<(usize, usize)>,
+}
+
+impl RequestHook for CountInFlight {
+    fn before_send(&self, _request: &mut HttpRequest) {
+        let mut counts = self.in_flight.lock().unwrap();
+        counts.0 += 1;
+        counts.1 = counts.1.max(counts.0);
+        drop(counts);
+        // Keep each request open long enough for the others to pile up behind it.
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    fn after_response(&self, _response: &HttpResponse) {
+        self.in_flight.lock().unwrap().0 -= 1;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_max_concurrent_requests_bounds_requests_in_flight() {
+    let mock = MockS3::new().with_bucket("slots_bucket");
+    for index in 0..8 {
+        mock.put_object("slots_bucket", format!("{}.txt", index), "data");
+    }
+
+    let hook = Arc::new(CountInFlight::default());
+    let open_options = OpenOptions::new("slots_bucket".to_string(), Some(mock.client()))
+        .await
+        .request_hook(hook.clone())
+        .max_concurrent_requests(2);
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for index in 0..8 {
+        let open_options = open_options.clone();
+        tasks.spawn(async move { open_options.read_s3(format!("{}.txt", index)).await });
+    }
+    while let Some(read) = tasks.join_next().await {
+        assert_eq!(read.unwrap().unwrap(), "data".as_bytes());
+    }
+
+    assert_eq!(*hook.in_flight.lock().unwrap(), (0, 2));
+}
+
+#[tokio::test]
+async fn test_adaptive_backoff_slows_down_after_slow_down() {
+    let mock = MockS3::new().with_bucket("throttled_bucket");
+    mock.put_object("throttled_bucket", "a.txt", "a");
+    mock.put_object("throttled_bucket", "b.txt", "b");
+
+    let hook = Arc::new(RecordStatuses::default());
+    let open_options = OpenOptions::new("throttled_bucket".to_string(), Some(mock.client()))
+        .await
+        .request_hook(hook.clone());
+
+    // MockS3 clients do not retry, so the SlowDown reaches the caller.
+    mock.throttle_next(1);
+    assert!(open_options
+        .read_s3("a.txt")
+        .await
+        .unwrap_err()
+        .is_throttled());
+
+    // The next request waits before it is sent, then S3 is happy again and the delay shrinks.
+    let started = std::time::Instant::now();
+    assert_eq!(open_options.read_s3("b.txt").await.unwrap(), "b".as_bytes());
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert_eq!(*hook.statuses.lock().unwrap(), vec![503, 200]);
+    assert_eq!(open_options.read_s3("a.txt").await.unwrap(), "a".as_bytes());
+}
+
+#[tokio::test]
+async fn test_errors_convert_to_io_errors_of_matching_kind() {
+    let mock = MockS3::new().with_bucket("io_bucket");
+
+    let open_options = OpenOptions::new("io_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-io-errors/");
+
+    let err = std::io::Error::from(open_options.read_s3("missing.txt").await.unwrap_err());
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    let source = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<S3FilesystemError>())
+        .unwrap();
+    assert_eq!(source.context().unwrap().key(), Some("missing.txt"));
+
+    let err = std::io::Error::from(
+        open_options
+            .clone()
+            .read_only(true)
+            .write_s3("missing.txt", b"data")
+            .await
+            .unwrap_err(),
+    );
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+    let local = std::io::Error::new(std::io::ErrorKind::InvalidData, "bad data");
+    let err = std::io::Error::from(S3FilesystemError::from(local));
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "bad data");
+}
+
+#[tokio::test]
+async fn test_s3_key_normalises_and_rejects_invalid_keys() {
+    let mock = MockS3::new().with_bucket("key_bucket");
+
+    let open_options = OpenOptions::new("key_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-s3-key/");
+
+    let key: S3Key = r"reports\2024\q1.csv".parse().unwrap();
+    assert_eq!(key.as_str(), "reports/2024/q1.csv");
+    open_options.write_s3(&key, b"a,b,c").await.unwrap();
+    assert_eq!(mock.keys("key_bucket"), vec!["reports/2024/q1.csv"]);
+    assert_eq!(open_options.read_s3(&key).await.unwrap(), &b"a,b,c"[..]);
+
+    // Plain paths are normalised in the same way.
+    let read = open_options.read_s3(r"reports\2024\q1.csv").await.unwrap();
+    assert_eq!(read, &b"a,b,c"[..]);
+
+    let empty = S3Key::new("").unwrap_err();
+    assert!(matches!(empty, S3FilesystemError::InvalidKey { .. }));
+    let long = "a".repeat(s3_filesystem::MAX_KEY_LENGTH + 1);
+    assert!(S3Key::new(long.as_str()).is_err());
+    assert!(S3Key::new("a".repeat(s3_filesystem::MAX_KEY_LENGTH)).is_ok());
+
+    // An invalid key is rejected before any request is made.
+    let err = std::io::Error::from(open_options.write_s3(&long, b"data").await.unwrap_err());
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(mock.keys("key_bucket").len(), 1);
+}
+
+#[tokio::test]
+async fn test_walk_start_after_and_max_keys_window_the_listing() {
+    let mock = MockS3::new().with_bucket("window_bucket");
+    for key in ["a.csv", "b.csv", "c.txt", "d.csv", "e.csv", "f.csv"] {
+        mock.put_object("window_bucket", format!("logs/{}", key), "data");
+    }
+
+    let open_options = OpenOptions::new("window_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-walk-window/");
+
+    let window = |start_after: &'static str| {
+        open_options
+            .walk("logs/")
+            .filter_extension("csv")
+            .start_after(start_after)
+            .max_keys(2)
+            .list()
+    };
+    let paths = |entries: Vec<DirEntry>| {
+        entries
+            .into_iter()
+            .map(|entry| entry.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        paths(window("").await.unwrap()),
+        vec!["logs/a.csv", "logs/b.csv"]
+    );
+    assert_eq!(
+        paths(window("logs/b.csv").await.unwrap()),
+        vec!["logs/d.csv", "logs/e.csv"]
+    );
+    assert_eq!(
+        paths(window("logs/e.csv").await.unwrap()),
+        vec!["logs/f.csv"]
+    );
+    assert!(window("logs/f.csv").await.unwrap().is_empty());
+
+    // Other kinds of listing are windowed after the fact.
+    let entries = open_options
+        .walk("logs/")
+        .max_depth(1)
+        .start_after("logs/c.txt")
+        .max_keys(1)
+        .list()
+        .await
+        .unwrap();
+    assert_eq!(paths(entries), vec!["logs/d.csv"]);
+}
+
+#[tokio::test]
+async fn test_preload_downloads_prefix_into_cache() {
+    let mount_path = "target/test-preload/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("preload_bucket");
+    mock.put_object("preload_bucket", "models/a.bin", "aaaa");
+    mock.put_object("preload_bucket", "models/b.bin", "bb");
+    mock.put_object("preload_bucket", "models/nested/c.bin", "c");
+    mock.put_object("preload_bucket", "other/d.bin", "d");
+
+    let open_options = OpenOptions::new("preload_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    open_options.open_s3("models/a.bin").await.unwrap();
+
+    let progress = open_options.preload("models/", 2).wait().await.unwrap();
+    assert_eq!(progress.total, Some(3));
+    assert_eq!(
+        (progress.downloaded, progress.cached, progress.failed),
+        (2, 1, 0)
+    );
+    assert_eq!(progress.bytes, 3);
+    assert!(progress.is_complete());
+
+    assert!(open_options
+        .cached_object("models/nested/c.bin")
+        .await
+        .unwrap()
+        .is_some());
+    assert!(open_options
+        .cached_object("other/d.bin")
+        .await
+        .unwrap()
+        .is_none());
+
+    // Objects which cannot be downloaded are counted rather than failing the preload.
+    let progress = open_options
+        .clone()
+        .cache_policy(CachePolicy::OfflineOnly)
+        .preload("other/", 1)
+        .wait()
+        .await
+        .unwrap();
+    assert_eq!((progress.total, progress.failed), (Some(1), 1));
+}
+
+#[tokio::test]
+async fn test_spawn_sync_keeps_mount_path_up_to_date() {
+    let mount_path = "target/test-spawn-sync/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("sync_task_bucket");
+    mock.put_object("sync_task_bucket", "ref/a.txt", "a");
+    mock.put_object("sync_task_bucket", "ref/b.txt", "b");
+
+    let open_options = OpenOptions::new("sync_task_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    // Another writer, with its own mount path, changes the bucket while the sync waits between cycles.
+    let writer = OpenOptions::new("sync_task_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-spawn-sync-writer/");
+
+    let mut sync = open_options.spawn_sync("ref/", Duration::from_millis(300));
+    let first = sync.next_cycle().await.unwrap().unwrap();
+    assert_eq!(
+        first.downloaded,
+        vec![PathBuf::from("ref/a.txt"), PathBuf::from("ref/b.txt")]
+    );
+
+    writer.write_s3("ref/a.txt", b"changed").await.unwrap();
+    writer.delete_many(["ref/b.txt"]).await.unwrap();
+    writer.write_s3("ref/c.txt", b"c").await.unwrap();
+
+    let second = loop {
+        let cycle = sync.next_cycle().await.unwrap().unwrap();
+        if !cycle.is_empty() {
+            break cycle;
+        }
+    };
+    sync.stop();
+
+    assert_eq!(second.downloaded, vec![PathBuf::from("ref/c.txt")]);
+    assert_eq!(second.updated, vec![PathBuf::from("ref/a.txt")]);
+    assert_eq!(second.removed, vec![PathBuf::from("ref/b.txt")]);
+    assert!(second.failed.is_empty());
+    let cached = |key: &'static str| open_options.cached_object(key);
+    assert_eq!(cached("ref/a.txt").await.unwrap().unwrap().size, 7);
+    assert!(cached("ref/b.txt").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_watch_reports_changes_in_path_order() {
+    let mock = MockS3::new().with_bucket("watch_bucket");
+    mock.put_object("watch_bucket", "incoming/a.csv", "a");
+    mock.put_object("watch_bucket", "incoming/b.csv", "b");
+    mock.put_object("watch_bucket", "incoming/c.csv", "c");
+    let open_options = OpenOptions::new("watch_bucket".to_string(), Some(mock.client())).await;
+
+    let mut events = open_options.watch("incoming/", Duration::from_millis(20));
+
+    // Change a sentinel until an event for it arrives, so the baseline listing has been taken.
+    let mut round = 0;
+    loop {
+        round += 1;
+        mock.put_object("watch_bucket", "incoming/sentinel", round.to_string());
+        match tokio::time::timeout(Duration::from_millis(200), events.next()).await {
+            Ok(Some(Ok(WatchEvent::Created(entry) | WatchEvent::Modified(entry))))
+                if entry.path == std::path::Path::new("incoming/sentinel") =>
+            {
+                break
+            }
+            Ok(event) => panic!("unexpected event {:?}", event),
+            Err(_) => assert!(round < 25, "the watch never took its baseline"),
+        }
+    }
+
+    // Made without awaiting, so the next listing sees all of them at once.
+    mock.put_object("watch_bucket", "incoming/d.csv", "d");
+    mock.put_object("watch_bucket", "incoming/b.csv", "changed");
+    mock.delete_object("watch_bucket", "incoming/a.csv");
+    mock.delete_object("watch_bucket", "incoming/sentinel");
+
+    let mut seen = Vec::new();
+    while seen.len() < 4 {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        seen.push(match event {
+            WatchEvent::Created(entry) => ("created", entry.path, entry.size),
+            WatchEvent::Modified(entry) => ("modified", entry.path, entry.size),
+            WatchEvent::Deleted(entry) => ("deleted", entry.path, entry.size),
+        });
+    }
+    assert_eq!(
+        seen,
+        [
+            ("deleted", PathBuf::from("incoming/a.csv"), 1),
+            ("modified", PathBuf::from("incoming/b.csv"), 7),
+            ("created", PathBuf::from("incoming/d.csv"), 1),
+            (
+                "deleted",
+                PathBuf::from("incoming/sentinel"),
+                round.to_string().len() as i64
+            ),
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_max_bandwidth_caps_throughput() {
+    let mount_path = "target/test-bandwidth/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+    let mock = MockS3::new().with_bucket("bandwidth_bucket");
+    mock.put_object("bandwidth_bucket", "large.bin", vec![7; 3000]);
+
+    let open_options = OpenOptions::new("bandwidth_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .max_bandwidth(1000);
+
+    // The first second's worth is a burst, so the rest of the 3000 bytes take two seconds.
+    let started = tokio::time::Instant::now();
+    assert_eq!(open_options.read("large.bin").await.unwrap().len(), 3000);
+    let elapsed = started.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(1900) && elapsed < Duration::from_millis(2500),
+        "3000 bytes at 1000 bytes a second took {:?}",
+        elapsed
+    );
+
+    // Clones share the limit, so two uploads of 1000 bytes take two seconds between them.
+    let clone = open_options.clone();
+    let started = tokio::time::Instant::now();
+    let (first, second) = tokio::join!(
+        open_options.write_s3("first.bin", &[1; 1000]),
+        clone.write_s3("second.bin", &[2; 1000]),
+    );
+    first.unwrap();
+    second.unwrap();
+    let elapsed = started.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(1900) && elapsed < Duration::from_millis(2500),
+        "2000 bytes at 1000 bytes a second took {:?}",
+        elapsed
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_max_requests_per_second_spaces_out_requests() {
+    let mock = MockS3::new().with_bucket("request_rate_bucket");
+    mock.put_object("request_rate_bucket", "file.txt", "data");
+
+    let open_options = OpenOptions::new("request_rate_bucket".to_string(), Some(mock.client()))
+        .await
+        .max_requests_per_second(2);
+
+    // The first two go straight through as a burst, after which they come half a second apart.
+    let started = tokio::time::Instant::now();
+    let mut starts = Vec::new();
+    for _ in 0..5 {
+        open_options.stat("file.txt").await.unwrap();
+        starts.push(started.elapsed());
+    }
+    assert!(starts[1] < Duration::from_millis(100), "{:?}", starts);
+    for pair in starts[1..].windows(2) {
+        assert!(
+            pair[1] - pair[0] >= Duration::from_millis(450)
+                && pair[1] - pair[0] < Duration::from_millis(600),
+            "{:?}",
+            starts
+        );
+    }
+
+    // After a quiet second, a second's worth of requests go straight through.
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    let started = tokio::time::Instant::now();
+    open_options.stat("file.txt").await.unwrap();
+    open_options.stat("file.txt").await.unwrap();
+    assert!(started.elapsed() < Duration::from_millis(100));
+    open_options.stat("file.txt").await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(450));
+}
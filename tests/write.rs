@@ -1,9 +1,32 @@
-use s3_filesystem::OpenOptions;
+use s3_filesystem::{
+    ArchiveFormat, BlockingOpenOptions, CachePolicy, CancellationToken, CannedAcl,
+    ChecksumAlgorithm, DeleteOutcome, DryRunOperation, HttpRequest, JournalOperation, LocalBackend,
+    MetricsSink, MockS3, OpenOptions, OpenOptionsBuilder, Recovery, RequestHook, RestoreTier,
+    RetentionMode, S3FilesystemError, WriteOptions,
+};
 
-use tokio::fs;
+use aws_sdk_s3::config::Credentials;
+use aws_smithy_runtime_api::{
+    box_error::BoxError,
+    client::{
+        interceptors::{context::BeforeTransmitInterceptorContextRef, Intercept},
+        runtime_components::RuntimeComponents,
+    },
+};
+use aws_smithy_types::config_bag::ConfigBag;
+use std::{
+    io::SeekFrom,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
 
 // eu-west2 public data.
-const BUCKET: &'static str = "test-bucket";
+const BUCKET: &str = "test-bucket";
 
 #[tokio::test]
 async fn test_write_file() {
@@ -12,7 +35,7 @@ async fn test_write_file() {
     let open_options = OpenOptions::new(bucket, None)
         .await
         .mount_path("data/test/")
-        .force_download(true);
+        .cache_policy(CachePolicy::AlwaysDownload);
 
     let data = fs::read("data/manifest.txt").await.unwrap();
 
@@ -20,3 +43,1628 @@ async fn test_write_file() {
 
     println!("Data uploaded successfully");
 }
+
+#[tokio::test]
+async fn test_dry_run_write_file() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("data/test/")
+        .dry_run(true);
+
+    open_options
+        .write_s3("dry_run/manifest.txt", b"not uploaded")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        open_options.dry_run_report(),
+        vec![DryRunOperation::Upload {
+            key: "dry_run/manifest.txt".to_string(),
+            size: 12,
+        }]
+    );
+    assert!(!std::path::Path::new("data/test/test-bucket/dry_run/manifest.txt").exists());
+}
+
+#[tokio::test]
+async fn test_read_only_write_file() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("data/test/")
+        .read_only(true);
+
+    let result = open_options
+        .write_s3("read_only/manifest.txt", b"rejected")
+        .await;
+
+    assert!(matches!(result, Err(S3FilesystemError::ReadOnly)));
+}
+
+#[tokio::test]
+async fn test_dry_run_delete_many() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await.dry_run(true);
+
+    let outcomes = open_options
+        .delete_many(["old/a.txt", "old/b.txt"])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        outcomes,
+        vec![
+            DeleteOutcome::Deleted("old/a.txt".to_string()),
+            DeleteOutcome::Deleted("old/b.txt".to_string()),
+        ]
+    );
+    assert_eq!(open_options.dry_run_report().len(), 2);
+}
+
+#[tokio::test]
+async fn test_dry_run_copy_between() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await.dry_run(true);
+
+    open_options
+        .copy_between("staging-bucket", "models/a.bin", BUCKET, "models/a.bin")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        open_options.dry_run_report(),
+        vec![DryRunOperation::CopyBetween {
+            from_bucket: "staging-bucket".to_string(),
+            from: "models/a.bin".to_string(),
+            to_bucket: BUCKET.to_string(),
+            to: "models/a.bin".to_string(),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_dry_run_restore() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await.dry_run(true);
+
+    open_options
+        .restore("archive/old.csv", RestoreTier::Bulk, 7)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        open_options.dry_run_report(),
+        vec![DryRunOperation::Restore {
+            key: "archive/old.csv".to_string(),
+            days: 7,
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_write_error_has_context() {
+    let bucket = BUCKET.to_string();
+
+    // A mount path inside a regular file can never be created, so the write fails locally.
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("Cargo.toml");
+
+    let err = open_options
+        .write_s3("context/manifest.txt", b"unwritable")
+        .await
+        .unwrap_err();
+
+    let context = err.context().unwrap();
+    assert_eq!(context.operation(), "PutObject");
+    assert_eq!(context.bucket(), BUCKET);
+    assert_eq!(context.key(), Some("context/manifest.txt"));
+    assert!(matches!(err.without_context(), S3FilesystemError::Io(_)));
+    assert!(!err.retryable());
+}
+
+#[tokio::test]
+async fn test_only_network_body_errors_are_retryable() {
+    use aws_sdk_s3::primitives::{ByteStream, SdkBody};
+
+    // The file to upload is missing, which will not change on a retry.
+    let missing = ByteStream::from_path("target/test-missing-upload-source.bin")
+        .await
+        .unwrap_err();
+    assert!(!S3FilesystemError::ByteStream(missing).retryable());
+
+    let unreadable = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+    let unreadable = aws_sdk_s3::primitives::ByteStreamError::from(unreadable);
+    assert!(!S3FilesystemError::ByteStream(unreadable).retryable());
+
+    // A body which fails while streaming, as a dropped connection does part way through a download.
+    let interrupted = ByteStream::new(SdkBody::taken())
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(S3FilesystemError::ByteStream(interrupted).retryable());
+}
+
+#[tokio::test]
+async fn test_write_rejects_path_traversal() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await.dry_run(true);
+
+    for key in [
+        "../escape.txt",
+        "nested/../../escape.txt",
+        "/etc/escape.txt",
+    ] {
+        let err = open_options.write_s3(key, b"escaped").await.unwrap_err();
+        assert!(
+            matches!(err.without_context(), S3FilesystemError::PathTraversal(k) if k == key),
+            "{} was not rejected",
+            key
+        );
+    }
+    assert!(open_options.dry_run_report().is_empty());
+}
+
+#[tokio::test]
+async fn test_resume_without_pending_upload() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None)
+        .await
+        .mount_path("target/test-resume-upload/");
+
+    assert!(open_options.pending_uploads().await.unwrap().is_empty());
+
+    let err = open_options
+        .resume_upload("large/data.bin")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err.without_context(), S3FilesystemError::Io(e) if e.kind() == std::io::ErrorKind::NotFound)
+    );
+    assert_eq!(err.context().unwrap().key(), Some("large/data.bin"));
+
+    open_options.abort_upload("large/data.bin").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dry_run_write_with_options() {
+    let bucket = BUCKET.to_string();
+
+    let open_options = OpenOptions::new(bucket, None).await.dry_run(true);
+
+    open_options
+        .write_s3_with(
+            "site/feed",
+            b"<rss></rss>",
+            &WriteOptions::new()
+                .content_type("application/rss+xml")
+                .acl(CannedAcl::BucketOwnerFullControl),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        open_options.dry_run_report(),
+        vec![DryRunOperation::Upload {
+            key: "site/feed".to_string(),
+            size: 11,
+        }]
+    );
+}
+
+#[test]
+fn test_blocking_open_options_outside_a_runtime() {
+    let mock = MockS3::new().with_bucket("blocking_bucket");
+    mock.put_object("blocking_bucket", "in/seed.txt", "seeded");
+
+    let mount_path = "target/test-blocking/";
+    let _ = std::fs::remove_dir_all(mount_path);
+    let open_options = BlockingOpenOptions::connect(
+        OpenOptionsBuilder::new("blocking_bucket")
+            .client(mock.client())
+            .mount_path(mount_path),
+    )
+    .unwrap();
+
+    let mut contents = String::new();
+    let mut file = open_options.open_s3("in/seed.txt").unwrap();
+    std::io::Read::read_to_string(&mut file, &mut contents).unwrap();
+    assert_eq!(contents, "seeded");
+
+    open_options.write_s3("out/reply.txt", b"written").unwrap();
+    assert_eq!(open_options.read("out/reply.txt").unwrap(), b"written");
+    assert_eq!(
+        mock.get_object("blocking_bucket", "out/reply.txt").unwrap(),
+        b"written"
+    );
+
+    let listed = open_options.walkdir("").unwrap();
+    let listed = listed
+        .iter()
+        .map(|entry| entry.path.to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(listed, ["in/seed.txt", "out/reply.txt"]);
+}
+
+#[tokio::test]
+async fn test_mock_round_trip() {
+    let mock = MockS3::new().with_bucket("mock_bucket");
+    mock.put_object("mock_bucket", "in/seed.txt", "seeded");
+
+    let open_options = OpenOptions::new("mock_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-mock-round-trip/")
+        .cache_policy(CachePolicy::AlwaysDownload);
+
+    open_options
+        .write_s3("out/copy.txt", b"written")
+        .await
+        .unwrap();
+    open_options
+        .copy_s3("in/seed.txt", "out/seed.txt")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        open_options.read_to_string("out/seed.txt").await.unwrap(),
+        "seeded"
+    );
+    assert_eq!(
+        mock.get_object("mock_bucket", "out/copy.txt").unwrap(),
+        b"written"
+    );
+
+    let entries = open_options.walkdir("out/").await.unwrap();
+    let mut keys: Vec<_> = entries
+        .iter()
+        .filter(|entry| !entry.folder)
+        .map(|entry| entry.path.to_string_lossy().into_owned())
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec!["out/copy.txt", "out/seed.txt"]);
+
+    open_options
+        .delete_many(["out/copy.txt", "out/seed.txt"])
+        .await
+        .unwrap();
+    assert_eq!(mock.keys("mock_bucket"), vec!["in/seed.txt"]);
+}
+
+#[tokio::test]
+async fn test_local_backend_round_trip() {
+    let root = "target/test-local-backend/objects";
+    let _ = fs::remove_dir_all("target/test-local-backend").await;
+
+    let open_options = OpenOptions::new("local_bucket".to_string(), None)
+        .await
+        .mount_path("target/test-local-backend/mirror/")
+        .backend(Arc::new(LocalBackend::new(root)));
+
+    open_options
+        .write_s3("reports/a.csv", b"a,b,c")
+        .await
+        .unwrap();
+    open_options
+        .write_s3("reports/nested/b.csv", b"d,e,f")
+        .await
+        .unwrap();
+    assert_eq!(
+        fs::read("target/test-local-backend/objects/local_bucket/reports/a.csv")
+            .await
+            .unwrap(),
+        b"a,b,c"
+    );
+
+    let err = open_options
+        .write_s3_if_absent("reports/a.csv", b"x")
+        .await
+        .unwrap_err();
+    assert!(err.is_precondition_failed());
+
+    assert_eq!(
+        open_options.read_s3("reports/nested/b.csv").await.unwrap(),
+        &b"d,e,f"[..]
+    );
+    let missing = open_options
+        .read_s3("reports/missing.csv")
+        .await
+        .unwrap_err();
+    assert!(missing.is_not_found());
+
+    let mut keys: Vec<_> = open_options
+        .walkdir("reports/")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.path.to_string_lossy().into_owned())
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec!["reports/a.csv", "reports/nested/b.csv"]);
+
+    let first = open_options
+        .walkdir_page("reports/", None, 1)
+        .await
+        .unwrap();
+    let second = open_options
+        .walkdir_page("reports/", first.continuation_token.clone(), 1)
+        .await
+        .unwrap();
+    assert_eq!(first.entries[0].path.to_str(), Some("reports/a.csv"));
+    assert_eq!(
+        second.entries[0].path.to_str(),
+        Some("reports/nested/b.csv")
+    );
+    assert!(second.continuation_token.is_none());
+
+    let outcomes = open_options.delete_many(["reports/a.csv"]).await.unwrap();
+    assert_eq!(
+        outcomes,
+        vec![DeleteOutcome::Deleted("reports/a.csv".to_string())]
+    );
+    assert!(
+        fs::metadata("target/test-local-backend/objects/local_bucket/reports/a.csv")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_lazy_file_reads_through_backend_and_pending_writes() {
+    let root = "target/test-lazy-backend/objects";
+    let _ = fs::remove_dir_all("target/test-lazy-backend").await;
+
+    let open_options = OpenOptions::new("lazy_bucket".to_string(), None)
+        .await
+        .mount_path("target/test-lazy-backend/mirror/")
+        .backend(Arc::new(LocalBackend::new(root)));
+    open_options
+        .write_s3("data/table.bin", b"0123456789")
+        .await
+        .unwrap();
+
+    let mut file = open_options
+        .open_s3_lazy("data/table.bin")
+        .await
+        .unwrap()
+        .buffer_size(4);
+    assert_eq!(file.size(), 10);
+    let mut tail = String::new();
+    file.seek(SeekFrom::End(-3)).await.unwrap();
+    file.read_to_string(&mut tail).await.unwrap();
+    assert_eq!(tail, "789");
+
+    let held = open_options.clone().write_back(true);
+    held.write_s3("data/table.bin", b"pending").await.unwrap();
+    let mut file = held.open_s3_lazy("data/table.bin").await.unwrap();
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await.unwrap();
+    assert_eq!(contents, "pending");
+}
+
+#[tokio::test]
+async fn test_sync_up_skips_unchanged_files() {
+    let local_dir = "target/test-sync-up/site";
+    let _ = fs::remove_dir_all("target/test-sync-up").await;
+    fs::create_dir_all(format!("{}/css", local_dir))
+        .await
+        .unwrap();
+    fs::write(format!("{}/index.html", local_dir), "<h1>hi</h1>")
+        .await
+        .unwrap();
+    fs::write(format!("{}/about.html", local_dir), "new about")
+        .await
+        .unwrap();
+    fs::write(format!("{}/css/site.css", local_dir), "body {}")
+        .await
+        .unwrap();
+
+    let mock = MockS3::new().with_bucket("sync_bucket");
+    mock.put_object("sync_bucket", "www/index.html", "<h1>hi</h1>");
+    mock.put_object("sync_bucket", "www/about.html", "old about");
+    mock.put_object("sync_bucket", "www/stale.html", "left alone");
+
+    let open_options = OpenOptions::new("sync_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-sync-up/mirror/");
+
+    let report = open_options.sync_up(local_dir, "www").await.unwrap();
+    assert!(report.failed.is_empty());
+    assert_eq!(
+        report.uploaded,
+        vec![
+            std::path::PathBuf::from("www/about.html"),
+            std::path::PathBuf::from("www/css/site.css")
+        ]
+    );
+    assert_eq!(
+        report.unchanged,
+        vec![std::path::PathBuf::from("www/index.html")]
+    );
+    assert_eq!(
+        mock.get_object("sync_bucket", "www/about.html").unwrap(),
+        b"new about"
+    );
+    assert_eq!(
+        mock.get_object("sync_bucket", "www/stale.html").unwrap(),
+        b"left alone"
+    );
+
+    let again = open_options.sync_up(local_dir, "www/").await.unwrap();
+    assert!(again.uploaded.is_empty());
+    assert_eq!(again.unchanged.len(), 3);
+}
+
+#[tokio::test]
+async fn test_concurrent_multipart_upload() {
+    let mock = MockS3::new().with_bucket("multipart_bucket");
+
+    let open_options = OpenOptions::new("multipart_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-concurrent-multipart/")
+        .part_size(1)
+        .upload_concurrency(4)
+        .download_buffer_size(64 * 1024);
+
+    let data: Vec<u8> = (0..64 * 1024 * 1024 + 1).map(|i| (i % 251) as u8).collect();
+    open_options
+        .write_s3("large/data.bin", &data)
+        .await
+        .unwrap();
+
+    assert!(
+        mock.get_object("multipart_bucket", "large/data.bin")
+            .unwrap()
+            == data
+    );
+    assert!(open_options.pending_uploads().await.unwrap().is_empty());
+
+    let copy = "target/test-concurrent-multipart/copy.bin";
+    let _ = tokio::fs::remove_file(copy).await;
+    open_options
+        .open_s3_to("large/data.bin", copy)
+        .await
+        .unwrap();
+    assert!(tokio::fs::read(copy).await.unwrap() == data);
+}
+
+#[tokio::test]
+async fn test_write_direct_skips_mount_path() {
+    let mount_path = "target/test-write-direct/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("direct_bucket");
+    let open_options = OpenOptions::new("direct_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .part_size(1)
+        .upload_concurrency(4);
+
+    open_options
+        .write_s3("small.txt", b"mirrored")
+        .await
+        .unwrap();
+    open_options
+        .write_s3_direct("small.txt", "replaced")
+        .await
+        .unwrap();
+    assert_eq!(
+        mock.get_object("direct_bucket", "small.txt").unwrap(),
+        b"replaced"
+    );
+    assert!(!PathBuf::from(mount_path)
+        .join("direct_bucket/small.txt")
+        .exists());
+    assert_eq!(
+        open_options.read_to_string("small.txt").await.unwrap(),
+        "replaced"
+    );
+
+    let data: Vec<u8> = (0..64 * 1024 * 1024 + 1).map(|i| (i % 251) as u8).collect();
+    open_options
+        .write_s3_direct("large.bin", data.clone())
+        .await
+        .unwrap();
+    assert!(mock.get_object("direct_bucket", "large.bin").unwrap() == data);
+    assert!(!PathBuf::from(mount_path)
+        .join("direct_bucket/large.bin")
+        .exists());
+    assert!(open_options.pending_uploads().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_s3_lock_excludes_other_workers() {
+    let mock = MockS3::new().with_bucket("lock_bucket");
+    let worker = |mock: &MockS3| {
+        let client = mock.client();
+        async move { OpenOptions::new("lock_bucket".to_string(), Some(client)).await }
+    };
+    let first = worker(&mock).await;
+    let second = worker(&mock).await;
+    let ttl = std::time::Duration::from_secs(60);
+
+    let mut lock = first.lock("locks/job", ttl).await.unwrap().unwrap();
+    assert!(second.lock("locks/job", ttl).await.unwrap().is_none());
+    first.renew_lock(&mut lock, ttl).await.unwrap();
+    first.unlock(lock).await.unwrap();
+    assert!(mock.keys("lock_bucket").is_empty());
+
+    // A lock left to expire is taken over, and its old holder can no longer renew or release it.
+    let mut stale = first
+        .lock("locks/job", std::time::Duration::ZERO)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(stale.is_expired());
+    let taken = second.lock("locks/job", ttl).await.unwrap().unwrap();
+    assert!(first
+        .renew_lock(&mut stale, ttl)
+        .await
+        .unwrap_err()
+        .is_precondition_failed());
+    assert!(first
+        .unlock(stale)
+        .await
+        .unwrap_err()
+        .is_precondition_failed());
+    second.unlock(taken).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_prefix_is_treated_as_root() {
+    let mount_path = "target/test-prefix/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("prefix_bucket");
+    mock.put_object("prefix_bucket", "datasets/v2/train.csv", "a,b");
+    mock.put_object("prefix_bucket", "datasets/v1/train.csv", "old");
+
+    let open_options = OpenOptions::new("prefix_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .prefix("datasets/v2");
+
+    assert_eq!(
+        open_options.read_to_string("train.csv").await.unwrap(),
+        "a,b"
+    );
+    assert!(PathBuf::from(mount_path)
+        .join("prefix_bucket/train.csv")
+        .exists());
+
+    open_options.write_s3("test.csv", b"c,d").await.unwrap();
+    open_options.copy_s3("test.csv", "copy.csv").await.unwrap();
+    assert_eq!(
+        mock.get_object("prefix_bucket", "datasets/v2/test.csv")
+            .unwrap(),
+        b"c,d"
+    );
+    assert_eq!(
+        mock.get_object("prefix_bucket", "datasets/v2/copy.csv")
+            .unwrap(),
+        b"c,d"
+    );
+
+    let mut keys: Vec<_> = open_options
+        .walkdir("")
+        .await
+        .unwrap()
+        .iter()
+        .filter(|entry| !entry.folder)
+        .map(|entry| entry.path.to_string_lossy().into_owned())
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec!["copy.csv", "test.csv", "train.csv"]);
+
+    let deleted = open_options.delete_many(["copy.csv"]).await.unwrap();
+    assert!(matches!(&deleted[..], [DeleteOutcome::Deleted(key)] if key == "copy.csv"));
+    assert!(mock
+        .get_object("prefix_bucket", "datasets/v2/copy.csv")
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_access_point_arn_as_bucket() {
+    let access_point = "arn:aws:s3:eu-west-2:123456789012:accesspoint/shared-data";
+    let root = "target/test-access-point/objects/";
+    let mount_path = "target/test-access-point/mirror/";
+    let _ = fs::remove_dir_all("target/test-access-point/").await;
+
+    let open_options = OpenOptions::new(access_point.to_string(), None)
+        .await
+        .mount_path(mount_path)
+        .backend(Arc::new(LocalBackend::new(root)));
+
+    open_options.write_s3("in/a.txt", b"shared").await.unwrap();
+    assert!(PathBuf::from(mount_path)
+        .join("arn_aws_s3_eu-west-2_123456789012_accesspoint_shared-data/in/a.txt")
+        .exists());
+
+    let entries = open_options.walkdir("in/").await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].path, PathBuf::from("in/a.txt"));
+    assert_eq!(
+        open_options
+            .clone()
+            .cache_policy(CachePolicy::AlwaysDownload)
+            .read_to_string("in/a.txt")
+            .await
+            .unwrap(),
+        "shared"
+    );
+}
+
+#[tokio::test]
+async fn test_upload_checksum_sent_and_indexed() {
+    let mount_path = "target/test-upload-checksum/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("checksum_bucket");
+    let open_options = OpenOptions::new("checksum_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .upload_checksum(ChecksumAlgorithm::Crc32c);
+
+    // MockS3 refuses uploads whose checksum does not match, as S3 does.
+    open_options.write_s3("small.csv", b"a,b,c").await.unwrap();
+    let data: Vec<u8> = (0..64 * 1024 * 1024 + 1).map(|i| (i % 251) as u8).collect();
+    open_options
+        .clone()
+        .upload_checksum(ChecksumAlgorithm::Sha256)
+        .write_s3("large.bin", &data)
+        .await
+        .unwrap();
+
+    // A fresh OpenOptions reads the checksums back from the index on disk.
+    let reopened = OpenOptions::new("checksum_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    let small = reopened
+        .cached_object("small.csv")
+        .await
+        .unwrap()
+        .unwrap()
+        .checksum
+        .unwrap();
+    assert_eq!(small.algorithm, ChecksumAlgorithm::Crc32c);
+    assert_eq!(small.value.len(), 8);
+
+    let large = reopened
+        .cached_object("large.bin")
+        .await
+        .unwrap()
+        .unwrap()
+        .checksum
+        .unwrap();
+    assert_eq!(large.algorithm, ChecksumAlgorithm::Sha256);
+    assert!(large.value.ends_with("-5"));
+}
+
+/// A zip written by Python's zipfile with a directory entry and one deflated file, drop/readme.txt.
+const DEFLATED_ZIP: &[u8] = b"\
+\x50\x4b\x03\x04\x14\x00\x00\x00\x08\x00\x6b\x3e\x4f\x5d\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\
+\x00\x00\x05\x00\x00\x00\x64\x72\x6f\x70\x2f\x03\x00\x50\x4b\x03\x04\x14\x00\x00\x00\x08\x00\x6b\
+\x3e\x4f\x5d\x75\xc0\xec\xec\x11\x00\x00\x00\x60\x00\x00\x00\x0f\x00\x00\x00\x64\x72\x6f\x70\x2f\
+\x72\x65\x61\x64\x6d\x65\x2e\x74\x78\x74\x2b\x4b\xcd\x4b\xc9\x2f\x52\x48\x49\x2c\x49\x54\x28\xa3\
+\x01\x1b\x00\x50\x4b\x01\x02\x14\x03\x14\x00\x00\x00\x08\x00\x6b\x3e\x4f\x5d\x00\x00\x00\x00\x02\
+\x00\x00\x00\x00\x00\x00\x00\x05\x00\x00\x00\x00\x00\x00\x00\x00\x00\x10\x00\xfd\x41\x00\x00\x00\
+\x00\x64\x72\x6f\x70\x2f\x50\x4b\x01\x02\x14\x03\x14\x00\x00\x00\x08\x00\x6b\x3e\x4f\x5d\x75\xc0\
+\xec\xec\x11\x00\x00\x00\x60\x00\x00\x00\x0f\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x80\x01\
+\x25\x00\x00\x00\x64\x72\x6f\x70\x2f\x72\x65\x61\x64\x6d\x65\x2e\x74\x78\x74\x50\x4b\x05\x06\x00\
+\x00\x00\x00\x02\x00\x02\x00\x70\x00\x00\x00\x63\x00\x00\x00\x00\x00";
+
+#[tokio::test]
+async fn test_upload_archive_round_trips_and_ingests_zip() {
+    let mount_path = "target/test-upload-archive/";
+    let _ = tokio::fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("ingest_bucket");
+    mock.put_object("ingest_bucket", "export/a.csv", "a,b");
+    mock.put_object("ingest_bucket", "export/nested/b.csv", "c,d");
+
+    let open_options = OpenOptions::new("ingest_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+
+    for (format, prefix) in [(ArchiveFormat::Tar, "tar"), (ArchiveFormat::Zip, "zip")] {
+        let mut archive = Vec::new();
+        open_options
+            .download_archive("export/", &mut archive, format)
+            .await
+            .unwrap();
+
+        let keys = open_options
+            .upload_archive(archive.as_slice(), prefix)
+            .await
+            .unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                PathBuf::from(format!("{}/export/a.csv", prefix)),
+                PathBuf::from(format!("{}/export/nested/b.csv", prefix)),
+            ]
+        );
+        assert_eq!(
+            mock.get_object("ingest_bucket", &format!("{}/export/nested/b.csv", prefix))
+                .unwrap(),
+            b"c,d"
+        );
+    }
+
+    let keys = open_options
+        .upload_archive(DEFLATED_ZIP, "vendor/")
+        .await
+        .unwrap();
+    assert_eq!(keys, vec![PathBuf::from("vendor/drop/readme.txt")]);
+    assert_eq!(
+        mock.get_object("ingest_bucket", "vendor/drop/readme.txt")
+            .unwrap(),
+        "vendor data ".repeat(8).as_bytes()
+    );
+    assert!(!PathBuf::from(mount_path).join("ingest_bucket").exists());
+
+    let err = open_options
+        .upload_archive(&b"not an archive at all"[..], "")
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("neither a zip nor a tar"),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_attributes_lists_parts_and_checksums() {
+    let mock = MockS3::new().with_bucket("attributes_bucket");
+    let open_options = OpenOptions::new("attributes_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-attributes/")
+        .upload_checksum(ChecksumAlgorithm::Crc32c);
+
+    open_options.write_s3("small.csv", b"a,b,c").await.unwrap();
+    let small = open_options.attributes("small.csv").await.unwrap();
+    assert_eq!(small.size, 5);
+    assert_eq!(small.parts_count, None);
+    assert!(small.parts.is_empty());
+    assert_eq!(
+        small.checksum,
+        open_options
+            .cached_object("small.csv")
+            .await
+            .unwrap()
+            .unwrap()
+            .checksum
+    );
+
+    let data: Vec<u8> = (0..64 * 1024 * 1024 + 1).map(|i| (i % 251) as u8).collect();
+    open_options
+        .write_s3_direct("large.bin", data.clone())
+        .await
+        .unwrap();
+    let large = open_options.attributes("large.bin").await.unwrap();
+    assert_eq!(large.size, data.len() as u64);
+    assert_eq!(large.parts_count, Some(large.parts.len() as u32));
+    assert!(large.parts.len() > 1);
+    assert!(large
+        .checksum
+        .unwrap()
+        .value
+        .ends_with(&format!("-{}", large.parts.len())));
+
+    let mut offset = 0;
+    for (index, part) in large.parts.iter().enumerate() {
+        assert_eq!(part.number, index as u32 + 1);
+        assert_eq!(part.offset, offset);
+        assert_eq!(
+            part.checksum.as_ref().unwrap().algorithm,
+            ChecksumAlgorithm::Crc32c
+        );
+        offset += part.size;
+    }
+    assert_eq!(offset, data.len() as u64);
+
+    let err = open_options.attributes("missing.bin").await.unwrap_err();
+    assert!(err.is_not_found(), "{}", err);
+}
+
+#[derive(Default)]
+struct Requests(std::sync::Mutex<Vec<String>>);
+
+impl MetricsSink for Requests {
+    fn request(&self, operation: &str, _latency: std::time::Duration, _succeeded: bool) {
+        self.0.lock().unwrap().push(operation.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_skip_unchanged_uploads() {
+    let mount_path = "target/test-skip-unchanged/";
+    let _ = fs::remove_dir_all(mount_path).await;
+    let requests = Arc::new(Requests::default());
+
+    let mock = MockS3::new().with_bucket("dedup_bucket");
+    mock.put_object("dedup_bucket", "out/same.csv", "a,b,c");
+    mock.put_object("dedup_bucket", "out/changed.csv", "a,b,c");
+    let open_options = OpenOptions::new("dedup_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .metrics(requests.clone())
+        .skip_unchanged_uploads(true);
+
+    open_options
+        .write_s3("out/same.csv", b"a,b,c")
+        .await
+        .unwrap();
+    open_options
+        .write_s3("out/changed.csv", b"a,b,d")
+        .await
+        .unwrap();
+    open_options
+        .write_s3_direct("out/new.csv", &b"x,y,z"[..])
+        .await
+        .unwrap();
+    open_options
+        .write_s3_direct("out/new.csv", &b"x,y,z"[..])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *requests.0.lock().unwrap(),
+        vec![
+            "HeadObject",
+            "HeadObject",
+            "PutObject",
+            "HeadObject",
+            "PutObject",
+            "HeadObject"
+        ]
+    );
+    assert_eq!(
+        mock.get_object("dedup_bucket", "out/changed.csv").unwrap(),
+        b"a,b,d"
+    );
+    assert!(open_options
+        .cached_object("out/same.csv")
+        .await
+        .unwrap()
+        .is_some());
+
+    // With a checksum the comparison does not rely on the ETag.
+    let checksummed = open_options
+        .clone()
+        .upload_checksum(ChecksumAlgorithm::Crc32c);
+    checksummed.write_s3("out/sum.csv", b"1,2").await.unwrap();
+    requests.0.lock().unwrap().clear();
+    checksummed.write_s3("out/sum.csv", b"1,2").await.unwrap();
+    assert_eq!(*requests.0.lock().unwrap(), vec!["HeadObject"]);
+}
+
+#[tokio::test]
+async fn test_trash_keeps_deleted_objects_for_restore() {
+    let mock = MockS3::new().with_bucket("trash_bucket");
+    mock.put_object("trash_bucket", "reports/q1.csv", "first");
+    mock.put_object("trash_bucket", "reports/q2.csv", "second");
+
+    let open_options = OpenOptions::new("trash_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-trash/")
+        .trash(".trash");
+
+    let outcomes = open_options
+        .delete_many(["reports/q1.csv", "reports/q2.csv"])
+        .await
+        .unwrap();
+    assert_eq!(outcomes.len(), 2);
+    let keys = mock.keys("trash_bucket");
+    assert_eq!(keys.len(), 2);
+    assert!(keys
+        .iter()
+        .all(|key| key.starts_with(".trash/") && key.contains("Z/reports/q")));
+
+    // A later deletion of the same key is restored in preference to the earlier one.
+    mock.put_object("trash_bucket", "reports/q1.csv", "first, revised");
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    open_options.delete_many(["reports/q1.csv"]).await.unwrap();
+    open_options
+        .restore_from_trash("reports/q1.csv")
+        .await
+        .unwrap();
+    assert_eq!(
+        mock.get_object("trash_bucket", "reports/q1.csv").unwrap(),
+        b"first, revised"
+    );
+    assert_eq!(mock.keys("trash_bucket").len(), 3);
+
+    let err = open_options
+        .restore_from_trash("reports/q3.csv")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    // Deleting from the trash itself empties it rather than copying again.
+    let trashed: Vec<String> = mock
+        .keys("trash_bucket")
+        .into_iter()
+        .filter(|key| key.starts_with(".trash/"))
+        .collect();
+    open_options.delete_many(&trashed).await.unwrap();
+    assert_eq!(mock.keys("trash_bucket"), vec!["reports/q1.csv"]);
+}
+
+#[derive(Default)]
+struct RecordPutHeaders(Mutex<Vec<(String, String)>>);
+
+impl RequestHook for RecordPutHeaders {
+    fn before_send(&self, request: &mut HttpRequest) {
+        if request.method() == "PUT" {
+            let mut headers = self.0.lock().unwrap();
+            for (name, value) in request.headers().iter() {
+                headers.push((name.to_string(), value.to_string()));
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_object_lock_settings_sent_with_upload() {
+    let mock = MockS3::new().with_bucket("lock_bucket");
+    let headers = Arc::new(RecordPutHeaders::default());
+
+    let open_options = OpenOptions::new("lock_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-object-lock/")
+        .request_hook(headers.clone());
+    open_options
+        .write_s3_with(
+            "audit/2024-05-01.log",
+            b"signed off",
+            &WriteOptions::new()
+                .retention(RetentionMode::Compliance, Duration::from_secs(24 * 60 * 60))
+                .legal_hold(true),
+        )
+        .await
+        .unwrap();
+
+    let headers = headers.0.lock().unwrap();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(header("x-amz-object-lock-mode"), Some("COMPLIANCE"));
+    assert!(header("x-amz-object-lock-retain-until-date").is_some());
+    assert_eq!(header("x-amz-object-lock-legal-hold"), Some("ON"));
+    // S3 only accepts Object Lock settings on uploads with a checksum.
+    assert!(header("x-amz-checksum-crc32c").is_some());
+
+    let locked = DeleteOutcome::Failed {
+        key: "audit/2024-05-01.log".to_string(),
+        code: Some("AccessDenied".to_string()),
+        message: Some("Access Denied because object protected by object lock.".to_string()),
+    };
+    assert!(locked.is_object_locked());
+    let denied = DeleteOutcome::Failed {
+        key: "audit/2024-05-01.log".to_string(),
+        code: Some("AccessDenied".to_string()),
+        message: Some("Access Denied".to_string()),
+    };
+    assert!(!denied.is_object_locked());
+}
+
+/// Records the canned ACL sent with each PutObject, by key.
+#[derive(Default)]
+struct RecordAcls(Mutex<Vec<(String, Option<String>)>>);
+
+impl RequestHook for RecordAcls {
+    fn before_send(&self, request: &mut HttpRequest) {
+        if request.method() == "PUT" {
+            let path = request.uri().split('?').next().unwrap_or_default();
+            let key = path.rsplit('/').next().unwrap_or_default().to_string();
+            let acl = request.headers().get("x-amz-acl").map(str::to_string);
+            self.0.lock().unwrap().push((key, acl));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_canned_acl_sent_with_put_object() {
+    let mock = MockS3::new().with_bucket("acl_bucket");
+    let acls = Arc::new(RecordAcls::default());
+
+    let open_options = OpenOptions::new("acl_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path("target/test-canned-acl/")
+        .request_hook(acls.clone());
+    open_options.write_s3("private.txt", b"a").await.unwrap();
+
+    let open_options = open_options.upload_acl(CannedAcl::PublicRead);
+    open_options.write_s3("public.txt", b"b").await.unwrap();
+    open_options
+        .write_s3_with(
+            "delivered.txt",
+            b"c",
+            &WriteOptions::new().acl(CannedAcl::BucketOwnerFullControl),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *acls.0.lock().unwrap(),
+        [
+            ("private.txt".to_string(), None),
+            ("public.txt".to_string(), Some("public-read".to_string())),
+            (
+                "delivered.txt".to_string(),
+                Some("bucket-owner-full-control".to_string())
+            ),
+        ]
+    );
+}
+
+/// Records the access key each request was signed with.
+#[derive(Debug, Clone, Default)]
+struct RecordAccessKeys(Arc<Mutex<Vec<String>>>);
+
+impl Intercept for RecordAccessKeys {
+    fn name(&self) -> &'static str {
+        "record access keys"
+    }
+
+    fn read_before_transmit(
+        &self,
+        context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let authorization = context
+            .request()
+            .headers()
+            .get("authorization")
+            .unwrap_or_default();
+        let access_key = authorization
+            .split("Credential=")
+            .nth(1)
+            .and_then(|credential| credential.split('/').next())
+            .unwrap_or_default();
+        self.0.lock().unwrap().push(access_key.to_string());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_write_and_open_with_scoped_credentials() {
+    let mock = MockS3::new().with_bucket("tenant_bucket");
+    let access_keys = RecordAccessKeys::default();
+    let client = aws_sdk_s3::Client::from_conf(
+        mock.client()
+            .config()
+            .to_builder()
+            .interceptor(access_keys.clone())
+            .build(),
+    );
+
+    let open_options = OpenOptions::new("tenant_bucket".to_string(), Some(client))
+        .await
+        .mount_path("target/test-scoped-credentials/")
+        .cache_policy(CachePolicy::AlwaysDownload);
+    let tenant = Credentials::new(
+        "TENANTKEY",
+        "secret",
+        Some("token".to_string()),
+        None,
+        "sts",
+    );
+
+    open_options
+        .write_s3_as("tenant-a/report.csv", b"a,b,c", tenant.clone())
+        .await
+        .unwrap();
+    open_options
+        .open_s3_as("tenant-a/report.csv", tenant)
+        .await
+        .unwrap();
+    open_options.open_s3("tenant-a/report.csv").await.unwrap();
+
+    let access_keys = access_keys.0.lock().unwrap();
+    assert!(access_keys.len() >= 3);
+    // Only the last call was made with the OpenOptions' own credentials.
+    let (own, scoped) = access_keys.split_last().unwrap();
+    assert_eq!(own, "mock");
+    assert!(scoped.iter().all(|access_key| access_key == "TENANTKEY"));
+}
+
+#[tokio::test]
+async fn test_write_back_holds_writes_until_flushed() {
+    let mount_path = "target/test-write-back/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("write_back_bucket");
+    mock.put_object("write_back_bucket", "outputs/a.txt", "old");
+
+    let open_options = OpenOptions::new("write_back_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cache_policy(CachePolicy::AlwaysDownload)
+        .write_back(true);
+
+    open_options
+        .write_s3("outputs/a.txt", b"new")
+        .await
+        .unwrap();
+    open_options.write_s3("outputs/b.txt", b"b").await.unwrap();
+    open_options.write_s3("logs/run.txt", b"log").await.unwrap();
+    assert_eq!(mock.keys("write_back_bucket"), ["outputs/a.txt"]);
+
+    // Pending writes are read back from the mirror, even though the cache policy always downloads.
+    let mut contents = String::new();
+    let mut file = open_options.open_s3("outputs/a.txt").await.unwrap();
+    tokio::io::AsyncReadExt::read_to_string(&mut file, &mut contents)
+        .await
+        .unwrap();
+    assert_eq!(contents, "new");
+    assert_eq!(
+        &open_options.read_s3("outputs/b.txt").await.unwrap()[..],
+        b"b"
+    );
+
+    // The pending writes are recorded under the mount path, so another run can flush them.
+    let next_run = OpenOptions::new("write_back_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    assert_eq!(
+        next_run.pending_writes().await.unwrap(),
+        [
+            PathBuf::from("logs/run.txt"),
+            PathBuf::from("outputs/a.txt"),
+            PathBuf::from("outputs/b.txt")
+        ]
+    );
+
+    let flushed = next_run.flush("outputs/").await.unwrap();
+    assert_eq!(
+        flushed,
+        [
+            PathBuf::from("outputs/a.txt"),
+            PathBuf::from("outputs/b.txt")
+        ]
+    );
+    assert_eq!(
+        mock.get_object("write_back_bucket", "outputs/a.txt")
+            .unwrap(),
+        b"new"
+    );
+    assert_eq!(
+        next_run.pending_writes().await.unwrap(),
+        [PathBuf::from("logs/run.txt")]
+    );
+
+    assert_eq!(
+        next_run.flush_all().await.unwrap(),
+        [PathBuf::from("logs/run.txt")]
+    );
+    assert!(next_run.pending_writes().await.unwrap().is_empty());
+    assert_eq!(
+        mock.keys("write_back_bucket"),
+        ["logs/run.txt", "outputs/a.txt", "outputs/b.txt"]
+    );
+
+    // Conditional writes still go straight to S3.
+    open_options
+        .write_s3_if_absent("outputs/c.txt", b"c")
+        .await
+        .unwrap();
+    assert!(mock
+        .get_object("write_back_bucket", "outputs/c.txt")
+        .is_some());
+    assert_eq!(mock.keys("write_back_bucket").len(), 4);
+}
+
+#[tokio::test]
+async fn test_sync_leaves_pending_writes_to_be_flushed() {
+    let mount_path = "target/test-sync-write-back/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("sync_write_back_bucket");
+    mock.put_object("sync_write_back_bucket", "outputs/a.txt", "old");
+
+    let open_options = OpenOptions::new("sync_write_back_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .write_back(true);
+
+    open_options
+        .write_s3("outputs/a.txt", b"newer")
+        .await
+        .unwrap();
+    open_options.write_s3("outputs/b.txt", b"b").await.unwrap();
+
+    // S3 has an older version of one pending write and nothing of the other, yet neither is touched.
+    let mut sync = open_options.spawn_sync("outputs/", Duration::from_secs(3600));
+    let cycle = sync.next_cycle().await.unwrap().unwrap();
+    sync.stop();
+    assert!(cycle.is_empty(), "{:?}", cycle);
+    assert_eq!(
+        &open_options.read_s3("outputs/a.txt").await.unwrap()[..],
+        b"newer"
+    );
+    assert_eq!(open_options.pending_writes().await.unwrap().len(), 2);
+
+    open_options.flush_all().await.unwrap();
+    assert_eq!(
+        mock.get_object("sync_write_back_bucket", "outputs/a.txt")
+            .unwrap(),
+        b"newer"
+    );
+    assert_eq!(
+        mock.get_object("sync_write_back_bucket", "outputs/b.txt")
+            .unwrap(),
+        b"b"
+    );
+}
+
+#[tokio::test]
+async fn test_recover_completes_or_rolls_back_interrupted_operations() {
+    let mount_path = "target/test-journal/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("journal_bucket");
+    mock.put_object("journal_bucket", "half.txt", "remote");
+
+    let open_options = OpenOptions::new("journal_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    open_options
+        .write_s3("finished.txt", b"done")
+        .await
+        .unwrap();
+    open_options.open_s3("half.txt").await.unwrap();
+    assert!(open_options
+        .interrupted_operations()
+        .await
+        .unwrap()
+        .is_empty());
+    let journal = format!("{}.s3-filesystem/journal_bucket.journal", mount_path);
+    let entries = |contents: String| {
+        let (header, entries) = contents.split_once('\n').unwrap();
+        assert!(header.starts_with("# s3-filesystem journal "), "{}", header);
+        entries.to_string()
+    };
+    assert_eq!(
+        entries(fs::read_to_string(&journal).await.unwrap()),
+        "upload\t4\tfinished.txt\n-\tfinished.txt\n\
+         download\t-\thalf.txt\n-\thalf.txt\n"
+    );
+
+    // The state a crashed run leaves behind: a written but not uploaded file, a file cut short while
+    // being written and a download cut short.
+    let folder = format!("{}journal_bucket/", mount_path);
+    fs::write(format!("{}uploaded.txt", folder), "local data")
+        .await
+        .unwrap();
+    fs::write(format!("{}cut.txt", folder), "partial")
+        .await
+        .unwrap();
+    fs::write(format!("{}half.txt", folder), "rem")
+        .await
+        .unwrap();
+    fs::write(
+        format!("{}.s3-filesystem/journal_bucket.journal", mount_path),
+        "# s3-filesystem journal crashed\n\
+         upload\t10\tuploaded.txt\nupload\t100\tcut.txt\ndownload\t-\thalf.txt\n\
+         upload\t4\tfinished.txt\n-\tfinished.txt\nupload\t1",
+    )
+    .await
+    .unwrap();
+
+    let next_run = OpenOptions::new("journal_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    let interrupted = next_run.interrupted_operations().await.unwrap();
+    assert_eq!(
+        interrupted
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry.operation, entry.size))
+            .collect::<Vec<_>>(),
+        [
+            ("cut.txt", JournalOperation::Upload, Some(100)),
+            ("half.txt", JournalOperation::Download, None),
+            ("uploaded.txt", JournalOperation::Upload, Some(10)),
+        ]
+    );
+
+    let recovered = next_run.recover(Recovery::Complete).await.unwrap();
+    assert_eq!(recovered, interrupted);
+    assert!(next_run.interrupted_operations().await.unwrap().is_empty());
+    // Recovery drops the lines of every finished operation.
+    assert_eq!(entries(fs::read_to_string(&journal).await.unwrap()), "");
+
+    assert_eq!(
+        mock.get_object("journal_bucket", "uploaded.txt").unwrap(),
+        b"local data"
+    );
+    // The file cut short could not be sent, so it is rolled back instead.
+    assert!(mock.get_object("journal_bucket", "cut.txt").is_none());
+    assert!(fs::metadata(format!("{}cut.txt", folder)).await.is_err());
+    assert_eq!(
+        fs::read_to_string(format!("{}half.txt", folder))
+            .await
+            .unwrap(),
+        "remote"
+    );
+
+    fs::write(format!("{}new.txt", folder), "data")
+        .await
+        .unwrap();
+    fs::write(
+        format!("{}.s3-filesystem/journal_bucket.journal", mount_path),
+        "# s3-filesystem journal crashed-again\nupload\t4\tnew.txt\n",
+    )
+    .await
+    .unwrap();
+    let last_run = OpenOptions::new("journal_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    last_run.recover(Recovery::RollBack).await.unwrap();
+    assert!(mock.get_object("journal_bucket", "new.txt").is_none());
+    assert!(fs::metadata(format!("{}new.txt", folder)).await.is_err());
+}
+
+/// "plain,text\n1,2\n", gzipped.
+const GZIPPED_CSV: &[u8] = b"\
+\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x2b\xc8\x49\xcc\xcc\xd3\x29\x49\xad\x28\xe1\x32\xd4\x31\
+\xe2\x02\x00\x7e\xa4\x5f\x02\x0f\x00\x00\x00";
+
+#[tokio::test]
+async fn test_auto_upload_skips_kept_compressed_copies() {
+    let mount_path = "target/test-auto-upload-compressed/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("auto_upload_gzip");
+    mock.put_object("auto_upload_gzip", "logs/day.log.gz", GZIPPED_CSV);
+    let open_options = OpenOptions::new("auto_upload_gzip".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .decompress_gz_suffix(true)
+        .keep_compressed(true);
+    assert_eq!(
+        open_options
+            .read_to_string("logs/day.log.gz")
+            .await
+            .unwrap(),
+        "plain,text\n1,2\n"
+    );
+    let kept = format!("{}auto_upload_gzip/logs/day.log.gz.compressed", mount_path);
+    assert!(fs::metadata(&kept).await.is_ok());
+
+    let mut uploads = open_options.spawn_auto_upload(Duration::from_millis(50));
+    for _ in 0..3 {
+        let cycle = tokio::time::timeout(Duration::from_secs(5), uploads.next_cycle())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(cycle.is_empty());
+    }
+    uploads.stop();
+    assert_eq!(mock.keys("auto_upload_gzip"), ["logs/day.log.gz"]);
+}
+
+#[tokio::test]
+async fn test_auto_upload_sends_files_changed_in_mount_path() {
+    let mount_path = "target/test-auto-upload/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("auto_upload_bucket");
+    let open_options = OpenOptions::new("auto_upload_bucket".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    open_options
+        .write_s3("written.txt", b"as uploaded")
+        .await
+        .unwrap();
+    open_options
+        .write_s3("edited.txt", b"before")
+        .await
+        .unwrap();
+    open_options.write_s3("same.txt", b"aaaa").await.unwrap();
+    // Let the filesystem's clock, which can lag the system clock by a tick, pass the time it was cached.
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let mut uploads = open_options.spawn_auto_upload(Duration::from_millis(50));
+
+    // Another program saves into the mount path.
+    let folder = format!("{}auto_upload_bucket/", mount_path);
+    fs::create_dir_all(format!("{}reports", folder))
+        .await
+        .unwrap();
+    fs::write(format!("{}reports/new.csv", folder), "a,b")
+        .await
+        .unwrap();
+    fs::write(format!("{}edited.txt", folder), "after editing")
+        .await
+        .unwrap();
+    // Changed within the same second it was cached, keeping its size.
+    fs::write(format!("{}same.txt", folder), "bbbb")
+        .await
+        .unwrap();
+
+    let mut uploaded = Vec::new();
+    while uploaded.len() < 3 {
+        let cycle = tokio::time::timeout(Duration::from_secs(5), uploads.next_cycle())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(cycle.failed.is_empty());
+        uploaded.extend(cycle.uploaded);
+    }
+    uploaded.sort();
+    assert_eq!(
+        uploaded,
+        [
+            PathBuf::from("edited.txt"),
+            PathBuf::from("reports/new.csv"),
+            PathBuf::from("same.txt")
+        ]
+    );
+    assert_eq!(
+        mock.get_object("auto_upload_bucket", "edited.txt").unwrap(),
+        b"after editing"
+    );
+    assert_eq!(
+        mock.get_object("auto_upload_bucket", "same.txt").unwrap(),
+        b"bbbb"
+    );
+    assert_eq!(
+        mock.get_object("auto_upload_bucket", "reports/new.csv")
+            .unwrap(),
+        b"a,b"
+    );
+
+    // Once uploaded, files are not sent again.
+    for _ in 0..3 {
+        let cycle = uploads.next_cycle().await.unwrap().unwrap();
+        assert!(cycle.is_empty());
+    }
+    uploads.stop();
+}
+
+#[tokio::test]
+async fn test_sync_leaves_files_auto_upload_has_not_sent() {
+    let mount_path = "target/test-sync-auto-upload/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("sync_auto_upload");
+    mock.put_object("sync_auto_upload", "ref/a.txt", "from s3");
+    mock.put_object("sync_auto_upload", "ref/b.txt", "b");
+
+    let open_options = OpenOptions::new("sync_auto_upload".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+    open_options.open_s3("ref/a.txt").await.unwrap();
+    open_options.open_s3("ref/b.txt").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // Another program edits one file and adds another, while S3 changes and loses the other.
+    let folder = format!("{}sync_auto_upload/", mount_path);
+    fs::write(format!("{}ref/a.txt", folder), "edited")
+        .await
+        .unwrap();
+    fs::write(format!("{}ref/new.txt", folder), "new")
+        .await
+        .unwrap();
+    mock.put_object("sync_auto_upload", "ref/a.txt", "changed in s3");
+    mock.delete_object("sync_auto_upload", "ref/b.txt");
+
+    let mut sync = open_options.spawn_sync("ref/", Duration::from_secs(3600));
+    let cycle = sync.next_cycle().await.unwrap().unwrap();
+    sync.stop();
+    assert!(cycle.updated.is_empty());
+    assert_eq!(cycle.removed, [PathBuf::from("ref/b.txt")]);
+    assert_eq!(
+        fs::read_to_string(format!("{}ref/a.txt", folder))
+            .await
+            .unwrap(),
+        "edited"
+    );
+    assert_eq!(
+        fs::read_to_string(format!("{}ref/new.txt", folder))
+            .await
+            .unwrap(),
+        "new"
+    );
+
+    let mut uploads = open_options.spawn_auto_upload(Duration::from_millis(20));
+    let mut uploaded = Vec::new();
+    while uploaded.len() < 2 {
+        let cycle = tokio::time::timeout(Duration::from_secs(5), uploads.next_cycle())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        uploaded.extend(cycle.uploaded);
+    }
+    uploads.stop();
+    assert_eq!(
+        mock.get_object("sync_auto_upload", "ref/a.txt").unwrap(),
+        b"edited"
+    );
+    assert_eq!(
+        mock.get_object("sync_auto_upload", "ref/new.txt").unwrap(),
+        b"new"
+    );
+}
+
+#[tokio::test]
+async fn test_background_tasks_stop_between_cycles_when_cancelled() {
+    let mount_path = "target/test-cancel-between-cycles/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("cancel_between_cycles");
+    let token = CancellationToken::new();
+    let open_options = OpenOptions::new("cancel_between_cycles".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path)
+        .cancel_on(token.clone());
+
+    let mut sync = open_options.spawn_sync("", Duration::from_secs(3600));
+    let mut uploads = open_options.spawn_auto_upload(Duration::from_secs(3600));
+    assert!(sync.next_cycle().await.unwrap().unwrap().is_empty());
+    assert!(uploads.next_cycle().await.unwrap().unwrap().is_empty());
+
+    token.cancel();
+    let stopped = Duration::from_secs(5);
+    assert!(tokio::time::timeout(stopped, sync.next_cycle())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(tokio::time::timeout(stopped, uploads.next_cycle())
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[cfg(feature = "notify")]
+#[tokio::test]
+async fn test_auto_upload_scans_when_the_mount_path_changes() {
+    let mount_path = "target/test-auto-upload-notify/";
+    let _ = fs::remove_dir_all(mount_path).await;
+
+    let mock = MockS3::new().with_bucket("auto_upload_notify");
+    let open_options = OpenOptions::new("auto_upload_notify".to_string(), Some(mock.client()))
+        .await
+        .mount_path(mount_path);
+
+    let interval = Duration::from_secs(2);
+    let mut uploads = open_options.spawn_auto_upload(interval);
+    assert!(uploads.next_cycle().await.unwrap().unwrap().is_empty());
+
+    // The watch wakes a scan straight away, then the file is sent once an interval shows it unchanged.
+    let written = std::time::Instant::now();
+    fs::write(
+        "target/test-auto-upload-notify/auto_upload_notify/out.txt",
+        "out",
+    )
+    .await
+    .unwrap();
+    let seen = uploads.next_cycle().await.unwrap().unwrap();
+    assert!(seen.is_empty());
+    assert!(written.elapsed() < interval, "{:?}", written.elapsed());
+
+    let sent = uploads.next_cycle().await.unwrap().unwrap();
+    assert_eq!(sent.uploaded, [PathBuf::from("out.txt")]);
+    assert!(written.elapsed() < interval * 2, "{:?}", written.elapsed());
+    uploads.stop();
+}